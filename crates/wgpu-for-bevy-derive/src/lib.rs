@@ -0,0 +1,656 @@
+//! `#[derive(AsBindGroup)]` for `wgpu-for-bevy`, a small
+//! version of Bevy's
+//! [`AsBindGroup` derive](https://docs.rs/bevy/latest/bevy/render/render_resource/trait.AsBindGroup.html).
+//!
+//! The attributes are documented on the
+//! `wgpu_for_bevy::AsBindGroup` trait. The generated code
+//! refers to `::wgpu_for_bevy`, so this crate is only
+//! useful through that one.
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::{format_ident, quote, quote_spanned};
+use std::collections::HashMap;
+use syn::{
+    Data, DeriveInput, Field, Ident, LitBool, LitInt,
+    LitStr, Member, Token, parenthesized,
+    parse::ParseStream, parse_macro_input,
+    punctuated::Punctuated, spanned::Spanned,
+};
+
+#[proc_macro_derive(
+    AsBindGroup,
+    attributes(uniform, storage, texture, sampler)
+)]
+pub fn derive_as_bind_group(
+    input: TokenStream,
+) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// One `#[uniform]`, `#[storage]`, `#[texture]` or
+/// `#[sampler]` attribute
+struct Binding {
+    index: u32,
+    visibility: TokenStream2,
+    kind: BindingKind,
+    member: Member,
+    ty: syn::Type,
+}
+
+enum BindingKind {
+    Uniform {
+        buffer: bool,
+    },
+    Storage {
+        read_only: bool,
+        buffer: bool,
+    },
+    Texture {
+        dimension: TokenStream2,
+        sample_type: TokenStream2,
+        multisampled: bool,
+    },
+    Sampler {
+        sampler_type: TokenStream2,
+    },
+}
+
+/// The value of an argument after the binding index:
+/// `read_only`, `dimension = "2d"`,
+/// `filterable = false` or `visibility(vertex)`
+enum ArgValue {
+    Flag,
+    Str(LitStr),
+    Bool(LitBool),
+    List(Vec<Ident>),
+}
+
+struct Arg {
+    name: Ident,
+    value: ArgValue,
+}
+
+fn expand(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let Data::Struct(data) = &input.data else {
+        return Err(syn::Error::new_spanned(
+            &input.ident,
+            "AsBindGroup can only be derived for structs",
+        ));
+    };
+
+    let mut bindings = vec![];
+    // binding index -> the field that used it first
+    let mut used: HashMap<u32, Member> = HashMap::new();
+    for (i, field) in data.fields.iter().enumerate() {
+        let member = match &field.ident {
+            Some(ident) => Member::Named(ident.clone()),
+            None => Member::Unnamed(i.into()),
+        };
+        for attr in &field.attrs {
+            let Some(kind) = attr.path().get_ident() else {
+                continue;
+            };
+            if !["uniform", "storage", "texture", "sampler"]
+                .iter()
+                .any(|name| kind == name)
+            {
+                continue;
+            }
+            let (index_lit, args) = attr.parse_args_with(
+                |input: ParseStream| {
+                    parse_args(input, kind)
+                },
+            )?;
+            let binding = binding(
+                kind,
+                &index_lit,
+                args,
+                field,
+                member.clone(),
+            )?;
+            if let Some(first) =
+                used.insert(binding.index, member.clone())
+            {
+                return Err(syn::Error::new_spanned(
+                    index_lit,
+                    format!(
+                        "binding {} is already used by `{}`",
+                        binding.index,
+                        member_name(&first)
+                    ),
+                ));
+            }
+            bindings.push(binding);
+        }
+    }
+
+    let krate = quote!(::wgpu_for_bevy);
+    let wgpu = quote!(#krate::__private::wgpu);
+
+    let layout_entries = bindings.iter().map(|binding| {
+        let Binding {
+            index,
+            visibility,
+            kind,
+            ty,
+            ..
+        } = binding;
+        let ty = match kind {
+            BindingKind::Uniform { buffer: false } => {
+                return quote! {
+                    #krate::Uniform::<#ty>::layout_entry(
+                        #index,
+                        #visibility,
+                    )
+                };
+            }
+            BindingKind::Storage {
+                read_only,
+                buffer: false,
+            } => {
+                return quote! {
+                    #krate::Storage::<#ty>::layout_entry(
+                        #index,
+                        #visibility,
+                        #read_only,
+                    )
+                };
+            }
+            BindingKind::Uniform { buffer: true } => {
+                buffer_binding_type(
+                    &wgpu,
+                    ty,
+                    quote!(#wgpu::BufferBindingType::Uniform),
+                )
+            }
+            BindingKind::Storage {
+                read_only,
+                buffer: true,
+            } => buffer_binding_type(
+                &wgpu,
+                ty,
+                quote!(#wgpu::BufferBindingType::Storage {
+                    read_only: #read_only,
+                }),
+            ),
+            BindingKind::Texture {
+                dimension,
+                sample_type,
+                multisampled,
+            } => quote! {
+                #wgpu::BindingType::Texture {
+                    sample_type: #sample_type,
+                    view_dimension: #dimension,
+                    multisampled: #multisampled,
+                }
+            },
+            BindingKind::Sampler { sampler_type } => quote! {
+                #wgpu::BindingType::Sampler(#sampler_type)
+            },
+        };
+        quote! {
+            #wgpu::BindGroupLayoutEntry {
+                binding: #index,
+                visibility: #visibility,
+                ty: #ty,
+                count: ::std::option::Option::None,
+            }
+        }
+    });
+
+    // buffers created from field values have to outlive
+    // the `BindGroupEntry`s that borrow them
+    let mut buffers = vec![];
+    let entries = bindings.iter().map(|binding| {
+        let Binding {
+            index,
+            kind,
+            member,
+            ty,
+            ..
+        } = binding;
+        // trait bound errors point at the field's type
+        let span = ty.span();
+        let resource = match kind {
+            BindingKind::Uniform { buffer: false }
+            | BindingKind::Storage { buffer: false, .. } => {
+                let create = match kind {
+                    BindingKind::Uniform { .. } => {
+                        quote!(create_uniform_buffer)
+                    }
+                    _ => quote!(create_storage_buffer),
+                };
+                let buffer = format_ident!("binding_{index}");
+                buffers.push(quote_spanned! {span=>
+                    let #buffer =
+                        #krate::bind_group::#create(
+                            device,
+                            Self::label(),
+                            &self.#member,
+                        );
+                });
+                quote!(#buffer.as_entire_binding())
+            }
+            BindingKind::Uniform { buffer: true }
+            | BindingKind::Storage { buffer: true, .. } => {
+                quote_spanned! {span=>
+                    #krate::bind_group::AsBuffer::buffer(
+                        &self.#member,
+                    )
+                    .as_entire_binding()
+                }
+            }
+            BindingKind::Texture { .. } => quote_spanned! {span=>
+                #wgpu::BindingResource::TextureView(
+                    #krate::bind_group::AsTextureView::texture_view(
+                        &self.#member,
+                    ),
+                )
+            },
+            BindingKind::Sampler { .. } => quote_spanned! {span=>
+                #wgpu::BindingResource::Sampler(
+                    #krate::bind_group::AsSampler::sampler(
+                        &self.#member,
+                    ),
+                )
+            },
+        };
+        quote! {
+            #wgpu::BindGroupEntry {
+                binding: #index,
+                resource: #resource,
+            }
+        }
+    });
+    let entries: Vec<_> = entries.collect();
+
+    let ident = &input.ident;
+    let label = snake_case(&ident.to_string());
+    let (impl_generics, ty_generics, where_clause) =
+        input.generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics #krate::AsBindGroup
+            for #ident #ty_generics #where_clause
+        {
+            fn label() -> &'static str {
+                #label
+            }
+
+            fn bind_group_layout_entries()
+            -> ::std::vec::Vec<#wgpu::BindGroupLayoutEntry> {
+                ::std::vec![#(#layout_entries),*]
+            }
+
+            fn as_bind_group(
+                &self,
+                device: &#wgpu::Device,
+                layout: &#wgpu::BindGroupLayout,
+            ) -> #wgpu::BindGroup {
+                #(#buffers)*
+                device.create_bind_group(
+                    &#wgpu::BindGroupDescriptor {
+                        label: ::std::option::Option::Some(
+                            Self::label(),
+                        ),
+                        layout,
+                        entries: &[#(#entries),*],
+                    },
+                )
+            }
+        }
+    })
+}
+
+/// Parses `0, read_only, dimension = "2d",
+/// visibility(vertex)`
+fn parse_args(
+    input: ParseStream,
+    kind: &Ident,
+) -> syn::Result<(LitInt, Vec<Arg>)> {
+    let index: LitInt = input.parse().map_err(|err| {
+        syn::Error::new(
+            err.span(),
+            format!(
+                "expected a binding index, such as `#[{kind}(0)]`"
+            ),
+        )
+    })?;
+    let mut args = vec![];
+    while !input.is_empty() {
+        input.parse::<Token![,]>()?;
+        if input.is_empty() {
+            break;
+        }
+        let name: Ident = input.parse()?;
+        let value = if input.peek(Token![=]) {
+            input.parse::<Token![=]>()?;
+            if input.peek(LitBool) {
+                ArgValue::Bool(input.parse()?)
+            } else {
+                ArgValue::Str(input.parse()?)
+            }
+        } else if input.peek(syn::token::Paren) {
+            let content;
+            parenthesized!(content in input);
+            let list = Punctuated::<Ident, Token![,]>::parse_terminated(&content)?;
+            ArgValue::List(list.into_iter().collect())
+        } else {
+            ArgValue::Flag
+        };
+        args.push(Arg { name, value });
+    }
+    Ok((index, args))
+}
+
+fn binding(
+    kind: &Ident,
+    index: &LitInt,
+    args: Vec<Arg>,
+    field: &Field,
+    member: Member,
+) -> syn::Result<Binding> {
+    let index = index.base10_parse::<u32>()?;
+    let mut visibility = quote!(
+        ::wgpu_for_bevy::__private::wgpu::ShaderStages::VERTEX_FRAGMENT
+    );
+
+    // arguments shared by every kind are taken out first,
+    // the rest are checked against what `kind` accepts
+    let mut flags = vec![];
+    let mut strs = vec![];
+    let mut bools = vec![];
+    for Arg { name, value } in args {
+        match value {
+            ArgValue::List(stages)
+                if name == "visibility" =>
+            {
+                visibility = shader_stages(&stages)?;
+            }
+            ArgValue::Flag => flags.push(name),
+            ArgValue::Str(value) => {
+                strs.push((name, value))
+            }
+            ArgValue::Bool(value) => {
+                bools.push((name, value))
+            }
+            ArgValue::List(_) => {
+                return Err(unknown_argument(kind, &name));
+            }
+        }
+    }
+
+    let flag = |flags: &mut Vec<Ident>, wanted: &str| {
+        let found = flags.iter().position(|f| f == wanted);
+        found.map(|i| flags.remove(i)).is_some()
+    };
+    let binding_kind = if kind == "uniform" {
+        BindingKind::Uniform {
+            buffer: flag(&mut flags, "buffer"),
+        }
+    } else if kind == "storage" {
+        BindingKind::Storage {
+            read_only: flag(&mut flags, "read_only"),
+            buffer: flag(&mut flags, "buffer"),
+        }
+    } else if kind == "texture" {
+        texture(&mut strs, &mut bools)?
+    } else {
+        sampler(&mut strs)?
+    };
+
+    // anything left over isn't something this kind takes
+    let leftover = flags
+        .first()
+        .or(strs.first().map(|(name, _)| name))
+        .or(bools.first().map(|(name, _)| name));
+    if let Some(name) = leftover {
+        return Err(unknown_argument(kind, name));
+    }
+
+    Ok(Binding {
+        index,
+        visibility,
+        kind: binding_kind,
+        member,
+        ty: field.ty.clone(),
+    })
+}
+
+fn texture(
+    strs: &mut Vec<(Ident, LitStr)>,
+    bools: &mut Vec<(Ident, LitBool)>,
+) -> syn::Result<BindingKind> {
+    let wgpu = quote!(::wgpu_for_bevy::__private::wgpu);
+
+    let dimension = match take(strs, "dimension") {
+        None => quote!(#wgpu::TextureViewDimension::D2),
+        Some(value) => {
+            let variant = match value.value().as_str() {
+                "1d" => "D1",
+                "2d" => "D2",
+                "2d_array" => "D2Array",
+                "cube" => "Cube",
+                "cube_array" => "CubeArray",
+                "3d" => "D3",
+                _ => {
+                    return Err(unknown_value(
+                        &value,
+                        "dimension",
+                        &[
+                            "1d",
+                            "2d",
+                            "2d_array",
+                            "cube",
+                            "cube_array",
+                            "3d",
+                        ],
+                    ));
+                }
+            };
+            let variant = Ident::new(variant, value.span());
+            quote!(#wgpu::TextureViewDimension::#variant)
+        }
+    };
+
+    let filterable = take(bools, "filterable");
+    let sample_type = take(strs, "sample_type");
+    let sample_type = match sample_type
+        .as_ref()
+        .map(LitStr::value)
+        .as_deref()
+    {
+        None | Some("float") => {
+            let filterable = filterable
+                .as_ref()
+                .is_none_or(LitBool::value);
+            quote!(#wgpu::TextureSampleType::Float {
+                filterable: #filterable,
+            })
+        }
+        Some(other) => {
+            let sample_type = sample_type.unwrap();
+            if let Some(filterable) = filterable {
+                return Err(syn::Error::new_spanned(
+                    filterable,
+                    "`filterable` only applies to \
+                     `sample_type = \"float\"`",
+                ));
+            }
+            match other {
+                "depth" => {
+                    quote!(#wgpu::TextureSampleType::Depth)
+                }
+                "s_int" => {
+                    quote!(#wgpu::TextureSampleType::Sint)
+                }
+                "u_int" => {
+                    quote!(#wgpu::TextureSampleType::Uint)
+                }
+                _ => {
+                    return Err(unknown_value(
+                        &sample_type,
+                        "sample_type",
+                        &[
+                            "float", "depth", "s_int",
+                            "u_int",
+                        ],
+                    ));
+                }
+            }
+        }
+    };
+
+    let multisampled = take(bools, "multisampled")
+        .is_some_and(|value| value.value());
+
+    Ok(BindingKind::Texture {
+        dimension,
+        sample_type,
+        multisampled,
+    })
+}
+
+fn sampler(
+    strs: &mut Vec<(Ident, LitStr)>,
+) -> syn::Result<BindingKind> {
+    let wgpu = quote!(::wgpu_for_bevy::__private::wgpu);
+    let variant = match take(strs, "sampler_type") {
+        None => "Filtering",
+        Some(value) => match value.value().as_str() {
+            "filtering" => "Filtering",
+            "non_filtering" => "NonFiltering",
+            "comparison" => "Comparison",
+            _ => {
+                return Err(unknown_value(
+                    &value,
+                    "sampler_type",
+                    &[
+                        "filtering",
+                        "non_filtering",
+                        "comparison",
+                    ],
+                ));
+            }
+        },
+    };
+    let variant = Ident::new(variant, Span::call_site());
+    Ok(BindingKind::Sampler {
+        sampler_type: quote!(
+            #wgpu::SamplerBindingType::#variant
+        ),
+    })
+}
+
+fn shader_stages(
+    stages: &[Ident],
+) -> syn::Result<TokenStream2> {
+    let wgpu = quote!(::wgpu_for_bevy::__private::wgpu);
+    let mut flags = vec![];
+    for stage in stages {
+        let flag = match stage.to_string().as_str() {
+            "vertex" => quote!(VERTEX),
+            "fragment" => quote!(FRAGMENT),
+            "compute" => quote!(COMPUTE),
+            "task" => quote!(TASK),
+            "mesh" => quote!(MESH),
+            "all" => quote!(all()),
+            "none" => quote!(NONE),
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    stage,
+                    format!(
+                        "unknown shader stage `{stage}`, \
+                         expected `vertex`, `fragment`, \
+                         `compute`, `task`, `mesh`, `all` \
+                         or `none`"
+                    ),
+                ));
+            }
+        };
+        flags.push(quote!(#wgpu::ShaderStages::#flag));
+    }
+    if flags.is_empty() {
+        return Ok(quote!(#wgpu::ShaderStages::NONE));
+    }
+    Ok(quote!(#(#flags)|*))
+}
+
+fn buffer_binding_type(
+    wgpu: &TokenStream2,
+    ty: &syn::Type,
+    buffer_type: TokenStream2,
+) -> TokenStream2 {
+    quote! {
+        #wgpu::BindingType::Buffer {
+            ty: #buffer_type,
+            has_dynamic_offset: false,
+            min_binding_size:
+                <#ty as ::wgpu_for_bevy::bind_group::AsBuffer>::min_binding_size(),
+        }
+    }
+}
+
+/// Removes and returns the argument called `name`
+fn take<T>(
+    args: &mut Vec<(Ident, T)>,
+    name: &str,
+) -> Option<T> {
+    let i = args.iter().position(|(n, _)| n == name)?;
+    Some(args.remove(i).1)
+}
+
+fn unknown_argument(
+    kind: &Ident,
+    name: &Ident,
+) -> syn::Error {
+    syn::Error::new_spanned(
+        name,
+        format!("unknown `{kind}` argument `{name}`"),
+    )
+}
+
+fn unknown_value(
+    value: &LitStr,
+    name: &str,
+    expected: &[&str],
+) -> syn::Error {
+    let expected = expected
+        .iter()
+        .map(|e| format!("\"{e}\""))
+        .collect::<Vec<_>>()
+        .join(", ");
+    syn::Error::new_spanned(
+        value,
+        format!(
+            "unknown {name} \"{}\", expected one of {expected}",
+            value.value()
+        ),
+    )
+}
+
+fn member_name(member: &Member) -> String {
+    match member {
+        Member::Named(ident) => ident.to_string(),
+        Member::Unnamed(index) => index.index.to_string(),
+    }
+}
+
+/// `FlatColorMaterial` -> `flat_color_material`
+fn snake_case(name: &str) -> String {
+    let mut snake = String::new();
+    for (i, c) in name.chars().enumerate() {
+        if c.is_uppercase() {
+            if i > 0 {
+                snake.push('_');
+            }
+            snake.extend(c.to_lowercase());
+        } else {
+            snake.push(c);
+        }
+    }
+    snake
+}
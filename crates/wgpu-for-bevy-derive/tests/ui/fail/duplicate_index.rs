@@ -0,0 +1,13 @@
+use glam::Vec4;
+use wgpu::Sampler;
+use wgpu_for_bevy::AsBindGroup;
+
+#[derive(AsBindGroup)]
+struct Material {
+    #[uniform(0)]
+    color: Vec4,
+    #[sampler(0)]
+    sampler: Sampler,
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use wgpu_for_bevy::AsBindGroup;
+
+#[derive(AsBindGroup)]
+enum Material {
+    Red,
+    Green,
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use glam::Vec4;
+use wgpu_for_bevy::AsBindGroup;
+
+#[derive(AsBindGroup)]
+struct Material {
+    #[uniform(visibility(fragment))]
+    color: Vec4,
+}
+
+fn main() {}
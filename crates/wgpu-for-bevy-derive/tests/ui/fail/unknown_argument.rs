@@ -0,0 +1,10 @@
+use wgpu::Sampler;
+use wgpu_for_bevy::AsBindGroup;
+
+#[derive(AsBindGroup)]
+struct Material {
+    #[sampler(0, read_only)]
+    sampler: Sampler,
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use wgpu::TextureView;
+use wgpu_for_bevy::AsBindGroup;
+
+#[derive(AsBindGroup)]
+struct Material {
+    #[texture(0, dimension = "4d")]
+    image: TextureView,
+}
+
+fn main() {}
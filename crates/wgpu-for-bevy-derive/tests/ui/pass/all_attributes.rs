@@ -0,0 +1,52 @@
+use encase::ShaderType;
+use glam::Vec4;
+use wgpu::{Buffer, Sampler, TextureView};
+use wgpu_for_bevy::{AsBindGroup, GpuImage, Storage, Uniform};
+
+#[derive(ShaderType)]
+struct Settings {
+    color: Vec4,
+    speed: f32,
+}
+
+#[derive(AsBindGroup)]
+struct CustomMaterial {
+    #[uniform(0)]
+    settings: Settings,
+    #[texture(1)]
+    image: GpuImage,
+    #[sampler(2)]
+    sampler: Sampler,
+    #[storage(3, read_only)]
+    offsets: Vec<Vec4>,
+    #[texture(
+        4,
+        dimension = "cube",
+        sample_type = "depth",
+        visibility(fragment)
+    )]
+    shadow: TextureView,
+    #[sampler(5, sampler_type = "comparison")]
+    shadow_sampler: Sampler,
+    #[uniform(6, buffer, visibility(all))]
+    time: Uniform<f32>,
+    #[storage(7, buffer, visibility(vertex, compute))]
+    instances: Storage<Vec<Vec4>>,
+    #[texture(8, sample_type = "float", filterable = false)]
+    positions: TextureView,
+    #[storage(9, buffer)]
+    raw: Buffer,
+    // fields without attributes aren't part of the bind group
+    #[allow(dead_code)]
+    name: String,
+}
+
+#[derive(AsBindGroup)]
+struct TupleMaterial(#[uniform(0)] Vec4, #[texture(1, dimension = "3d")] TextureView);
+
+fn main() {
+    assert_eq!(CustomMaterial::label(), "custom_material");
+    assert_eq!(CustomMaterial::bind_group_layout_entries().len(), 10);
+    assert_eq!(TupleMaterial::label(), "tuple_material");
+    assert_eq!(TupleMaterial::bind_group_layout_entries().len(), 2);
+}
@@ -0,0 +1,199 @@
+//! [`AsBindGroup`], and the traits and functions the
+//! `#[derive(AsBindGroup)]` code calls for each field.
+use crate::{
+    image::GpuImage, storage::Storage, uniform::Uniform,
+};
+use encase::{
+    ShaderType, StorageBuffer, UniformBuffer,
+    internal::WriteInto,
+};
+use wgpu::{
+    BindGroup, BindGroupLayout, BindGroupLayoutEntry,
+    Buffer, BufferSize, BufferUsages, Device, Sampler,
+    TextureView, util::DeviceExt,
+};
+
+/// Turns a value into a bind group, like Bevy's
+/// [`AsBindGroup`](https://docs.rs/bevy/latest/bevy/render/render_resource/trait.AsBindGroup.html).
+///
+/// The layout only depends on the type, so it can be
+/// created before any value exists.
+///
+/// Usually this is derived, with an attribute on each
+/// field that's part of the bind group:
+///
+/// ```ignore
+/// #[derive(AsBindGroup)]
+/// struct CustomMaterial {
+///     #[uniform(0)]
+///     color: Vec4,
+///     #[texture(1)]
+///     image: GpuImage,
+///     #[sampler(2)]
+///     sampler: Sampler,
+///     #[storage(3, read_only)]
+///     offsets: Vec<Vec4>,
+/// }
+/// ```
+///
+/// - `#[uniform(N)]` writes a `ShaderType` field into a
+///   new uniform buffer. With `buffer`, the field is an
+///   [`AsBuffer`] (such as a [`Uniform`]) and its buffer
+///   is bound as is.
+/// - `#[storage(N)]` is the same for storage buffers, and
+///   also takes `read_only`.
+/// - `#[texture(N)]` binds an [`AsTextureView`]. It takes
+///   `dimension` (`"1d"`, `"2d"` (the default),
+///   `"2d_array"`, `"cube"`, `"cube_array"` or `"3d"`),
+///   `sample_type` (`"float"` (the default), `"depth"`,
+///   `"s_int"` or `"u_int"`), `filterable = false` and
+///   `multisampled = true`.
+/// - `#[sampler(N)]` binds an [`AsSampler`]. It takes
+///   `sampler_type` (`"filtering"` (the default),
+///   `"non_filtering"` or `"comparison"`).
+///
+/// Every attribute also takes `visibility(..)`, a list of
+/// `vertex`, `fragment`, `compute`, `task`, `mesh`, `all`
+/// or `none`. The default is `visibility(vertex,
+/// fragment)`.
+pub trait AsBindGroup {
+    /// Used in the labels of the layout and bind groups
+    fn label() -> &'static str;
+
+    fn bind_group_layout_entries()
+    -> Vec<BindGroupLayoutEntry>;
+
+    fn bind_group_layout(
+        device: &Device,
+    ) -> BindGroupLayout {
+        device.create_bind_group_layout(
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some(Self::label()),
+                entries: &Self::bind_group_layout_entries(),
+            },
+        )
+    }
+
+    /// Create the GPU resources for this value (such as
+    /// uniform buffers) and a bind group pointing at them.
+    fn as_bind_group(
+        &self,
+        device: &Device,
+        layout: &BindGroupLayout,
+    ) -> BindGroup;
+}
+
+/// Field types `#[texture]` can be used on
+pub trait AsTextureView {
+    fn texture_view(&self) -> &TextureView;
+}
+
+impl AsTextureView for TextureView {
+    fn texture_view(&self) -> &TextureView {
+        self
+    }
+}
+
+impl AsTextureView for GpuImage {
+    fn texture_view(&self) -> &TextureView {
+        &self.view
+    }
+}
+
+/// Field types `#[sampler]` can be used on
+pub trait AsSampler {
+    fn sampler(&self) -> &Sampler;
+}
+
+impl AsSampler for Sampler {
+    fn sampler(&self) -> &Sampler {
+        self
+    }
+}
+
+/// Field types `#[uniform(N, buffer)]` and
+/// `#[storage(N, buffer)]` can be used on: anything that
+/// already owns a buffer.
+pub trait AsBuffer {
+    /// The `min_binding_size` of the layout entry. Plain
+    /// [`Buffer`]s don't know what they hold, so they
+    /// leave it to be checked at draw time.
+    fn min_binding_size() -> Option<BufferSize>;
+
+    fn buffer(&self) -> &Buffer;
+}
+
+impl AsBuffer for Buffer {
+    fn min_binding_size() -> Option<BufferSize> {
+        None
+    }
+
+    fn buffer(&self) -> &Buffer {
+        self
+    }
+}
+
+impl<T: ShaderType + WriteInto> AsBuffer for Uniform<T> {
+    fn min_binding_size() -> Option<BufferSize> {
+        Some(T::min_size())
+    }
+
+    fn buffer(&self) -> &Buffer {
+        Uniform::buffer(self)
+    }
+}
+
+/// Storage buffers are recreated when they grow, so bind
+/// groups made from one have to be recreated when
+/// [`Storage::upload`] returns `true`.
+impl<T: ShaderType + WriteInto> AsBuffer for Storage<T> {
+    fn min_binding_size() -> Option<BufferSize> {
+        Some(T::min_size())
+    }
+
+    fn buffer(&self) -> &Buffer {
+        Storage::buffer(self)
+    }
+}
+
+/// A uniform buffer initialized with `value`, for
+/// `#[uniform(N)]` fields. Nothing keeps the value around
+/// for later uploads, use a [`Uniform`] for data that
+/// changes.
+pub fn create_uniform_buffer<T: ShaderType + WriteInto>(
+    device: &Device,
+    label: &str,
+    value: &T,
+) -> Buffer {
+    let mut contents = UniformBuffer::new(Vec::new());
+    contents
+        .write(value)
+        .expect("value to fit in a uniform buffer");
+    device.create_buffer_init(
+        &wgpu::util::BufferInitDescriptor {
+            label: Some(label),
+            contents: contents.as_ref(),
+            usage: BufferUsages::UNIFORM,
+        },
+    )
+}
+
+/// A storage buffer initialized with `value`, for
+/// `#[storage(N)]` fields
+pub fn create_storage_buffer<T: ShaderType + WriteInto>(
+    device: &Device,
+    label: &str,
+    value: &T,
+) -> Buffer {
+    let mut contents = StorageBuffer::new(Vec::new());
+    contents
+        .write(value)
+        .expect("value to fit in a storage buffer");
+    device.create_buffer_init(
+        &wgpu::util::BufferInitDescriptor {
+            label: Some(label),
+            contents: contents.as_ref(),
+            usage: BufferUsages::STORAGE,
+        },
+    )
+}
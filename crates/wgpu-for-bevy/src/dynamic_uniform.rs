@@ -0,0 +1,168 @@
+//! Many `ShaderType` values packed into one uniform
+//! buffer and selected with dynamic offsets, similar to
+//! Bevy's
+//! [`DynamicUniformBuffer<T>`](https://docs.rs/bevy/latest/bevy/render/render_resource/struct.DynamicUniformBuffer.html).
+use encase::{ShaderType, internal::WriteInto};
+use std::marker::PhantomData;
+use wgpu::{
+    BindGroupEntry, BindGroupLayoutEntry, BindingResource,
+    BindingType, Buffer, BufferBinding, BufferBindingType,
+    BufferDescriptor, BufferUsages, Device, Queue,
+    ShaderStages,
+};
+
+/// A uniform buffer holding one `T` per object.
+///
+/// Every value starts at a multiple of the device's
+/// `min_uniform_buffer_offset_alignment` (usually 256
+/// bytes), because that's the granularity dynamic offsets
+/// can be given in. A bind group is created once for the
+/// whole buffer and each draw picks its value by passing
+/// the offset returned from [`DynamicUniform::push`] to
+/// `set_bind_group`.
+///
+/// Values are pushed into a CPU-side `Vec` each frame and
+/// then copied to the GPU with
+/// [`DynamicUniform::write_buffer`].
+pub struct DynamicUniform<T> {
+    scratch: encase::DynamicUniformBuffer<Vec<u8>>,
+    buffer: Option<Buffer>,
+    label: String,
+    len: usize,
+    _marker: PhantomData<fn(T)>,
+}
+
+impl<T: ShaderType + WriteInto> DynamicUniform<T> {
+    pub fn new(device: &Device, label: &str) -> Self {
+        let alignment = device
+            .limits()
+            .min_uniform_buffer_offset_alignment
+            as u64;
+
+        Self {
+            scratch:
+                encase::DynamicUniformBuffer::new_with_alignment(
+                    Vec::new(),
+                    alignment,
+                ),
+            buffer: None,
+            label: label.to_string(),
+            len: 0,
+            _marker: PhantomData,
+        }
+    }
+
+    /// The layout entry for a dynamic uniform of this
+    /// type. The binding size is a single `T`, not the
+    /// whole buffer.
+    pub fn layout_entry(
+        binding: u32,
+        visibility: ShaderStages,
+    ) -> BindGroupLayoutEntry {
+        BindGroupLayoutEntry {
+            binding,
+            visibility,
+            ty: BindingType::Buffer {
+                ty: BufferBindingType::Uniform,
+                has_dynamic_offset: true,
+                min_binding_size: Some(T::min_size()),
+            },
+            count: None,
+        }
+    }
+
+    /// Add a value and get back the dynamic offset to
+    /// bind it with.
+    pub fn push(&mut self, value: &T) -> u32 {
+        self.len += 1;
+        self.scratch
+            .write(value)
+            .expect("value to fit in the staging buffer")
+            as u32
+    }
+
+    /// Forget every pushed value, keeping the staging and
+    /// GPU allocations around for reuse.
+    pub fn clear(&mut self) {
+        self.scratch.as_mut().clear();
+        self.scratch.set_offset(0);
+        self.len = 0;
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Copy the pushed values to the GPU, growing the
+    /// buffer if they no longer fit.
+    ///
+    /// Returns `true` when a new buffer was created, which
+    /// means any bind group using the old one has to be
+    /// recreated.
+    pub fn write_buffer(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+    ) -> bool {
+        let contents = self.scratch.as_ref();
+        if contents.is_empty() {
+            return false;
+        }
+
+        let size = contents.len() as u64;
+        let reallocated = match &self.buffer {
+            Some(buffer) if buffer.size() >= size => false,
+            _ => {
+                self.buffer = Some(device.create_buffer(
+                    &BufferDescriptor {
+                        label: Some(&self.label),
+                        size,
+                        usage: BufferUsages::UNIFORM
+                            | BufferUsages::COPY_DST,
+                        mapped_at_creation: false,
+                    },
+                ));
+                true
+            }
+        };
+
+        queue.write_buffer(
+            self.buffer.as_ref().unwrap(),
+            0,
+            contents,
+        );
+        reallocated
+    }
+
+    pub fn buffer(&self) -> Option<&Buffer> {
+        self.buffer.as_ref()
+    }
+
+    /// A binding covering one `T`, which the dynamic
+    /// offset then moves around inside the buffer.
+    ///
+    /// `None` until the first [`DynamicUniform::write_buffer`].
+    pub fn binding(&self) -> Option<BindingResource<'_>> {
+        self.buffer.as_ref().map(|buffer| {
+            BindingResource::Buffer(BufferBinding {
+                buffer,
+                offset: 0,
+                size: Some(T::min_size()),
+            })
+        })
+    }
+
+    pub fn bind_group_entry(
+        &self,
+        binding: u32,
+    ) -> Option<BindGroupEntry<'_>> {
+        self.binding().map(|resource| BindGroupEntry {
+            binding,
+            resource,
+        })
+    }
+}
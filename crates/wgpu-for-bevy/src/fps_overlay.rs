@@ -0,0 +1,587 @@
+//! Frame time, FPS, and a frame time graph drawn on top
+//! of a frame, similar to Bevy's
+//! [`FpsOverlayPlugin`](https://docs.rs/bevy/latest/bevy/dev_tools/fps_overlay/struct.FpsOverlayPlugin.html).
+//!
+//! Bevy draws its overlay with `bevy_ui` and a real font.
+//! This one has its own pipeline that draws rectangles
+//! from a storage buffer, some of them solid and some of
+//! them glyphs from a tiny bitmap font.
+use crate::{Image, Storage, Uniform, image::GpuImage};
+use encase::ShaderType;
+use glam::{Vec2, Vec4};
+use std::{
+    borrow::Cow, collections::VecDeque, time::Instant,
+};
+use wgpu::{
+    AdapterInfo, BindGroup, BindGroupLayout,
+    CommandEncoder, Device, Queue, RenderPipeline,
+    ShaderStages, TextureFormat, TextureView,
+};
+
+/// Must match `GLYPH_SIZE` in `fps_overlay.wgsl`
+const GLYPH_WIDTH: u32 = 5;
+const GLYPH_HEIGHT: u32 = 8;
+/// Screen pixels per font texel
+const SCALE: f32 = 2.0;
+/// How many frames the average and the graph cover
+const HISTORY_LEN: usize = 120;
+/// Frame times at or above this fill the graph
+const GRAPH_MAX_MS: f32 = 50.0;
+const GRAPH_HEIGHT: f32 = 48.0;
+const PADDING: f32 = 8.0;
+const TEXT_COLOR: Vec4 = Vec4::new(0.9, 0.9, 0.9, 1.0);
+
+#[derive(ShaderType)]
+struct View {
+    size: Vec2,
+}
+
+#[derive(ShaderType, Clone, Copy)]
+struct Rect {
+    position: Vec2,
+    size: Vec2,
+    color: Vec4,
+    /// -1 for a solid rectangle
+    glyph: i32,
+}
+
+/// Measures how long frames take and draws the numbers
+/// over whatever was rendered.
+pub struct FpsOverlay {
+    pipeline: RenderPipeline,
+    layout: BindGroupLayout,
+    bind_group: BindGroup,
+    font: GpuImage,
+    view: Uniform<View>,
+    rects: Storage<Vec<Rect>>,
+    /// In milliseconds, oldest first
+    frame_times: VecDeque<f32>,
+    last_frame: Option<Instant>,
+    adapter_name: String,
+    visible: bool,
+}
+
+impl FpsOverlay {
+    /// `format` is the format of the views the overlay
+    /// will be rendered to.
+    pub fn new(
+        device: &Device,
+        queue: &Queue,
+        format: TextureFormat,
+        adapter_info: &AdapterInfo,
+    ) -> Self {
+        let shader = device.create_shader_module(
+            wgpu::ShaderModuleDescriptor {
+                label: "fps_overlay_shader".into(),
+                source: wgpu::ShaderSource::Wgsl(
+                    Cow::Borrowed(include_str!(
+                        "fps_overlay.wgsl"
+                    )),
+                ),
+            },
+        );
+
+        let layout = device.create_bind_group_layout(
+            &wgpu::BindGroupLayoutDescriptor {
+                label: "fps_overlay_layout".into(),
+                entries: &[
+                    Uniform::<View>::layout_entry(
+                        0,
+                        ShaderStages::VERTEX,
+                    ),
+                    Storage::<Vec<Rect>>::layout_entry(
+                        1,
+                        ShaderStages::VERTEX,
+                        true,
+                    ),
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            // only read with `textureLoad`
+                            sample_type:
+                                wgpu::TextureSampleType::Float {
+                                    filterable: false,
+                                },
+                            view_dimension:
+                                wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                ],
+            },
+        );
+
+        let pipeline_layout = device
+            .create_pipeline_layout(
+                &wgpu::PipelineLayoutDescriptor {
+                    label: "fps_overlay_layout".into(),
+                    bind_group_layouts: &[&layout],
+                    immediate_size: 0,
+                },
+            );
+        let pipeline = device.create_render_pipeline(
+            &wgpu::RenderPipelineDescriptor {
+                label: "fps_overlay_pipeline".into(),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vertex".into(),
+                    buffers: &[],
+                    compilation_options: Default::default(),
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: "fragment".into(),
+                    compilation_options: Default::default(),
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format,
+                        // the background panel is
+                        // translucent
+                        blend: Some(
+                            wgpu::BlendState::ALPHA_BLENDING,
+                        ),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                multiview_mask: None,
+                cache: None,
+            },
+        );
+
+        let font = font_image().upload(
+            device,
+            queue,
+            "fps_overlay_font",
+        );
+        let view = Uniform::new(
+            device,
+            "fps_overlay_view",
+            View { size: Vec2::ONE },
+        );
+        // never empty, so the buffer is never too small to
+        // bind
+        let rects = Storage::new(
+            device,
+            "fps_overlay_rects",
+            vec![Rect {
+                position: Vec2::ZERO,
+                size: Vec2::ZERO,
+                color: Vec4::ZERO,
+                glyph: -1,
+            }],
+        );
+        let bind_group = create_bind_group(
+            device, &layout, &view, &rects, &font,
+        );
+
+        Self {
+            pipeline,
+            layout,
+            bind_group,
+            font,
+            view,
+            rects,
+            frame_times: VecDeque::with_capacity(
+                HISTORY_LEN,
+            ),
+            last_frame: None,
+            adapter_name: adapter_info.name.clone(),
+            visible: true,
+        }
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    pub fn set_visible(&mut self, visible: bool) {
+        self.visible = visible;
+    }
+
+    pub fn toggle(&mut self) {
+        self.visible = !self.visible;
+    }
+
+    /// Measure the time since the last call. Call this
+    /// once per frame, whether or not the overlay is
+    /// visible, so the numbers are current when it's
+    /// shown.
+    ///
+    /// This reads the wall clock on purpose: it's
+    /// measuring how fast frames actually are, not how
+    /// fast the app's clocks say they are.
+    pub fn update(&mut self) {
+        let now = Instant::now();
+        if let Some(last_frame) = self.last_frame {
+            if self.frame_times.len() == HISTORY_LEN {
+                self.frame_times.pop_front();
+            }
+            self.frame_times.push_back(
+                (now - last_frame).as_secs_f32() * 1000.0,
+            );
+        }
+        self.last_frame = Some(now);
+    }
+
+    /// Lay out the overlay for a target of this size and
+    /// upload it. Call this before [`FpsOverlay::render`]
+    /// every frame it's visible.
+    pub fn prepare(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        width: u32,
+        height: u32,
+    ) {
+        if !self.visible {
+            return;
+        }
+
+        self.view.set(View {
+            size: Vec2::new(width as f32, height as f32),
+        });
+        self.view.upload(queue);
+
+        let rects = self.layout_rects();
+        self.rects.set(rects);
+        if self.rects.upload(device, queue) {
+            self.bind_group = create_bind_group(
+                device,
+                &self.layout,
+                &self.view,
+                &self.rects,
+                &self.font,
+            );
+        }
+    }
+
+    /// Draw the overlay on top of what's already in
+    /// `view`.
+    pub fn render(
+        &self,
+        encoder: &mut CommandEncoder,
+        view: &TextureView,
+    ) {
+        if !self.visible {
+            return;
+        }
+
+        let mut rpass = encoder.begin_render_pass(
+            &wgpu::RenderPassDescriptor {
+                label: "fps_overlay_pass".into(),
+                color_attachments: &[Some(
+                    wgpu::RenderPassColorAttachment {
+                        view,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            // keep the frame underneath
+                            load: wgpu::LoadOp::Load,
+                            store: wgpu::StoreOp::Store,
+                        },
+                        depth_slice: None,
+                    },
+                )],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+                multiview_mask: None,
+            },
+        );
+        rpass.set_pipeline(&self.pipeline);
+        rpass.set_bind_group(
+            0,
+            Some(&self.bind_group),
+            &[],
+        );
+        // 6 vertices per rectangle
+        rpass.draw(0..6, 0..self.rects.get().len() as u32);
+    }
+
+    /// The panel, three lines of text, and the graph
+    fn layout_rects(&self) -> Vec<Rect> {
+        let average_ms = if self.frame_times.is_empty() {
+            0.0
+        } else {
+            self.frame_times.iter().sum::<f32>()
+                / self.frame_times.len() as f32
+        };
+        let fps = if average_ms > 0.0 {
+            1000.0 / average_ms
+        } else {
+            0.0
+        };
+        let lines = [
+            format!("{fps:.0} FPS"),
+            format!("{average_ms:.2} ms"),
+            self.adapter_name.clone(),
+        ];
+
+        let line_height =
+            (GLYPH_HEIGHT as f32 + 2.0) * SCALE;
+        let bar_width = SCALE;
+        let graph_width = HISTORY_LEN as f32 * bar_width;
+        let text_width = lines
+            .iter()
+            .map(|line| text_width(line))
+            .fold(0.0, f32::max);
+        let panel_size = Vec2::new(
+            graph_width.max(text_width) + PADDING * 2.0,
+            line_height * lines.len() as f32
+                + GRAPH_HEIGHT
+                + PADDING * 3.0,
+        );
+
+        let mut rects = vec![Rect {
+            position: Vec2::splat(PADDING),
+            size: panel_size,
+            color: Vec4::new(0.0, 0.0, 0.0, 0.6),
+            glyph: -1,
+        }];
+
+        let mut cursor = Vec2::splat(PADDING * 2.0);
+        for line in &lines {
+            push_text(&mut rects, cursor, line);
+            cursor.y += line_height;
+        }
+
+        // newest frame on the right, with a line marking
+        // 60 FPS
+        let graph_bottom =
+            cursor.y + PADDING + GRAPH_HEIGHT;
+        let start = HISTORY_LEN - self.frame_times.len();
+        for (index, ms) in
+            self.frame_times.iter().enumerate()
+        {
+            let height =
+                (ms / GRAPH_MAX_MS).min(1.0) * GRAPH_HEIGHT;
+            let color = if *ms <= 1000.0 / 60.0 {
+                Vec4::new(0.3, 0.9, 0.3, 1.0)
+            } else if *ms <= 1000.0 / 30.0 {
+                Vec4::new(0.95, 0.8, 0.2, 1.0)
+            } else {
+                Vec4::new(0.95, 0.3, 0.2, 1.0)
+            };
+            rects.push(Rect {
+                position: Vec2::new(
+                    cursor.x
+                        + (start + index) as f32
+                            * bar_width,
+                    graph_bottom - height,
+                ),
+                size: Vec2::new(bar_width, height),
+                color,
+                glyph: -1,
+            });
+        }
+        let target_height =
+            (1000.0 / 60.0) / GRAPH_MAX_MS * GRAPH_HEIGHT;
+        rects.push(Rect {
+            position: Vec2::new(
+                cursor.x,
+                graph_bottom - target_height,
+            ),
+            size: Vec2::new(graph_width, 1.0),
+            color: Vec4::new(1.0, 1.0, 1.0, 0.5),
+            glyph: -1,
+        });
+        rects
+    }
+}
+
+fn create_bind_group(
+    device: &Device,
+    layout: &BindGroupLayout,
+    view: &Uniform<View>,
+    rects: &Storage<Vec<Rect>>,
+    font: &GpuImage,
+) -> BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: "fps_overlay_bind_group".into(),
+        layout,
+        entries: &[
+            view.bind_group_entry(0),
+            rects.bind_group_entry(1),
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource:
+                    wgpu::BindingResource::TextureView(
+                        &font.view,
+                    ),
+            },
+        ],
+    })
+}
+
+/// Glyphs are one texel apart
+fn text_width(text: &str) -> f32 {
+    text.chars().count() as f32
+        * (GLYPH_WIDTH + 1) as f32
+        * SCALE
+}
+
+fn push_text(
+    rects: &mut Vec<Rect>,
+    position: Vec2,
+    text: &str,
+) {
+    let advance = (GLYPH_WIDTH + 1) as f32 * SCALE;
+    for (index, char) in text.chars().enumerate() {
+        if char == ' ' {
+            continue;
+        }
+        rects.push(Rect {
+            position: position
+                + Vec2::new(index as f32 * advance, 0.0),
+            size: Vec2::new(
+                GLYPH_WIDTH as f32,
+                GLYPH_HEIGHT as f32,
+            ) * SCALE,
+            color: TEXT_COLOR,
+            glyph: glyph_index(char),
+        });
+    }
+}
+
+/// The font covers printable ASCII. Anything else is
+/// drawn as `?`.
+fn glyph_index(char: char) -> i32 {
+    match char {
+        ' '..='~' => char as i32 - ' ' as i32,
+        _ => '?' as i32 - ' ' as i32,
+    }
+}
+
+/// One row of glyphs in an `R8Unorm` texture
+fn font_image() -> Image {
+    let width = FONT.len() as u32 * GLYPH_WIDTH;
+    let mut data = vec![0; (width * GLYPH_HEIGHT) as usize];
+    for (glyph, columns) in FONT.iter().enumerate() {
+        for (column, bits) in columns.iter().enumerate() {
+            for row in 0..GLYPH_HEIGHT {
+                if bits & (1 << row) != 0 {
+                    let x = glyph as u32 * GLYPH_WIDTH
+                        + column as u32;
+                    data[(row * width + x) as usize] =
+                        u8::MAX;
+                }
+            }
+        }
+    }
+    Image::new(
+        wgpu::Extent3d {
+            width,
+            height: GLYPH_HEIGHT,
+            depth_or_array_layers: 1,
+        },
+        TextureFormat::R8Unorm,
+        1,
+        data,
+    )
+}
+
+/// A 5x8 font for the printable ASCII characters, from
+/// `' '` to `'~'`. Each glyph is 5 columns, and bit `n`
+/// of a column is row `n` from the top.
+#[rustfmt::skip]
+const FONT: [[u8; 5]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00], // ' '
+    [0x00, 0x00, 0x5f, 0x00, 0x00], // !
+    [0x00, 0x07, 0x00, 0x07, 0x00], // "
+    [0x14, 0x7f, 0x14, 0x7f, 0x14], // #
+    [0x24, 0x2a, 0x7f, 0x2a, 0x12], // $
+    [0x23, 0x13, 0x08, 0x64, 0x62], // %
+    [0x36, 0x49, 0x56, 0x20, 0x50], // &
+    [0x00, 0x08, 0x07, 0x03, 0x00], // '
+    [0x00, 0x1c, 0x22, 0x41, 0x00], // (
+    [0x00, 0x41, 0x22, 0x1c, 0x00], // )
+    [0x2a, 0x1c, 0x7f, 0x1c, 0x2a], // *
+    [0x08, 0x08, 0x3e, 0x08, 0x08], // +
+    [0x00, 0x80, 0x70, 0x30, 0x00], // ,
+    [0x08, 0x08, 0x08, 0x08, 0x08], // -
+    [0x00, 0x00, 0x60, 0x60, 0x00], // .
+    [0x20, 0x10, 0x08, 0x04, 0x02], // /
+    [0x3e, 0x51, 0x49, 0x45, 0x3e], // 0
+    [0x00, 0x42, 0x7f, 0x40, 0x00], // 1
+    [0x72, 0x49, 0x49, 0x49, 0x46], // 2
+    [0x21, 0x41, 0x49, 0x4d, 0x33], // 3
+    [0x18, 0x14, 0x12, 0x7f, 0x10], // 4
+    [0x27, 0x45, 0x45, 0x45, 0x39], // 5
+    [0x3c, 0x4a, 0x49, 0x49, 0x31], // 6
+    [0x41, 0x21, 0x11, 0x09, 0x07], // 7
+    [0x36, 0x49, 0x49, 0x49, 0x36], // 8
+    [0x46, 0x49, 0x49, 0x29, 0x1e], // 9
+    [0x00, 0x00, 0x14, 0x00, 0x00], // :
+    [0x00, 0x40, 0x34, 0x00, 0x00], // ;
+    [0x00, 0x08, 0x14, 0x22, 0x41], // <
+    [0x14, 0x14, 0x14, 0x14, 0x14], // =
+    [0x00, 0x41, 0x22, 0x14, 0x08], // >
+    [0x02, 0x01, 0x59, 0x09, 0x06], // ?
+    [0x3e, 0x41, 0x5d, 0x59, 0x4e], // @
+    [0x7c, 0x12, 0x11, 0x12, 0x7c], // A
+    [0x7f, 0x49, 0x49, 0x49, 0x36], // B
+    [0x3e, 0x41, 0x41, 0x41, 0x22], // C
+    [0x7f, 0x41, 0x41, 0x41, 0x3e], // D
+    [0x7f, 0x49, 0x49, 0x49, 0x41], // E
+    [0x7f, 0x09, 0x09, 0x09, 0x01], // F
+    [0x3e, 0x41, 0x41, 0x51, 0x73], // G
+    [0x7f, 0x08, 0x08, 0x08, 0x7f], // H
+    [0x00, 0x41, 0x7f, 0x41, 0x00], // I
+    [0x20, 0x40, 0x41, 0x3f, 0x01], // J
+    [0x7f, 0x08, 0x14, 0x22, 0x41], // K
+    [0x7f, 0x40, 0x40, 0x40, 0x40], // L
+    [0x7f, 0x02, 0x1c, 0x02, 0x7f], // M
+    [0x7f, 0x04, 0x08, 0x10, 0x7f], // N
+    [0x3e, 0x41, 0x41, 0x41, 0x3e], // O
+    [0x7f, 0x09, 0x09, 0x09, 0x06], // P
+    [0x3e, 0x41, 0x51, 0x21, 0x5e], // Q
+    [0x7f, 0x09, 0x19, 0x29, 0x46], // R
+    [0x26, 0x49, 0x49, 0x49, 0x32], // S
+    [0x03, 0x01, 0x7f, 0x01, 0x03], // T
+    [0x3f, 0x40, 0x40, 0x40, 0x3f], // U
+    [0x1f, 0x20, 0x40, 0x20, 0x1f], // V
+    [0x3f, 0x40, 0x38, 0x40, 0x3f], // W
+    [0x63, 0x14, 0x08, 0x14, 0x63], // X
+    [0x03, 0x04, 0x78, 0x04, 0x03], // Y
+    [0x61, 0x59, 0x49, 0x4d, 0x43], // Z
+    [0x00, 0x7f, 0x41, 0x41, 0x41], // [
+    [0x02, 0x04, 0x08, 0x10, 0x20], // \
+    [0x00, 0x41, 0x41, 0x41, 0x7f], // ]
+    [0x04, 0x02, 0x01, 0x02, 0x04], // ^
+    [0x40, 0x40, 0x40, 0x40, 0x40], // _
+    [0x00, 0x03, 0x07, 0x08, 0x00], // `
+    [0x20, 0x54, 0x54, 0x78, 0x40], // a
+    [0x7f, 0x28, 0x44, 0x44, 0x38], // b
+    [0x38, 0x44, 0x44, 0x44, 0x28], // c
+    [0x38, 0x44, 0x44, 0x28, 0x7f], // d
+    [0x38, 0x54, 0x54, 0x54, 0x18], // e
+    [0x00, 0x08, 0x7e, 0x09, 0x02], // f
+    [0x18, 0xa4, 0xa4, 0x9c, 0x78], // g
+    [0x7f, 0x08, 0x04, 0x04, 0x78], // h
+    [0x00, 0x44, 0x7d, 0x40, 0x00], // i
+    [0x20, 0x40, 0x40, 0x3d, 0x00], // j
+    [0x7f, 0x10, 0x28, 0x44, 0x00], // k
+    [0x00, 0x41, 0x7f, 0x40, 0x00], // l
+    [0x7c, 0x04, 0x78, 0x04, 0x78], // m
+    [0x7c, 0x08, 0x04, 0x04, 0x78], // n
+    [0x38, 0x44, 0x44, 0x44, 0x38], // o
+    [0xfc, 0x18, 0x24, 0x24, 0x18], // p
+    [0x18, 0x24, 0x24, 0x18, 0xfc], // q
+    [0x7c, 0x08, 0x04, 0x04, 0x08], // r
+    [0x48, 0x54, 0x54, 0x54, 0x24], // s
+    [0x04, 0x04, 0x3f, 0x44, 0x24], // t
+    [0x3c, 0x40, 0x40, 0x20, 0x7c], // u
+    [0x1c, 0x20, 0x40, 0x20, 0x1c], // v
+    [0x3c, 0x40, 0x30, 0x40, 0x3c], // w
+    [0x44, 0x28, 0x10, 0x28, 0x44], // x
+    [0x4c, 0x90, 0x90, 0x90, 0x7c], // y
+    [0x44, 0x64, 0x54, 0x4c, 0x44], // z
+    [0x00, 0x08, 0x36, 0x41, 0x00], // {
+    [0x00, 0x00, 0x77, 0x00, 0x00], // |
+    [0x00, 0x41, 0x36, 0x08, 0x00], // }
+    [0x02, 0x01, 0x02, 0x04, 0x02], // ~
+];
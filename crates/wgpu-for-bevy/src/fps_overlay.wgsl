@@ -0,0 +1,85 @@
+struct View {
+    // the size of the render target in pixels
+    size: vec2<f32>,
+}
+
+// Everything in the overlay is a rectangle: either a
+// solid color, or one glyph of the font
+struct Rect {
+    // top left corner, in pixels from the top left of
+    // the render target
+    position: vec2<f32>,
+    size: vec2<f32>,
+    color: vec4<f32>,
+    // the glyph's index in the font texture, or -1 for a
+    // solid rectangle
+    glyph: i32,
+}
+
+// must match `GLYPH_WIDTH` and `GLYPH_HEIGHT` in
+// `fps_overlay.rs`
+const GLYPH_SIZE: vec2<f32> = vec2<f32>(5.0, 8.0);
+
+@group(0) @binding(0) var<uniform> view: View;
+@group(0) @binding(1) var<storage, read> rects: array<Rect>;
+// one row of glyphs, each GLYPH_SIZE texels, where a
+// texel of 1 is part of the glyph
+@group(0) @binding(2) var font: texture_2d<f32>;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) color: vec4<f32>,
+    // where in the glyph this is, in font texels
+    @location(1) texel: vec2<f32>,
+    @location(2) @interpolate(flat) glyph: i32,
+}
+
+@vertex
+fn vertex(
+    @builtin(vertex_index) in_vertex_index: u32,
+    @builtin(instance_index) in_instance_index: u32,
+) -> VertexOutput {
+    // two triangles making a quad, from (0, 0) to (1, 1)
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(0.0, 0.0),
+        vec2<f32>(1.0, 0.0),
+        vec2<f32>(0.0, 1.0),
+        vec2<f32>(0.0, 1.0),
+        vec2<f32>(1.0, 0.0),
+        vec2<f32>(1.0, 1.0),
+    );
+    let corner = corners[in_vertex_index];
+    let rect = rects[in_instance_index];
+
+    // pixels, with y going down, to clip space, with y
+    // going up
+    let pixel = rect.position + corner * rect.size;
+    let clip = pixel / view.size * vec2<f32>(2.0, -2.0)
+        + vec2<f32>(-1.0, 1.0);
+
+    var out: VertexOutput;
+    out.position = vec4<f32>(clip, 0.0, 1.0);
+    out.color = rect.color;
+    out.texel = corner * GLYPH_SIZE;
+    out.glyph = rect.glyph;
+    return out;
+}
+
+@fragment
+fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
+    if in.glyph < 0 {
+        return in.color;
+    }
+
+    // clamped, since the far edges of the quad are one
+    // past the glyph's last texel
+    let local = min(vec2<i32>(in.texel), vec2<i32>(GLYPH_SIZE) - 1);
+    let texel = vec2<i32>(
+        in.glyph * i32(GLYPH_SIZE.x) + local.x,
+        local.y,
+    );
+    if textureLoad(font, texel, 0).r < 0.5 {
+        discard;
+    }
+    return in.color;
+}
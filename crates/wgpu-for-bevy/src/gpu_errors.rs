@@ -0,0 +1,182 @@
+//! Handling wgpu's errors instead of panicking on them.
+//!
+//! wgpu reports most errors asynchronously: creating a
+//! resource always returns one, and if something was
+//! wrong the resource is invalid and the error goes to
+//! the innermost error scope, or to the device's
+//! uncaptured error handler when there's no scope. The
+//! default handler panics.
+//!
+//! - [`capture_errors`] wraps resource creation in error
+//!   scopes, so a bad shader or pipeline becomes a
+//!   `Result`.
+//! - [`log_uncaptured_errors`] replaces the panicking
+//!   handler with one that logs.
+//! - [`DeviceLostWatcher`] reports when the device stops
+//!   working, which no error scope catches.
+use futures_lite::future::block_on;
+use std::{
+    fmt,
+    sync::{
+        Arc,
+        mpsc::{self, Receiver},
+    },
+};
+use tracing::error;
+use wgpu::{Device, DeviceLostReason, ErrorFilter};
+
+/// An error wgpu reported while running
+/// [`capture_errors`]
+#[derive(Debug)]
+pub struct GpuError {
+    /// What was being created
+    pub label: String,
+    pub error: wgpu::Error,
+}
+
+impl fmt::Display for GpuError {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+    ) -> fmt::Result {
+        write!(
+            f,
+            "failed to create `{}`: {}",
+            self.label, self.error
+        )
+    }
+}
+
+impl std::error::Error for GpuError {}
+
+/// Run `create` inside error scopes for every kind of
+/// error, and return the first error it caused.
+///
+/// Error scopes belong to the thread they're pushed on,
+/// so `create` should do all of its work on this thread.
+/// Waiting for the scopes to pop waits for the device to
+/// finish validating, which is fine at startup but not
+/// every frame.
+pub fn capture_errors<T>(
+    device: &Device,
+    label: &str,
+    create: impl FnOnce() -> T,
+) -> Result<T, GpuError> {
+    let internal =
+        device.push_error_scope(ErrorFilter::Internal);
+    let out_of_memory =
+        device.push_error_scope(ErrorFilter::OutOfMemory);
+    let validation =
+        device.push_error_scope(ErrorFilter::Validation);
+
+    let value = create();
+
+    // scopes pop in the reverse order they were pushed
+    let error = block_on(validation.pop())
+        .or(block_on(out_of_memory.pop()))
+        .or(block_on(internal.pop()));
+    match error {
+        None => Ok(value),
+        Some(error) => Err(GpuError {
+            label: label.to_string(),
+            error,
+        }),
+    }
+}
+
+/// Log errors that aren't caught by an error scope,
+/// instead of panicking on them.
+///
+/// Errors are logged with the label of the resource
+/// they're about, when wgpu's message names one, so they
+/// can be filtered on.
+pub fn log_uncaptured_errors(device: &Device) {
+    device.on_uncaptured_error(Arc::new(|error| {
+        let description = error.to_string();
+        let label = resource_label(&description);
+        let kind = match &error {
+            wgpu::Error::OutOfMemory { .. } => {
+                "out_of_memory"
+            }
+            wgpu::Error::Validation { .. } => "validation",
+            wgpu::Error::Internal { .. } => "internal",
+        };
+        error!(
+            kind,
+            label,
+            %description,
+            "uncaptured wgpu error"
+        );
+    }));
+}
+
+/// wgpu's messages name the resource they're about as
+/// `label = 'name'`
+fn resource_label(description: &str) -> Option<&str> {
+    let start =
+        description.find("label = '")? + "label = '".len();
+    let len = description[start..].find('\'')?;
+    Some(&description[start..start + len])
+}
+
+/// Why the device stopped working
+#[derive(Clone, Debug)]
+pub struct DeviceLost {
+    pub reason: DeviceLostReason,
+    pub message: String,
+}
+
+impl fmt::Display for DeviceLost {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+    ) -> fmt::Result {
+        match self.reason {
+            DeviceLostReason::Destroyed => {
+                write!(f, "the device was destroyed")?
+            }
+            DeviceLostReason::Unknown => {
+                write!(f, "the device was lost")?
+            }
+        }
+        if !self.message.is_empty() {
+            write!(f, ": {}", self.message)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for DeviceLost {}
+
+/// Hears about the device being lost, for example after
+/// a driver reset or the GPU being unplugged. Every
+/// resource created from a lost device is unusable.
+///
+/// wgpu can call the callback from any thread, so the
+/// loss is sent here to be checked on from the event
+/// loop, like Bevy checks for it once per frame.
+pub struct DeviceLostWatcher {
+    receiver: Receiver<DeviceLost>,
+}
+
+impl DeviceLostWatcher {
+    /// Replaces any device lost callback `device`
+    /// already had.
+    pub fn new(device: &Device) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        device.set_device_lost_callback(
+            move |reason, message| {
+                // the receiver is gone if the watcher was
+                // dropped, and then nobody is listening
+                let _ = sender
+                    .send(DeviceLost { reason, message });
+            },
+        );
+        Self { receiver }
+    }
+
+    /// Whether the device has been lost. Never blocks.
+    pub fn check(&self) -> Option<DeviceLost> {
+        self.receiver.try_recv().ok()
+    }
+}
@@ -0,0 +1,743 @@
+//! Measuring how long passes take on the GPU with
+//! timestamp queries, similar to Bevy's
+//! [`RenderDiagnosticsPlugin`](https://docs.rs/bevy/latest/bevy/render/diagnostic/struct.RenderDiagnosticsPlugin.html).
+//!
+//! Timestamps are written into a `QuerySet` as the GPU
+//! gets to them, resolved into a buffer at the end of the
+//! frame, and read back a frame or two later the same way
+//! any other [`Readback`] is. Nothing here ever waits on
+//! the GPU.
+//!
+//! Timestamp queries are optional features. When the
+//! device wasn't created with
+//! [`TIMESTAMP_QUERY`](wgpu::Features::TIMESTAMP_QUERY)
+//! the profiler does nothing, so it can be left in render
+//! code unconditionally.
+use crate::{
+    json::escape_json,
+    readback::{PendingReadback, Readback},
+};
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+    time::{Duration, Instant},
+};
+use tracing::{error, info, warn};
+use wgpu::{
+    BufferUsages, CommandEncoder, ComputePass,
+    ComputePassTimestampWrites, Device, Features, QuerySet,
+    Queue, RenderPass, RenderPassTimestampWrites,
+};
+
+/// Timestamps that can be written in one frame, two per
+/// scope
+const MAX_QUERIES: u32 = 256;
+/// Frames whose timestamps can be waiting to be read
+/// back. A frame that would go past this isn't measured.
+const MAX_FRAMES_IN_FLIGHT: usize = 4;
+/// How often averages are logged
+const REPORT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Times passes and debug groups, logs their averages,
+/// and optionally writes every measurement to a Chrome
+/// trace.
+pub struct GpuProfiler {
+    /// `None` when timestamp queries aren't supported
+    queries: Option<Queries>,
+    features: Features,
+    /// Nanoseconds per timestamp tick
+    timestamp_period: f32,
+    /// Scopes recorded this frame
+    scopes: Vec<Scope>,
+    /// Indices into `scopes` of debug groups that haven't
+    /// been popped yet. `None` for groups that weren't
+    /// given queries.
+    open: Vec<Option<usize>>,
+    next_query: u32,
+    warned_full: bool,
+    /// Resolved copies, waiting for the frame's commands
+    /// to be submitted
+    resolved: Option<Frame<Readback>>,
+    mapping: VecDeque<Frame<PendingReadback>>,
+    frame: u64,
+    report: Report,
+    last_timings: Vec<GpuTiming>,
+    trace: Option<ChromeTrace>,
+}
+
+struct Queries {
+    query_set: QuerySet,
+    resolve_buffer: wgpu::Buffer,
+}
+
+struct Scope {
+    label: String,
+    start: u32,
+    end: u32,
+}
+
+struct Frame<R> {
+    readback: R,
+    scopes: Vec<Scope>,
+    index: u64,
+}
+
+/// How long one pass or debug group took
+#[derive(Clone, Debug)]
+pub struct GpuTiming {
+    pub label: String,
+    pub duration: Duration,
+}
+
+impl GpuProfiler {
+    /// The features the profiler uses. Request whichever
+    /// of these the adapter has:
+    ///
+    /// ```ignore
+    /// required_features: adapter.features() & GpuProfiler::FEATURES,
+    /// ```
+    ///
+    /// `TIMESTAMP_QUERY` alone times whole passes. Debug
+    /// groups are also timed with
+    /// `TIMESTAMP_QUERY_INSIDE_ENCODERS` (for groups in a
+    /// command encoder) and
+    /// `TIMESTAMP_QUERY_INSIDE_PASSES` (for groups in a
+    /// pass).
+    pub const FEATURES: Features =
+        Features::TIMESTAMP_QUERY
+            .union(
+                Features::TIMESTAMP_QUERY_INSIDE_ENCODERS,
+            )
+            .union(Features::TIMESTAMP_QUERY_INSIDE_PASSES);
+
+    pub fn new(device: &Device, queue: &Queue) -> Self {
+        let features = device.features();
+        let queries = features
+            .contains(Features::TIMESTAMP_QUERY)
+            .then(|| Queries {
+                query_set: device.create_query_set(
+                    &wgpu::QuerySetDescriptor {
+                        label: "gpu_profiler_queries"
+                            .into(),
+                        ty: wgpu::QueryType::Timestamp,
+                        count: MAX_QUERIES,
+                    },
+                ),
+                resolve_buffer: device.create_buffer(
+                    &wgpu::BufferDescriptor {
+                        label: "gpu_profiler_resolve"
+                            .into(),
+                        size: (MAX_QUERIES
+                            * wgpu::QUERY_SIZE)
+                            as u64,
+                        usage: BufferUsages::QUERY_RESOLVE
+                            | BufferUsages::COPY_SRC,
+                        mapped_at_creation: false,
+                    },
+                ),
+            });
+        Self {
+            queries,
+            features,
+            timestamp_period: queue.get_timestamp_period(),
+            scopes: vec![],
+            open: vec![],
+            next_query: 0,
+            warned_full: false,
+            resolved: None,
+            mapping: VecDeque::new(),
+            frame: 0,
+            report: Report::new(),
+            last_timings: vec![],
+            trace: None,
+        }
+    }
+
+    /// Also write every measurement to `path`, as JSON
+    /// that `chrome://tracing` and
+    /// [Perfetto](https://ui.perfetto.dev) open.
+    pub fn with_chrome_trace(
+        mut self,
+        path: impl AsRef<Path>,
+    ) -> io::Result<Self> {
+        self.trace =
+            Some(ChromeTrace::create(path.as_ref())?);
+        Ok(self)
+    }
+
+    /// Whether anything is measured at all
+    pub fn is_enabled(&self) -> bool {
+        self.queries.is_some()
+    }
+
+    /// The timings of the most recent frame that's been
+    /// read back, in the order they were recorded.
+    pub fn last_timings(&self) -> &[GpuTiming] {
+        &self.last_timings
+    }
+
+    /// Timestamp writes for a render pass, which time the
+    /// whole pass:
+    ///
+    /// ```ignore
+    /// encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+    ///     timestamp_writes: profiler.render_pass_timestamp_writes("main_pass"),
+    ///     ..
+    /// });
+    /// ```
+    pub fn render_pass_timestamp_writes(
+        &mut self,
+        label: &str,
+    ) -> Option<RenderPassTimestampWrites<'_>> {
+        let (start, end) = self.pass_queries(label)?;
+        Some(RenderPassTimestampWrites {
+            query_set: &self.queries.as_ref()?.query_set,
+            beginning_of_pass_write_index: Some(start),
+            end_of_pass_write_index: Some(end),
+        })
+    }
+
+    /// Like
+    /// [`GpuProfiler::render_pass_timestamp_writes`],
+    /// for a compute pass
+    pub fn compute_pass_timestamp_writes(
+        &mut self,
+        label: &str,
+    ) -> Option<ComputePassTimestampWrites<'_>> {
+        let (start, end) = self.pass_queries(label)?;
+        Some(ComputePassTimestampWrites {
+            query_set: &self.queries.as_ref()?.query_set,
+            beginning_of_pass_write_index: Some(start),
+            end_of_pass_write_index: Some(end),
+        })
+    }
+
+    fn pass_queries(
+        &mut self,
+        label: &str,
+    ) -> Option<(u32, u32)> {
+        let start = self.allocate(2)?;
+        self.scopes.push(Scope {
+            label: label.to_string(),
+            start,
+            end: start + 1,
+        });
+        Some((start, start + 1))
+    }
+
+    /// Push a debug group, and time everything until the
+    /// matching [`GpuProfiler::pop_debug_group`] when the
+    /// device can write timestamps there. The debug group
+    /// is pushed either way, so it still shows up in
+    /// graphics debuggers.
+    pub fn push_debug_group<T: TimestampTarget>(
+        &mut self,
+        target: &mut T,
+        label: &str,
+    ) {
+        target.push_debug_group(label);
+
+        let start = if self.features.contains(T::FEATURE) {
+            self.allocate(2)
+        } else {
+            None
+        };
+        if let (Some(start), Some(queries)) =
+            (start, &self.queries)
+        {
+            target
+                .write_timestamp(&queries.query_set, start);
+            self.scopes.push(Scope {
+                label: label.to_string(),
+                start,
+                end: start + 1,
+            });
+        }
+        self.open
+            .push(start.map(|_| self.scopes.len() - 1));
+    }
+
+    /// Pop the debug group pushed last, with the same
+    /// target it was pushed with.
+    ///
+    /// # Panics
+    ///
+    /// If there's no group to pop.
+    pub fn pop_debug_group<T: TimestampTarget>(
+        &mut self,
+        target: &mut T,
+    ) {
+        let scope =
+            self.open.pop().expect("a debug group to pop");
+        if let (Some(scope), Some(queries)) =
+            (scope, &self.queries)
+        {
+            target.write_timestamp(
+                &queries.query_set,
+                self.scopes[scope].end,
+            );
+        }
+        target.pop_debug_group();
+    }
+
+    /// Both queries of a scope are allocated together,
+    /// so a scope is never left with only a start.
+    fn allocate(&mut self, count: u32) -> Option<u32> {
+        self.queries.as_ref()?;
+        if self.next_query + count > MAX_QUERIES {
+            if !self.warned_full {
+                warn!(
+                    max_queries = MAX_QUERIES,
+                    "too many GPU timestamps in one frame, the rest aren't measured"
+                );
+                self.warned_full = true;
+            }
+            return None;
+        }
+        let start = self.next_query;
+        self.next_query += count;
+        Some(start)
+    }
+
+    /// Resolve this frame's timestamps and copy them for
+    /// reading back. Call this once per frame, after the
+    /// last pass and before submitting `encoder`.
+    pub fn resolve(
+        &mut self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+    ) {
+        assert!(
+            self.open.is_empty(),
+            "every debug group should be popped before resolving"
+        );
+        let scopes = std::mem::take(&mut self.scopes);
+        let count = std::mem::take(&mut self.next_query);
+        let index = self.frame;
+        self.frame += 1;
+
+        let Some(queries) = &self.queries else {
+            return;
+        };
+        if count == 0
+            || self.mapping.len() >= MAX_FRAMES_IN_FLIGHT
+        {
+            // skipping a frame is better than stalling
+            // the one being rendered
+            return;
+        }
+
+        encoder.resolve_query_set(
+            &queries.query_set,
+            0..count,
+            &queries.resolve_buffer,
+            0,
+        );
+        let readback = Readback::copy_buffer(
+            device,
+            encoder,
+            &queries.resolve_buffer,
+            0..(count * wgpu::QUERY_SIZE) as u64,
+        );
+        self.resolved = Some(Frame {
+            readback,
+            scopes,
+            index,
+        });
+    }
+
+    /// Start mapping this frame's timestamps and process
+    /// any earlier frames that have been read back. Call
+    /// this once per frame, after submitting.
+    pub fn update(&mut self, device: &Device) {
+        if let Some(frame) = self.resolved.take() {
+            self.mapping.push_back(Frame {
+                readback: frame.readback.map_async(),
+                scopes: frame.scopes,
+                index: frame.index,
+            });
+        }
+
+        // copies finish in the order they were submitted
+        while let Some(front) = self.mapping.front() {
+            let Some(result) =
+                front.readback.try_read(device)
+            else {
+                break;
+            };
+            let frame = self
+                .mapping
+                .pop_front()
+                .expect("the front frame to exist");
+            match result {
+                Ok(data) => {
+                    self.read_frame(&frame, &data.cast())
+                }
+                Err(error) => error!(
+                    %error,
+                    "failed to read GPU timestamps"
+                ),
+            }
+        }
+
+        if let Some(report) = self.report.take_due() {
+            for (label, average) in report {
+                info!(
+                    "{label}: {:.3} ms",
+                    average.as_secs_f64() * 1000.0
+                );
+            }
+        }
+    }
+
+    fn read_frame(
+        &mut self,
+        frame: &Frame<PendingReadback>,
+        timestamps: &[u64],
+    ) {
+        self.last_timings.clear();
+        for scope in &frame.scopes {
+            let start = timestamps[scope.start as usize];
+            let end = timestamps[scope.end as usize];
+            // some drivers report timestamps out of order
+            // for very short scopes
+            let duration = Duration::from_nanos(
+                (end.saturating_sub(start) as f64
+                    * self.timestamp_period as f64)
+                    as u64,
+            );
+            self.report.add(&scope.label, duration);
+
+            if let Some(trace) = &mut self.trace {
+                let start_ns = start as f64
+                    * self.timestamp_period as f64;
+                if let Err(error) = trace.write_event(
+                    &scope.label,
+                    start_ns,
+                    duration,
+                    frame.index,
+                ) {
+                    error!(
+                        %error,
+                        "failed to write the GPU trace, stopping it"
+                    );
+                    self.trace = None;
+                }
+            }
+
+            self.last_timings.push(GpuTiming {
+                label: scope.label.clone(),
+                duration,
+            });
+        }
+    }
+}
+
+/// Something timestamps and debug groups can be written
+/// into, which each need their own feature.
+pub trait TimestampTarget {
+    const FEATURE: Features;
+
+    fn write_timestamp(
+        &mut self,
+        query_set: &QuerySet,
+        index: u32,
+    );
+    fn push_debug_group(&mut self, label: &str);
+    fn pop_debug_group(&mut self);
+}
+
+impl TimestampTarget for CommandEncoder {
+    const FEATURE: Features =
+        Features::TIMESTAMP_QUERY_INSIDE_ENCODERS;
+
+    fn write_timestamp(
+        &mut self,
+        query_set: &QuerySet,
+        index: u32,
+    ) {
+        CommandEncoder::write_timestamp(
+            self, query_set, index,
+        );
+    }
+
+    fn push_debug_group(&mut self, label: &str) {
+        CommandEncoder::push_debug_group(self, label);
+    }
+
+    fn pop_debug_group(&mut self) {
+        CommandEncoder::pop_debug_group(self);
+    }
+}
+
+impl TimestampTarget for RenderPass<'_> {
+    const FEATURE: Features =
+        Features::TIMESTAMP_QUERY_INSIDE_PASSES;
+
+    fn write_timestamp(
+        &mut self,
+        query_set: &QuerySet,
+        index: u32,
+    ) {
+        RenderPass::write_timestamp(self, query_set, index);
+    }
+
+    fn push_debug_group(&mut self, label: &str) {
+        RenderPass::push_debug_group(self, label);
+    }
+
+    fn pop_debug_group(&mut self) {
+        RenderPass::pop_debug_group(self);
+    }
+}
+
+impl TimestampTarget for ComputePass<'_> {
+    const FEATURE: Features =
+        Features::TIMESTAMP_QUERY_INSIDE_PASSES;
+
+    fn write_timestamp(
+        &mut self,
+        query_set: &QuerySet,
+        index: u32,
+    ) {
+        ComputePass::write_timestamp(
+            self, query_set, index,
+        );
+    }
+
+    fn push_debug_group(&mut self, label: &str) {
+        ComputePass::push_debug_group(self, label);
+    }
+
+    fn pop_debug_group(&mut self) {
+        ComputePass::pop_debug_group(self);
+    }
+}
+
+/// Totals per label since the last report, in the order
+/// labels were first seen
+struct Report {
+    totals: Vec<(String, Duration, u32)>,
+    last: Instant,
+}
+
+impl Report {
+    fn new() -> Self {
+        Self {
+            totals: vec![],
+            last: Instant::now(),
+        }
+    }
+
+    fn add(&mut self, label: &str, duration: Duration) {
+        match self
+            .totals
+            .iter_mut()
+            .find(|(existing, ..)| existing == label)
+        {
+            Some((_, total, count)) => {
+                *total += duration;
+                *count += 1;
+            }
+            None => self.totals.push((
+                label.to_string(),
+                duration,
+                1,
+            )),
+        }
+    }
+
+    /// The average of each label, once per
+    /// `REPORT_INTERVAL`
+    fn take_due(
+        &mut self,
+    ) -> Option<Vec<(String, Duration)>> {
+        if self.last.elapsed() < REPORT_INTERVAL
+            || self.totals.is_empty()
+        {
+            return None;
+        }
+        self.last = Instant::now();
+        Some(
+            self.totals
+                .drain(..)
+                .map(|(label, total, count)| {
+                    (label, total / count)
+                })
+                .collect(),
+        )
+    }
+}
+
+/// The JSON array form of the
+/// [trace event format](https://docs.google.com/document/d/1CvAClvFfyA5R-PhYUmn5OOQtYMH4h6I0nSsKchNAySU),
+/// with one complete (`"ph": "X"`) event per scope.
+///
+/// GPU timestamps have no relation to the CPU's clock, so
+/// they're written relative to the first one.
+struct ChromeTrace {
+    file: BufWriter<File>,
+    /// The first timestamp, in nanoseconds
+    origin: Option<f64>,
+}
+
+impl ChromeTrace {
+    fn create(path: &Path) -> io::Result<Self> {
+        let mut file = BufWriter::new(File::create(path)?);
+        write!(file, "[")?;
+        Ok(Self { file, origin: None })
+    }
+
+    fn write_event(
+        &mut self,
+        label: &str,
+        start_ns: f64,
+        duration: Duration,
+        frame: u64,
+    ) -> io::Result<()> {
+        let separator =
+            if self.origin.is_some() { "," } else { "" };
+        let origin = *self.origin.get_or_insert(start_ns);
+        // microseconds, which is what the format uses
+        let ts = (start_ns - origin) / 1000.0;
+        let dur = duration.as_nanos() as f64 / 1000.0;
+        write!(
+            self.file,
+            "{separator}\n{{\"name\":\"{}\",\"cat\":\"gpu\",\"ph\":\"X\",\"pid\":0,\"tid\":\"gpu\",\"ts\":{ts:.3},\"dur\":{dur:.3},\"args\":{{\"frame\":{frame}}}}}",
+            escape_json(label)
+        )
+    }
+}
+
+impl Drop for ChromeTrace {
+    fn drop(&mut self) {
+        // viewers accept a trace without the closing
+        // bracket, but other JSON readers don't
+        if let Err(error) = writeln!(self.file, "\n]")
+            .and_then(|_| self.file.flush())
+        {
+            error!(%error, "failed to finish the GPU trace");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "wgpu-for-bevy-{}-{name}",
+            std::process::id()
+        ))
+    }
+
+    /// A report whose interval has already passed
+    fn due_report() -> Report {
+        let mut report = Report::new();
+        report.last -= REPORT_INTERVAL;
+        report
+    }
+
+    #[test]
+    fn report_averages_each_label_in_first_seen_order() {
+        let mut report = due_report();
+        report.add("shadows", Duration::from_micros(300));
+        report.add("main", Duration::from_micros(100));
+        report.add("shadows", Duration::from_micros(500));
+        assert_eq!(
+            report.take_due(),
+            Some(vec![
+                (
+                    "shadows".to_string(),
+                    Duration::from_micros(400)
+                ),
+                (
+                    "main".to_string(),
+                    Duration::from_micros(100)
+                ),
+            ])
+        );
+    }
+
+    #[test]
+    fn report_waits_for_the_interval() {
+        let mut report = Report::new();
+        report.add("main", Duration::from_micros(100));
+        assert_eq!(report.take_due(), None);
+        // nothing was drained
+        assert_eq!(report.totals.len(), 1);
+    }
+
+    #[test]
+    fn report_starts_over_after_it_is_taken() {
+        let mut report = due_report();
+        report.add("main", Duration::from_micros(100));
+        assert!(report.take_due().is_some());
+        assert!(report.totals.is_empty());
+        // the interval starts again from the report
+        report.add("main", Duration::from_micros(100));
+        assert_eq!(report.take_due(), None);
+    }
+
+    #[test]
+    fn empty_report_is_never_due() {
+        let mut report = due_report();
+        assert_eq!(report.take_due(), None);
+    }
+
+    #[test]
+    fn chrome_trace_is_a_json_array_of_events() {
+        let path = temp_path("gpu-trace.json");
+        {
+            let mut trace =
+                ChromeTrace::create(&path).unwrap();
+            trace
+                .write_event(
+                    "main \"pass\"",
+                    5_000.0,
+                    Duration::from_micros(2),
+                    7,
+                )
+                .unwrap();
+            trace
+                .write_event(
+                    "overlay",
+                    9_500.0,
+                    Duration::from_nanos(1500),
+                    7,
+                )
+                .unwrap();
+        }
+        let json = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let events: serde_json::Value =
+            serde_json::from_str(&json).unwrap();
+        let events = events.as_array().unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0]["name"], "main \"pass\"");
+        assert_eq!(events[0]["ph"], "X");
+        assert_eq!(events[0]["args"]["frame"], 7);
+        // relative to the first event, in microseconds
+        assert_eq!(events[0]["ts"], 0.0);
+        assert_eq!(events[0]["dur"], 2.0);
+        assert_eq!(events[1]["ts"], 4.5);
+        assert_eq!(events[1]["dur"], 1.5);
+    }
+
+    #[test]
+    fn chrome_trace_without_events_is_an_empty_array() {
+        let path = temp_path("empty-gpu-trace.json");
+        drop(ChromeTrace::create(&path).unwrap());
+        let json = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let events: serde_json::Value =
+            serde_json::from_str(&json).unwrap();
+        assert_eq!(events, serde_json::json!([]));
+    }
+}
@@ -0,0 +1,308 @@
+//! Creating resources in a way that can be repeated on a
+//! new device, for recovering from a lost one.
+//!
+//! Nothing created from a lost device works again, and
+//! there's no way to move a buffer or pipeline to another
+//! device. Instead, each resource is registered with the
+//! closure that creates it from its descriptor, and
+//! [`GpuResources::rebuild`] runs every closure again with
+//! the new device.
+//!
+//! Closures run in the order they were registered, so a
+//! resource can use any resource registered before it,
+//! like a bind group using a buffer and a layout:
+//!
+//! ```ignore
+//! let layout = resources.register(&device, &queue, |cx| {
+//!     cx.device.create_bind_group_layout(&layout_descriptor())
+//! });
+//! let bind_group = resources.register(&device, &queue, move |cx| {
+//!     cx.device.create_bind_group(&wgpu::BindGroupDescriptor {
+//!         layout: cx.get(layout),
+//!         ..
+//!     })
+//! });
+//! ```
+use std::{any::Any, marker::PhantomData};
+use wgpu::{Device, Queue};
+
+/// Refers to a resource registered on a
+/// [`GpuResources`]. It stays valid across rebuilds.
+pub struct ResourceHandle<T> {
+    index: usize,
+    _marker: PhantomData<fn() -> T>,
+}
+
+// derived impls would require `T: Clone`
+impl<T> Clone for ResourceHandle<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for ResourceHandle<T> {}
+
+/// What a resource's closure gets to create it with
+pub struct ResourceContext<'a> {
+    pub device: &'a Device,
+    pub queue: &'a Queue,
+    /// Only the resources registered before the one being
+    /// created
+    earlier: &'a [Entry],
+}
+
+impl<'a> ResourceContext<'a> {
+    /// # Panics
+    ///
+    /// If `handle` wasn't registered before the resource
+    /// being created.
+    pub fn get<T: 'static>(
+        &self,
+        handle: ResourceHandle<T>,
+    ) -> &'a T {
+        downcast(&self.earlier[handle.index])
+    }
+}
+
+type CreateFn = dyn Fn(&ResourceContext) -> Box<dyn Any>;
+
+struct Entry {
+    value: Box<dyn Any>,
+    create: Box<CreateFn>,
+}
+
+/// Every registered resource, and how to create it
+/// again.
+#[derive(Default)]
+pub struct GpuResources {
+    entries: Vec<Entry>,
+}
+
+impl GpuResources {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a resource with `create`, and keep `create`
+    /// around to make it again in
+    /// [`GpuResources::rebuild`].
+    pub fn register<T: 'static>(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        create: impl Fn(&ResourceContext) -> T + 'static,
+    ) -> ResourceHandle<T> {
+        let create: Box<CreateFn> =
+            Box::new(move |cx| Box::new(create(cx)));
+        let value = create(&ResourceContext {
+            device,
+            queue,
+            earlier: &self.entries,
+        });
+        self.entries.push(Entry { value, create });
+        ResourceHandle {
+            index: self.entries.len() - 1,
+            _marker: PhantomData,
+        }
+    }
+
+    /// # Panics
+    ///
+    /// If `handle` came from a different `GpuResources`.
+    pub fn get<T: 'static>(
+        &self,
+        handle: ResourceHandle<T>,
+    ) -> &T {
+        downcast(&self.entries[handle.index])
+    }
+
+    /// Mutable access, for resources that change after
+    /// they're created, like a [`Uniform`](crate::Uniform)
+    /// that's uploaded every frame. A rebuild creates the
+    /// resource from its closure again, so changes made
+    /// here don't survive it.
+    ///
+    /// # Panics
+    ///
+    /// If `handle` came from a different `GpuResources`.
+    pub fn get_mut<T: 'static>(
+        &mut self,
+        handle: ResourceHandle<T>,
+    ) -> &mut T {
+        self.entries[handle.index]
+            .value
+            .downcast_mut()
+            .expect("resource handle to match its type")
+    }
+
+    /// Create every resource again on `device`, in the
+    /// order they were registered. The old resources are
+    /// dropped as the new ones replace them.
+    pub fn rebuild(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+    ) {
+        for index in 0..self.entries.len() {
+            let (earlier, rest) =
+                self.entries.split_at_mut(index);
+            let entry = &mut rest[0];
+            entry.value =
+                (entry.create)(&ResourceContext {
+                    device,
+                    queue,
+                    earlier,
+                });
+        }
+    }
+}
+
+fn downcast<T: 'static>(entry: &Entry) -> &T {
+    entry
+        .value
+        .downcast_ref()
+        .expect("resource handle to match its type")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_device::test_device;
+    use std::{cell::RefCell, rc::Rc};
+
+    /// Each resource records when it was created, and
+    /// copies the value of the resource it depends on
+    #[derive(Debug, PartialEq)]
+    struct Created {
+        name: &'static str,
+        generation: u32,
+        dependency: Option<u32>,
+    }
+
+    #[test]
+    fn register_runs_the_closure_right_away() {
+        let Some((device, queue)) = test_device() else {
+            return;
+        };
+        let mut resources = GpuResources::new();
+        let a =
+            resources.register(&device, &queue, |_| 1u32);
+        let b = resources.register(
+            &device,
+            &queue,
+            move |cx| *cx.get(a) + 1,
+        );
+        assert_eq!(*resources.get(a), 1);
+        assert_eq!(*resources.get(b), 2);
+    }
+
+    #[test]
+    fn rebuild_runs_closures_in_registration_order() {
+        let Some((device, queue)) = test_device() else {
+            return;
+        };
+        let generation = Rc::new(RefCell::new(0));
+        let log = Rc::new(RefCell::new(vec![]));
+        let create = |name, dependency: Option<_>| {
+            let generation = generation.clone();
+            let log = log.clone();
+            move |cx: &ResourceContext| {
+                log.borrow_mut().push(name);
+                Created {
+                    name,
+                    generation: *generation.borrow(),
+                    dependency: dependency.map(
+                        |handle: ResourceHandle<
+                            Created,
+                        >| {
+                            cx.get(handle).generation
+                        },
+                    ),
+                }
+            }
+        };
+
+        let mut resources = GpuResources::new();
+        let layout = resources.register(
+            &device,
+            &queue,
+            create("layout", None),
+        );
+        let buffer = resources.register(
+            &device,
+            &queue,
+            create("buffer", None),
+        );
+        let bind_group = resources.register(
+            &device,
+            &queue,
+            create("bind_group", Some(layout)),
+        );
+
+        *generation.borrow_mut() = 1;
+        log.borrow_mut().clear();
+        resources.rebuild(&device, &queue);
+
+        assert_eq!(
+            *log.borrow(),
+            ["layout", "buffer", "bind_group"]
+        );
+        assert_eq!(resources.get(buffer).generation, 1);
+        // the bind group saw the rebuilt layout, not the
+        // one from before the rebuild
+        assert_eq!(
+            *resources.get(bind_group),
+            Created {
+                name: "bind_group",
+                generation: 1,
+                dependency: Some(1),
+            }
+        );
+    }
+
+    #[test]
+    fn context_only_holds_earlier_resources() {
+        let Some((device, queue)) = test_device() else {
+            return;
+        };
+        let mut resources = GpuResources::new();
+        resources.register(&device, &queue, |cx| {
+            assert!(cx.earlier.is_empty());
+        });
+        resources.register(&device, &queue, |cx| {
+            assert_eq!(cx.earlier.len(), 1);
+        });
+        resources.rebuild(&device, &queue);
+    }
+
+    #[test]
+    #[should_panic]
+    fn getting_a_later_resource_panics() {
+        let Some((device, queue)) = test_device() else {
+            panic!("no adapter");
+        };
+        let mut resources = GpuResources::new();
+        let later = ResourceHandle::<u32> {
+            index: 1,
+            _marker: PhantomData,
+        };
+        resources.register(&device, &queue, |_| 0u32);
+        resources.register(&device, &queue, move |cx| {
+            *cx.get(later)
+        });
+    }
+
+    #[test]
+    fn rebuild_drops_changes_made_with_get_mut() {
+        let Some((device, queue)) = test_device() else {
+            return;
+        };
+        let mut resources = GpuResources::new();
+        let value =
+            resources.register(&device, &queue, |_| 1u32);
+        *resources.get_mut(value) = 5;
+        assert_eq!(*resources.get(value), 5);
+        resources.rebuild(&device, &queue);
+        assert_eq!(*resources.get(value), 1);
+    }
+}
@@ -0,0 +1,366 @@
+//! Decoded images and the textures they're uploaded to,
+//! a small version of Bevy's
+//! [`Image`](https://docs.rs/bevy/latest/bevy/image/struct.Image.html).
+use crate::mipmap::{MipmapGenerator, mip_level_count};
+use std::path::Path;
+use wgpu::{
+    Device, Extent3d, Queue, TexelCopyBufferLayout,
+    Texture, TextureDescriptor, TextureDimension,
+    TextureFormat, TextureUsages, TextureView,
+};
+
+/// Pixel data on the CPU, in a layout that matches
+/// `format`.
+///
+/// `data` holds every mip level, largest first, with the
+/// rows of each level tightly packed.
+pub struct Image {
+    pub data: Vec<u8>,
+    pub size: Extent3d,
+    pub format: TextureFormat,
+    pub mip_level_count: u32,
+}
+
+impl Image {
+    pub fn new(
+        size: Extent3d,
+        format: TextureFormat,
+        mip_level_count: u32,
+        data: Vec<u8>,
+    ) -> Self {
+        let image = Self {
+            data,
+            size,
+            format,
+            mip_level_count,
+        };
+        let expected: usize = (0..mip_level_count)
+            .map(|level| image.level_byte_len(level))
+            .sum();
+        assert_eq!(
+            image.data.len(),
+            expected,
+            "image data doesn't match its size and format"
+        );
+        image
+    }
+
+    /// Decode a PNG or JPEG from memory.
+    ///
+    /// Color textures are usually authored in sRGB, so
+    /// `is_srgb` should be `true` for them and `false` for
+    /// data like normal maps, where the shader wants the
+    /// stored values as they are.
+    pub fn from_buffer(
+        bytes: &[u8],
+        is_srgb: bool,
+    ) -> Result<Self, ::image::ImageError> {
+        let image = ::image::load_from_memory(bytes)?;
+        Ok(Self::from_dynamic(image, is_srgb))
+    }
+
+    /// Read and decode a PNG or JPEG file. The format is
+    /// guessed from the file's contents, not its
+    /// extension.
+    pub fn load(
+        path: impl AsRef<Path>,
+        is_srgb: bool,
+    ) -> Result<Self, ::image::ImageError> {
+        let image = ::image::ImageReader::open(path)?
+            .with_guessed_format()?
+            .decode()?;
+        Ok(Self::from_dynamic(image, is_srgb))
+    }
+
+    /// wgpu has no 8-bit RGB formats, so everything is
+    /// expanded to RGBA.
+    fn from_dynamic(
+        image: ::image::DynamicImage,
+        is_srgb: bool,
+    ) -> Self {
+        let image = image.into_rgba8();
+        let size = Extent3d {
+            width: image.width(),
+            height: image.height(),
+            depth_or_array_layers: 1,
+        };
+        let format = if is_srgb {
+            TextureFormat::Rgba8UnormSrgb
+        } else {
+            TextureFormat::Rgba8Unorm
+        };
+        Self::new(size, format, 1, image.into_raw())
+    }
+
+    pub fn width(&self) -> u32 {
+        self.size.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.size.height
+    }
+
+    /// The size of a mip level in pixels
+    pub fn level_size(&self, level: u32) -> Extent3d {
+        self.size
+            .mip_level_size(level, TextureDimension::D2)
+    }
+
+    /// Bytes in one row of blocks. For uncompressed
+    /// formats a block is a single pixel.
+    fn bytes_per_row(&self, level: u32) -> u32 {
+        let (block_width, _) =
+            self.format.block_dimensions();
+        let block_size = self
+            .format
+            .block_copy_size(None)
+            .expect("image format to be a color format");
+        self.level_size(level).width.div_ceil(block_width)
+            * block_size
+    }
+
+    /// Rows of blocks in a mip level.
+    fn rows(&self, level: u32) -> u32 {
+        let (_, block_height) =
+            self.format.block_dimensions();
+        self.level_size(level).height.div_ceil(block_height)
+    }
+
+    pub(crate) fn level_byte_len(
+        &self,
+        level: u32,
+    ) -> usize {
+        self.bytes_per_row(level) as usize
+            * self.rows(level) as usize
+    }
+
+    /// Create a texture for this image and write the
+    /// pixels to it.
+    pub fn upload(
+        &self,
+        device: &Device,
+        queue: &Queue,
+        label: &str,
+    ) -> GpuImage {
+        let texture = self.create_texture(
+            device,
+            label,
+            self.mip_level_count,
+            TextureUsages::empty(),
+        );
+        self.write_levels(queue, &texture);
+        GpuImage::new(texture, self.size)
+    }
+
+    /// Like [`Image::upload`], but with a full mip chain
+    /// rendered on the GPU from the image's pixels.
+    ///
+    /// Images that already have mips, like most KTX2
+    /// files, are uploaded as they are. So are images in
+    /// block-compressed formats, which can't be rendered
+    /// to.
+    pub fn upload_with_mipmaps(
+        &self,
+        device: &Device,
+        queue: &Queue,
+        mipmaps: &mut MipmapGenerator,
+        label: &str,
+    ) -> GpuImage {
+        if self.mip_level_count > 1
+            || self.format.is_compressed()
+        {
+            return self.upload(device, queue, label);
+        }
+
+        let texture = self.create_texture(
+            device,
+            label,
+            mip_level_count(self.size),
+            TextureUsages::RENDER_ATTACHMENT,
+        );
+        self.write_levels(queue, &texture);
+
+        // `write_texture` is ordered before any command
+        // buffers submitted after it, so the first level
+        // is there by the time the mips are rendered
+        let mut encoder = device.create_command_encoder(
+            &wgpu::CommandEncoderDescriptor {
+                label: "mipmap_command_encoder".into(),
+            },
+        );
+        mipmaps.generate(device, &mut encoder, &texture);
+        queue.submit(Some(encoder.finish()));
+
+        GpuImage::new(texture, self.size)
+    }
+
+    fn create_texture(
+        &self,
+        device: &Device,
+        label: &str,
+        mip_level_count: u32,
+        usage: TextureUsages,
+    ) -> Texture {
+        device.create_texture(&TextureDescriptor {
+            label: Some(label),
+            size: self.size,
+            mip_level_count,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: self.format,
+            usage: usage
+                | TextureUsages::TEXTURE_BINDING
+                | TextureUsages::COPY_DST,
+            view_formats: &[],
+        })
+    }
+
+    fn write_levels(
+        &self,
+        queue: &Queue,
+        texture: &Texture,
+    ) {
+        let mut offset = 0;
+        for level in 0..self.mip_level_count {
+            let len = self.level_byte_len(level);
+            self.write_level(
+                queue,
+                texture,
+                level,
+                &self.data[offset..offset + len],
+            );
+            offset += len;
+        }
+    }
+
+    fn write_level(
+        &self,
+        queue: &Queue,
+        texture: &Texture,
+        level: u32,
+        data: &[u8],
+    ) {
+        // unlike a buffer to texture copy, writing
+        // straight to a texture doesn't need rows padded
+        // to `COPY_BYTES_PER_ROW_ALIGNMENT`
+        let bytes_per_row = self.bytes_per_row(level);
+        let rows = self.rows(level);
+
+        queue.write_texture(
+            wgpu::TexelCopyTextureInfo {
+                texture,
+                mip_level: level,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            data,
+            TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(bytes_per_row),
+                rows_per_image: Some(rows),
+            },
+            // compressed levels smaller than a block still
+            // copy a whole block
+            self.level_size(level)
+                .physical_size(self.format),
+        );
+    }
+}
+
+/// An [`Image`] that lives on the GPU
+pub struct GpuImage {
+    pub texture: Texture,
+    pub view: TextureView,
+    pub size: Extent3d,
+}
+
+impl GpuImage {
+    fn new(texture: Texture, size: Extent3d) -> Self {
+        let view = texture.create_view(&Default::default());
+        Self {
+            texture,
+            view,
+            size,
+        }
+    }
+
+    /// An empty texture that render passes draw into and
+    /// shaders sample from, like the images Bevy creates
+    /// with `Image::new_target_texture` for a camera's
+    /// `RenderTarget::Image`.
+    ///
+    /// There's no CPU data to keep in sync, so a target
+    /// that changes size is simply created again.
+    pub fn new_render_target(
+        device: &Device,
+        label: &str,
+        size: Extent3d,
+        format: TextureFormat,
+    ) -> Self {
+        let texture =
+            device.create_texture(&TextureDescriptor {
+                label: Some(label),
+                size,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: TextureDimension::D2,
+                format,
+                usage: TextureUsages::RENDER_ATTACHMENT
+                    | TextureUsages::TEXTURE_BINDING
+                    | TextureUsages::COPY_SRC,
+                view_formats: &[],
+            });
+        Self::new(texture, size)
+    }
+
+    pub fn mip_level_count(&self) -> u32 {
+        self.texture.mip_level_count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Readback, test_device::test_device};
+
+    #[test]
+    fn levels_are_written_with_unpadded_rows() {
+        let Some((device, queue)) = test_device() else {
+            return;
+        };
+        // 20 and 8 byte rows, neither a multiple of 256
+        let level_0: Vec<u8> = (0..60).collect();
+        let level_1: Vec<u8> = (100..108).collect();
+        let image = Image::new(
+            Extent3d {
+                width: 5,
+                height: 3,
+                depth_or_array_layers: 1,
+            },
+            TextureFormat::Rgba8Unorm,
+            2,
+            [level_0.clone(), level_1.clone()].concat(),
+        );
+        let texture = image.create_texture(
+            &device,
+            "image",
+            2,
+            TextureUsages::COPY_SRC,
+        );
+        image.write_levels(&queue, &texture);
+
+        for (level, expected) in
+            [level_0, level_1].into_iter().enumerate()
+        {
+            let data = Readback::texture(
+                &device,
+                &queue,
+                &texture,
+                level as u32,
+            )
+            .read_blocking(&device)
+            .unwrap();
+            assert_eq!(data.bytes(), expected);
+        }
+    }
+}
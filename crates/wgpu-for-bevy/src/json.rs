@@ -0,0 +1,38 @@
+//! Writing JSON by hand, for the trace files, without
+//! pulling in a serializer.
+
+/// `text` with everything a JSON string can't hold
+/// escaped, ready to go between quotes
+pub(crate) fn escape_json(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for char in text.chars() {
+        match char {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            char if char.is_control() => escaped.push_str(
+                &format!("\\u{:04x}", char as u32),
+            ),
+            char => escaped.push(char),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escape_json_escapes_quotes_backslashes_and_controls()
+    {
+        assert_eq!(
+            escape_json(r#"a "b" \c"#),
+            r#"a \"b\" \\c"#
+        );
+        assert_eq!(
+            escape_json("line\nbreak\t\u{7f}"),
+            r"line\u000abreak\u0009\u007f"
+        );
+        assert_eq!(escape_json("pässe ✓"), "pässe ✓");
+    }
+}
@@ -0,0 +1,580 @@
+//! Loading KTX2 files, the container Bevy uses for
+//! GPU compressed textures.
+//!
+//! Compressed formats only work when the adapter supports
+//! them, which varies a lot: desktop GPUs usually have BCn,
+//! mobile GPUs ETC2 and ASTC. When a file's format isn't
+//! supported the levels are decompressed to RGBA8 on the
+//! CPU instead, which costs load time and four to eight
+//! times the memory but still draws the right pixels.
+use crate::image::Image;
+use ::ktx2::{Format, SupercompressionScheme};
+use std::{fmt, io::Read, path::Path};
+use wgpu::{
+    AstcBlock, AstcChannel, Extent3d, Features,
+    TextureFormat,
+};
+
+/// The features compressed KTX2 textures can use.
+///
+/// Request the ones the adapter has when creating the
+/// device, then pass `device.features()` to
+/// [`Image::from_ktx2`]:
+///
+/// ```ignore
+/// required_features: adapter.features()
+///     & TEXTURE_COMPRESSION_FEATURES,
+/// ```
+pub const TEXTURE_COMPRESSION_FEATURES: Features =
+    Features::TEXTURE_COMPRESSION_BC
+        .union(Features::TEXTURE_COMPRESSION_ETC2)
+        .union(Features::TEXTURE_COMPRESSION_ASTC);
+
+impl Image {
+    /// Parse a KTX2 file, decompressing zstd
+    /// supercompressed levels.
+    ///
+    /// If `features` doesn't include what the file's
+    /// format needs, the image is decoded to `Rgba8Unorm`
+    /// (or `Rgba8UnormSrgb`) on the CPU.
+    pub fn from_ktx2(
+        bytes: &[u8],
+        features: Features,
+    ) -> Result<Self, Ktx2Error> {
+        let reader = ::ktx2::Reader::new(bytes)?;
+        let header = reader.header();
+
+        if header.pixel_depth > 1
+            || header.layer_count > 1
+            || header.face_count != 1
+        {
+            return Err(Ktx2Error::UnsupportedLayout);
+        }
+        let ktx2_format = header
+            .format
+            .ok_or(Ktx2Error::UnsupportedFormat(None))?;
+        let format = texture_format(ktx2_format).ok_or(
+            Ktx2Error::UnsupportedFormat(Some(ktx2_format)),
+        )?;
+
+        let size = Extent3d {
+            width: header.pixel_width,
+            height: header.pixel_height.max(1),
+            depth_or_array_layers: 1,
+        };
+        // a level count of 0 asks the loader to generate
+        // mips, which `upload_with_mipmaps` can do later for
+        // uncompressed formats. Compressed formats can't be
+        // rendered to, so they keep their single level.
+        let mip_level_count = header.level_count.max(1);
+
+        let mut levels = vec![];
+        for level in reader.levels() {
+            let data = match header.supercompression_scheme
+            {
+                None => level.data.to_vec(),
+                Some(SupercompressionScheme::Zstandard) => {
+                    let mut decoder =
+                        ruzstd::decoding::StreamingDecoder::new(
+                            level.data,
+                        )
+                        .map_err(|error| {
+                            Ktx2Error::Zstd(error.to_string())
+                        })?;
+                    let mut data = Vec::with_capacity(
+                        level.uncompressed_byte_length
+                            as usize,
+                    );
+                    decoder
+                        .read_to_end(&mut data)
+                        .map_err(|error| {
+                            Ktx2Error::Zstd(
+                                error.to_string(),
+                            )
+                        })?;
+                    data
+                }
+                Some(scheme) => {
+                    return Err(
+                        Ktx2Error::UnsupportedSupercompression(
+                            scheme,
+                        ),
+                    );
+                }
+            };
+            levels.push(data);
+        }
+
+        let image = Image {
+            data: vec![],
+            size,
+            format,
+            mip_level_count,
+        };
+        for (level, data) in levels.iter().enumerate() {
+            if data.len()
+                != image.level_byte_len(level as u32)
+            {
+                return Err(Ktx2Error::InvalidLevelSize {
+                    level: level as u32,
+                });
+            }
+        }
+
+        if features.contains(format.required_features()) {
+            return Ok(Image::new(
+                size,
+                format,
+                mip_level_count,
+                levels.concat(),
+            ));
+        }
+
+        let mut data = vec![];
+        for (level, level_data) in levels.iter().enumerate()
+        {
+            decompress(
+                format,
+                image.level_size(level as u32),
+                level_data,
+                &mut data,
+            )?;
+        }
+        let fallback_format = if format.is_srgb() {
+            TextureFormat::Rgba8UnormSrgb
+        } else {
+            TextureFormat::Rgba8Unorm
+        };
+        Ok(Image::new(
+            size,
+            fallback_format,
+            mip_level_count,
+            data,
+        ))
+    }
+
+    /// Read a KTX2 file from disk. See
+    /// [`Image::from_ktx2`].
+    pub fn load_ktx2(
+        path: impl AsRef<Path>,
+        features: Features,
+    ) -> Result<Self, Ktx2Error> {
+        let bytes = std::fs::read(path)?;
+        Self::from_ktx2(&bytes, features)
+    }
+}
+
+/// The `TextureFormat` for a KTX2 (Vulkan) format, for
+/// the formats wgpu can sample from.
+fn texture_format(format: Format) -> Option<TextureFormat> {
+    use TextureFormat as T;
+
+    let format = match format {
+        Format::R8G8B8A8_UNORM => T::Rgba8Unorm,
+        Format::R8G8B8A8_SRGB => T::Rgba8UnormSrgb,
+        // BC1 always has a punch through alpha bit in
+        // wgpu, which opaque textures simply don't use
+        Format::BC1_RGB_UNORM_BLOCK
+        | Format::BC1_RGBA_UNORM_BLOCK => T::Bc1RgbaUnorm,
+        Format::BC1_RGB_SRGB_BLOCK
+        | Format::BC1_RGBA_SRGB_BLOCK => {
+            T::Bc1RgbaUnormSrgb
+        }
+        Format::BC2_UNORM_BLOCK => T::Bc2RgbaUnorm,
+        Format::BC2_SRGB_BLOCK => T::Bc2RgbaUnormSrgb,
+        Format::BC3_UNORM_BLOCK => T::Bc3RgbaUnorm,
+        Format::BC3_SRGB_BLOCK => T::Bc3RgbaUnormSrgb,
+        Format::BC4_UNORM_BLOCK => T::Bc4RUnorm,
+        Format::BC4_SNORM_BLOCK => T::Bc4RSnorm,
+        Format::BC5_UNORM_BLOCK => T::Bc5RgUnorm,
+        Format::BC5_SNORM_BLOCK => T::Bc5RgSnorm,
+        Format::BC6H_UFLOAT_BLOCK => T::Bc6hRgbUfloat,
+        Format::BC6H_SFLOAT_BLOCK => T::Bc6hRgbFloat,
+        Format::BC7_UNORM_BLOCK => T::Bc7RgbaUnorm,
+        Format::BC7_SRGB_BLOCK => T::Bc7RgbaUnormSrgb,
+        Format::ETC2_R8G8B8_UNORM_BLOCK => T::Etc2Rgb8Unorm,
+        Format::ETC2_R8G8B8_SRGB_BLOCK => {
+            T::Etc2Rgb8UnormSrgb
+        }
+        Format::ETC2_R8G8B8A1_UNORM_BLOCK => {
+            T::Etc2Rgb8A1Unorm
+        }
+        Format::ETC2_R8G8B8A1_SRGB_BLOCK => {
+            T::Etc2Rgb8A1UnormSrgb
+        }
+        Format::ETC2_R8G8B8A8_UNORM_BLOCK => {
+            T::Etc2Rgba8Unorm
+        }
+        Format::ETC2_R8G8B8A8_SRGB_BLOCK => {
+            T::Etc2Rgba8UnormSrgb
+        }
+        Format::EAC_R11_UNORM_BLOCK => T::EacR11Unorm,
+        Format::EAC_R11_SNORM_BLOCK => T::EacR11Snorm,
+        Format::EAC_R11G11_UNORM_BLOCK => T::EacRg11Unorm,
+        Format::EAC_R11G11_SNORM_BLOCK => T::EacRg11Snorm,
+        _ => return astc_format(format),
+    };
+    Some(format)
+}
+
+/// The LDR ASTC formats are numbered in pairs of
+/// (unorm, srgb), one pair per block size, starting at
+/// `ASTC_4x4_UNORM_BLOCK`.
+fn astc_format(format: Format) -> Option<TextureFormat> {
+    const BLOCKS: [AstcBlock; 14] = [
+        AstcBlock::B4x4,
+        AstcBlock::B5x4,
+        AstcBlock::B5x5,
+        AstcBlock::B6x5,
+        AstcBlock::B6x6,
+        AstcBlock::B8x5,
+        AstcBlock::B8x6,
+        AstcBlock::B8x8,
+        AstcBlock::B10x5,
+        AstcBlock::B10x6,
+        AstcBlock::B10x8,
+        AstcBlock::B10x10,
+        AstcBlock::B12x10,
+        AstcBlock::B12x12,
+    ];
+
+    let index = format
+        .value()
+        .checked_sub(Format::ASTC_4x4_UNORM_BLOCK.value())?
+        as usize;
+    let block = *BLOCKS.get(index / 2)?;
+    let channel = if index.is_multiple_of(2) {
+        AstcChannel::Unorm
+    } else {
+        AstcChannel::UnormSrgb
+    };
+    Some(TextureFormat::Astc { block, channel })
+}
+
+/// Decode one compressed level to RGBA8, appending the
+/// pixels to `out`.
+fn decompress(
+    format: TextureFormat,
+    size: Extent3d,
+    data: &[u8],
+    out: &mut Vec<u8>,
+) -> Result<(), Ktx2Error> {
+    use TextureFormat as T;
+    use texture2ddecoder as d;
+
+    let width = size.width as usize;
+    let height = size.height as usize;
+    let mut pixels = vec![0u32; width * height];
+    let result = match format {
+        T::Rgba8Unorm | T::Rgba8UnormSrgb => {
+            out.extend_from_slice(data);
+            return Ok(());
+        }
+        T::Bc1RgbaUnorm | T::Bc1RgbaUnormSrgb => {
+            d::decode_bc1a(data, width, height, &mut pixels)
+        }
+        T::Bc2RgbaUnorm | T::Bc2RgbaUnormSrgb => {
+            d::decode_bc2(data, width, height, &mut pixels)
+        }
+        T::Bc3RgbaUnorm | T::Bc3RgbaUnormSrgb => {
+            d::decode_bc3(data, width, height, &mut pixels)
+        }
+        T::Bc4RUnorm => {
+            d::decode_bc4(data, width, height, &mut pixels)
+        }
+        T::Bc5RgUnorm => {
+            d::decode_bc5(data, width, height, &mut pixels)
+        }
+        T::Bc7RgbaUnorm | T::Bc7RgbaUnormSrgb => {
+            d::decode_bc7(data, width, height, &mut pixels)
+        }
+        T::Etc2Rgb8Unorm | T::Etc2Rgb8UnormSrgb => {
+            d::decode_etc2_rgb(
+                data,
+                width,
+                height,
+                &mut pixels,
+            )
+        }
+        T::Etc2Rgb8A1Unorm | T::Etc2Rgb8A1UnormSrgb => {
+            d::decode_etc2_rgba1(
+                data,
+                width,
+                height,
+                &mut pixels,
+            )
+        }
+        T::Etc2Rgba8Unorm | T::Etc2Rgba8UnormSrgb => {
+            d::decode_etc2_rgba8(
+                data,
+                width,
+                height,
+                &mut pixels,
+            )
+        }
+        T::EacR11Unorm => {
+            d::decode_eacr(data, width, height, &mut pixels)
+        }
+        T::EacRg11Unorm => d::decode_eacrg(
+            data,
+            width,
+            height,
+            &mut pixels,
+        ),
+        T::Astc {
+            channel:
+                AstcChannel::Unorm | AstcChannel::UnormSrgb,
+            ..
+        } => {
+            let (block_width, block_height) =
+                format.block_dimensions();
+            d::decode_astc(
+                data,
+                width,
+                height,
+                block_width as usize,
+                block_height as usize,
+                &mut pixels,
+            )
+        }
+        // signed and HDR formats don't fit in RGBA8
+        _ => {
+            return Err(Ktx2Error::NoFallback(format));
+        }
+    };
+    result.map_err(Ktx2Error::Decompression)?;
+
+    // the decoder packs pixels as BGRA
+    out.extend(pixels.iter().flat_map(|pixel| {
+        let [b, g, r, a] = pixel.to_le_bytes();
+        [r, g, b, a]
+    }));
+    Ok(())
+}
+
+#[derive(Debug)]
+pub enum Ktx2Error {
+    Io(std::io::Error),
+    Parse(::ktx2::ParseError),
+    /// `None` is a format of `VK_FORMAT_UNDEFINED`, used by
+    /// Basis Universal files that need transcoding
+    UnsupportedFormat(Option<Format>),
+    UnsupportedSupercompression(SupercompressionScheme),
+    /// Cube maps, arrays, and 3d textures
+    UnsupportedLayout,
+    InvalidLevelSize {
+        level: u32,
+    },
+    Zstd(String),
+    /// The adapter doesn't support the format and it
+    /// can't be decompressed on the CPU either
+    NoFallback(TextureFormat),
+    Decompression(&'static str),
+}
+
+impl From<std::io::Error> for Ktx2Error {
+    fn from(error: std::io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+impl From<::ktx2::ParseError> for Ktx2Error {
+    fn from(error: ::ktx2::ParseError) -> Self {
+        Self::Parse(error)
+    }
+}
+
+impl fmt::Display for Ktx2Error {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+    ) -> fmt::Result {
+        match self {
+            Self::Io(error) => error.fmt(f),
+            Self::Parse(error) => {
+                write!(f, "invalid KTX2 file: {error}")
+            }
+            Self::UnsupportedFormat(None) => write!(
+                f,
+                "Basis Universal KTX2 files are not supported"
+            ),
+            Self::UnsupportedFormat(Some(format)) => {
+                write!(
+                    f,
+                    "unsupported KTX2 format {format:?}"
+                )
+            }
+            Self::UnsupportedSupercompression(scheme) => {
+                write!(
+                    f,
+                    "unsupported KTX2 supercompression {scheme:?}"
+                )
+            }
+            Self::UnsupportedLayout => write!(
+                f,
+                "only 2d KTX2 textures with one layer and face are supported"
+            ),
+            Self::InvalidLevelSize { level } => write!(
+                f,
+                "mip level {level} has the wrong size for its format"
+            ),
+            Self::Zstd(error) => {
+                write!(
+                    f,
+                    "zstd decompression failed: {error}"
+                )
+            }
+            Self::NoFallback(format) => write!(
+                f,
+                "{format:?} isn't supported by the device and can't be decompressed on the CPU"
+            ),
+            Self::Decompression(error) => {
+                write!(f, "decompression failed: {error}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for Ktx2Error {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 256x256, BC1 sRGB with a full mip chain and zstd
+    /// supercompression
+    const RINGS_BC1: &[u8] = include_bytes!(
+        "../../../assets/textures/rings_bc1.ktx2"
+    );
+
+    #[test]
+    fn compressed_when_supported() {
+        let image = Image::from_ktx2(
+            RINGS_BC1,
+            Features::TEXTURE_COMPRESSION_BC,
+        )
+        .unwrap();
+        assert_eq!(
+            image.format,
+            TextureFormat::Bc1RgbaUnormSrgb
+        );
+        assert_eq!(image.mip_level_count, 9);
+        assert_eq!(
+            (image.width(), image.height()),
+            (256, 256)
+        );
+        // every level down to 1x1 rounds up to whole 4x4
+        // blocks of 8 bytes
+        assert_eq!(image.data.len(), 43_704);
+    }
+
+    #[test]
+    fn decompressed_when_unsupported() {
+        let image =
+            Image::from_ktx2(RINGS_BC1, Features::empty())
+                .unwrap();
+        assert_eq!(
+            image.format,
+            TextureFormat::Rgba8UnormSrgb
+        );
+        assert_eq!(image.mip_level_count, 9);
+        // 4 bytes per pixel for every level, with no
+        // rounding up to blocks
+        assert_eq!(image.data.len(), 349_524);
+    }
+
+    #[test]
+    fn other_compression_features_do_not_count() {
+        let image = Image::from_ktx2(
+            RINGS_BC1,
+            Features::TEXTURE_COMPRESSION_ETC2
+                | Features::TEXTURE_COMPRESSION_ASTC,
+        )
+        .unwrap();
+        assert_eq!(
+            image.format,
+            TextureFormat::Rgba8UnormSrgb
+        );
+    }
+
+    #[test]
+    fn not_ktx2() {
+        assert!(matches!(
+            Image::from_ktx2(
+                b"not a ktx2 file",
+                Features::all()
+            ),
+            Err(Ktx2Error::Parse(_))
+        ));
+    }
+
+    fn astc(
+        block: AstcBlock,
+        channel: AstcChannel,
+    ) -> Option<TextureFormat> {
+        Some(TextureFormat::Astc { block, channel })
+    }
+
+    #[test]
+    fn astc_first_block_size() {
+        assert_eq!(
+            astc_format(Format::ASTC_4x4_UNORM_BLOCK),
+            astc(AstcBlock::B4x4, AstcChannel::Unorm)
+        );
+        assert_eq!(
+            astc_format(Format::ASTC_4x4_SRGB_BLOCK),
+            astc(AstcBlock::B4x4, AstcChannel::UnormSrgb)
+        );
+    }
+
+    #[test]
+    fn astc_block_sizes_in_between() {
+        assert_eq!(
+            astc_format(Format::ASTC_8x6_SRGB_BLOCK),
+            astc(AstcBlock::B8x6, AstcChannel::UnormSrgb)
+        );
+        assert_eq!(
+            astc_format(Format::ASTC_10x5_UNORM_BLOCK),
+            astc(AstcBlock::B10x5, AstcChannel::Unorm)
+        );
+    }
+
+    #[test]
+    fn astc_last_block_size() {
+        assert_eq!(
+            astc_format(Format::ASTC_12x12_UNORM_BLOCK),
+            astc(AstcBlock::B12x12, AstcChannel::Unorm)
+        );
+        assert_eq!(
+            astc_format(Format::ASTC_12x12_SRGB_BLOCK),
+            astc(
+                AstcBlock::B12x12,
+                AstcChannel::UnormSrgb
+            )
+        );
+    }
+
+    #[test]
+    fn not_astc() {
+        // just before and just after the LDR range, and
+        // the HDR formats, which come from an extension
+        assert_eq!(
+            astc_format(Format::BC7_SRGB_BLOCK),
+            None
+        );
+        assert_eq!(
+            astc_format(
+                Format::new(
+                    Format::ASTC_12x12_SRGB_BLOCK.value()
+                        + 1
+                )
+                .unwrap()
+            ),
+            None
+        );
+        assert_eq!(
+            astc_format(Format::ASTC_4x4_SFLOAT_BLOCK),
+            None
+        );
+    }
+}
@@ -0,0 +1,86 @@
+//! Small pieces of rendering infrastructure shared by the
+//! examples.
+//!
+//! Each module is a stripped down version of something
+//! Bevy's renderer provides, written directly against
+//! wgpu so the moving parts are visible.
+pub mod bind_group;
+pub mod dynamic_uniform;
+pub mod fps_overlay;
+pub mod gpu_errors;
+pub mod gpu_profiler;
+pub mod gpu_resources;
+pub mod image;
+mod json;
+pub mod ktx2;
+pub mod material;
+pub mod mesh;
+pub mod mipmap;
+pub mod pipeline_cache;
+pub mod pipeline_specializer;
+pub mod readback;
+pub mod recording;
+pub mod screenshot;
+pub mod storage;
+#[cfg(test)]
+mod test_device;
+pub mod time;
+#[cfg(feature = "trace_chrome")]
+pub mod trace_chrome;
+pub mod uniform;
+
+pub use self::image::{GpuImage, Image};
+pub use bind_group::AsBindGroup;
+pub use dynamic_uniform::DynamicUniform;
+pub use fps_overlay::FpsOverlay;
+pub use gpu_errors::{
+    DeviceLost, DeviceLostWatcher, GpuError,
+    capture_errors, log_uncaptured_errors,
+};
+pub use gpu_profiler::{
+    GpuProfiler, GpuTiming, TimestampTarget,
+};
+pub use gpu_resources::{
+    GpuResources, ResourceContext, ResourceHandle,
+};
+pub use ktx2::{Ktx2Error, TEXTURE_COMPRESSION_FEATURES};
+pub use material::{
+    AlphaMode, Material, MaterialLayouts, MaterialPipeline,
+    ShaderRef,
+};
+pub use mesh::{
+    GpuMesh, Indices, Mesh, MeshVertexBufferLayout,
+};
+pub use mipmap::MipmapGenerator;
+pub use pipeline_cache::{
+    CachedPipelineId, CachedPipelineState, PipelineCache,
+    PipelineCacheError,
+};
+pub use pipeline_specializer::{
+    SpecializedMeshPipeline, SpecializedMeshPipelineError,
+    SpecializedMeshPipelines, SpecializedRenderPipeline,
+    SpecializedRenderPipelines,
+};
+pub use readback::{
+    PendingReadback, Readback, ReadbackData, ReadbackError,
+};
+pub use recording::{
+    FrameRecorder, RecordingError, RecordingOutput,
+};
+pub use screenshot::{ScreenshotError, ScreenshotManager};
+pub use storage::Storage;
+pub use time::{
+    Clocks, Fixed, Real, Time, TimeUpdateStrategy, Virtual,
+};
+#[cfg(feature = "trace_chrome")]
+pub use trace_chrome::{ChromeLayer, FlushGuard};
+pub use uniform::Uniform;
+pub use wgpu_for_bevy_derive::AsBindGroup;
+
+/// Paths the derive macros use, so crates deriving
+/// [`AsBindGroup`] don't need their own `wgpu`
+/// dependency to compile the generated code.
+#[doc(hidden)]
+pub mod __private {
+    pub use wgpu;
+}
@@ -0,0 +1,278 @@
+//! A small version of Bevy's
+//! [`Material`](https://docs.rs/bevy/latest/bevy/pbr/trait.Material.html)
+//! trait, and the pipeline that draws meshes with one.
+//!
+//! Bind groups are laid out like Bevy's: the view in
+//! `@group(0)`, the mesh transform in `@group(1)`, and the
+//! material's own data in `@group(2)`. `material.wgsl`
+//! declares the first two and the `VertexOutput` a custom
+//! fragment shader receives.
+use crate::{
+    bind_group::AsBindGroup,
+    mesh::{Mesh, MeshVertexBufferLayout},
+    pipeline_specializer::{
+        SpecializedMeshPipeline,
+        SpecializedMeshPipelineError,
+    },
+    uniform::Uniform,
+};
+use encase::ShaderType;
+use glam::Mat4;
+use std::{borrow::Cow, marker::PhantomData};
+use wgpu::{
+    BindGroupLayout, Device, PipelineLayout,
+    PrimitiveTopology, RenderPipeline, ShaderModule,
+    ShaderStages, TextureFormat,
+};
+
+/// How a material's output is combined with what's
+/// already been drawn
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, Hash,
+)]
+pub enum AlphaMode {
+    /// Alpha is ignored and depth is written
+    #[default]
+    Opaque,
+    /// Alpha blended over what's behind it. Blended
+    /// meshes don't write depth, so they should be drawn
+    /// after opaque ones, back to front.
+    Blend,
+}
+
+/// Where a material's shader comes from
+pub enum ShaderRef {
+    /// The entry point from `material.wgsl`
+    Default,
+    /// WGSL source, usually from `include_str!`
+    Wgsl(&'static str),
+}
+
+/// The shaders and settings that decide how a mesh
+/// looks. The data the shaders read comes from
+/// [`AsBindGroup`].
+pub trait Material: AsBindGroup + 'static {
+    fn vertex_shader() -> ShaderRef {
+        ShaderRef::Default
+    }
+
+    fn fragment_shader() -> ShaderRef {
+        ShaderRef::Default
+    }
+
+    fn alpha_mode(&self) -> AlphaMode {
+        AlphaMode::Opaque
+    }
+}
+
+/// The camera, and the time for animated materials
+#[derive(ShaderType)]
+pub struct ViewUniform {
+    pub view_proj: Mat4,
+    pub time: f32,
+}
+
+#[derive(ShaderType)]
+pub struct MeshUniform {
+    pub model: Mat4,
+}
+
+/// The view and mesh layouts, shared by every material
+/// so the same view and mesh bind groups work with all of
+/// them.
+pub struct MaterialLayouts {
+    pub view: BindGroupLayout,
+    pub mesh: BindGroupLayout,
+}
+
+impl MaterialLayouts {
+    pub fn new(device: &Device) -> Self {
+        let view = device.create_bind_group_layout(
+            &wgpu::BindGroupLayoutDescriptor {
+                label: "view_layout".into(),
+                entries: &[
+                    Uniform::<ViewUniform>::layout_entry(
+                        0,
+                        ShaderStages::VERTEX_FRAGMENT,
+                    ),
+                ],
+            },
+        );
+        let mesh = device.create_bind_group_layout(
+            &wgpu::BindGroupLayoutDescriptor {
+                label: "mesh_layout".into(),
+                entries: &[
+                    Uniform::<MeshUniform>::layout_entry(
+                        0,
+                        ShaderStages::VERTEX,
+                    ),
+                ],
+            },
+        );
+        Self { view, mesh }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct MaterialPipelineKey {
+    pub alpha_mode: AlphaMode,
+    pub primitive_topology: PrimitiveTopology,
+}
+
+/// Everything shared by the pipelines of one material
+/// type. Pipelines are built through
+/// [`SpecializedMeshPipelines`](crate::SpecializedMeshPipelines),
+/// one per mesh layout and [`MaterialPipelineKey`].
+pub struct MaterialPipeline<M> {
+    material_layout: BindGroupLayout,
+    pipeline_layout: PipelineLayout,
+    vertex_shader: ShaderModule,
+    fragment_shader: ShaderModule,
+    format: TextureFormat,
+    depth_format: TextureFormat,
+    marker: PhantomData<M>,
+}
+
+impl<M: Material> MaterialPipeline<M> {
+    pub fn new(
+        device: &Device,
+        layouts: &MaterialLayouts,
+        format: TextureFormat,
+        depth_format: TextureFormat,
+    ) -> Self {
+        let material_layout = M::bind_group_layout(device);
+        let pipeline_layout = device
+            .create_pipeline_layout(
+                &wgpu::PipelineLayoutDescriptor {
+                    label: Some(M::label()),
+                    bind_group_layouts: &[
+                        &layouts.view,
+                        &layouts.mesh,
+                        &material_layout,
+                    ],
+                    immediate_size: 0,
+                },
+            );
+
+        let create_module = |shader: ShaderRef| {
+            let source = match shader {
+                ShaderRef::Default => {
+                    include_str!("material.wgsl")
+                }
+                ShaderRef::Wgsl(source) => source,
+            };
+            device.create_shader_module(
+                wgpu::ShaderModuleDescriptor {
+                    label: Some(M::label()),
+                    source: wgpu::ShaderSource::Wgsl(
+                        Cow::Borrowed(source),
+                    ),
+                },
+            )
+        };
+
+        Self {
+            material_layout,
+            pipeline_layout,
+            vertex_shader: create_module(M::vertex_shader()),
+            fragment_shader: create_module(
+                M::fragment_shader(),
+            ),
+            format,
+            depth_format,
+            marker: PhantomData,
+        }
+    }
+
+    /// The layout [`AsBindGroup::as_bind_group`] needs
+    pub fn material_layout(&self) -> &BindGroupLayout {
+        &self.material_layout
+    }
+
+    /// The key for drawing `mesh` with `material`
+    pub fn key(
+        material: &M,
+        mesh: &Mesh,
+    ) -> MaterialPipelineKey {
+        MaterialPipelineKey {
+            alpha_mode: material.alpha_mode(),
+            primitive_topology: mesh.primitive_topology(),
+        }
+    }
+}
+
+impl<M: Material> SpecializedMeshPipeline
+    for MaterialPipeline<M>
+{
+    type Key = MaterialPipelineKey;
+
+    fn specialize(
+        &self,
+        device: &Device,
+        key: Self::Key,
+        layout: &MeshVertexBufferLayout,
+    ) -> Result<RenderPipeline, SpecializedMeshPipelineError>
+    {
+        let vertex_layout = layout.get_layout(&[
+            Mesh::ATTRIBUTE_POSITION.at_shader_location(0),
+            Mesh::ATTRIBUTE_NORMAL.at_shader_location(1),
+            Mesh::ATTRIBUTE_UV_0.at_shader_location(2),
+        ])?;
+
+        let (blend, depth_write_enabled) = match key
+            .alpha_mode
+        {
+            AlphaMode::Opaque => {
+                (wgpu::BlendState::REPLACE, true)
+            }
+            AlphaMode::Blend => {
+                (wgpu::BlendState::ALPHA_BLENDING, false)
+            }
+        };
+
+        Ok(device.create_render_pipeline(
+            &wgpu::RenderPipelineDescriptor {
+                label: Some(M::label()),
+                layout: Some(&self.pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &self.vertex_shader,
+                    entry_point: "vertex".into(),
+                    buffers: &[vertex_layout.as_wgpu()],
+                    compilation_options: Default::default(),
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &self.fragment_shader,
+                    entry_point: "fragment".into(),
+                    compilation_options: Default::default(),
+                    targets: &[Some(
+                        wgpu::ColorTargetState {
+                            format: self.format,
+                            blend: Some(blend),
+                            write_mask:
+                                wgpu::ColorWrites::ALL,
+                        },
+                    )],
+                }),
+                primitive: wgpu::PrimitiveState {
+                    topology: key.primitive_topology,
+                    cull_mode: Some(wgpu::Face::Back),
+                    ..Default::default()
+                },
+                depth_stencil: Some(
+                    wgpu::DepthStencilState {
+                        format: self.depth_format,
+                        depth_write_enabled,
+                        depth_compare:
+                            wgpu::CompareFunction::Less,
+                        stencil: Default::default(),
+                        bias: Default::default(),
+                    },
+                ),
+                multisample:
+                    wgpu::MultisampleState::default(),
+                multiview_mask: None,
+                cache: None,
+            },
+        ))
+    }
+}
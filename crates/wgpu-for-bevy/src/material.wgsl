@@ -0,0 +1,46 @@
+// The default material shader. Its vertex entry point is
+// used by every material that doesn't bring its own, so
+// the bindings and `VertexOutput` here are the interface
+// material fragment shaders have to match.
+
+struct View {
+    view_proj: mat4x4<f32>,
+    time: f32,
+}
+
+struct Mesh {
+    model: mat4x4<f32>,
+}
+
+@group(0) @binding(0) var<uniform> view: View;
+@group(1) @binding(0) var<uniform> mesh: Mesh;
+// materials put their own bindings in @group(2)
+
+struct Vertex {
+    @location(0) position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(2) uv: vec2<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) world_normal: vec3<f32>,
+    @location(1) uv: vec2<f32>,
+}
+
+@vertex
+fn vertex(vertex: Vertex) -> VertexOutput {
+    var out: VertexOutput;
+    out.position = view.view_proj * mesh.model * vec4<f32>(vertex.position, 1.0);
+    // fine for rotations and uniform scales
+    out.world_normal = (mesh.model * vec4<f32>(vertex.normal, 0.0)).xyz;
+    out.uv = vertex.uv;
+    return out;
+}
+
+@fragment
+fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
+    let light = normalize(vec3<f32>(0.4, 1.0, 0.6));
+    let diffuse = max(dot(normalize(in.world_normal), light), 0.0);
+    return vec4<f32>(vec3<f32>(0.2 + 0.8 * diffuse), 1.0);
+}
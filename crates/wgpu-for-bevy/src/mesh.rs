@@ -0,0 +1,811 @@
+//! CPU-side mesh data with named vertex attributes, and
+//! the GPU buffers it turns into. This is a small version
+//! of Bevy's
+//! [`Mesh`](https://docs.rs/bevy/latest/bevy/prelude/struct.Mesh.html).
+use std::{collections::BTreeMap, fmt};
+use wgpu::{
+    Buffer, BufferUsages, Device, IndexFormat,
+    PrimitiveTopology, RenderPass, VertexAttribute,
+    VertexFormat, VertexStepMode, util::DeviceExt,
+};
+
+/// Identifies a vertex attribute. Attributes are stored
+/// and interleaved in id order.
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+pub struct MeshVertexAttributeId(pub u64);
+
+/// A named attribute and the format its values are
+/// stored in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct MeshVertexAttribute {
+    pub name: &'static str,
+    pub id: MeshVertexAttributeId,
+    pub format: VertexFormat,
+}
+
+impl MeshVertexAttribute {
+    pub const fn new(
+        name: &'static str,
+        id: u64,
+        format: VertexFormat,
+    ) -> Self {
+        Self {
+            name,
+            id: MeshVertexAttributeId(id),
+            format,
+        }
+    }
+
+    /// Ask for this attribute at a particular `@location`
+    /// in a vertex shader.
+    pub const fn at_shader_location(
+        &self,
+        shader_location: u32,
+    ) -> VertexAttributeDescriptor {
+        VertexAttributeDescriptor {
+            shader_location,
+            id: self.id,
+            name: self.name,
+        }
+    }
+}
+
+/// An attribute a vertex shader expects, and the
+/// `@location` it expects it at.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct VertexAttributeDescriptor {
+    pub shader_location: u32,
+    pub id: MeshVertexAttributeId,
+    pub name: &'static str,
+}
+
+/// The values of one attribute, one entry per vertex.
+#[derive(Clone, Debug)]
+pub enum VertexAttributeValues {
+    Float32(Vec<f32>),
+    Float32x2(Vec<[f32; 2]>),
+    Float32x3(Vec<[f32; 3]>),
+    Float32x4(Vec<[f32; 4]>),
+    Uint32(Vec<u32>),
+}
+
+impl VertexAttributeValues {
+    pub fn len(&self) -> usize {
+        match self {
+            Self::Float32(values) => values.len(),
+            Self::Float32x2(values) => values.len(),
+            Self::Float32x3(values) => values.len(),
+            Self::Float32x4(values) => values.len(),
+            Self::Uint32(values) => values.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn format(&self) -> VertexFormat {
+        match self {
+            Self::Float32(_) => VertexFormat::Float32,
+            Self::Float32x2(_) => VertexFormat::Float32x2,
+            Self::Float32x3(_) => VertexFormat::Float32x3,
+            Self::Float32x4(_) => VertexFormat::Float32x4,
+            Self::Uint32(_) => VertexFormat::Uint32,
+        }
+    }
+
+    pub fn get_bytes(&self) -> &[u8] {
+        match self {
+            Self::Float32(values) => {
+                bytemuck::cast_slice(values)
+            }
+            Self::Float32x2(values) => {
+                bytemuck::cast_slice(values)
+            }
+            Self::Float32x3(values) => {
+                bytemuck::cast_slice(values)
+            }
+            Self::Float32x4(values) => {
+                bytemuck::cast_slice(values)
+            }
+            Self::Uint32(values) => {
+                bytemuck::cast_slice(values)
+            }
+        }
+    }
+}
+
+impl From<Vec<f32>> for VertexAttributeValues {
+    fn from(values: Vec<f32>) -> Self {
+        Self::Float32(values)
+    }
+}
+
+impl From<Vec<[f32; 2]>> for VertexAttributeValues {
+    fn from(values: Vec<[f32; 2]>) -> Self {
+        Self::Float32x2(values)
+    }
+}
+
+impl From<Vec<[f32; 3]>> for VertexAttributeValues {
+    fn from(values: Vec<[f32; 3]>) -> Self {
+        Self::Float32x3(values)
+    }
+}
+
+impl From<Vec<[f32; 4]>> for VertexAttributeValues {
+    fn from(values: Vec<[f32; 4]>) -> Self {
+        Self::Float32x4(values)
+    }
+}
+
+impl From<Vec<u32>> for VertexAttributeValues {
+    fn from(values: Vec<u32>) -> Self {
+        Self::Uint32(values)
+    }
+}
+
+/// Index data. `U16` indices take half the memory but
+/// can only address 65536 vertices.
+#[derive(Clone, Debug)]
+pub enum Indices {
+    U16(Vec<u16>),
+    U32(Vec<u32>),
+}
+
+impl Indices {
+    pub fn len(&self) -> usize {
+        match self {
+            Self::U16(indices) => indices.len(),
+            Self::U32(indices) => indices.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn format(&self) -> IndexFormat {
+        match self {
+            Self::U16(_) => IndexFormat::Uint16,
+            Self::U32(_) => IndexFormat::Uint32,
+        }
+    }
+
+    pub fn get_bytes(&self) -> &[u8] {
+        match self {
+            Self::U16(indices) => {
+                bytemuck::cast_slice(indices)
+            }
+            Self::U32(indices) => {
+                bytemuck::cast_slice(indices)
+            }
+        }
+    }
+}
+
+/// An owned version of [`wgpu::VertexBufferLayout`], which
+/// only borrows its attributes.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct OwnedVertexBufferLayout {
+    pub array_stride: u64,
+    pub step_mode: VertexStepMode,
+    pub attributes: Vec<VertexAttribute>,
+}
+
+impl OwnedVertexBufferLayout {
+    pub fn as_wgpu(&self) -> wgpu::VertexBufferLayout<'_> {
+        wgpu::VertexBufferLayout {
+            array_stride: self.array_stride,
+            step_mode: self.step_mode,
+            attributes: &self.attributes,
+        }
+    }
+}
+
+/// Which attributes a mesh has and how they're laid out
+/// in its interleaved vertex buffer.
+///
+/// Meshes with the same attributes have equal layouts, so
+/// this is what pipelines get specialized (and cached) on.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct MeshVertexBufferLayout {
+    attribute_ids: Vec<MeshVertexAttributeId>,
+    layout: OwnedVertexBufferLayout,
+}
+
+impl MeshVertexBufferLayout {
+    pub fn contains(
+        &self,
+        id: MeshVertexAttributeId,
+    ) -> bool {
+        self.attribute_ids.contains(&id)
+    }
+
+    pub fn attribute_ids(
+        &self,
+    ) -> &[MeshVertexAttributeId] {
+        &self.attribute_ids
+    }
+
+    /// The full layout, with each attribute at a
+    /// `@location` matching its position in id order.
+    pub fn layout(&self) -> &OwnedVertexBufferLayout {
+        &self.layout
+    }
+
+    /// Build a layout that places the requested attributes
+    /// at the shader locations a vertex shader expects.
+    ///
+    /// Attributes the mesh has but the shader doesn't ask
+    /// for are skipped by keeping the mesh's stride, so the
+    /// same vertex buffer works with any shader that needs
+    /// a subset of its attributes.
+    pub fn get_layout(
+        &self,
+        attribute_descriptors: &[VertexAttributeDescriptor],
+    ) -> Result<
+        OwnedVertexBufferLayout,
+        MissingVertexAttributeError,
+    > {
+        let attributes = attribute_descriptors
+            .iter()
+            .map(|descriptor| {
+                let index = self
+                    .attribute_ids
+                    .iter()
+                    .position(|id| *id == descriptor.id)
+                    .ok_or(MissingVertexAttributeError {
+                        id: descriptor.id,
+                        name: descriptor.name,
+                        shader_location: descriptor
+                            .shader_location,
+                    })?;
+                let attribute =
+                    self.layout.attributes[index];
+                Ok(VertexAttribute {
+                    shader_location: descriptor
+                        .shader_location,
+                    ..attribute
+                })
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(OwnedVertexBufferLayout {
+            array_stride: self.layout.array_stride,
+            step_mode: self.layout.step_mode,
+            attributes,
+        })
+    }
+}
+
+/// A shader wanted an attribute the mesh doesn't have.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MissingVertexAttributeError {
+    pub id: MeshVertexAttributeId,
+    pub name: &'static str,
+    pub shader_location: u32,
+}
+
+impl fmt::Display for MissingVertexAttributeError {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+    ) -> fmt::Result {
+        write!(
+            f,
+            "mesh is missing vertex attribute {} ({:?}) required at @location({})",
+            self.name, self.id, self.shader_location,
+        )
+    }
+}
+
+impl std::error::Error for MissingVertexAttributeError {}
+
+#[derive(Clone, Debug)]
+pub struct Mesh {
+    primitive_topology: PrimitiveTopology,
+    attributes: BTreeMap<
+        MeshVertexAttributeId,
+        (
+            MeshVertexAttribute,
+            VertexAttributeValues,
+        ),
+    >,
+    indices: Option<Indices>,
+}
+
+impl Mesh {
+    pub const ATTRIBUTE_POSITION: MeshVertexAttribute =
+        MeshVertexAttribute::new(
+            "Vertex_Position",
+            0,
+            VertexFormat::Float32x3,
+        );
+    pub const ATTRIBUTE_NORMAL: MeshVertexAttribute =
+        MeshVertexAttribute::new(
+            "Vertex_Normal",
+            1,
+            VertexFormat::Float32x3,
+        );
+    pub const ATTRIBUTE_UV_0: MeshVertexAttribute =
+        MeshVertexAttribute::new(
+            "Vertex_Uv",
+            2,
+            VertexFormat::Float32x2,
+        );
+    pub const ATTRIBUTE_COLOR: MeshVertexAttribute =
+        MeshVertexAttribute::new(
+            "Vertex_Color",
+            5,
+            VertexFormat::Float32x4,
+        );
+
+    pub fn new(
+        primitive_topology: PrimitiveTopology,
+    ) -> Self {
+        Self {
+            primitive_topology,
+            attributes: BTreeMap::new(),
+            indices: None,
+        }
+    }
+
+    pub fn primitive_topology(&self) -> PrimitiveTopology {
+        self.primitive_topology
+    }
+
+    /// Set the values of an attribute, replacing any
+    /// previous values.
+    ///
+    /// # Panics
+    ///
+    /// If the values aren't in the attribute's format.
+    pub fn insert_attribute(
+        &mut self,
+        attribute: MeshVertexAttribute,
+        values: impl Into<VertexAttributeValues>,
+    ) {
+        let values = values.into();
+        assert_eq!(
+            values.format(),
+            attribute.format,
+            "attribute {} expects {:?} values",
+            attribute.name,
+            attribute.format,
+        );
+        self.attributes
+            .insert(attribute.id, (attribute, values));
+    }
+
+    pub fn with_inserted_attribute(
+        mut self,
+        attribute: MeshVertexAttribute,
+        values: impl Into<VertexAttributeValues>,
+    ) -> Self {
+        self.insert_attribute(attribute, values);
+        self
+    }
+
+    pub fn attribute(
+        &self,
+        id: MeshVertexAttributeId,
+    ) -> Option<&VertexAttributeValues> {
+        self.attributes.get(&id).map(|(_, values)| values)
+    }
+
+    pub fn contains_attribute(
+        &self,
+        id: MeshVertexAttributeId,
+    ) -> bool {
+        self.attributes.contains_key(&id)
+    }
+
+    /// The attributes this mesh has, in id order.
+    pub fn attributes(
+        &self,
+    ) -> impl Iterator<Item = &MeshVertexAttribute> {
+        self.attributes
+            .values()
+            .map(|(attribute, _)| attribute)
+    }
+
+    pub fn insert_indices(&mut self, indices: Indices) {
+        self.indices = Some(indices);
+    }
+
+    pub fn with_inserted_indices(
+        mut self,
+        indices: Indices,
+    ) -> Self {
+        self.insert_indices(indices);
+        self
+    }
+
+    pub fn indices(&self) -> Option<&Indices> {
+        self.indices.as_ref()
+    }
+
+    /// # Panics
+    ///
+    /// If attributes have different numbers of values.
+    pub fn count_vertices(&self) -> usize {
+        let mut count = None;
+        for (attribute, values) in self.attributes.values()
+        {
+            let len = values.len();
+            if let Some(previous) = count {
+                assert_eq!(
+                    previous, len,
+                    "attribute {} has {len} values but other attributes have {previous}",
+                    attribute.name,
+                );
+            }
+            count = Some(len);
+        }
+        count.unwrap_or(0)
+    }
+
+    /// The number of bytes one interleaved vertex takes.
+    pub fn get_vertex_size(&self) -> u64 {
+        self.attributes()
+            .map(|attribute| attribute.format.size())
+            .sum()
+    }
+
+    /// The layout of the buffer created by
+    /// [`Mesh::create_packed_vertex_buffer_data`].
+    ///
+    /// Attributes are laid out in id order. Use
+    /// [`MeshVertexBufferLayout::get_layout`] to map them
+    /// to the locations a particular shader expects.
+    pub fn get_mesh_vertex_buffer_layout(
+        &self,
+    ) -> MeshVertexBufferLayout {
+        let mut offset = 0;
+        let mut attribute_ids = vec![];
+        let attributes = self
+            .attributes()
+            .enumerate()
+            .map(|(shader_location, attribute)| {
+                attribute_ids.push(attribute.id);
+                let vertex_attribute = VertexAttribute {
+                    format: attribute.format,
+                    offset,
+                    shader_location: shader_location as u32,
+                };
+                offset += attribute.format.size();
+                vertex_attribute
+            })
+            .collect();
+
+        MeshVertexBufferLayout {
+            attribute_ids,
+            layout: OwnedVertexBufferLayout {
+                array_stride: offset,
+                step_mode: VertexStepMode::Vertex,
+                attributes,
+            },
+        }
+    }
+
+    /// Interleave every attribute into a single buffer,
+    /// so each vertex's position, normal, and so on sit
+    /// next to each other in memory.
+    pub fn create_packed_vertex_buffer_data(
+        &self,
+    ) -> Vec<u8> {
+        let vertex_count = self.count_vertices();
+        let vertex_size = self.get_vertex_size() as usize;
+        let mut data = vec![0; vertex_count * vertex_size];
+
+        let mut offset = 0;
+        for (attribute, values) in self.attributes.values()
+        {
+            let size = attribute.format.size() as usize;
+            let bytes = values.get_bytes();
+            for (vertex, value) in
+                bytes.chunks_exact(size).enumerate()
+            {
+                let start = vertex * vertex_size + offset;
+                data[start..start + size]
+                    .copy_from_slice(value);
+            }
+            offset += size;
+        }
+
+        data
+    }
+
+    /// Create the GPU buffers for this mesh.
+    pub fn upload(
+        &self,
+        device: &Device,
+        label: &str,
+    ) -> GpuMesh {
+        let vertex_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some(&format!("{label}_vertices")),
+                contents: &self
+                    .create_packed_vertex_buffer_data(),
+                usage: BufferUsages::VERTEX,
+            },
+        );
+
+        let index_buffer =
+            self.indices.as_ref().map(|indices| {
+                GpuIndices {
+                    buffer: device.create_buffer_init(
+                        &wgpu::util::BufferInitDescriptor {
+                            label: Some(&format!(
+                                "{label}_indices"
+                            )),
+                            contents: indices.get_bytes(),
+                            usage: BufferUsages::INDEX,
+                        },
+                    ),
+                    format: indices.format(),
+                    count: indices.len() as u32,
+                }
+            });
+
+        GpuMesh {
+            vertex_buffer,
+            vertex_count: self.count_vertices() as u32,
+            index_buffer,
+            primitive_topology: self.primitive_topology,
+            layout: self.get_mesh_vertex_buffer_layout(),
+        }
+    }
+}
+
+pub struct GpuIndices {
+    pub buffer: Buffer,
+    pub format: IndexFormat,
+    pub count: u32,
+}
+
+/// The GPU side of a [`Mesh`].
+pub struct GpuMesh {
+    pub vertex_buffer: Buffer,
+    pub vertex_count: u32,
+    pub index_buffer: Option<GpuIndices>,
+    pub primitive_topology: PrimitiveTopology,
+    pub layout: MeshVertexBufferLayout,
+}
+
+impl GpuMesh {
+    /// Bind the buffers and issue the draw. The pipeline
+    /// and bind groups have to be set already.
+    ///
+    /// Meshes without vertices or indices draw nothing.
+    /// Their buffers are empty, and an empty buffer can't
+    /// be bound.
+    pub fn draw(&self, rpass: &mut RenderPass<'_>) {
+        if self.vertex_count == 0
+            || self
+                .index_buffer
+                .as_ref()
+                .is_some_and(|indices| indices.count == 0)
+        {
+            return;
+        }
+        rpass.set_vertex_buffer(
+            0,
+            self.vertex_buffer.slice(..),
+        );
+        match &self.index_buffer {
+            Some(indices) => {
+                rpass.set_index_buffer(
+                    indices.buffer.slice(..),
+                    indices.format,
+                );
+                rpass.draw_indexed(
+                    0..indices.count,
+                    0,
+                    0..1,
+                );
+            }
+            None => rpass.draw(0..self.vertex_count, 0..1),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_device::test_device;
+
+    fn triangle() -> Mesh {
+        Mesh::new(PrimitiveTopology::TriangleList)
+            // inserted out of id order on purpose
+            .with_inserted_attribute(
+                Mesh::ATTRIBUTE_UV_0,
+                vec![[0.0, 0.0], [1.0, 0.0], [0.0, 1.0]],
+            )
+            .with_inserted_attribute(
+                Mesh::ATTRIBUTE_POSITION,
+                vec![
+                    [0.0, 0.0, 0.0],
+                    [1.0, 0.0, 0.0],
+                    [0.0, 1.0, 0.0],
+                ],
+            )
+    }
+
+    #[test]
+    fn vertices_are_interleaved_in_id_order() {
+        let mesh = triangle();
+        assert_eq!(mesh.get_vertex_size(), 20);
+
+        let data = mesh.create_packed_vertex_buffer_data();
+        let floats: &[f32] = bytemuck::cast_slice(&data);
+        #[rustfmt::skip]
+        assert_eq!(
+            floats,
+            [
+                0.0, 0.0, 0.0, 0.0, 0.0,
+                1.0, 0.0, 0.0, 1.0, 0.0,
+                0.0, 1.0, 0.0, 0.0, 1.0,
+            ]
+        );
+    }
+
+    #[test]
+    fn empty_mesh_has_no_vertex_data() {
+        let mesh =
+            Mesh::new(PrimitiveTopology::TriangleList);
+        assert_eq!(mesh.count_vertices(), 0);
+        assert!(
+            mesh.create_packed_vertex_buffer_data()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "has 2 values")]
+    fn mismatched_attribute_lengths_panic() {
+        triangle()
+            .with_inserted_attribute(
+                Mesh::ATTRIBUTE_NORMAL,
+                vec![[0.0, 0.0, 1.0]; 2],
+            )
+            .count_vertices();
+    }
+
+    #[test]
+    fn vertex_buffer_layout_follows_id_order() {
+        let layout =
+            triangle().get_mesh_vertex_buffer_layout();
+        assert_eq!(
+            layout.attribute_ids(),
+            [
+                Mesh::ATTRIBUTE_POSITION.id,
+                Mesh::ATTRIBUTE_UV_0.id
+            ]
+        );
+        assert_eq!(
+            *layout.layout(),
+            OwnedVertexBufferLayout {
+                array_stride: 20,
+                step_mode: VertexStepMode::Vertex,
+                attributes: vec![
+                    VertexAttribute {
+                        format: VertexFormat::Float32x3,
+                        offset: 0,
+                        shader_location: 0,
+                    },
+                    VertexAttribute {
+                        format: VertexFormat::Float32x2,
+                        offset: 12,
+                        shader_location: 1,
+                    },
+                ],
+            }
+        );
+    }
+
+    #[test]
+    fn get_layout_moves_attributes_to_shader_locations() {
+        let layout =
+            triangle().get_mesh_vertex_buffer_layout();
+        let requested = layout
+            .get_layout(&[
+                Mesh::ATTRIBUTE_UV_0.at_shader_location(0)
+            ])
+            .unwrap();
+        // the stride still skips over the position
+        assert_eq!(
+            requested,
+            OwnedVertexBufferLayout {
+                array_stride: 20,
+                step_mode: VertexStepMode::Vertex,
+                attributes: vec![VertexAttribute {
+                    format: VertexFormat::Float32x2,
+                    offset: 12,
+                    shader_location: 0,
+                }],
+            }
+        );
+    }
+
+    #[test]
+    fn get_layout_reports_missing_attributes() {
+        let layout =
+            triangle().get_mesh_vertex_buffer_layout();
+        let error = layout
+            .get_layout(&[
+                Mesh::ATTRIBUTE_POSITION
+                    .at_shader_location(0),
+                Mesh::ATTRIBUTE_NORMAL
+                    .at_shader_location(1),
+            ])
+            .unwrap_err();
+        assert_eq!(
+            error,
+            MissingVertexAttributeError {
+                id: Mesh::ATTRIBUTE_NORMAL.id,
+                name: "Vertex_Normal",
+                shader_location: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn empty_mesh_draws_nothing() {
+        let Some((device, queue)) = test_device() else {
+            return;
+        };
+        let empty =
+            Mesh::new(PrimitiveTopology::TriangleList)
+                .upload(&device, "empty");
+        let no_indices = triangle()
+            .with_inserted_indices(Indices::U16(vec![]))
+            .upload(&device, "no_indices");
+
+        let texture =
+            device
+                .create_texture(&wgpu::TextureDescriptor {
+                label: None,
+                size: wgpu::Extent3d {
+                    width: 1,
+                    height: 1,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Rgba8Unorm,
+                usage:
+                    wgpu::TextureUsages::RENDER_ATTACHMENT,
+                view_formats: &[],
+            });
+        let view = texture.create_view(&Default::default());
+        let mut encoder = device
+            .create_command_encoder(&Default::default());
+        {
+            let mut rpass = encoder.begin_render_pass(
+                &wgpu::RenderPassDescriptor {
+                    label: None,
+                    color_attachments: &[Some(
+                        wgpu::RenderPassColorAttachment {
+                            view: &view,
+                            resolve_target: None,
+                            ops: Default::default(),
+                            depth_slice: None,
+                        },
+                    )],
+                    depth_stencil_attachment: None,
+                    timestamp_writes: None,
+                    occlusion_query_set: None,
+                    multiview_mask: None,
+                },
+            );
+            empty.draw(&mut rpass);
+            no_indices.draw(&mut rpass);
+        }
+        queue.submit(Some(encoder.finish()));
+    }
+}
@@ -0,0 +1,220 @@
+//! Filling in a texture's mip chain on the GPU.
+//!
+//! Bevy expects mips to be generated ahead of time (for
+//! example in KTX2 files), but images decoded from PNG or
+//! JPEG only have one level. This fills in the rest by
+//! rendering each level from the one above it.
+use std::{borrow::Cow, collections::HashMap};
+use wgpu::{
+    BindGroupLayout, CommandEncoder, Device, Extent3d,
+    RenderPipeline, Sampler, ShaderModule, Texture,
+    TextureFormat,
+};
+
+/// The number of levels in a full mip chain for a 2d
+/// texture of this size, down to 1x1.
+pub fn mip_level_count(size: Extent3d) -> u32 {
+    size.max_mips(wgpu::TextureDimension::D2)
+}
+
+/// Owns the shader, sampler, and one pipeline per texture
+/// format used to render mip levels.
+///
+/// sRGB formats work the same as linear ones: sampling an
+/// sRGB view decodes to linear values and rendering to one
+/// encodes them again, so levels are averaged in linear
+/// space either way.
+pub struct MipmapGenerator {
+    shader: ShaderModule,
+    layout: BindGroupLayout,
+    sampler: Sampler,
+    pipelines: HashMap<TextureFormat, RenderPipeline>,
+}
+
+impl MipmapGenerator {
+    pub fn new(device: &Device) -> Self {
+        let shader = device.create_shader_module(
+            wgpu::ShaderModuleDescriptor {
+                label: "mipmap_shader".into(),
+                source: wgpu::ShaderSource::Wgsl(
+                    Cow::Borrowed(include_str!(
+                        "mipmap.wgsl"
+                    )),
+                ),
+            },
+        );
+
+        let layout = device.create_bind_group_layout(
+            &wgpu::BindGroupLayoutDescriptor {
+                label: "mipmap_layout".into(),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility:
+                            wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type:
+                                wgpu::TextureSampleType::Float {
+                                    filterable: true,
+                                },
+                            view_dimension:
+                                wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility:
+                            wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler(
+                            wgpu::SamplerBindingType::Filtering,
+                        ),
+                        count: None,
+                    },
+                ],
+            },
+        );
+
+        let sampler = device.create_sampler(
+            &wgpu::SamplerDescriptor {
+                label: "mipmap_sampler".into(),
+                mag_filter: wgpu::FilterMode::Linear,
+                min_filter: wgpu::FilterMode::Linear,
+                ..Default::default()
+            },
+        );
+
+        Self {
+            shader,
+            layout,
+            sampler,
+            pipelines: HashMap::new(),
+        }
+    }
+
+    fn pipeline(
+        &mut self,
+        device: &Device,
+        format: TextureFormat,
+    ) -> &RenderPipeline {
+        self.pipelines.entry(format).or_insert_with(|| {
+            let layout = device.create_pipeline_layout(
+                &wgpu::PipelineLayoutDescriptor {
+                    label: "mipmap_pipeline_layout".into(),
+                    bind_group_layouts: &[&self.layout],
+                    immediate_size: 0,
+                },
+            );
+            device.create_render_pipeline(
+                &wgpu::RenderPipelineDescriptor {
+                    label: "mipmap_pipeline".into(),
+                    layout: Some(&layout),
+                    vertex: wgpu::VertexState {
+                        module: &self.shader,
+                        entry_point: "vertex".into(),
+                        buffers: &[],
+                        compilation_options:
+                            Default::default(),
+                    },
+                    fragment: Some(wgpu::FragmentState {
+                        module: &self.shader,
+                        entry_point: "fragment".into(),
+                        compilation_options:
+                            Default::default(),
+                        targets: &[Some(format.into())],
+                    }),
+                    primitive:
+                        wgpu::PrimitiveState::default(),
+                    depth_stencil: None,
+                    multisample:
+                        wgpu::MultisampleState::default(),
+                    multiview_mask: None,
+                    cache: None,
+                },
+            )
+        })
+    }
+
+    /// Record passes that render every mip level after
+    /// the first from the level above it.
+    ///
+    /// The texture needs `TEXTURE_BINDING` and
+    /// `RENDER_ATTACHMENT` usages, and its format has to be
+    /// renderable and filterable, which rules out
+    /// compressed formats.
+    pub fn generate(
+        &mut self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        texture: &Texture,
+    ) {
+        let format = texture.format();
+        let views = (0..texture.mip_level_count())
+            .map(|level| {
+                texture.create_view(
+                    &wgpu::TextureViewDescriptor {
+                        label: "mipmap_view".into(),
+                        base_mip_level: level,
+                        mip_level_count: Some(1),
+                        ..Default::default()
+                    },
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let pipeline =
+            self.pipeline(device, format).clone();
+        for pair in views.windows(2) {
+            let [source, target] = pair else {
+                unreachable!()
+            };
+            let bind_group = device.create_bind_group(
+                &wgpu::BindGroupDescriptor {
+                    label: "mipmap_bind_group".into(),
+                    layout: &self.layout,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: wgpu::BindingResource::TextureView(
+                                source,
+                            ),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: wgpu::BindingResource::Sampler(
+                                &self.sampler,
+                            ),
+                        },
+                    ],
+                },
+            );
+
+            let mut pass = encoder.begin_render_pass(
+                &wgpu::RenderPassDescriptor {
+                    label: "mipmap_pass".into(),
+                    color_attachments: &[Some(
+                        wgpu::RenderPassColorAttachment {
+                            view: target,
+                            resolve_target: None,
+                            ops: wgpu::Operations {
+                                load: wgpu::LoadOp::Clear(
+                                    wgpu::Color::TRANSPARENT,
+                                ),
+                                store: wgpu::StoreOp::Store,
+                            },
+                            depth_slice: None,
+                        },
+                    )],
+                    depth_stencil_attachment: None,
+                    timestamp_writes: None,
+                    occlusion_query_set: None,
+                    multiview_mask: None,
+                },
+            );
+            pass.set_pipeline(&pipeline);
+            pass.set_bind_group(0, Some(&bind_group), &[]);
+            pass.draw(0..3, 0..1);
+        }
+    }
+}
@@ -0,0 +1,554 @@
+//! Creating pipelines off the render thread, like Bevy's
+//! [`PipelineCache`](https://docs.rs/bevy/latest/bevy/render/render_resource/struct.PipelineCache.html).
+//!
+//! wgpu's descriptors borrow their shader modules and
+//! layouts, so this module has owned versions of them.
+//! Owned descriptors can be sent to another thread, and
+//! they're `Hash + Eq`, which is what deduplicates
+//! pipelines: queueing an equal descriptor twice returns
+//! the same [`CachedPipelineId`].
+use crate::mesh::OwnedVertexBufferLayout;
+use futures_lite::future::block_on;
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    sync::mpsc::{Receiver, Sender, channel},
+    thread,
+};
+use tracing::info_span;
+use wgpu::{
+    AdapterInfo, ColorTargetState, ComputePipeline,
+    DepthStencilState, Device, ErrorFilter, Features,
+    MultisampleState, PipelineLayout, PrimitiveState,
+    RenderPipeline, ShaderModule,
+};
+
+/// A shader module and the entry point to use from it
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ShaderStage {
+    pub module: ShaderModule,
+    pub entry_point: Cow<'static, str>,
+}
+
+/// An owned [`wgpu::VertexState`]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct VertexState {
+    pub module: ShaderModule,
+    pub entry_point: Cow<'static, str>,
+    pub buffers: Vec<OwnedVertexBufferLayout>,
+}
+
+/// An owned [`wgpu::FragmentState`]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct FragmentState {
+    pub module: ShaderModule,
+    pub entry_point: Cow<'static, str>,
+    pub targets: Vec<Option<ColorTargetState>>,
+}
+
+impl FragmentState {
+    pub fn as_wgpu(&self) -> wgpu::FragmentState<'_> {
+        wgpu::FragmentState {
+            module: &self.module,
+            entry_point: Some(&self.entry_point),
+            compilation_options: Default::default(),
+            targets: &self.targets,
+        }
+    }
+}
+
+/// An owned [`wgpu::RenderPipelineDescriptor`]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct RenderPipelineDescriptor {
+    pub label: Option<Cow<'static, str>>,
+    pub layout: Option<PipelineLayout>,
+    pub vertex: VertexState,
+    pub primitive: PrimitiveState,
+    pub depth_stencil: Option<DepthStencilState>,
+    pub multisample: MultisampleState,
+    pub fragment: Option<FragmentState>,
+}
+
+/// An owned [`wgpu::MeshPipelineDescriptor`]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct MeshPipelineDescriptor {
+    pub label: Option<Cow<'static, str>>,
+    pub layout: Option<PipelineLayout>,
+    pub task: Option<ShaderStage>,
+    pub mesh: ShaderStage,
+    pub primitive: PrimitiveState,
+    pub depth_stencil: Option<DepthStencilState>,
+    pub multisample: MultisampleState,
+    pub fragment: Option<FragmentState>,
+}
+
+/// An owned [`wgpu::ComputePipelineDescriptor`]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ComputePipelineDescriptor {
+    pub label: Option<Cow<'static, str>>,
+    pub layout: Option<PipelineLayout>,
+    pub compute: ShaderStage,
+}
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+enum PipelineDescriptor {
+    Render(RenderPipelineDescriptor),
+    Mesh(MeshPipelineDescriptor),
+    Compute(ComputePipelineDescriptor),
+}
+
+impl PipelineDescriptor {
+    fn label(&self) -> Option<&str> {
+        match self {
+            PipelineDescriptor::Render(d) => {
+                d.label.as_deref()
+            }
+            PipelineDescriptor::Mesh(d) => {
+                d.label.as_deref()
+            }
+            PipelineDescriptor::Compute(d) => {
+                d.label.as_deref()
+            }
+        }
+    }
+
+    fn create(
+        &self,
+        device: &Device,
+        cache: Option<&wgpu::PipelineCache>,
+    ) -> Pipeline {
+        match self {
+            PipelineDescriptor::Render(d) => {
+                let buffers: Vec<_> = d
+                    .vertex
+                    .buffers
+                    .iter()
+                    .map(OwnedVertexBufferLayout::as_wgpu)
+                    .collect();
+                Pipeline::Render(
+                    device.create_render_pipeline(
+                        &wgpu::RenderPipelineDescriptor {
+                            label: d.label.as_deref(),
+                            layout: d.layout.as_ref(),
+                            vertex: wgpu::VertexState {
+                                module: &d.vertex.module,
+                                entry_point: Some(
+                                    &d.vertex.entry_point,
+                                ),
+                                buffers: &buffers,
+                                compilation_options:
+                                    Default::default(),
+                            },
+                            primitive: d.primitive,
+                            depth_stencil: d
+                                .depth_stencil
+                                .clone(),
+                            multisample: d.multisample,
+                            fragment: d
+                                .fragment
+                                .as_ref()
+                                .map(
+                                    FragmentState::as_wgpu,
+                                ),
+                            multiview_mask: None,
+                            cache,
+                        },
+                    ),
+                )
+            }
+            PipelineDescriptor::Mesh(d) => {
+                Pipeline::Render(
+                    device.create_mesh_pipeline(
+                        &wgpu::MeshPipelineDescriptor {
+                            label: d.label.as_deref(),
+                            layout: d.layout.as_ref(),
+                            task: d.task.as_ref().map(
+                                |task| wgpu::TaskState {
+                                    module: &task.module,
+                                    entry_point: Some(
+                                        &task.entry_point,
+                                    ),
+                                    compilation_options:
+                                        Default::default(),
+                                },
+                            ),
+                            mesh: wgpu::MeshState {
+                                module: &d.mesh.module,
+                                entry_point: Some(
+                                    &d.mesh.entry_point,
+                                ),
+                                compilation_options:
+                                    Default::default(),
+                            },
+                            primitive: d.primitive,
+                            depth_stencil: d
+                                .depth_stencil
+                                .clone(),
+                            multisample: d.multisample,
+                            fragment: d
+                                .fragment
+                                .as_ref()
+                                .map(
+                                    FragmentState::as_wgpu,
+                                ),
+                            multiview: None,
+                            cache,
+                        },
+                    ),
+                )
+            }
+            PipelineDescriptor::Compute(d) => {
+                Pipeline::Compute(
+                    device.create_compute_pipeline(
+                        &wgpu::ComputePipelineDescriptor {
+                            label: d.label.as_deref(),
+                            layout: d.layout.as_ref(),
+                            module: &d.compute.module,
+                            entry_point: Some(
+                                &d.compute.entry_point,
+                            ),
+                            compilation_options:
+                                Default::default(),
+                            cache,
+                        },
+                    ),
+                )
+            }
+        }
+    }
+}
+
+/// Mesh pipelines are render pipelines too, they only
+/// differ in how they're created.
+#[derive(Clone, Debug)]
+pub enum Pipeline {
+    Render(RenderPipeline),
+    Compute(ComputePipeline),
+}
+
+/// Refers to a pipeline queued on a [`PipelineCache`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct CachedPipelineId(usize);
+
+#[derive(Debug)]
+pub enum CachedPipelineState {
+    /// Still being created on another thread
+    Pending,
+    Ready(Pipeline),
+    Error(PipelineCacheError),
+}
+
+/// The error wgpu reported while creating a pipeline
+#[derive(Debug)]
+pub struct PipelineCacheError {
+    pub label: Option<String>,
+    pub error: wgpu::Error,
+}
+
+impl fmt::Display for PipelineCacheError {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+    ) -> fmt::Result {
+        match &self.label {
+            Some(label) => write!(
+                f,
+                "failed to create pipeline `{label}`: {}",
+                self.error
+            ),
+            None => write!(
+                f,
+                "failed to create pipeline: {}",
+                self.error
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PipelineCacheError {}
+
+/// Creates each distinct pipeline once, on a background
+/// thread, so compiling shaders doesn't stall frames.
+///
+/// One compiler thread is started with the cache and
+/// creates queued pipelines in order, until the cache is
+/// dropped.
+///
+/// Queueing a pipeline returns an id right away. The
+/// pipeline shows up after a later
+/// [`PipelineCache::process_queue`], so callers skip
+/// drawing with it until then, the same way Bevy does.
+///
+/// With [`PipelineCache::with_disk_cache`], the driver's
+/// compiled pipelines are also kept in a
+/// [`wgpu::PipelineCache`] that [`PipelineCache::save`]
+/// writes to disk, making later runs start faster.
+pub struct PipelineCache {
+    cache: Option<wgpu::PipelineCache>,
+    path: Option<PathBuf>,
+    ids: HashMap<PipelineDescriptor, CachedPipelineId>,
+    states: Vec<CachedPipelineState>,
+    /// Pipelines for the compiler thread to create
+    queued: Sender<(CachedPipelineId, PipelineDescriptor)>,
+    /// Pipelines the compiler thread has created
+    finished:
+        Receiver<(CachedPipelineId, CachedPipelineState)>,
+}
+
+impl PipelineCache {
+    pub fn new(device: &Device) -> Self {
+        Self::with_cache(device, None, None)
+    }
+
+    fn with_cache(
+        device: &Device,
+        cache: Option<wgpu::PipelineCache>,
+        path: Option<PathBuf>,
+    ) -> Self {
+        let (queued, jobs) = channel();
+        let (results, finished) = channel();
+        let compiler_device = device.clone();
+        let compiler_cache = cache.clone();
+        thread::Builder::new()
+            .name("pipeline_compiler".to_string())
+            .spawn(move || {
+                compile_pipelines(
+                    &compiler_device,
+                    compiler_cache.as_ref(),
+                    jobs,
+                    results,
+                )
+            })
+            .expect("pipeline compiler thread to spawn");
+
+        Self {
+            cache,
+            path,
+            ids: HashMap::new(),
+            states: vec![],
+            queued,
+            finished,
+        }
+    }
+
+    /// Like [`PipelineCache::new`], also loading and saving
+    /// the driver's pipeline cache in `dir`.
+    ///
+    /// Only some backends (currently Vulkan) support this,
+    /// and only when the device was created with
+    /// [`Features::PIPELINE_CACHE`]. Otherwise this is the
+    /// same as [`PipelineCache::new`].
+    pub fn with_disk_cache(
+        device: &Device,
+        adapter_info: &AdapterInfo,
+        dir: impl AsRef<Path>,
+    ) -> Self {
+        if !device
+            .features()
+            .contains(Features::PIPELINE_CACHE)
+        {
+            return Self::new(device);
+        }
+        // the key names the backend, vendor and device,
+        // so data is never loaded on a different GPU
+        let Some(key) =
+            wgpu::util::pipeline_cache_key(adapter_info)
+        else {
+            return Self::new(device);
+        };
+        let path = dir.as_ref().join(key);
+        let data = fs::read(&path).ok();
+
+        // SAFETY: the data was written by `save`, for an
+        // adapter with the same cache key. `fallback`
+        // makes wgpu start with an empty cache if the
+        // driver rejects it anyway (such as after a driver
+        // update).
+        let cache = unsafe {
+            device.create_pipeline_cache(
+                &wgpu::PipelineCacheDescriptor {
+                    label: Some("pipeline_cache"),
+                    data: data.as_deref(),
+                    fallback: true,
+                },
+            )
+        };
+        Self::with_cache(device, Some(cache), Some(path))
+    }
+
+    pub fn queue_render_pipeline(
+        &mut self,
+        descriptor: RenderPipelineDescriptor,
+    ) -> CachedPipelineId {
+        self.queue(PipelineDescriptor::Render(descriptor))
+    }
+
+    /// Needs a device created with
+    /// [`Features::EXPERIMENTAL_MESH_SHADER`].
+    pub fn queue_mesh_pipeline(
+        &mut self,
+        descriptor: MeshPipelineDescriptor,
+    ) -> CachedPipelineId {
+        self.queue(PipelineDescriptor::Mesh(descriptor))
+    }
+
+    pub fn queue_compute_pipeline(
+        &mut self,
+        descriptor: ComputePipelineDescriptor,
+    ) -> CachedPipelineId {
+        self.queue(PipelineDescriptor::Compute(descriptor))
+    }
+
+    fn queue(
+        &mut self,
+        descriptor: PipelineDescriptor,
+    ) -> CachedPipelineId {
+        if let Some(id) = self.ids.get(&descriptor) {
+            return *id;
+        }
+        let id = CachedPipelineId(self.states.len());
+        self.states.push(CachedPipelineState::Pending);
+        self.ids.insert(descriptor.clone(), id);
+
+        // the compiler thread only stops once this
+        // sender is dropped, along with the cache
+        self.queued
+            .send((id, descriptor))
+            .expect("pipeline compiler to be running");
+        id
+    }
+
+    /// Picks up pipelines that finished since the last call.
+    /// Call once per frame, before looking pipelines up.
+    pub fn process_queue(&mut self) {
+        while let Ok((id, state)) = self.finished.try_recv()
+        {
+            self.states[id.0] = state;
+        }
+    }
+
+    /// Waits for the pipeline to be created. Useful at
+    /// startup, or for pipelines a frame can't be drawn
+    /// without.
+    pub fn block_on_pipeline(
+        &mut self,
+        id: CachedPipelineId,
+    ) -> &CachedPipelineState {
+        while matches!(
+            self.states[id.0],
+            CachedPipelineState::Pending
+        ) {
+            let (finished, state) = self
+                .finished
+                .recv()
+                .expect("pipeline compiler to finish");
+            self.states[finished.0] = state;
+        }
+        &self.states[id.0]
+    }
+
+    pub fn get_pipeline_state(
+        &self,
+        id: CachedPipelineId,
+    ) -> &CachedPipelineState {
+        &self.states[id.0]
+    }
+
+    /// The render (or mesh) pipeline, once it's ready
+    pub fn get_render_pipeline(
+        &self,
+        id: CachedPipelineId,
+    ) -> Option<&RenderPipeline> {
+        match &self.states[id.0] {
+            CachedPipelineState::Ready(
+                Pipeline::Render(pipeline),
+            ) => Some(pipeline),
+            _ => None,
+        }
+    }
+
+    pub fn get_compute_pipeline(
+        &self,
+        id: CachedPipelineId,
+    ) -> Option<&ComputePipeline> {
+        match &self.states[id.0] {
+            CachedPipelineState::Ready(
+                Pipeline::Compute(pipeline),
+            ) => Some(pipeline),
+            _ => None,
+        }
+    }
+
+    /// Writes the driver's pipeline cache to the file
+    /// [`PipelineCache::with_disk_cache`] read it from.
+    /// Does nothing if there's no disk cache.
+    pub fn save(&self) -> io::Result<()> {
+        let (Some(cache), Some(path)) =
+            (&self.cache, &self.path)
+        else {
+            return Ok(());
+        };
+        let Some(data) = cache.get_data() else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        // write next to the real file and rename, so a
+        // crash halfway through can't leave a truncated
+        // cache behind
+        let temp = path.with_extension("tmp");
+        fs::write(&temp, data)?;
+        fs::rename(&temp, path)
+    }
+}
+
+/// The compiler thread, which creates pipelines in the
+/// order they were queued until the [`PipelineCache`] is
+/// dropped
+fn compile_pipelines(
+    device: &Device,
+    cache: Option<&wgpu::PipelineCache>,
+    jobs: Receiver<(CachedPipelineId, PipelineDescriptor)>,
+    results: Sender<(
+        CachedPipelineId,
+        CachedPipelineState,
+    )>,
+) {
+    for (id, descriptor) in jobs {
+        let _span = info_span!(
+            "create_pipeline",
+            label = descriptor.label()
+        )
+        .entered();
+        // error scopes are per thread, and this thread
+        // creates one pipeline at a time, so these only
+        // catch errors from this pipeline
+        let internal =
+            device.push_error_scope(ErrorFilter::Internal);
+        let validation = device
+            .push_error_scope(ErrorFilter::Validation);
+        let pipeline = descriptor.create(device, cache);
+        let error = block_on(validation.pop())
+            .or(block_on(internal.pop()));
+
+        let state = match error {
+            None => CachedPipelineState::Ready(pipeline),
+            Some(error) => CachedPipelineState::Error(
+                PipelineCacheError {
+                    label: descriptor
+                        .label()
+                        .map(str::to_string),
+                    error,
+                },
+            ),
+        };
+        // the receiver is gone if the cache was dropped,
+        // and then nobody wants the results
+        if results.send((id, state)).is_err() {
+            return;
+        }
+    }
+}
@@ -0,0 +1,171 @@
+//! Creating variants of a pipeline for each mesh vertex
+//! layout, like Bevy's
+//! [`SpecializedMeshPipelines`](https://docs.rs/bevy/latest/bevy/render/render_resource/struct.SpecializedMeshPipelines.html),
+//! or for each key through a [`PipelineCache`].
+use crate::{
+    mesh::{
+        MeshVertexBufferLayout, MissingVertexAttributeError,
+    },
+    pipeline_cache::{
+        CachedPipelineId, PipelineCache,
+        RenderPipelineDescriptor,
+    },
+};
+use std::{collections::HashMap, fmt, hash::Hash};
+use wgpu::{Device, RenderPipeline};
+
+/// Something that can build a render pipeline for a
+/// given key and mesh vertex layout.
+///
+/// The key holds whatever else changes the pipeline (for
+/// example whether vertex colors are used), and is
+/// usually derived from the layout itself.
+pub trait SpecializedMeshPipeline {
+    type Key: Clone + Hash + Eq;
+
+    fn specialize(
+        &self,
+        device: &Device,
+        key: Self::Key,
+        layout: &MeshVertexBufferLayout,
+    ) -> Result<RenderPipeline, SpecializedMeshPipelineError>;
+}
+
+/// Caches one pipeline per key and mesh layout, so meshes
+/// that share attributes share a pipeline.
+pub struct SpecializedMeshPipelines<
+    S: SpecializedMeshPipeline,
+> {
+    cache: HashMap<
+        (S::Key, MeshVertexBufferLayout),
+        RenderPipeline,
+    >,
+}
+
+impl<S: SpecializedMeshPipeline> Default
+    for SpecializedMeshPipelines<S>
+{
+    fn default() -> Self {
+        Self {
+            cache: HashMap::new(),
+        }
+    }
+}
+
+impl<S: SpecializedMeshPipeline>
+    SpecializedMeshPipelines<S>
+{
+    /// Return the cached pipeline for this key and layout,
+    /// creating it first if needed. Errors are not cached.
+    pub fn specialize(
+        &mut self,
+        device: &Device,
+        pipeline: &S,
+        key: S::Key,
+        layout: &MeshVertexBufferLayout,
+    ) -> Result<RenderPipeline, SpecializedMeshPipelineError>
+    {
+        let cache_key = (key, layout.clone());
+        if let Some(pipeline) = self.cache.get(&cache_key) {
+            return Ok(pipeline.clone());
+        }
+
+        let render_pipeline = pipeline.specialize(
+            device,
+            cache_key.0.clone(),
+            layout,
+        )?;
+        self.cache
+            .insert(cache_key, render_pipeline.clone());
+        Ok(render_pipeline)
+    }
+
+    /// The number of pipelines created so far
+    pub fn len(&self) -> usize {
+        self.cache.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cache.is_empty()
+    }
+}
+
+/// Something that describes a render pipeline for a
+/// given key, to be created by a [`PipelineCache`].
+pub trait SpecializedRenderPipeline {
+    type Key: Clone + Hash + Eq;
+
+    fn specialize(
+        &self,
+        key: Self::Key,
+    ) -> RenderPipelineDescriptor;
+}
+
+/// Remembers the [`CachedPipelineId`] for each key, so
+/// descriptors are only built the first time a key is
+/// seen.
+pub struct SpecializedRenderPipelines<
+    S: SpecializedRenderPipeline,
+> {
+    cache: HashMap<S::Key, CachedPipelineId>,
+}
+
+impl<S: SpecializedRenderPipeline> Default
+    for SpecializedRenderPipelines<S>
+{
+    fn default() -> Self {
+        Self {
+            cache: HashMap::new(),
+        }
+    }
+}
+
+impl<S: SpecializedRenderPipeline>
+    SpecializedRenderPipelines<S>
+{
+    /// Queue the pipeline for `key` if it hasn't been
+    /// already. Different keys that produce equal
+    /// descriptors still share one pipeline.
+    pub fn specialize(
+        &mut self,
+        pipeline_cache: &mut PipelineCache,
+        pipeline: &S,
+        key: S::Key,
+    ) -> CachedPipelineId {
+        *self.cache.entry(key.clone()).or_insert_with(
+            || {
+                pipeline_cache.queue_render_pipeline(
+                    pipeline.specialize(key),
+                )
+            },
+        )
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum SpecializedMeshPipelineError {
+    MissingVertexAttribute(MissingVertexAttributeError),
+}
+
+impl From<MissingVertexAttributeError>
+    for SpecializedMeshPipelineError
+{
+    fn from(error: MissingVertexAttributeError) -> Self {
+        Self::MissingVertexAttribute(error)
+    }
+}
+
+impl fmt::Display for SpecializedMeshPipelineError {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+    ) -> fmt::Result {
+        match self {
+            Self::MissingVertexAttribute(error) => {
+                error.fmt(f)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SpecializedMeshPipelineError {}
@@ -0,0 +1,721 @@
+//! Copying buffers and textures back to the CPU, similar
+//! to Bevy's
+//! [`Readback`](https://docs.rs/bevy/latest/bevy/render/gpu_readback/enum.Readback.html).
+//!
+//! The GPU can't write into memory the CPU reads from
+//! directly, so the data is first copied into a staging
+//! buffer with `MAP_READ` usage, which is then mapped once
+//! the copy has finished executing.
+use encase::{
+    ShaderType, StorageBuffer, internal::CreateFrom,
+};
+use std::{
+    fmt,
+    future::poll_fn,
+    ops::Range,
+    sync::{
+        Arc, Mutex,
+        mpsc::{self, Receiver, TryRecvError},
+    },
+    task::{Poll, Waker},
+};
+use wgpu::{
+    Buffer, BufferAddress, BufferAsyncError,
+    BufferDescriptor, BufferUsages, CommandEncoder, Device,
+    Extent3d, MapMode, PollError, Queue, Texture,
+    TextureFormat,
+};
+
+/// A copy into a staging buffer that has been recorded,
+/// but not read yet.
+///
+/// The command encoder the copy was recorded into has to
+/// be submitted before the readback is read, otherwise
+/// mapping waits for a copy that never happens.
+pub struct Readback {
+    staging: Buffer,
+    /// Set for textures, whose rows are padded in the
+    /// staging buffer
+    rows: Option<PaddedRows>,
+}
+
+/// Texture copies have to start each row at a multiple of
+/// `COPY_BYTES_PER_ROW_ALIGNMENT` (256) bytes. Rows of
+/// most textures are shorter than that, so the staging
+/// buffer holds padding that's removed after mapping.
+#[derive(Debug, PartialEq)]
+struct PaddedRows {
+    unpadded_bytes_per_row: u32,
+    padded_bytes_per_row: u32,
+    rows_per_image: u32,
+    /// Rows in every layer
+    count: u32,
+}
+
+impl PaddedRows {
+    /// The rows of a texture of `format` that's `size`
+    /// big. Compressed formats are copied in whole blocks,
+    /// so "rows" are rows of blocks.
+    fn new(format: TextureFormat, size: Extent3d) -> Self {
+        let block_size = format
+            .block_copy_size(None)
+            .expect("texture format to have a copy size");
+        let (block_width, block_height) =
+            format.block_dimensions();
+        let physical = size.physical_size(format);
+        let unpadded_bytes_per_row =
+            physical.width / block_width * block_size;
+        let rows_per_image = physical.height / block_height;
+        Self {
+            unpadded_bytes_per_row,
+            padded_bytes_per_row: wgpu::util::align_to(
+                unpadded_bytes_per_row,
+                wgpu::COPY_BYTES_PER_ROW_ALIGNMENT,
+            ),
+            rows_per_image,
+            count: rows_per_image
+                * size.depth_or_array_layers,
+        }
+    }
+
+    /// Copy the rows out of `padded`, leaving the padding
+    /// behind
+    fn strip(&self, padded: &[u8]) -> Vec<u8> {
+        padded
+            .chunks(self.padded_bytes_per_row as usize)
+            .take(self.count as usize)
+            .flat_map(|row| {
+                &row[..self.unpadded_bytes_per_row as usize]
+            })
+            .copied()
+            .collect()
+    }
+}
+
+impl Readback {
+    /// Copy `range` of `buffer`, which needs `COPY_SRC`
+    /// usage. The range has to be a multiple of
+    /// `COPY_BUFFER_ALIGNMENT` (4) bytes.
+    pub fn copy_buffer(
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        buffer: &Buffer,
+        range: Range<BufferAddress>,
+    ) -> Self {
+        let size = range.end - range.start;
+        let staging =
+            create_staging_buffer(device, "buffer", size);
+        encoder.copy_buffer_to_buffer(
+            buffer,
+            range.start,
+            &staging,
+            0,
+            size,
+        );
+        Self {
+            staging,
+            rows: None,
+        }
+    }
+
+    /// Copy every layer of one mip level of `texture`,
+    /// which needs `COPY_SRC` usage.
+    ///
+    /// # Panics
+    ///
+    /// If the format has no single copy size, such as the
+    /// combined depth stencil formats.
+    pub fn copy_texture(
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        texture: &Texture,
+        mip_level: u32,
+    ) -> Self {
+        let format = texture.format();
+        let size = texture
+            .size()
+            .mip_level_size(mip_level, texture.dimension());
+        let rows = PaddedRows::new(format, size);
+
+        let staging = create_staging_buffer(
+            device,
+            "texture",
+            rows.padded_bytes_per_row as u64
+                * rows.count as u64,
+        );
+        encoder.copy_texture_to_buffer(
+            wgpu::TexelCopyTextureInfo {
+                texture,
+                mip_level,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::TexelCopyBufferInfo {
+                buffer: &staging,
+                layout: wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(
+                        rows.padded_bytes_per_row,
+                    ),
+                    rows_per_image: Some(
+                        rows.rows_per_image,
+                    ),
+                },
+            },
+            size.physical_size(format),
+        );
+        Self {
+            staging,
+            rows: Some(rows),
+        }
+    }
+
+    /// [`Readback::copy_buffer`] in its own command
+    /// buffer, submitted right away.
+    pub fn buffer(
+        device: &Device,
+        queue: &Queue,
+        buffer: &Buffer,
+        range: Range<BufferAddress>,
+    ) -> Self {
+        submit(device, queue, |encoder| {
+            Self::copy_buffer(
+                device, encoder, buffer, range,
+            )
+        })
+    }
+
+    /// [`Readback::copy_texture`] in its own command
+    /// buffer, submitted right away.
+    pub fn texture(
+        device: &Device,
+        queue: &Queue,
+        texture: &Texture,
+        mip_level: u32,
+    ) -> Self {
+        submit(device, queue, |encoder| {
+            Self::copy_texture(
+                device, encoder, texture, mip_level,
+            )
+        })
+    }
+
+    /// Wait for the copy to finish and read the data,
+    /// blocking the thread.
+    ///
+    /// This waits on the device itself instead of
+    /// spinning, so it's the one to use outside of async
+    /// code (tests, tools, or a one-off readback at
+    /// startup).
+    pub fn read_blocking(
+        self,
+        device: &Device,
+    ) -> Result<ReadbackData, ReadbackError> {
+        let pending = self.map_async();
+        device.poll(wgpu::PollType::wait_indefinitely())?;
+        // waiting runs the callbacks of everything it
+        // waited for, so the result has been sent
+        pending
+            .try_read(device)
+            .expect("map callback to run after waiting")
+    }
+
+    /// Wait for the copy to finish and read the data,
+    /// without blocking the thread.
+    ///
+    /// The future is woken by wgpu's map callback, which
+    /// only runs while the device is being polled. Every
+    /// `queue.submit` does that, so a render loop that
+    /// keeps submitting frames is enough. Otherwise
+    /// something else has to poll the device (or use
+    /// [`Readback::read_blocking`]), or this never
+    /// finishes.
+    pub async fn read(
+        self,
+        device: &Device,
+    ) -> Result<ReadbackData, ReadbackError> {
+        let pending = self.map_async();
+        poll_fn(|cx| {
+            // stored before checking, so a callback that
+            // runs in between still wakes this task
+            *lock(&pending.waker) =
+                Some(cx.waker().clone());
+            match pending.try_read(device) {
+                Some(result) => Poll::Ready(result),
+                None => Poll::Pending,
+            }
+        })
+        .await
+    }
+
+    /// Start mapping the staging buffer, to be checked on
+    /// later with [`PendingReadback::try_read`]. This is
+    /// for render loops, which can check once per frame
+    /// instead of waiting.
+    pub fn map_async(self) -> PendingReadback {
+        let (sender, receiver) = mpsc::channel();
+        let waker = Arc::new(Mutex::new(None::<Waker>));
+        let callback_waker = waker.clone();
+        self.staging.map_async(
+            MapMode::Read,
+            ..,
+            move |result| {
+                // the receiver is gone if the readback was
+                // dropped, in which case nobody wants the
+                // data anyway
+                let _ = sender.send(result);
+                if let Some(waker) =
+                    lock(&callback_waker).take()
+                {
+                    waker.wake();
+                }
+            },
+        );
+        PendingReadback {
+            readback: self,
+            mapped: receiver,
+            waker,
+        }
+    }
+}
+
+/// A [`Readback`] whose staging buffer is being mapped
+pub struct PendingReadback {
+    readback: Readback,
+    mapped: Receiver<Result<(), BufferAsyncError>>,
+    /// The task waiting in [`Readback::read`], if any
+    waker: Arc<Mutex<Option<Waker>>>,
+}
+
+impl PendingReadback {
+    /// Poll the device once, and return the data if the
+    /// copy has finished and the buffer is mapped. This
+    /// never blocks.
+    ///
+    /// wgpu only runs map callbacks while the device is
+    /// being polled, which is why this needs the device.
+    ///
+    /// The data is only returned once. Later calls return
+    /// `None`.
+    pub fn try_read(
+        &self,
+        device: &Device,
+    ) -> Option<Result<ReadbackData, ReadbackError>> {
+        if let Err(error) =
+            device.poll(wgpu::PollType::Poll)
+        {
+            return Some(Err(error.into()));
+        }
+        match self.mapped.try_recv() {
+            Ok(Ok(())) => Some(Ok(self.data())),
+            Ok(Err(error)) => Some(Err(error.into())),
+            // the callback sends exactly once, so a
+            // disconnected channel means the result was
+            // already received and returned
+            Err(
+                TryRecvError::Empty
+                | TryRecvError::Disconnected,
+            ) => None,
+        }
+    }
+
+    /// Copy the mapped bytes out, leaving the row padding
+    /// behind.
+    fn data(&self) -> ReadbackData {
+        let Readback { staging, rows } = &self.readback;
+        let bytes = {
+            let mapped = staging.get_mapped_range(..);
+            match rows {
+                None => mapped.to_vec(),
+                Some(rows) => rows.strip(&mapped),
+            }
+        };
+        staging.unmap();
+        ReadbackData { bytes }
+    }
+}
+
+/// The waker is only ever replaced or taken, so one left
+/// behind by a panic is still fine to use
+fn lock(
+    waker: &Mutex<Option<Waker>>,
+) -> std::sync::MutexGuard<'_, Option<Waker>> {
+    waker
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn create_staging_buffer(
+    device: &Device,
+    source: &str,
+    size: BufferAddress,
+) -> Buffer {
+    device.create_buffer(&BufferDescriptor {
+        label: Some(&format!("{source}_readback_staging")),
+        size,
+        usage: BufferUsages::MAP_READ
+            | BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+fn submit(
+    device: &Device,
+    queue: &Queue,
+    copy: impl FnOnce(&mut CommandEncoder) -> Readback,
+) -> Readback {
+    let mut encoder = device.create_command_encoder(
+        &wgpu::CommandEncoderDescriptor {
+            label: "readback_command_encoder".into(),
+        },
+    );
+    let readback = copy(&mut encoder);
+    queue.submit(Some(encoder.finish()));
+    readback
+}
+
+/// Bytes read back from the GPU. Texture rows are tightly
+/// packed, with no padding between them.
+pub struct ReadbackData {
+    bytes: Vec<u8>,
+}
+
+impl ReadbackData {
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+
+    /// Reinterpret the bytes as plain data, such as
+    /// `[u8; 4]` pixels or `u32` counters.
+    ///
+    /// # Panics
+    ///
+    /// If the length isn't a multiple of `T`'s size.
+    pub fn cast<T: bytemuck::Pod>(&self) -> Vec<T> {
+        // `pod_collect_to_vec` would zero-pad a partial
+        // last element, which hides reading back the
+        // wrong type or range
+        assert!(
+            self.bytes.len().is_multiple_of(size_of::<T>()),
+            "read back {} bytes, which isn't a whole number of {}",
+            self.bytes.len(),
+            std::any::type_name::<T>(),
+        );
+        bytemuck::pod_collect_to_vec(&self.bytes)
+    }
+
+    /// Decode a `ShaderType` value laid out the way WGSL
+    /// lays out storage buffers, which is what a
+    /// [`Storage`](crate::Storage) holds.
+    pub fn create<T: ShaderType + CreateFrom>(
+        &self,
+    ) -> Result<T, ReadbackError> {
+        Ok(
+            StorageBuffer::new(self.bytes.as_slice())
+                .create()?,
+        )
+    }
+}
+
+#[derive(Debug)]
+pub enum ReadbackError {
+    Poll(PollError),
+    Map(BufferAsyncError),
+    Decode(encase::internal::Error),
+}
+
+impl From<PollError> for ReadbackError {
+    fn from(error: PollError) -> Self {
+        Self::Poll(error)
+    }
+}
+
+impl From<BufferAsyncError> for ReadbackError {
+    fn from(error: BufferAsyncError) -> Self {
+        Self::Map(error)
+    }
+}
+
+impl From<encase::internal::Error> for ReadbackError {
+    fn from(error: encase::internal::Error) -> Self {
+        Self::Decode(error)
+    }
+}
+
+impl fmt::Display for ReadbackError {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+    ) -> fmt::Result {
+        match self {
+            Self::Poll(error) => {
+                write!(
+                    f,
+                    "polling the device failed: {error}"
+                )
+            }
+            Self::Map(error) => write!(
+                f,
+                "mapping the staging buffer failed: {error}"
+            ),
+            Self::Decode(error) => write!(
+                f,
+                "read back data doesn't match its type: {error}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ReadbackError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_device::test_device;
+    use std::{
+        sync::atomic::{AtomicBool, Ordering},
+        thread,
+        time::Duration,
+    };
+
+    fn size(
+        width: u32,
+        height: u32,
+        depth_or_array_layers: u32,
+    ) -> Extent3d {
+        Extent3d {
+            width,
+            height,
+            depth_or_array_layers,
+        }
+    }
+
+    #[test]
+    fn rows_are_padded_to_256_bytes() {
+        assert_eq!(
+            PaddedRows::new(
+                TextureFormat::Rgba8Unorm,
+                size(5, 3, 1)
+            ),
+            PaddedRows {
+                unpadded_bytes_per_row: 20,
+                padded_bytes_per_row: 256,
+                rows_per_image: 3,
+                count: 3,
+            }
+        );
+    }
+
+    #[test]
+    fn aligned_rows_have_no_padding() {
+        let rows = PaddedRows::new(
+            TextureFormat::Rgba8Unorm,
+            size(64, 1, 1),
+        );
+        assert_eq!(rows.unpadded_bytes_per_row, 256);
+        assert_eq!(rows.padded_bytes_per_row, 256);
+    }
+
+    #[test]
+    fn compressed_rows_are_rows_of_blocks() {
+        // 10x10 rounds up to 3x3 blocks of 8 bytes
+        let rows = PaddedRows::new(
+            TextureFormat::Bc1RgbaUnorm,
+            size(10, 10, 1),
+        );
+        assert_eq!(rows.unpadded_bytes_per_row, 24);
+        assert_eq!(rows.rows_per_image, 3);
+        assert_eq!(rows.count, 3);
+    }
+
+    #[test]
+    fn every_layer_has_rows() {
+        let rows = PaddedRows::new(
+            TextureFormat::R8Unorm,
+            size(4, 3, 2),
+        );
+        assert_eq!(rows.rows_per_image, 3);
+        assert_eq!(rows.count, 6);
+    }
+
+    #[test]
+    fn strip_removes_padding() {
+        let rows = PaddedRows {
+            unpadded_bytes_per_row: 3,
+            padded_bytes_per_row: 256,
+            rows_per_image: 2,
+            count: 2,
+        };
+        let mut padded = vec![0xff; 512];
+        padded[..3].copy_from_slice(&[1, 2, 3]);
+        padded[256..259].copy_from_slice(&[4, 5, 6]);
+        assert_eq!(rows.strip(&padded), [1, 2, 3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn strip_ignores_a_short_last_row() {
+        // the staging buffer only has to reach the end of
+        // the last row's data
+        let rows = PaddedRows {
+            unpadded_bytes_per_row: 2,
+            padded_bytes_per_row: 256,
+            rows_per_image: 2,
+            count: 2,
+        };
+        let mut padded = vec![0xff; 258];
+        padded[..2].copy_from_slice(&[1, 2]);
+        padded[256..].copy_from_slice(&[3, 4]);
+        assert_eq!(rows.strip(&padded), [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn cast_reinterprets_bytes() {
+        let data = ReadbackData {
+            bytes: [1u32, 2, 3]
+                .iter()
+                .flat_map(|value| value.to_ne_bytes())
+                .collect(),
+        };
+        assert_eq!(data.cast::<u32>(), [1, 2, 3]);
+        assert_eq!(data.cast::<[u8; 4]>().len(), 3);
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "isn't a whole number of u32"
+    )]
+    fn cast_rejects_partial_elements() {
+        let data = ReadbackData { bytes: vec![0; 6] };
+        data.cast::<u32>();
+    }
+
+    /// A 5x3 texture, whose 20 byte rows are padded in the
+    /// staging buffer
+    fn texture_with_pixels(
+        device: &Device,
+        queue: &Queue,
+    ) -> (Texture, Vec<u8>) {
+        let size = size(5, 3, 1);
+        let texture = device.create_texture(
+            &wgpu::TextureDescriptor {
+                label: None,
+                size,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: TextureFormat::Rgba8Unorm,
+                usage: wgpu::TextureUsages::COPY_SRC
+                    | wgpu::TextureUsages::COPY_DST,
+                view_formats: &[],
+            },
+        );
+        let pixels: Vec<u8> = (0..60).collect();
+        queue.write_texture(
+            texture.as_image_copy(),
+            &pixels,
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(20),
+                rows_per_image: None,
+            },
+            size,
+        );
+        (texture, pixels)
+    }
+
+    #[test]
+    fn texture_round_trip() {
+        let Some((device, queue)) = test_device() else {
+            return;
+        };
+        let (texture, pixels) =
+            texture_with_pixels(&device, &queue);
+        let data =
+            Readback::texture(&device, &queue, &texture, 0)
+                .read_blocking(&device)
+                .unwrap();
+        assert_eq!(data.bytes(), pixels);
+    }
+
+    #[test]
+    fn try_read_returns_data_once() {
+        let Some((device, queue)) = test_device() else {
+            return;
+        };
+        let (texture, _) =
+            texture_with_pixels(&device, &queue);
+        let pending =
+            Readback::texture(&device, &queue, &texture, 0)
+                .map_async();
+        device
+            .poll(wgpu::PollType::wait_indefinitely())
+            .unwrap();
+        assert!(matches!(
+            pending.try_read(&device),
+            Some(Ok(_))
+        ));
+        assert!(pending.try_read(&device).is_none());
+    }
+
+    #[test]
+    fn map_callback_wakes_the_waiting_task() {
+        struct Flag(AtomicBool);
+        impl std::task::Wake for Flag {
+            fn wake(self: Arc<Self>) {
+                self.0.store(true, Ordering::Relaxed);
+            }
+        }
+
+        let Some((device, queue)) = test_device() else {
+            return;
+        };
+        let (texture, _) =
+            texture_with_pixels(&device, &queue);
+        let pending =
+            Readback::texture(&device, &queue, &texture, 0)
+                .map_async();
+        let woken = Arc::new(Flag(AtomicBool::new(false)));
+        *lock(&pending.waker) = Some(woken.clone().into());
+
+        device
+            .poll(wgpu::PollType::wait_indefinitely())
+            .unwrap();
+        assert!(woken.0.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn read_finishes_while_the_device_is_polled() {
+        let Some((device, queue)) = test_device() else {
+            return;
+        };
+        let (texture, pixels) =
+            texture_with_pixels(&device, &queue);
+        let readback =
+            Readback::texture(&device, &queue, &texture, 0);
+
+        // stands in for a render loop that keeps the
+        // device polled
+        let done = Arc::new(AtomicBool::new(false));
+        let poller = thread::spawn({
+            let device = device.clone();
+            let done = done.clone();
+            move || {
+                while !done.load(Ordering::Relaxed) {
+                    device.poll(wgpu::PollType::Poll).ok();
+                    thread::sleep(Duration::from_millis(1));
+                }
+            }
+        });
+        let data = futures_lite::future::block_on(
+            readback.read(&device),
+        );
+        done.store(true, Ordering::Relaxed);
+        poller.join().unwrap();
+
+        assert_eq!(data.unwrap().bytes(), pixels);
+    }
+}
@@ -0,0 +1,541 @@
+//! Recording every frame a window shows, for turning
+//! into animations or video.
+//!
+//! Frames are read back the same way screenshots are,
+//! but the app is expected to advance its clock by a
+//! fixed step per recorded frame instead of by wall-clock
+//! time (see
+//! [`TimeUpdateStrategy::FixedStep`](crate::TimeUpdateStrategy::FixedStep)).
+//! How long a frame takes to render (or to read back and
+//! encode) then doesn't change what ends up in the
+//! recording.
+use crate::{
+    readback::{PendingReadback, Readback, ReadbackError},
+    screenshot::{
+        ScreenshotError, copy_frame, save_png,
+        surface_usage, to_srgba8,
+    },
+};
+use std::{
+    collections::VecDeque,
+    fmt,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::mpsc::{self, SyncSender},
+    thread::JoinHandle,
+};
+use wgpu::{
+    CommandEncoder, Device, SurfaceCapabilities, Texture,
+    TextureFormat, TextureUsages, TextureView,
+};
+
+/// Frames that can be in flight between being copied and
+/// being written. Past this, [`FrameRecorder::update`]
+/// waits on the GPU and the writer, which keeps memory use
+/// bounded when rendering outpaces encoding.
+const MAX_FRAMES_IN_FLIGHT: usize = 4;
+
+pub enum RecordingOutput {
+    /// `frame-00000.png`, `frame-00001.png`, ... in a
+    /// directory
+    PngSequence(PathBuf),
+    /// A raw YUV 4:4:4 stream with a header describing
+    /// the size and frame rate, which encoders such as
+    /// `ffmpeg -i recording.y4m` read directly
+    Y4m(PathBuf),
+}
+
+impl RecordingOutput {
+    /// A `.y4m` file, or a directory of PNGs for any other
+    /// path.
+    pub fn from_path(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        if path.extension().is_some_and(|ext| ext == "y4m")
+        {
+            Self::Y4m(path)
+        } else {
+            Self::PngSequence(path)
+        }
+    }
+}
+
+/// Copies every frame it's given and writes them, in
+/// order, on a writer thread.
+pub struct FrameRecorder {
+    frame: u64,
+    copy_from_surface: bool,
+    /// Copies recorded this frame, waiting for the
+    /// frame's commands to be submitted
+    captured: Vec<Frame<Readback>>,
+    mapping: VecDeque<Frame<PendingReadback>>,
+    sender: SyncSender<Frame<Vec<u8>>>,
+    /// Taken once the writer has stopped
+    writer: Option<JoinHandle<Result<(), RecordingError>>>,
+}
+
+struct Frame<T> {
+    data: T,
+    index: u64,
+    format: TextureFormat,
+    width: u32,
+    height: u32,
+}
+
+impl FrameRecorder {
+    /// Create the output directory or file and start the
+    /// writer thread.
+    pub fn new(
+        output: RecordingOutput,
+        fps: u32,
+        capabilities: &SurfaceCapabilities,
+    ) -> Result<Self, RecordingError> {
+        let mut writer = match output {
+            RecordingOutput::PngSequence(dir) => {
+                std::fs::create_dir_all(&dir)?;
+                Writer::Png(dir)
+            }
+            RecordingOutput::Y4m(path) => Writer::Y4m {
+                file: BufWriter::new(File::create(path)?),
+                size: None,
+            },
+        };
+        let (sender, receiver) =
+            mpsc::sync_channel::<Frame<Vec<u8>>>(
+                MAX_FRAMES_IN_FLIGHT,
+            );
+        let writer = std::thread::spawn(move || {
+            for frame in receiver {
+                writer.write(frame, fps)?;
+            }
+            writer.finish()
+        });
+
+        Ok(Self {
+            frame: 0,
+            copy_from_surface: capabilities
+                .usages
+                .contains(TextureUsages::COPY_SRC),
+            captured: vec![],
+            mapping: VecDeque::new(),
+            sender,
+            writer: Some(writer),
+        })
+    }
+
+    /// The usages to configure the surface with, which
+    /// include `COPY_SRC` when the surface supports it.
+    pub fn surface_usage(&self) -> TextureUsages {
+        surface_usage(self.copy_from_surface)
+    }
+
+    /// The number of frames recorded so far, which is
+    /// also the index of the next one.
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    /// Record a copy of the frame. Call this after
+    /// everything has been rendered, with the encoder
+    /// that's submitted before the frame is presented.
+    ///
+    /// `render` draws the frame into a view. It's only
+    /// called when the surface can't be copied from, to
+    /// render the frame again into a texture that can.
+    pub fn capture(
+        &mut self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        surface_texture: &Texture,
+        render: impl FnOnce(&mut CommandEncoder, &TextureView),
+    ) {
+        let readback = copy_frame(
+            self.copy_from_surface,
+            device,
+            encoder,
+            surface_texture,
+            render,
+        );
+        self.captured.push(Frame {
+            data: readback,
+            index: self.frame,
+            format: surface_texture.format(),
+            width: surface_texture.width(),
+            height: surface_texture.height(),
+        });
+        self.frame += 1;
+    }
+
+    /// Start mapping this frame's copy and hand finished
+    /// frames to the writer. Call this once per frame,
+    /// after submitting.
+    ///
+    /// This only blocks when too many frames are in
+    /// flight. An error means the writer stopped, and
+    /// nothing more will be recorded.
+    pub fn update(
+        &mut self,
+        device: &Device,
+    ) -> Result<(), RecordingError> {
+        // staging buffers can't be mapped until the copy
+        // into them has been submitted
+        self.mapping.extend(self.captured.drain(..).map(
+            |frame| Frame {
+                data: frame.data.map_async(),
+                index: frame.index,
+                format: frame.format,
+                width: frame.width,
+                height: frame.height,
+            },
+        ));
+
+        while self.mapping.len() > MAX_FRAMES_IN_FLIGHT {
+            device
+                .poll(wgpu::PollType::wait_indefinitely())
+                .map_err(ReadbackError::from)?;
+            self.send_mapped(device)?;
+        }
+        self.send_mapped(device)
+    }
+
+    /// Send every frame at the front of the queue that's
+    /// been mapped. Copies finish in the order they were
+    /// submitted, so this keeps frames in order.
+    fn send_mapped(
+        &mut self,
+        device: &Device,
+    ) -> Result<(), RecordingError> {
+        while let Some(front) = self.mapping.front() {
+            let Some(result) = front.data.try_read(device)
+            else {
+                break;
+            };
+            let frame = self
+                .mapping
+                .pop_front()
+                .expect("the front frame to exist");
+            let frame = Frame {
+                data: result?.into_bytes(),
+                index: frame.index,
+                format: frame.format,
+                width: frame.width,
+                height: frame.height,
+            };
+            if self.sender.send(frame).is_err() {
+                // the writer only hangs up when it fails
+                return Err(self.writer_error());
+            }
+        }
+        Ok(())
+    }
+
+    fn writer_error(&mut self) -> RecordingError {
+        match self.writer.take().map(JoinHandle::join) {
+            Some(Ok(Err(error))) => error,
+            _ => RecordingError::WriterStopped,
+        }
+    }
+
+    /// Wait for every recorded frame to be written.
+    /// Returns the number of frames recorded.
+    pub fn finish(
+        mut self,
+        device: &Device,
+    ) -> Result<u64, RecordingError> {
+        // frames captured after the last `update` were
+        // never submitted with a copy, so they're dropped
+        self.captured.clear();
+        while !self.mapping.is_empty() {
+            device
+                .poll(wgpu::PollType::wait_indefinitely())
+                .map_err(ReadbackError::from)?;
+            self.send_mapped(device)?;
+        }
+
+        // the writer returns once every frame it was sent
+        // is written and the channel is closed
+        let Self {
+            frame,
+            sender,
+            writer,
+            ..
+        } = self;
+        drop(sender);
+        writer
+            .ok_or(RecordingError::WriterStopped)?
+            .join()
+            .map_err(|_| RecordingError::WriterStopped)??;
+        Ok(frame)
+    }
+}
+
+enum Writer {
+    Png(PathBuf),
+    Y4m {
+        file: BufWriter<File>,
+        /// Set by the first frame. Y4M streams have one
+        /// size for every frame.
+        size: Option<(u32, u32)>,
+    },
+}
+
+impl Writer {
+    fn write(
+        &mut self,
+        frame: Frame<Vec<u8>>,
+        fps: u32,
+    ) -> Result<(), RecordingError> {
+        let pixels = to_srgba8(frame.format, frame.data)?;
+        match self {
+            Self::Png(dir) => save_png(
+                &frame_path(dir, frame.index),
+                &pixels,
+                frame.width,
+                frame.height,
+            )?,
+            Self::Y4m { file, size } => write_y4m_frame(
+                file,
+                size,
+                (frame.width, frame.height),
+                fps,
+                &pixels,
+            )?,
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Result<(), RecordingError> {
+        if let Self::Y4m { mut file, .. } = self {
+            file.flush()?;
+        }
+        Ok(())
+    }
+}
+
+fn frame_path(dir: &Path, index: u64) -> PathBuf {
+    dir.join(format!("frame-{index:05}.png"))
+}
+
+/// Write one frame of a Y4M stream, starting the stream
+/// with its header if `size` hasn't been set yet.
+fn write_y4m_frame(
+    file: &mut impl Write,
+    size: &mut Option<(u32, u32)>,
+    frame_size: (u32, u32),
+    fps: u32,
+    pixels: &[u8],
+) -> Result<(), RecordingError> {
+    match size {
+        None => {
+            // full range BT.601, the same as JPEG, so no
+            // values are clipped
+            writeln!(
+                file,
+                "YUV4MPEG2 W{} H{} F{fps}:1 Ip A1:1 C444 XCOLORRANGE=FULL",
+                frame_size.0, frame_size.1
+            )?;
+            *size = Some(frame_size);
+        }
+        Some(size) if *size != frame_size => {
+            return Err(RecordingError::SizeChanged {
+                expected: *size,
+                found: frame_size,
+            });
+        }
+        Some(_) => {}
+    }
+    writeln!(file, "FRAME")?;
+    write_yuv444(file, pixels)?;
+    Ok(())
+}
+
+/// Write the Y, U, and V planes of RGBA pixels, one after
+/// the other.
+fn write_yuv444(
+    file: &mut impl Write,
+    pixels: &[u8],
+) -> io::Result<()> {
+    let rgb = || {
+        pixels.chunks_exact(4).map(|pixel| {
+            (
+                pixel[0] as f32,
+                pixel[1] as f32,
+                pixel[2] as f32,
+            )
+        })
+    };
+    let planes: [&dyn Fn((f32, f32, f32)) -> f32; 3] = [
+        &|(r, g, b)| 0.299 * r + 0.587 * g + 0.114 * b,
+        &|(r, g, b)| {
+            -0.168_736 * r - 0.331_264 * g + 0.5 * b + 128.0
+        },
+        &|(r, g, b)| {
+            0.5 * r - 0.418_688 * g - 0.081_312 * b + 128.0
+        },
+    ];
+    for plane in planes {
+        let bytes: Vec<u8> = rgb()
+            .map(|texel| {
+                plane(texel).round().clamp(0.0, 255.0) as u8
+            })
+            .collect();
+        file.write_all(&bytes)?;
+    }
+    Ok(())
+}
+
+#[derive(Debug)]
+pub enum RecordingError {
+    Io(io::Error),
+    Readback(ReadbackError),
+    Screenshot(ScreenshotError),
+    /// The window was resized while recording to a Y4M
+    /// stream
+    SizeChanged {
+        expected: (u32, u32),
+        found: (u32, u32),
+    },
+    /// The writer thread panicked, or already reported
+    /// why it stopped
+    WriterStopped,
+}
+
+impl From<io::Error> for RecordingError {
+    fn from(error: io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+impl From<ReadbackError> for RecordingError {
+    fn from(error: ReadbackError) -> Self {
+        Self::Readback(error)
+    }
+}
+
+impl From<ScreenshotError> for RecordingError {
+    fn from(error: ScreenshotError) -> Self {
+        Self::Screenshot(error)
+    }
+}
+
+impl fmt::Display for RecordingError {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+    ) -> fmt::Result {
+        match self {
+            Self::Io(error) => error.fmt(f),
+            Self::Readback(error) => error.fmt(f),
+            Self::Screenshot(error) => error.fmt(f),
+            Self::SizeChanged {
+                expected: (width, height),
+                found: (new_width, new_height),
+            } => write!(
+                f,
+                "the window changed size from {width}x{height} to {new_width}x{new_height}, which a Y4M stream can't hold"
+            ),
+            Self::WriterStopped => {
+                write!(f, "the frame writer stopped")
+            }
+        }
+    }
+}
+
+impl std::error::Error for RecordingError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WHITE: [u8; 4] = [255, 255, 255, 255];
+    const BLACK: [u8; 4] = [0, 0, 0, 255];
+    const RED: [u8; 4] = [255, 0, 0, 255];
+
+    fn yuv444(pixels: &[u8]) -> Vec<u8> {
+        let mut out = vec![];
+        write_yuv444(&mut out, pixels).unwrap();
+        out
+    }
+
+    #[test]
+    fn white_is_full_luma_and_neutral_chroma() {
+        assert_eq!(yuv444(&WHITE), [255, 128, 128]);
+    }
+
+    #[test]
+    fn black_is_zero_luma_and_neutral_chroma() {
+        assert_eq!(yuv444(&BLACK), [0, 128, 128]);
+    }
+
+    #[test]
+    fn red_is_bt601_full_range() {
+        // Y = 0.299 * 255, U = 128 - 0.168736 * 255, and
+        // V = 128 + 0.5 * 255, which is clamped
+        assert_eq!(yuv444(&RED), [76, 85, 255]);
+    }
+
+    #[test]
+    fn planes_are_written_one_after_the_other() {
+        let pixels = [WHITE, BLACK, RED].concat();
+        assert_eq!(
+            yuv444(&pixels),
+            [255, 0, 76, 128, 128, 85, 128, 128, 255]
+        );
+    }
+
+    #[test]
+    fn first_frame_writes_the_header() {
+        let mut out = vec![];
+        let mut size = None;
+        write_y4m_frame(
+            &mut out,
+            &mut size,
+            (1, 1),
+            60,
+            &RED,
+        )
+        .unwrap();
+
+        let mut expected = b"YUV4MPEG2 W1 H1 F60:1 Ip A1:1 C444 XCOLORRANGE=FULL\nFRAME\n".to_vec();
+        expected.extend([76, 85, 255]);
+        assert_eq!(out, expected);
+        assert_eq!(size, Some((1, 1)));
+    }
+
+    #[test]
+    fn later_frames_only_write_the_frame() {
+        let mut out = vec![];
+        let mut size = Some((1, 1));
+        write_y4m_frame(
+            &mut out,
+            &mut size,
+            (1, 1),
+            60,
+            &WHITE,
+        )
+        .unwrap();
+        assert_eq!(out, b"FRAME\n\xff\x80\x80");
+    }
+
+    #[test]
+    fn frame_size_mismatch_is_an_error() {
+        let mut out = vec![];
+        let mut size = Some((2, 1));
+        let result = write_y4m_frame(
+            &mut out,
+            &mut size,
+            (1, 1),
+            60,
+            &WHITE,
+        );
+        assert!(matches!(
+            result,
+            Err(RecordingError::SizeChanged {
+                expected: (2, 1),
+                found: (1, 1),
+            })
+        ));
+        assert!(out.is_empty());
+        assert_eq!(size, Some((2, 1)));
+    }
+}
@@ -0,0 +1,542 @@
+//! Saving what a window shows to a PNG, similar to
+//! Bevy's
+//! [`Screenshot`](https://docs.rs/bevy/latest/bevy/render/view/window/screenshot/struct.Screenshot.html).
+//!
+//! A screenshot is a [`Readback`] of the frame's surface
+//! texture. The copy is recorded into the frame's command
+//! encoder, the staging buffer is checked once per frame
+//! until it's mapped, and the PNG is encoded and written
+//! on another thread, so taking a screenshot never waits
+//! on the GPU or the disk.
+use crate::readback::{PendingReadback, Readback};
+use std::{
+    fmt,
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tracing::{error, info};
+use wgpu::{
+    CommandEncoder, Device, SurfaceCapabilities, Texture,
+    TextureFormat, TextureUsages, TextureView,
+};
+
+/// Takes screenshots of one surface.
+pub struct ScreenshotManager {
+    /// Whether surface textures can be copied from
+    /// directly. Otherwise frames being captured are
+    /// rendered a second time, into an intermediate
+    /// texture that can.
+    copy_from_surface: bool,
+    requested: Vec<PathBuf>,
+    /// Copies recorded this frame, waiting for the
+    /// frame's commands to be submitted
+    captured: Vec<Capture<Readback>>,
+    mapping: Vec<Capture<PendingReadback>>,
+}
+
+struct Capture<R> {
+    readback: R,
+    paths: Vec<PathBuf>,
+    format: TextureFormat,
+    width: u32,
+    height: u32,
+}
+
+impl ScreenshotManager {
+    pub fn new(capabilities: &SurfaceCapabilities) -> Self {
+        Self {
+            copy_from_surface: capabilities
+                .usages
+                .contains(TextureUsages::COPY_SRC),
+            requested: vec![],
+            captured: vec![],
+            mapping: vec![],
+        }
+    }
+
+    /// The usages to configure the surface with, which
+    /// include `COPY_SRC` when the surface supports it.
+    pub fn surface_usage(&self) -> TextureUsages {
+        surface_usage(self.copy_from_surface)
+    }
+
+    /// Capture the next frame into
+    /// `screenshot-<date>_<time>.png` in the working
+    /// directory.
+    pub fn save_screenshot(&mut self) {
+        self.save_screenshot_to_disk(format!(
+            "screenshot-{}.png",
+            timestamp(SystemTime::now())
+        ));
+    }
+
+    /// Capture the next frame into a PNG at `path`.
+    pub fn save_screenshot_to_disk(
+        &mut self,
+        path: impl Into<PathBuf>,
+    ) {
+        self.requested.push(path.into());
+    }
+
+    /// Record a copy of the frame if a screenshot was
+    /// requested. Call this after everything has been
+    /// rendered, with the encoder that's submitted before
+    /// the frame is presented.
+    ///
+    /// `render` draws the frame into a view. It's only
+    /// called when the surface can't be copied from, to
+    /// render the frame again into a texture that can.
+    pub fn capture(
+        &mut self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        surface_texture: &Texture,
+        render: impl FnOnce(&mut CommandEncoder, &TextureView),
+    ) {
+        if self.requested.is_empty() {
+            return;
+        }
+
+        let readback = copy_frame(
+            self.copy_from_surface,
+            device,
+            encoder,
+            surface_texture,
+            render,
+        );
+
+        self.captured.push(Capture {
+            readback,
+            // one copy serves every screenshot requested
+            // for the same frame
+            paths: std::mem::take(&mut self.requested),
+            format: surface_texture.format(),
+            width: surface_texture.width(),
+            height: surface_texture.height(),
+        });
+    }
+
+    /// Start mapping this frame's copies and save any
+    /// screenshots that finished. Call this once per
+    /// frame, after submitting.
+    pub fn update(&mut self, device: &Device) {
+        // staging buffers can't be mapped until the copy
+        // into them has been submitted
+        self.mapping.extend(self.captured.drain(..).map(
+            |capture| Capture {
+                readback: capture.readback.map_async(),
+                paths: capture.paths,
+                format: capture.format,
+                width: capture.width,
+                height: capture.height,
+            },
+        ));
+
+        let mut index = 0;
+        while index < self.mapping.len() {
+            let Some(result) = self.mapping[index]
+                .readback
+                .try_read(device)
+            else {
+                index += 1;
+                continue;
+            };
+            let Capture {
+                paths,
+                format,
+                width,
+                height,
+                ..
+            } = self.mapping.swap_remove(index);
+            let data = match result {
+                Ok(data) => data,
+                Err(error) => {
+                    error!(
+                        %error,
+                        "failed to read back screenshot"
+                    );
+                    continue;
+                }
+            };
+            // encoding a large PNG takes long enough to
+            // drop frames
+            std::thread::spawn(move || {
+                let pixels = match to_srgba8(
+                    format,
+                    data.into_bytes(),
+                ) {
+                    Ok(pixels) => pixels,
+                    Err(error) => {
+                        error!(
+                            %error,
+                            "failed to convert screenshot"
+                        );
+                        return;
+                    }
+                };
+                for path in paths {
+                    match save_png(
+                        &path, &pixels, width, height,
+                    ) {
+                        Ok(()) => info!(
+                            path = %path.display(),
+                            "saved screenshot"
+                        ),
+                        Err(error) => error!(
+                            path = %path.display(),
+                            %error,
+                            "failed to save screenshot"
+                        ),
+                    }
+                }
+            });
+        }
+    }
+}
+
+pub(crate) fn surface_usage(
+    copy_from_surface: bool,
+) -> TextureUsages {
+    if copy_from_surface {
+        TextureUsages::RENDER_ATTACHMENT
+            | TextureUsages::COPY_SRC
+    } else {
+        TextureUsages::RENDER_ATTACHMENT
+    }
+}
+
+/// Record a copy of a frame, either straight from the
+/// surface texture or by rendering the frame again into
+/// a texture that can be copied from.
+pub(crate) fn copy_frame(
+    copy_from_surface: bool,
+    device: &Device,
+    encoder: &mut CommandEncoder,
+    surface_texture: &Texture,
+    render: impl FnOnce(&mut CommandEncoder, &TextureView),
+) -> Readback {
+    if copy_from_surface {
+        return Readback::copy_texture(
+            device,
+            encoder,
+            surface_texture,
+            0,
+        );
+    }
+
+    let texture =
+        device.create_texture(&wgpu::TextureDescriptor {
+            label: "frame_copy_texture".into(),
+            size: surface_texture.size(),
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: surface_texture.format(),
+            usage: TextureUsages::RENDER_ATTACHMENT
+                | TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+    render(
+        encoder,
+        &texture.create_view(&Default::default()),
+    );
+    Readback::copy_texture(device, encoder, &texture, 0)
+}
+
+#[derive(Debug)]
+pub enum ScreenshotError {
+    UnsupportedFormat(TextureFormat),
+    Image(image::ImageError),
+}
+
+impl From<image::ImageError> for ScreenshotError {
+    fn from(error: image::ImageError) -> Self {
+        Self::Image(error)
+    }
+}
+
+impl fmt::Display for ScreenshotError {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+    ) -> fmt::Result {
+        match self {
+            Self::UnsupportedFormat(format) => write!(
+                f,
+                "screenshots of {format:?} surfaces are not supported"
+            ),
+            Self::Image(error) => error.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for ScreenshotError {}
+
+pub(crate) fn save_png(
+    path: &std::path::Path,
+    pixels: &[u8],
+    width: u32,
+    height: u32,
+) -> Result<(), ScreenshotError> {
+    image::save_buffer(
+        path,
+        pixels,
+        width,
+        height,
+        image::ColorType::Rgba8,
+    )?;
+    Ok(())
+}
+
+/// Convert read back texels of a surface format to
+/// 8-bit RGBA for a PNG.
+///
+/// PNGs are sRGB, which is also what the display shows.
+///
+/// 8-bit surfaces hold the bytes the display shows
+/// whether or not their format is sRGB: an sRGB format
+/// only means the GPU encoded shader output before
+/// storing it. So those are only reordered. Float
+/// surfaces hold linear values, which are encoded here.
+pub fn to_srgba8(
+    format: TextureFormat,
+    mut bytes: Vec<u8>,
+) -> Result<Vec<u8>, ScreenshotError> {
+    match format {
+        TextureFormat::Rgba8Unorm
+        | TextureFormat::Rgba8UnormSrgb => {}
+        TextureFormat::Bgra8Unorm
+        | TextureFormat::Bgra8UnormSrgb => {
+            for pixel in bytes.chunks_exact_mut(4) {
+                pixel.swap(0, 2);
+            }
+        }
+        TextureFormat::Rgba16Float => {
+            bytes = bytes
+                .chunks_exact(2)
+                .enumerate()
+                .map(|(index, channel)| {
+                    let value =
+                        f16_to_f32(u16::from_le_bytes([
+                            channel[0], channel[1],
+                        ]))
+                        .clamp(0.0, 1.0);
+                    let value = if index % 4 == 3 {
+                        value
+                    } else {
+                        linear_to_srgb(value)
+                    };
+                    (value * 255.0).round() as u8
+                })
+                .collect();
+        }
+        format => {
+            return Err(
+                ScreenshotError::UnsupportedFormat(format),
+            );
+        }
+    }
+
+    // the window is opaque, whatever alpha the frame was
+    // rendered with
+    for pixel in bytes.chunks_exact_mut(4) {
+        pixel[3] = u8::MAX;
+    }
+    Ok(bytes)
+}
+
+fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.003_130_8 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Enough of IEEE half precision for colors: subnormals
+/// are kept, infinities and NaN become infinity (and are
+/// clamped to 1 by the caller).
+fn f16_to_f32(bits: u16) -> f32 {
+    let sign = if bits >> 15 == 1 { -1.0 } else { 1.0 };
+    let exponent = ((bits >> 10) & 0x1f) as i32;
+    let mantissa = (bits & 0x3ff) as f32;
+    sign * match exponent {
+        0 => mantissa * 2f32.powi(-24),
+        0x1f => f32::INFINITY,
+        _ => {
+            (1.0 + mantissa / 1024.0)
+                * 2f32.powi(exponent - 15)
+        }
+    }
+}
+
+/// `YYYY-MM-DD_HH-MM-SS.mmm` in UTC, which sorts in the
+/// order screenshots were taken.
+fn timestamp(time: SystemTime) -> String {
+    let since_epoch =
+        time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let seconds = since_epoch.as_secs();
+    let (year, month, day) =
+        civil_from_days((seconds / 86_400) as i64);
+    let seconds_of_day = seconds % 86_400;
+    format!(
+        "{year:04}-{month:02}-{day:02}_{:02}-{:02}-{:02}.{:03}",
+        seconds_of_day / 3600,
+        seconds_of_day / 60 % 60,
+        seconds_of_day % 60,
+        since_epoch.subsec_millis(),
+    )
+}
+
+/// Days since 1970-01-01 to a (year, month, day) date,
+/// from Howard Hinnant's `chrono`-compatible date
+/// algorithms.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460
+        + day_of_era / 36_524
+        - day_of_era / 146_096)
+        / 365;
+    let day_of_year = day_of_era
+        - (365 * year_of_era + year_of_era / 4
+            - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_index + 2) / 5
+        + 1) as u32;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    } as u32;
+    let year = year_of_era
+        + era * 400
+        + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    const DAY: u64 = 86_400;
+
+    #[test]
+    fn f16_zero() {
+        assert_eq!(f16_to_f32(0x0000), 0.0);
+        let negative = f16_to_f32(0x8000);
+        assert_eq!(negative, 0.0);
+        assert!(negative.is_sign_negative());
+    }
+
+    #[test]
+    fn f16_normal() {
+        assert_eq!(f16_to_f32(0x3c00), 1.0);
+        assert_eq!(f16_to_f32(0x3800), 0.5);
+        assert_eq!(f16_to_f32(0xc000), -2.0);
+        // the largest finite half
+        assert_eq!(f16_to_f32(0x7bff), 65_504.0);
+    }
+
+    #[test]
+    fn f16_subnormal() {
+        assert_eq!(f16_to_f32(0x0001), 2f32.powi(-24));
+        assert_eq!(
+            f16_to_f32(0x03ff),
+            1023.0 * 2f32.powi(-24)
+        );
+    }
+
+    #[test]
+    fn f16_inf_and_nan() {
+        assert_eq!(f16_to_f32(0x7c00), f32::INFINITY);
+        assert_eq!(f16_to_f32(0xfc00), f32::NEG_INFINITY);
+        assert_eq!(f16_to_f32(0x7e00), f32::INFINITY);
+    }
+
+    #[test]
+    fn civil_from_days_epoch() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(-1), (1969, 12, 31));
+    }
+
+    #[test]
+    fn civil_from_days_leap_day() {
+        assert_eq!(civil_from_days(11_016), (2000, 2, 29));
+        assert_eq!(civil_from_days(11_017), (2000, 3, 1));
+    }
+
+    #[test]
+    fn civil_from_days_century_without_leap_day() {
+        // 2100 isn't a leap year, so February ends on
+        // the 28th
+        assert_eq!(civil_from_days(47_540), (2100, 2, 28));
+        assert_eq!(civil_from_days(47_541), (2100, 3, 1));
+    }
+
+    #[test]
+    fn timestamp_format() {
+        let time = UNIX_EPOCH
+            + Duration::from_secs(
+                11_016 * DAY + 3600 + 2 * 60 + 3,
+            )
+            + Duration::from_millis(45);
+        assert_eq!(
+            timestamp(time),
+            "2000-02-29_01-02-03.045"
+        );
+    }
+
+    #[test]
+    fn bgra8_is_reordered() {
+        for format in [
+            TextureFormat::Bgra8Unorm,
+            TextureFormat::Bgra8UnormSrgb,
+        ] {
+            let pixel =
+                to_srgba8(format, vec![10, 20, 30, 40])
+                    .unwrap();
+            assert_eq!(pixel, [30, 20, 10, 255]);
+        }
+    }
+
+    #[test]
+    fn rgba16_float_is_encoded() {
+        // linear (1.0, 0.5, 0.0, 0.5)
+        let bytes = [0x3c00u16, 0x3800, 0x0000, 0x3800]
+            .iter()
+            .flat_map(|channel| channel.to_le_bytes())
+            .collect();
+        let pixel =
+            to_srgba8(TextureFormat::Rgba16Float, bytes)
+                .unwrap();
+        // 0.5 encodes to 0.7354 in sRGB, and alpha is
+        // always opaque
+        assert_eq!(pixel, [255, 188, 0, 255]);
+    }
+
+    #[test]
+    fn rgba16_float_out_of_range_is_clamped() {
+        // (2.0, -1.0, inf, NaN)
+        let bytes = [0x4000u16, 0xbc00, 0x7c00, 0x7e00]
+            .iter()
+            .flat_map(|channel| channel.to_le_bytes())
+            .collect();
+        let pixel =
+            to_srgba8(TextureFormat::Rgba16Float, bytes)
+                .unwrap();
+        assert_eq!(pixel, [255, 0, 255, 255]);
+    }
+
+    #[test]
+    fn unsupported_format() {
+        assert!(matches!(
+            to_srgba8(TextureFormat::R8Unorm, vec![0; 4]),
+            Err(ScreenshotError::UnsupportedFormat(
+                TextureFormat::R8Unorm
+            ))
+        ));
+    }
+}
@@ -0,0 +1,189 @@
+//! A `ShaderType` value stored in a storage buffer,
+//! similar to Bevy's
+//! [`StorageBuffer<T>`](https://docs.rs/bevy/latest/bevy/render/render_resource/struct.StorageBuffer.html).
+use encase::{
+    ShaderType, StorageBuffer, internal::WriteInto,
+};
+use wgpu::{
+    BindGroupEntry, BindGroupLayoutEntry, BindingResource,
+    BindingType, Buffer, BufferBindingType,
+    BufferDescriptor, BufferUsages, Device, Queue,
+    ShaderStages,
+};
+
+/// Owns a value, the storage buffer it lives in, and a
+/// reusable staging `Vec`.
+///
+/// Unlike uniforms, storage buffers can end in a
+/// runtime-sized array, so `T` is often a `Vec<_>` or a
+/// struct whose last field is a `Vec<_>` marked
+/// `#[size(runtime)]`. When the value grows past the
+/// buffer's size a bigger buffer is created, which is why
+/// [`Storage::upload`] reports whether bind groups need to
+/// be recreated.
+pub struct Storage<T> {
+    value: T,
+    scratch: StorageBuffer<Vec<u8>>,
+    buffer: Buffer,
+    label: String,
+    usage: BufferUsages,
+    changed: bool,
+}
+
+impl<T: ShaderType + WriteInto> Storage<T> {
+    pub fn new(
+        device: &Device,
+        label: &str,
+        value: T,
+    ) -> Self {
+        Self::with_usage(
+            device,
+            label,
+            value,
+            BufferUsages::empty(),
+        )
+    }
+
+    /// Like [`Storage::new`], with extra usages such as
+    /// `VERTEX` or `COPY_SRC` for buffers that are also
+    /// used outside of bind groups.
+    pub fn with_usage(
+        device: &Device,
+        label: &str,
+        value: T,
+        usage: BufferUsages,
+    ) -> Self {
+        let usage = usage
+            | BufferUsages::STORAGE
+            | BufferUsages::COPY_DST;
+        let buffer = Self::create_buffer(
+            device,
+            label,
+            usage,
+            value.size().get(),
+        );
+
+        Self {
+            value,
+            scratch: StorageBuffer::new(Vec::new()),
+            buffer,
+            label: label.to_string(),
+            usage,
+            changed: true,
+        }
+    }
+
+    fn create_buffer(
+        device: &Device,
+        label: &str,
+        usage: BufferUsages,
+        size: u64,
+    ) -> Buffer {
+        device.create_buffer(&BufferDescriptor {
+            label: Some(label),
+            size,
+            usage,
+            mapped_at_creation: false,
+        })
+    }
+
+    /// The layout entry for a storage buffer of this type.
+    pub fn layout_entry(
+        binding: u32,
+        visibility: ShaderStages,
+        read_only: bool,
+    ) -> BindGroupLayoutEntry {
+        BindGroupLayoutEntry {
+            binding,
+            visibility,
+            ty: BindingType::Buffer {
+                ty: BufferBindingType::Storage {
+                    read_only,
+                },
+                has_dynamic_offset: false,
+                min_binding_size: Some(T::min_size()),
+            },
+            count: None,
+        }
+    }
+
+    pub fn get(&self) -> &T {
+        &self.value
+    }
+
+    /// Mutable access to the value. This always marks
+    /// the value as changed.
+    pub fn get_mut(&mut self) -> &mut T {
+        self.changed = true;
+        &mut self.value
+    }
+
+    pub fn set(&mut self, value: T) {
+        self.value = value;
+        self.changed = true;
+    }
+
+    /// Write the value to the GPU if it changed since the
+    /// last upload.
+    ///
+    /// Returns `true` when the buffer had to grow, in
+    /// which case any bind group using the old buffer must
+    /// be recreated.
+    pub fn upload(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+    ) -> bool {
+        if !self.changed {
+            return false;
+        }
+        self.changed = false;
+
+        let size = self.value.size().get();
+        let reallocated = size > self.buffer.size();
+        if reallocated {
+            // grow geometrically so slowly growing data
+            // doesn't reallocate every frame
+            self.buffer = Self::create_buffer(
+                device,
+                &self.label,
+                self.usage,
+                size.max(self.buffer.size() * 2),
+            );
+        }
+
+        self.scratch
+            .write(&self.value)
+            .expect("value to fit in its staging buffer");
+        // the staging `Vec` never shrinks, so only upload
+        // the bytes that belong to the current value
+        let len = size as usize;
+        queue.write_buffer(
+            &self.buffer,
+            0,
+            &self.scratch.as_ref()[..len],
+        );
+        reallocated
+    }
+
+    pub fn buffer(&self) -> &Buffer {
+        &self.buffer
+    }
+
+    /// Binds the whole buffer. After the value shrinks
+    /// the buffer keeps its size, so `arrayLength` in a
+    /// shader can be larger than the current value.
+    pub fn binding(&self) -> BindingResource<'_> {
+        self.buffer.as_entire_binding()
+    }
+
+    pub fn bind_group_entry(
+        &self,
+        binding: u32,
+    ) -> BindGroupEntry<'_> {
+        BindGroupEntry {
+            binding,
+            resource: self.binding(),
+        }
+    }
+}
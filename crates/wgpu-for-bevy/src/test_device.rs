@@ -0,0 +1,28 @@
+//! A device for unit tests that need one.
+use futures_lite::future::block_on;
+use wgpu::{Device, Queue};
+
+/// A device on any adapter that's available, including
+/// software ones, or `None` when there isn't one (such as
+/// on CI machines without drivers), in which case the
+/// test should return early.
+///
+/// `WGPU_BACKEND` and the other `WGPU_*` variables pick
+/// the adapter, like they do for wgpu's own tests.
+pub(crate) fn test_device() -> Option<(Device, Queue)> {
+    let instance = wgpu::Instance::new(
+        &wgpu::InstanceDescriptor::from_env_or_default(),
+    );
+    let adapter = block_on(
+        wgpu::util::initialize_adapter_from_env_or_default(
+            &instance, None,
+        ),
+    )
+    .ok();
+    let Some(adapter) = adapter else {
+        eprintln!("skipping: no adapter available");
+        return None;
+    };
+    block_on(adapter.request_device(&Default::default()))
+        .ok()
+}
@@ -0,0 +1,572 @@
+//! Clocks that advance once per frame, similar to Bevy's
+//! [`Time`](https://docs.rs/bevy/latest/bevy/time/struct.Time.html).
+//!
+//! Reading `Instant::now()` while rendering makes every
+//! frame depend on the wall clock, so nothing can be
+//! reproduced. Instead [`Clocks::update`] samples the
+//! wall clock (or doesn't, see [`TimeUpdateStrategy`])
+//! once at the start of a frame, and everything rendered
+//! in that frame reads the same values:
+//!
+//! - [`Time<Real>`] follows the wall clock, and ignores
+//!   pausing and speed.
+//! - [`Time<Virtual>`] is game time: it can be paused and
+//!   sped up or slowed down, and a single frame of wall
+//!   clock time never advances it by more than
+//!   [`Time::<Virtual>::max_delta`].
+//! - [`Time<Fixed>`] advances in constant steps,
+//!   accumulated from virtual time, for simulations that
+//!   need the same step size every time they run.
+use std::time::{Duration, Instant};
+
+/// A clock, with a context type for what drives it.
+/// `delta` is how much it advanced in the last update.
+#[derive(Clone, Debug)]
+pub struct Time<T> {
+    context: T,
+    delta: Duration,
+    elapsed: Duration,
+}
+
+impl<T: Default> Default for Time<T> {
+    fn default() -> Self {
+        Self {
+            context: T::default(),
+            delta: Duration::ZERO,
+            elapsed: Duration::ZERO,
+        }
+    }
+}
+
+impl<T> Time<T> {
+    pub fn delta(&self) -> Duration {
+        self.delta
+    }
+
+    pub fn delta_secs(&self) -> f32 {
+        self.delta.as_secs_f32()
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    /// Loses precision as time goes on: after a day this
+    /// is only accurate to a few milliseconds. Prefer
+    /// [`Time::elapsed_secs_f64`] for anything that isn't
+    /// sent to a shader.
+    pub fn elapsed_secs(&self) -> f32 {
+        self.elapsed.as_secs_f32()
+    }
+
+    pub fn elapsed_secs_f64(&self) -> f64 {
+        self.elapsed.as_secs_f64()
+    }
+
+    pub fn context(&self) -> &T {
+        &self.context
+    }
+
+    pub fn context_mut(&mut self) -> &mut T {
+        &mut self.context
+    }
+
+    fn advance_by(&mut self, delta: Duration) {
+        self.delta = delta;
+        self.elapsed += delta;
+    }
+}
+
+/// Wall clock time
+#[derive(Clone, Debug)]
+pub struct Real {
+    startup: Instant,
+    last_update: Option<Instant>,
+}
+
+impl Default for Real {
+    fn default() -> Self {
+        Self {
+            startup: Instant::now(),
+            last_update: None,
+        }
+    }
+}
+
+impl Time<Real> {
+    /// When the clock was created
+    pub fn startup(&self) -> Instant {
+        self.context.startup
+    }
+
+    /// When [`Clocks::update`] last sampled the wall
+    /// clock
+    pub fn last_update(&self) -> Option<Instant> {
+        self.context.last_update
+    }
+
+    fn update_with_instant(&mut self, instant: Instant) {
+        // the first update has no previous frame, so it
+        // doesn't advance
+        let delta = match self.context.last_update {
+            Some(last_update) => instant - last_update,
+            None => Duration::ZERO,
+        };
+        self.context.last_update = Some(instant);
+        self.advance_by(delta);
+    }
+}
+
+/// Game time, driven by real time but with its own pause
+/// and speed
+#[derive(Clone, Debug)]
+pub struct Virtual {
+    paused: bool,
+    relative_speed: f64,
+    max_delta: Duration,
+}
+
+impl Default for Virtual {
+    fn default() -> Self {
+        Self {
+            paused: false,
+            relative_speed: 1.0,
+            // the same as Bevy. A frame that takes longer
+            // than this (like one spent waiting on a
+            // window being dragged) only advances this
+            // much, instead of skipping the animation
+            // ahead.
+            max_delta: Duration::from_millis(250),
+        }
+    }
+}
+
+impl Time<Virtual> {
+    pub fn pause(&mut self) {
+        self.context.paused = true;
+    }
+
+    pub fn unpause(&mut self) {
+        self.context.paused = false;
+    }
+
+    pub fn toggle(&mut self) {
+        self.context.paused = !self.context.paused;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.context.paused
+    }
+
+    pub fn relative_speed(&self) -> f64 {
+        self.context.relative_speed
+    }
+
+    /// # Panics
+    ///
+    /// If `speed` is negative or not finite. Time doesn't
+    /// run backwards.
+    pub fn set_relative_speed(&mut self, speed: f64) {
+        assert!(
+            speed.is_finite() && speed >= 0.0,
+            "relative speed must be finite and not negative, was {speed}"
+        );
+        self.context.relative_speed = speed;
+    }
+
+    /// The most a single update can advance virtual time
+    /// by (before speed is applied), when it's driven by
+    /// the wall clock. Steps from
+    /// [`TimeUpdateStrategy::FixedStep`] and
+    /// [`Clocks::advance_by`] were asked for exactly, so
+    /// they aren't limited.
+    pub fn max_delta(&self) -> Duration {
+        self.context.max_delta
+    }
+
+    pub fn set_max_delta(&mut self, max_delta: Duration) {
+        self.context.max_delta = max_delta;
+    }
+
+    /// Jump to `elapsed`, such as a time passed on the
+    /// command line. The next update's delta isn't
+    /// affected.
+    pub fn set_elapsed(&mut self, elapsed: Duration) {
+        self.elapsed = elapsed;
+    }
+
+    fn advance_with_raw_delta(
+        &mut self,
+        raw: Duration,
+        clamp: bool,
+    ) {
+        let raw = if clamp {
+            raw.min(self.context.max_delta)
+        } else {
+            raw
+        };
+        let delta = if self.context.paused {
+            Duration::ZERO
+        } else {
+            raw.mul_f64(self.context.relative_speed)
+        };
+        self.advance_by(delta);
+    }
+}
+
+/// Constant steps of virtual time
+#[derive(Clone, Debug)]
+pub struct Fixed {
+    timestep: Duration,
+    overstep: Duration,
+}
+
+impl Default for Fixed {
+    fn default() -> Self {
+        // Bevy's default of 64 steps per second
+        Self {
+            timestep: Duration::from_micros(15_625),
+            overstep: Duration::ZERO,
+        }
+    }
+}
+
+impl Time<Fixed> {
+    /// # Panics
+    ///
+    /// If `hz` isn't a positive number, or is so large
+    /// that a step would round down to zero.
+    pub fn from_hz(hz: f64) -> Self {
+        assert!(
+            hz > 0.0,
+            "the fixed rate has to be positive, not {hz}"
+        );
+        let mut time = Self::default();
+        time.set_timestep(Duration::from_secs_f64(
+            1.0 / hz,
+        ));
+        time
+    }
+
+    pub fn timestep(&self) -> Duration {
+        self.context.timestep
+    }
+
+    /// # Panics
+    ///
+    /// If `timestep` is zero.
+    pub fn set_timestep(&mut self, timestep: Duration) {
+        assert_ne!(
+            timestep,
+            Duration::ZERO,
+            "the fixed timestep can't be zero"
+        );
+        self.context.timestep = timestep;
+    }
+
+    /// Virtual time that's been accumulated but not used
+    /// for a step yet
+    pub fn overstep(&self) -> Duration {
+        self.context.overstep
+    }
+
+    /// How far into the next step virtual time is, from
+    /// 0 to 1. Useful for interpolating between the last
+    /// two steps when rendering.
+    pub fn overstep_fraction(&self) -> f32 {
+        self.context.overstep.as_secs_f32()
+            / self.context.timestep.as_secs_f32()
+    }
+
+    /// Take one step if enough virtual time has
+    /// accumulated. Run the simulation once for every time
+    /// this returns `true`:
+    ///
+    /// ```ignore
+    /// while clocks.fixed.expend() {
+    ///     simulate(clocks.fixed.delta());
+    /// }
+    /// ```
+    pub fn expend(&mut self) -> bool {
+        let timestep = self.context.timestep;
+        match self.context.overstep.checked_sub(timestep) {
+            Some(overstep) => {
+                self.context.overstep = overstep;
+                self.advance_by(timestep);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn accumulate(&mut self, delta: Duration) {
+        self.context.overstep += delta;
+    }
+}
+
+/// Where [`Clocks::update`] gets real time from, like
+/// Bevy's
+/// [`TimeUpdateStrategy`](https://docs.rs/bevy/latest/bevy/time/enum.TimeUpdateStrategy.html).
+#[derive(Clone, Copy, Debug, Default)]
+pub enum TimeUpdateStrategy {
+    /// Sample the wall clock every update
+    #[default]
+    Automatic,
+    /// Advance by exactly this much every update, however
+    /// long frames actually take. Frame `n` is always at
+    /// `n * step`, which makes recordings and tests
+    /// reproducible.
+    FixedStep(Duration),
+    /// Never advance on its own. Time only moves when the
+    /// app calls [`Clocks::advance_by`] or
+    /// [`Time::set_elapsed`].
+    Manual,
+}
+
+/// The three clocks, updated together once per frame.
+#[derive(Clone, Debug, Default)]
+pub struct Clocks {
+    pub real: Time<Real>,
+    pub virtual_time: Time<Virtual>,
+    pub fixed: Time<Fixed>,
+    pub strategy: TimeUpdateStrategy,
+    /// Whether the first update has happened. It never
+    /// advances, so the first frame is at the starting
+    /// time.
+    started: bool,
+}
+
+impl Clocks {
+    pub fn new(strategy: TimeUpdateStrategy) -> Self {
+        Self {
+            strategy,
+            ..Default::default()
+        }
+    }
+
+    /// Advance every clock for a new frame. Call this
+    /// once, before anything reads the time.
+    pub fn update(&mut self) {
+        // only the wall clock can produce a huge delta
+        // nobody asked for, so it's the only one clamped
+        let (raw, clamp) = match self.strategy {
+            TimeUpdateStrategy::Automatic => {
+                self.real
+                    .update_with_instant(Instant::now());
+                (self.real.delta(), true)
+            }
+            TimeUpdateStrategy::FixedStep(step) => {
+                let step = if self.started {
+                    step
+                } else {
+                    Duration::ZERO
+                };
+                self.real.advance_by(step);
+                (step, false)
+            }
+            TimeUpdateStrategy::Manual => {
+                self.real.advance_by(Duration::ZERO);
+                (Duration::ZERO, false)
+            }
+        };
+        self.started = true;
+        self.advance_virtual(raw, clamp);
+    }
+
+    /// Advance by `delta` of real time, the same as an
+    /// update that took that long. This is what drives
+    /// [`TimeUpdateStrategy::Manual`].
+    pub fn advance_by(&mut self, delta: Duration) {
+        self.real.advance_by(delta);
+        self.advance_virtual(delta, false);
+    }
+
+    fn advance_virtual(
+        &mut self,
+        raw: Duration,
+        clamp: bool,
+    ) {
+        self.virtual_time
+            .advance_with_raw_delta(raw, clamp);
+        self.fixed.accumulate(self.virtual_time.delta());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STEP: Duration = Duration::from_millis(100);
+
+    /// Clocks that have had their first, non-advancing
+    /// update
+    fn started(strategy: TimeUpdateStrategy) -> Clocks {
+        let mut clocks = Clocks::new(strategy);
+        clocks.update();
+        clocks
+    }
+
+    #[test]
+    fn fixed_step_frame_n_is_at_n_steps() {
+        let mut clocks = Clocks::new(
+            TimeUpdateStrategy::FixedStep(STEP),
+        );
+        for frame in 0..10 {
+            clocks.update();
+            assert_eq!(clocks.real.elapsed(), STEP * frame);
+            assert_eq!(
+                clocks.virtual_time.elapsed(),
+                STEP * frame
+            );
+        }
+    }
+
+    #[test]
+    fn fixed_step_longer_than_max_delta_is_not_clamped() {
+        // a 2 fps recording
+        let step = Duration::from_millis(500);
+        let mut clocks =
+            started(TimeUpdateStrategy::FixedStep(step));
+        clocks.update();
+        assert_eq!(clocks.virtual_time.delta(), step);
+        assert_eq!(clocks.virtual_time.elapsed(), step);
+    }
+
+    #[test]
+    fn manual_advance_is_not_clamped() {
+        let mut clocks =
+            started(TimeUpdateStrategy::Manual);
+        clocks.update();
+        assert_eq!(
+            clocks.virtual_time.elapsed(),
+            Duration::ZERO
+        );
+        clocks.advance_by(Duration::from_secs(3));
+        assert_eq!(
+            clocks.virtual_time.delta(),
+            Duration::from_secs(3)
+        );
+    }
+
+    #[test]
+    fn wall_clock_deltas_are_clamped() {
+        let mut time = Time::<Virtual>::default();
+        time.advance_with_raw_delta(
+            Duration::from_secs(1),
+            true,
+        );
+        assert_eq!(time.delta(), time.max_delta());
+        time.advance_with_raw_delta(STEP, true);
+        assert_eq!(time.delta(), STEP);
+    }
+
+    #[test]
+    fn pausing_stops_virtual_time_only() {
+        let mut clocks =
+            started(TimeUpdateStrategy::FixedStep(STEP));
+        clocks.virtual_time.pause();
+        clocks.update();
+        assert_eq!(clocks.real.delta(), STEP);
+        assert_eq!(
+            clocks.virtual_time.delta(),
+            Duration::ZERO
+        );
+        assert_eq!(
+            clocks.virtual_time.elapsed(),
+            Duration::ZERO
+        );
+
+        clocks.virtual_time.toggle();
+        assert!(!clocks.virtual_time.is_paused());
+        clocks.update();
+        assert_eq!(clocks.virtual_time.elapsed(), STEP);
+    }
+
+    #[test]
+    fn relative_speed_scales_virtual_time() {
+        let mut clocks =
+            started(TimeUpdateStrategy::FixedStep(STEP));
+        clocks.virtual_time.set_relative_speed(2.0);
+        clocks.update();
+        assert_eq!(clocks.real.delta(), STEP);
+        assert_eq!(clocks.virtual_time.delta(), STEP * 2);
+
+        clocks.virtual_time.set_relative_speed(0.5);
+        clocks.update();
+        assert_eq!(clocks.virtual_time.delta(), STEP / 2);
+    }
+
+    #[test]
+    fn speed_is_applied_after_the_clamp() {
+        let mut time = Time::<Virtual>::default();
+        time.set_relative_speed(2.0);
+        time.advance_with_raw_delta(
+            Duration::from_secs(1),
+            true,
+        );
+        assert_eq!(time.delta(), time.max_delta() * 2);
+    }
+
+    #[test]
+    #[should_panic]
+    fn negative_speed_panics() {
+        Time::<Virtual>::default().set_relative_speed(-1.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "has to be positive")]
+    fn zero_hz_panics() {
+        Time::<Fixed>::from_hz(0.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "has to be positive")]
+    fn nan_hz_panics() {
+        Time::<Fixed>::from_hz(f64::NAN);
+    }
+
+    #[test]
+    fn fixed_expends_whole_steps() {
+        let mut fixed = Time::<Fixed>::from_hz(10.0);
+        assert_eq!(fixed.timestep(), STEP);
+        fixed.accumulate(Duration::from_millis(250));
+
+        assert!(fixed.expend());
+        assert!(fixed.expend());
+        assert!(!fixed.expend());
+        assert_eq!(fixed.elapsed(), STEP * 2);
+        assert_eq!(fixed.delta(), STEP);
+        assert_eq!(
+            fixed.overstep(),
+            Duration::from_millis(50)
+        );
+        assert!(
+            (fixed.overstep_fraction() - 0.5).abs() < 1e-6
+        );
+
+        // the leftover carries over to the next frame
+        fixed.accumulate(Duration::from_millis(50));
+        assert!(fixed.expend());
+        assert_eq!(fixed.overstep(), Duration::ZERO);
+    }
+
+    #[test]
+    fn fixed_accumulates_virtual_time() {
+        let mut clocks =
+            started(TimeUpdateStrategy::FixedStep(STEP));
+        clocks
+            .fixed
+            .set_timestep(Duration::from_millis(40));
+        clocks.virtual_time.set_relative_speed(2.0);
+        clocks.update();
+        assert_eq!(
+            clocks.fixed.overstep(),
+            Duration::from_millis(200)
+        );
+        let mut steps = 0;
+        while clocks.fixed.expend() {
+            steps += 1;
+        }
+        assert_eq!(steps, 5);
+    }
+}
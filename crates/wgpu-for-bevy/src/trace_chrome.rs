@@ -0,0 +1,468 @@
+//! A `tracing` layer that writes spans to a JSON trace,
+//! similar to Bevy's `trace_chrome` feature (which uses
+//! the [`tracing-chrome`](https://docs.rs/tracing-chrome)
+//! crate).
+//!
+//! Every time a span is entered and exited, a begin and
+//! end event is written for the thread it ran on. Events
+//! such as `info!` are written as instant events. The
+//! file opens in `chrome://tracing` and
+//! [Perfetto](https://ui.perfetto.dev).
+//!
+//! Only built with the `trace_chrome` feature.
+use crate::json::escape_json;
+use std::{
+    cell::Cell,
+    fmt::{self, Write as _},
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+use tracing::{
+    Event, Subscriber,
+    field::{Field, Visit},
+    span,
+};
+use tracing_subscriber::{
+    Layer, layer::Context, registry::LookupSpan,
+};
+
+/// Writes every span and event it sees to a trace file.
+/// The file is only complete once the [`FlushGuard`] it
+/// was created with is dropped.
+pub struct ChromeLayer {
+    trace: Arc<Mutex<TraceFile>>,
+    start: Instant,
+    pid: u32,
+}
+
+/// Finishes the trace when dropped. Keep it alive until
+/// the app exits:
+///
+/// ```ignore
+/// let (layer, _guard) = ChromeLayer::from_env()?;
+/// tracing_subscriber::registry().with(layer).init();
+/// ```
+pub struct FlushGuard {
+    trace: Arc<Mutex<TraceFile>>,
+}
+
+struct TraceFile {
+    file: BufWriter<File>,
+    first: bool,
+    /// Set once the guard has closed the array
+    finished: bool,
+}
+
+impl ChromeLayer {
+    pub fn new(
+        path: impl AsRef<Path>,
+    ) -> io::Result<(Self, FlushGuard)> {
+        let mut file =
+            BufWriter::new(File::create(path.as_ref())?);
+        write!(file, "[")?;
+        let trace = Arc::new(Mutex::new(TraceFile {
+            file,
+            first: true,
+            finished: false,
+        }));
+
+        Ok((
+            Self {
+                trace: trace.clone(),
+                start: Instant::now(),
+                pid: std::process::id(),
+            },
+            FlushGuard { trace },
+        ))
+    }
+
+    /// Write to the path in the `TRACE_CHROME`
+    /// environment variable, the same one Bevy reads, or
+    /// to `trace-<unix time>.json` in the working
+    /// directory.
+    pub fn from_env() -> io::Result<(Self, FlushGuard)> {
+        let path = std::env::var_os("TRACE_CHROME")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| {
+                let secs = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs();
+                PathBuf::from(format!("trace-{secs}.json"))
+            });
+        Self::new(path)
+    }
+
+    /// Microseconds since the layer was created, which
+    /// is the unit the format uses
+    fn timestamp(&self) -> f64 {
+        self.start.elapsed().as_nanos() as f64 / 1000.0
+    }
+
+    fn write(
+        &self,
+        phase: char,
+        name: &str,
+        args: Option<&JsonFields>,
+    ) {
+        let mut trace =
+            self.trace.lock().unwrap_or_else(|poisoned| {
+                poisoned.into_inner()
+            });
+        let (tid, new_thread) = thread_id();
+        if new_thread {
+            let thread_name = std::thread::current()
+                .name()
+                .unwrap_or("unnamed")
+                .to_string();
+            trace.write(&format!(
+                "{{\"ph\":\"M\",\"name\":\"thread_name\",\"pid\":{},\"tid\":{tid},\"args\":{{\"name\":\"{}\"}}}}",
+                self.pid,
+                escape_json(&thread_name)
+            ));
+        }
+
+        let mut json = format!(
+            "{{\"ph\":\"{phase}\",\"name\":\"{}\",\"pid\":{},\"tid\":{tid},\"ts\":{:.3}",
+            escape_json(name),
+            self.pid,
+            self.timestamp()
+        );
+        if phase == 'i' {
+            // scoped to the thread, rather than drawn
+            // across the whole process
+            json.push_str(",\"s\":\"t\"");
+        }
+        if let Some(args) =
+            args.filter(|args| !args.fields.is_empty())
+        {
+            let _ = write!(
+                json,
+                ",\"args\":{{{}}}",
+                args.fields
+            );
+        }
+        json.push('}');
+        trace.write(&json);
+    }
+}
+
+impl<S> Layer<S> for ChromeLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(
+        &self,
+        attrs: &span::Attributes<'_>,
+        id: &span::Id,
+        ctx: Context<'_, S>,
+    ) {
+        let mut fields = JsonFields::default();
+        attrs.record(&mut fields);
+        if let Some(span) = ctx.span(id) {
+            span.extensions_mut().insert(fields);
+        }
+    }
+
+    fn on_record(
+        &self,
+        id: &span::Id,
+        values: &span::Record<'_>,
+        ctx: Context<'_, S>,
+    ) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut extensions = span.extensions_mut();
+        if let Some(fields) =
+            extensions.get_mut::<JsonFields>()
+        {
+            values.record(fields);
+        }
+    }
+
+    fn on_enter(&self, id: &span::Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let extensions = span.extensions();
+        self.write(
+            'B',
+            span.name(),
+            extensions.get::<JsonFields>(),
+        );
+    }
+
+    fn on_exit(&self, id: &span::Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        self.write('E', span.name(), None);
+    }
+
+    fn on_event(
+        &self,
+        event: &Event<'_>,
+        _ctx: Context<'_, S>,
+    ) {
+        let mut fields = JsonFields::default();
+        event.record(&mut fields);
+        let name =
+            fields.message.take().unwrap_or_else(|| {
+                event.metadata().name().to_string()
+            });
+        self.write('i', &name, Some(&fields));
+    }
+}
+
+impl Drop for FlushGuard {
+    fn drop(&mut self) {
+        let mut trace =
+            self.trace.lock().unwrap_or_else(|poisoned| {
+                poisoned.into_inner()
+            });
+        // viewers accept a trace without the closing
+        // bracket, but other JSON readers don't
+        let result = writeln!(trace.file, "\n]")
+            .and_then(|_| trace.file.flush());
+        trace.finished = true;
+        if let Err(error) = result {
+            // not `error!`, which would come back to this
+            // layer
+            eprintln!(
+                "failed to finish the trace: {error}"
+            );
+        }
+    }
+}
+
+impl TraceFile {
+    fn write(&mut self, json: &str) {
+        if self.finished {
+            return;
+        }
+        let separator = if self.first { "" } else { "," };
+        self.first = false;
+        // a trace that's missing events is still worth
+        // having, so write errors are ignored
+        let _ = write!(self.file, "{separator}\n{json}");
+    }
+}
+
+/// Small numbers for thread ids, which `std` doesn't
+/// expose on stable. The second value is whether this is
+/// the first time the thread was seen.
+fn thread_id() -> (u64, bool) {
+    static NEXT: AtomicU64 = AtomicU64::new(1);
+    thread_local! {
+        static ID: Cell<Option<u64>> = const { Cell::new(None) };
+    }
+    ID.with(|id| match id.get() {
+        Some(existing) => (existing, false),
+        None => {
+            let new = NEXT.fetch_add(1, Ordering::Relaxed);
+            id.set(Some(new));
+            (new, true)
+        }
+    })
+}
+
+/// Fields recorded as the inside of a JSON object. An
+/// event's `message` is kept apart to use as its name.
+#[derive(Default)]
+struct JsonFields {
+    fields: String,
+    message: Option<String>,
+}
+
+impl JsonFields {
+    fn push(&mut self, field: &Field, value: &str) {
+        if !self.fields.is_empty() {
+            self.fields.push(',');
+        }
+        let _ = write!(
+            self.fields,
+            "\"{}\":{value}",
+            escape_json(field.name())
+        );
+    }
+}
+
+impl Visit for JsonFields {
+    fn record_debug(
+        &mut self,
+        field: &Field,
+        value: &dyn fmt::Debug,
+    ) {
+        let value = format!("{value:?}");
+        if field.name() == "message" {
+            self.message = Some(value);
+        } else {
+            self.push(
+                field,
+                &format!("\"{}\"", escape_json(&value)),
+            );
+        }
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message = Some(value.to_string());
+        } else {
+            self.push(
+                field,
+                &format!("\"{}\"", escape_json(value)),
+            );
+        }
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.push(field, &value.to_string());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.push(field, &value.to_string());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.push(field, &value.to_string());
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        // JSON has no infinity or NaN
+        if value.is_finite() {
+            self.push(field, &value.to_string());
+        } else {
+            self.push(field, &format!("\"{value}\""));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{Value, json};
+    use tracing::{info, info_span};
+    use tracing_subscriber::layer::SubscriberExt;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "wgpu-for-bevy-{}-{name}.json",
+            std::process::id()
+        ))
+    }
+
+    /// Everything written while running `f`, parsed
+    fn trace(name: &str, f: impl FnOnce()) -> Vec<Value> {
+        let path = temp_path(name);
+        let (layer, guard) =
+            ChromeLayer::new(&path).unwrap();
+        tracing::subscriber::with_default(
+            tracing_subscriber::registry().with(layer),
+            f,
+        );
+        drop(guard);
+        let json = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let Value::Array(events) =
+            serde_json::from_str(&json).unwrap()
+        else {
+            panic!("the trace isn't an array: {json}");
+        };
+        events
+    }
+
+    #[test]
+    fn spans_and_events_are_written_as_json() {
+        let events = trace("spans", || {
+            let _span = info_span!(
+                "frame",
+                index = 3,
+                name = "a \"b\""
+            )
+            .entered();
+            info!(speed = 1.5, "paused {}", true);
+        });
+
+        let phases: Vec<_> = events
+            .iter()
+            .map(|event| event["ph"].as_str().unwrap())
+            .collect();
+        // the thread's name comes first, the first time
+        // it's seen
+        assert_eq!(phases, ["M", "B", "i", "E"]);
+        assert_eq!(events[0]["name"], "thread_name");
+        assert_eq!(
+            events[1]["args"],
+            json!({ "index": 3, "name": "a \"b\"" })
+        );
+        assert_eq!(events[2]["name"], "paused true");
+        assert_eq!(events[2]["s"], "t");
+        assert_eq!(
+            events[2]["args"],
+            json!({ "speed": 1.5 })
+        );
+        assert_eq!(events[3]["name"], "frame");
+        assert!(events[3].get("args").is_none());
+        let ts =
+            |event: &Value| event["ts"].as_f64().unwrap();
+        assert!(ts(&events[1]) <= ts(&events[2]));
+        assert!(ts(&events[2]) <= ts(&events[3]));
+    }
+
+    #[test]
+    fn non_finite_floats_are_written_as_strings() {
+        let events = trace("floats", || {
+            info!(
+                infinite = f64::INFINITY,
+                nan = f64::NAN,
+                finite = -0.25,
+            );
+        });
+
+        let event = events
+            .iter()
+            .find(|event| event["ph"] == "i")
+            .unwrap();
+        // without a message, the event is named after
+        // its callsite
+        assert!(
+            event["name"]
+                .as_str()
+                .unwrap()
+                .starts_with("event ")
+        );
+        assert_eq!(
+            event["args"],
+            json!({
+                "infinite": "inf",
+                "nan": "NaN",
+                "finite": -0.25,
+            })
+        );
+    }
+
+    #[test]
+    fn nothing_is_written_after_the_guard_is_dropped() {
+        let path = temp_path("finished");
+        let (layer, guard) =
+            ChromeLayer::new(&path).unwrap();
+        drop(guard);
+        tracing::subscriber::with_default(
+            tracing_subscriber::registry().with(layer),
+            || info!("too late"),
+        );
+        let json = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            serde_json::from_str::<Value>(&json).unwrap(),
+            json!([])
+        );
+    }
+}
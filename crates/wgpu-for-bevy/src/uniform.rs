@@ -0,0 +1,131 @@
+//! A single `ShaderType` value stored in a uniform
+//! buffer, similar to Bevy's
+//! [`UniformBuffer<T>`](https://docs.rs/bevy/latest/bevy/render/render_resource/struct.UniformBuffer.html).
+use encase::{
+    ShaderType, UniformBuffer, internal::WriteInto,
+};
+use wgpu::{
+    BindGroupEntry, BindGroupLayoutEntry, BindingResource,
+    BindingType, Buffer, BufferBindingType,
+    BufferDescriptor, BufferUsages, Device, Queue,
+    ShaderStages,
+};
+
+/// Owns a value, the GPU buffer it lives in, and a
+/// staging `Vec` that encase writes the value's bytes
+/// into.
+///
+/// The staging `Vec` is reused every time the value is
+/// uploaded, so updating a uniform every frame doesn't
+/// allocate. Changes made through [`Uniform::set`] or
+/// [`Uniform::get_mut`] are only written to the GPU the
+/// next time [`Uniform::upload`] is called.
+pub struct Uniform<T> {
+    value: T,
+    scratch: UniformBuffer<Vec<u8>>,
+    buffer: Buffer,
+    changed: bool,
+}
+
+impl<T: ShaderType + WriteInto> Uniform<T> {
+    pub fn new(
+        device: &Device,
+        label: &str,
+        value: T,
+    ) -> Self {
+        let buffer =
+            device.create_buffer(&BufferDescriptor {
+                label: Some(label),
+                size: T::min_size().get(),
+                usage: BufferUsages::UNIFORM
+                    | BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            });
+
+        Self {
+            value,
+            scratch: UniformBuffer::new(Vec::new()),
+            buffer,
+            // the buffer starts zeroed, so the first
+            // `upload` always has something to write
+            changed: true,
+        }
+    }
+
+    /// The layout entry for a uniform of this type.
+    ///
+    /// This doesn't need a `Uniform` to exist yet, which
+    /// lets bind group layouts (and the pipelines that
+    /// use them) be created before any buffers.
+    pub fn layout_entry(
+        binding: u32,
+        visibility: ShaderStages,
+    ) -> BindGroupLayoutEntry {
+        BindGroupLayoutEntry {
+            binding,
+            visibility,
+            ty: BindingType::Buffer {
+                ty: BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: Some(T::min_size()),
+            },
+            count: None,
+        }
+    }
+
+    pub fn get(&self) -> &T {
+        &self.value
+    }
+
+    /// Mutable access to the value. This always marks
+    /// the value as changed.
+    pub fn get_mut(&mut self) -> &mut T {
+        self.changed = true;
+        &mut self.value
+    }
+
+    pub fn set(&mut self, value: T) {
+        self.value = value;
+        self.changed = true;
+    }
+
+    /// Write the value to the GPU if it changed since the
+    /// last upload.
+    ///
+    /// `queue.write_buffer` copies the bytes into wgpu's
+    /// own staging memory immediately, so the write lands
+    /// before any command buffer submitted afterwards.
+    pub fn upload(&mut self, queue: &Queue) {
+        if !self.changed {
+            return;
+        }
+
+        self.scratch
+            .write(&self.value)
+            .expect("uniform to fit in its staging buffer");
+        queue.write_buffer(
+            &self.buffer,
+            0,
+            self.scratch.as_ref(),
+        );
+        self.changed = false;
+    }
+
+    pub fn buffer(&self) -> &Buffer {
+        &self.buffer
+    }
+
+    pub fn binding(&self) -> BindingResource<'_> {
+        self.buffer.as_entire_binding()
+    }
+
+    pub fn bind_group_entry(
+        &self,
+        binding: u32,
+    ) -> BindGroupEntry<'_> {
+        BindGroupEntry {
+            binding,
+            resource: self.binding(),
+        }
+    }
+}
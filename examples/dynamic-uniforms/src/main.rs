@@ -0,0 +1,452 @@
+use encase::ShaderType;
+use futures_lite::future::block_on;
+use glam::{Mat4, Quat, Vec3, Vec4};
+use std::{borrow::Cow, sync::Arc, time::Instant};
+use tracing::info;
+use wgpu::{
+    BindGroup, BindGroupLayout, Device, Queue,
+    RenderPipeline, ShaderStages, Surface,
+    SurfaceConfiguration,
+};
+use wgpu_for_bevy::DynamicUniform;
+use winit::{
+    application::ApplicationHandler,
+    dpi::PhysicalSize,
+    event::{ElementState, KeyEvent, WindowEvent},
+    event_loop::{ActiveEventLoop, ControlFlow, EventLoop},
+    keyboard::{Key, NamedKey},
+    window::Window,
+};
+
+/// How many quads to draw along each side of the grid
+const GRID_SIZE: u32 = 20;
+
+/// The per-object data. Bevy's equivalent is `MeshUniform`,
+/// which holds each mesh's transforms.
+#[derive(ShaderType)]
+struct ObjectUniform {
+    transform: Mat4,
+    color: Vec4,
+}
+
+struct ResumedData<'a> {
+    window: Arc<Window>,
+    config: SurfaceConfiguration,
+    render_pipeline: RenderPipeline,
+    surface: Surface<'a>,
+    device: Device,
+    queue: Queue,
+    object_layout: BindGroupLayout,
+    object_bind_group: BindGroup,
+    objects: DynamicUniform<ObjectUniform>,
+}
+
+struct App<'a> {
+    resumed_data: Option<ResumedData<'a>>,
+    start: Instant,
+}
+
+impl Default for App<'_> {
+    fn default() -> Self {
+        Self {
+            resumed_data: Default::default(),
+            start: Instant::now(),
+        }
+    }
+}
+
+/// Fill `objects` with one uniform per grid cell for the
+/// current frame, returning the dynamic offset of each.
+fn prepare_objects(
+    objects: &mut DynamicUniform<ObjectUniform>,
+    config: &SurfaceConfiguration,
+    time: f32,
+) -> Vec<u32> {
+    let aspect = config.width as f32 / config.height as f32;
+    let projection = Mat4::orthographic_rh(
+        -aspect, aspect, -1.0, 1.0, -1.0, 1.0,
+    );
+    let cell = 2.0 / GRID_SIZE as f32;
+
+    objects.clear();
+    (0..GRID_SIZE * GRID_SIZE)
+        .map(|i| {
+            let x = (i % GRID_SIZE) as f32;
+            let y = (i / GRID_SIZE) as f32;
+            let position = Vec3::new(
+                (x + 0.5) * cell - 1.0,
+                (y + 0.5) * cell - 1.0,
+                0.0,
+            );
+            // every object spins at its own speed, which
+            // is only possible because each one has its
+            // own uniform
+            let rotation = Quat::from_rotation_z(
+                time * (1.0 + (i % 7) as f32 * 0.3),
+            );
+            let transform = projection
+                * Mat4::from_scale_rotation_translation(
+                    Vec3::splat(cell * 0.6),
+                    rotation,
+                    position,
+                );
+
+            objects.push(&ObjectUniform {
+                transform,
+                color: Vec4::new(
+                    x / GRID_SIZE as f32,
+                    y / GRID_SIZE as f32,
+                    0.6,
+                    1.0,
+                ),
+            })
+        })
+        .collect()
+}
+
+fn create_object_bind_group(
+    device: &Device,
+    layout: &BindGroupLayout,
+    objects: &DynamicUniform<ObjectUniform>,
+) -> BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: "object_bind_group".into(),
+        layout,
+        entries: &[objects
+            .bind_group_entry(0)
+            .expect("object buffer to be written")],
+    })
+}
+
+/// Winit
+impl<'a> ApplicationHandler for App<'a> {
+    fn resumed(
+        &mut self,
+        event_loop: &winit::event_loop::ActiveEventLoop,
+    ) {
+        let window_attributes =
+            Window::default_attributes()
+                .with_title("wgpu for Bevy");
+
+        let window = Arc::new(
+            event_loop
+                .create_window(window_attributes)
+                .unwrap(),
+        );
+
+        let mut size = window.inner_size();
+        size.width = size.width.max(1);
+        size.height = size.height.max(1);
+
+        let instance = wgpu::Instance::default();
+
+        // `surface_window` is captured by the async closure,
+        // so we clone our Arc and let the closure take it
+        let surface_window = window.clone();
+        // wgpu apis for getting an adapter are async,
+        // so we block while waiting for them to complete
+        let (surface, adapter, device, queue) = block_on(
+            async move {
+                let surface = instance
+                    .create_surface(surface_window)
+                    .unwrap();
+
+                let adapter = instance
+                    .request_adapter(&wgpu::RequestAdapterOptions {
+                        power_preference:
+                            wgpu::PowerPreference::default(),
+                        force_fallback_adapter: false,
+                        // Request an adapter which can render to our surface
+                        compatible_surface: Some(&surface),
+                    })
+                    .await
+                    .expect(
+                        "Failed to find an appropriate adapter",
+                    );
+
+                info!(adapter=?adapter.get_info());
+
+                // Create the logical device and command
+                // queue
+                let (device, queue) = adapter
+                    .request_device(
+                        &wgpu::DeviceDescriptor::default(),
+                    )
+                    .await
+                    .expect("Failed to create device");
+
+                (surface, adapter, device, queue)
+            },
+        );
+
+        // dynamic offsets have to be a multiple of this,
+        // so each object takes up at least this many
+        // bytes in the buffer
+        info!(
+            min_uniform_buffer_offset_alignment = device
+                .limits()
+                .min_uniform_buffer_offset_alignment
+        );
+
+        let shader = device.create_shader_module(
+            wgpu::ShaderModuleDescriptor {
+                label: "object_shader".into(),
+                source: wgpu::ShaderSource::Wgsl(
+                    Cow::Borrowed(include_str!(
+                        "object.wgsl"
+                    )),
+                ),
+            },
+        );
+
+        let object_layout =
+            device.create_bind_group_layout(
+                &wgpu::BindGroupLayoutDescriptor {
+                    label: "object_layout".into(),
+                    entries: &[DynamicUniform::<
+                        ObjectUniform,
+                    >::layout_entry(
+                        0,
+                        ShaderStages::VERTEX_FRAGMENT,
+                    )],
+                },
+            );
+
+        let pipeline_layout = device
+            .create_pipeline_layout(
+                &wgpu::PipelineLayoutDescriptor {
+                    label: "object_pipeline_layout".into(),
+                    bind_group_layouts: &[&object_layout],
+                    immediate_size: 0,
+                },
+            );
+
+        let swapchain_format =
+            surface.get_capabilities(&adapter).formats[0];
+
+        let render_pipeline = device
+            .create_render_pipeline(
+                &wgpu::RenderPipelineDescriptor {
+                    label: "object_pipeline".into(),
+                    layout: Some(&pipeline_layout),
+                    vertex: wgpu::VertexState {
+                        module: &shader,
+                        entry_point: "vertex".into(),
+                        buffers: &[],
+                        compilation_options:
+                            Default::default(),
+                    },
+                    fragment: Some(wgpu::FragmentState {
+                        module: &shader,
+                        entry_point: "fragment".into(),
+                        compilation_options:
+                            Default::default(),
+                        targets: &[Some(
+                            swapchain_format.into(),
+                        )],
+                    }),
+                    primitive:
+                        wgpu::PrimitiveState::default(),
+                    depth_stencil: None,
+                    multisample:
+                        wgpu::MultisampleState::default(),
+                    multiview_mask: None,
+                    cache: None,
+                },
+            );
+
+        let config = surface
+            .get_default_config(
+                &adapter,
+                size.width,
+                size.height,
+            )
+            .unwrap();
+        surface.configure(&device, &config);
+
+        // The bind group needs a buffer to point at, so
+        // write the first frame's objects right away
+        let mut objects =
+            DynamicUniform::new(&device, "object_uniforms");
+        prepare_objects(&mut objects, &config, 0.0);
+        objects.write_buffer(&device, &queue);
+        let object_bind_group = create_object_bind_group(
+            &device,
+            &object_layout,
+            &objects,
+        );
+
+        self.resumed_data = Some(ResumedData {
+            window,
+            config,
+            render_pipeline,
+            surface,
+            device,
+            queue,
+            object_layout,
+            object_bind_group,
+            objects,
+        });
+    }
+
+    fn window_event(
+        &mut self,
+        event_loop: &winit::event_loop::ActiveEventLoop,
+        _window_id: winit::window::WindowId,
+        event: WindowEvent,
+    ) {
+        match event {
+            WindowEvent::CloseRequested => {
+                event_loop.exit();
+            }
+            WindowEvent::Resized(PhysicalSize {
+                width,
+                height,
+            }) => {
+                let Some(ResumedData {
+                    config,
+                    surface,
+                    device,
+                    ..
+                }) = self.resumed_data.as_mut()
+                else {
+                    return;
+                };
+
+                // Reconfigure the surface with the new size,
+                // making it so that the window is *at least* 1x1
+                config.width = width.max(1);
+                config.height = height.max(1);
+
+                surface.configure(device, config);
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        logical_key: key,
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } => {
+                // allow a single_match here so that people
+                // who use this example can easily match on
+                // new keys
+                #[allow(clippy::single_match)]
+                match key.as_ref() {
+                    Key::Named(NamedKey::Escape) => {
+                        event_loop.exit();
+                    }
+                    _ => (),
+                }
+            }
+            WindowEvent::RedrawRequested => {
+                let Some(ResumedData {
+                    config,
+                    surface,
+                    device,
+                    queue,
+                    render_pipeline,
+                    object_layout,
+                    object_bind_group,
+                    objects,
+                    ..
+                }) = self.resumed_data.as_mut()
+                else {
+                    return;
+                };
+
+                let offsets = prepare_objects(
+                    objects,
+                    config,
+                    self.start.elapsed().as_secs_f32(),
+                );
+                // the buffer only grows if more objects
+                // are pushed than before, but when it does
+                // the old bind group points at a dead buffer
+                if objects.write_buffer(device, queue) {
+                    *object_bind_group =
+                        create_object_bind_group(
+                            device,
+                            object_layout,
+                            objects,
+                        );
+                }
+
+                let frame = surface
+                    .get_current_texture()
+                    .expect("Failed to acquire next swap chain texture");
+                let view = frame.texture.create_view(
+                    &wgpu::TextureViewDescriptor::default(),
+                );
+                let mut encoder = device
+                    .create_command_encoder(
+                        &wgpu::CommandEncoderDescriptor {
+                            label: "object_command_encoder"
+                                .into(),
+                        },
+                    );
+                {
+                    let mut rpass =
+                    encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                        label: "object_render_pass".into(),
+                        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                            view: &view,
+                            resolve_target: None,
+                            ops: wgpu::Operations {
+                                load: wgpu::LoadOp::Clear(wgpu::Color{
+                                    r: 0.008,
+                                    g: 0.024,
+                                    b: 0.09,
+                                    a: 1.0,
+                                }),
+                                store: wgpu::StoreOp::Store,
+                            },
+                            depth_slice: None
+                        })],
+                        depth_stencil_attachment: None,
+                        timestamp_writes: None,
+                        occlusion_query_set: None,
+                        multiview_mask: None
+                    });
+                    rpass.set_pipeline(render_pipeline);
+                    // One bind group, one draw per object.
+                    // Only the offset changes between draws.
+                    for offset in offsets {
+                        rpass.set_bind_group(
+                            0,
+                            Some(&*object_bind_group),
+                            &[offset],
+                        );
+                        rpass.draw(0..6, 0..1);
+                    }
+                }
+
+                queue.submit(Some(encoder.finish()));
+                frame.present();
+            }
+            _ => (),
+        }
+    }
+    fn about_to_wait(
+        &mut self,
+        _event_loop: &ActiveEventLoop,
+    ) {
+        let Some(data) = self.resumed_data.as_ref() else {
+            return;
+        };
+
+        data.window.request_redraw();
+    }
+}
+
+fn main() {
+    tracing_subscriber::fmt().init();
+
+    let event_loop = EventLoop::new().unwrap();
+    event_loop.set_control_flow(ControlFlow::Poll);
+
+    let mut app = App::default();
+
+    event_loop.run_app(&mut app).expect("app to run")
+}
@@ -0,0 +1,31 @@
+struct Object {
+    transform: mat4x4<f32>,
+    color: vec4<f32>,
+}
+
+// The same binding is used for every draw. The dynamic
+// offset passed to `set_bind_group` decides which
+// `Object` in the buffer this actually reads.
+@group(0) @binding(0) var<uniform> object: Object;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) color: vec4<f32>,
+}
+
+@vertex
+fn vertex(@builtin(vertex_index) in_vertex_index: u32) -> VertexOutput {
+    // two triangles making a quad from -0.5 to 0.5
+    let x = f32((in_vertex_index + 1u) % 6u / 3u) - 0.5;
+    let y = f32(in_vertex_index % 6u % 2u) - 0.5;
+
+    var out: VertexOutput;
+    out.position = object.transform * vec4<f32>(x, y, 0.0, 1.0);
+    out.color = object.color;
+    return out;
+}
+
+@fragment
+fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
+    return in.color;
+}
@@ -0,0 +1,41 @@
+struct Instance {
+    offset: vec2<f32>,
+    scale: f32,
+    color: vec4<f32>,
+}
+
+// A runtime-sized array. Its length comes from the size
+// of the buffer bound here, not from the shader.
+@group(0) @binding(0) var<storage, read> instances: array<Instance>;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) color: vec4<f32>,
+}
+
+@vertex
+fn vertex(
+    @builtin(vertex_index) in_vertex_index: u32,
+    @builtin(instance_index) in_instance_index: u32,
+) -> VertexOutput {
+    // the same triangle as the triangle example...
+    let x = f32(i32(in_vertex_index) - 1);
+    let y = f32(i32(in_vertex_index & 1u) * 2 - 1);
+
+    // ...moved and scaled by this instance's data
+    let instance = instances[in_instance_index];
+
+    var out: VertexOutput;
+    out.position = vec4<f32>(
+        vec2<f32>(x, y) * instance.scale + instance.offset,
+        0.0,
+        1.0,
+    );
+    out.color = instance.color;
+    return out;
+}
+
+@fragment
+fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
+    return in.color;
+}
@@ -0,0 +1,443 @@
+use encase::ShaderType;
+use futures_lite::future::block_on;
+use glam::{Vec2, Vec4};
+use std::{borrow::Cow, sync::Arc};
+use tracing::info;
+use wgpu::{
+    BindGroup, BindGroupLayout, Device, Queue,
+    RenderPipeline, ShaderStages, Surface,
+    SurfaceConfiguration,
+};
+use wgpu_for_bevy::Storage;
+use winit::{
+    application::ApplicationHandler,
+    dpi::PhysicalSize,
+    event::{ElementState, KeyEvent, WindowEvent},
+    event_loop::{ActiveEventLoop, ControlFlow, EventLoop},
+    keyboard::{Key, NamedKey},
+    window::Window,
+};
+
+const INITIAL_INSTANCE_COUNT: u32 = 10_000;
+const MAX_INSTANCE_COUNT: u32 = 1 << 20;
+
+/// The per-instance data, read in the vertex shader with
+/// `instances[instance_index]`
+#[derive(ShaderType)]
+struct Instance {
+    offset: Vec2,
+    scale: f32,
+    color: Vec4,
+}
+
+struct ResumedData<'a> {
+    window: Arc<Window>,
+    config: SurfaceConfiguration,
+    render_pipeline: RenderPipeline,
+    surface: Surface<'a>,
+    device: Device,
+    queue: Queue,
+    instance_layout: BindGroupLayout,
+    instance_bind_group: BindGroup,
+    instances: Storage<Vec<Instance>>,
+}
+
+struct App<'a> {
+    resumed_data: Option<ResumedData<'a>>,
+    instance_count: u32,
+}
+
+impl Default for App<'_> {
+    fn default() -> Self {
+        Self {
+            resumed_data: Default::default(),
+            instance_count: INITIAL_INSTANCE_COUNT,
+        }
+    }
+}
+
+/// A cheap hash so instances are scattered around the
+/// window without pulling in a random number crate. The
+/// same index always ends up in the same place, so
+/// changing the count adds or removes instances rather
+/// than shuffling them.
+fn hash(mut x: u32) -> f32 {
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x7feb_352d);
+    x ^= x >> 15;
+    x = x.wrapping_mul(0x846c_a68b);
+    x ^= x >> 16;
+    x as f32 / u32::MAX as f32
+}
+
+fn create_instances(count: u32) -> Vec<Instance> {
+    (0..count)
+        .map(|i| {
+            let offset = Vec2::new(
+                hash(i * 4) * 2.0 - 1.0,
+                hash(i * 4 + 1) * 2.0 - 1.0,
+            );
+            Instance {
+                offset,
+                scale: 0.005 + hash(i * 4 + 2) * 0.02,
+                color: Vec4::new(
+                    offset.x * 0.5 + 0.5,
+                    offset.y * 0.5 + 0.5,
+                    hash(i * 4 + 3),
+                    1.0,
+                ),
+            }
+        })
+        .collect()
+}
+
+fn create_instance_bind_group(
+    device: &Device,
+    layout: &BindGroupLayout,
+    instances: &Storage<Vec<Instance>>,
+) -> BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: "instance_bind_group".into(),
+        layout,
+        entries: &[instances.bind_group_entry(0)],
+    })
+}
+
+/// Winit
+impl<'a> ApplicationHandler for App<'a> {
+    fn resumed(
+        &mut self,
+        event_loop: &winit::event_loop::ActiveEventLoop,
+    ) {
+        let window_attributes =
+            Window::default_attributes()
+                .with_title("wgpu for Bevy");
+
+        let window = Arc::new(
+            event_loop
+                .create_window(window_attributes)
+                .unwrap(),
+        );
+
+        let mut size = window.inner_size();
+        size.width = size.width.max(1);
+        size.height = size.height.max(1);
+
+        let instance = wgpu::Instance::default();
+
+        // `surface_window` is captured by the async closure,
+        // so we clone our Arc and let the closure take it
+        let surface_window = window.clone();
+        // wgpu apis for getting an adapter are async,
+        // so we block while waiting for them to complete
+        let (surface, adapter, device, queue) = block_on(
+            async move {
+                let surface = instance
+                    .create_surface(surface_window)
+                    .unwrap();
+
+                let adapter = instance
+                    .request_adapter(&wgpu::RequestAdapterOptions {
+                        power_preference:
+                            wgpu::PowerPreference::default(),
+                        force_fallback_adapter: false,
+                        // Request an adapter which can render to our surface
+                        compatible_surface: Some(&surface),
+                    })
+                    .await
+                    .expect(
+                        "Failed to find an appropriate adapter",
+                    );
+
+                info!(adapter=?adapter.get_info());
+
+                // Create the logical device and command
+                // queue
+                let (device, queue) = adapter
+                    .request_device(
+                        &wgpu::DeviceDescriptor::default(),
+                    )
+                    .await
+                    .expect("Failed to create device");
+
+                (surface, adapter, device, queue)
+            },
+        );
+
+        let shader = device.create_shader_module(
+            wgpu::ShaderModuleDescriptor {
+                label: "instancing_shader".into(),
+                source: wgpu::ShaderSource::Wgsl(
+                    Cow::Borrowed(include_str!(
+                        "instancing.wgsl"
+                    )),
+                ),
+            },
+        );
+
+        let instance_layout = device
+            .create_bind_group_layout(
+            &wgpu::BindGroupLayoutDescriptor {
+                label: "instance_layout".into(),
+                entries: &[
+                    Storage::<Vec<Instance>>::layout_entry(
+                        0,
+                        ShaderStages::VERTEX,
+                        true,
+                    ),
+                ],
+            },
+        );
+
+        let pipeline_layout = device
+            .create_pipeline_layout(
+                &wgpu::PipelineLayoutDescriptor {
+                    label: "instancing_layout".into(),
+                    bind_group_layouts: &[&instance_layout],
+                    immediate_size: 0,
+                },
+            );
+
+        let swapchain_format =
+            surface.get_capabilities(&adapter).formats[0];
+
+        let render_pipeline = device
+            .create_render_pipeline(
+                &wgpu::RenderPipelineDescriptor {
+                    label: "instancing_pipeline".into(),
+                    layout: Some(&pipeline_layout),
+                    vertex: wgpu::VertexState {
+                        module: &shader,
+                        entry_point: "vertex".into(),
+                        // no vertex buffers: per-instance
+                        // data comes from the storage buffer
+                        buffers: &[],
+                        compilation_options:
+                            Default::default(),
+                    },
+                    fragment: Some(wgpu::FragmentState {
+                        module: &shader,
+                        entry_point: "fragment".into(),
+                        compilation_options:
+                            Default::default(),
+                        targets: &[Some(
+                            swapchain_format.into(),
+                        )],
+                    }),
+                    primitive:
+                        wgpu::PrimitiveState::default(),
+                    depth_stencil: None,
+                    multisample:
+                        wgpu::MultisampleState::default(),
+                    multiview_mask: None,
+                    cache: None,
+                },
+            );
+
+        let config = surface
+            .get_default_config(
+                &adapter,
+                size.width,
+                size.height,
+            )
+            .unwrap();
+        surface.configure(&device, &config);
+
+        let mut instances = Storage::new(
+            &device,
+            "instance_storage",
+            create_instances(self.instance_count),
+        );
+        instances.upload(&device, &queue);
+        let instance_bind_group =
+            create_instance_bind_group(
+                &device,
+                &instance_layout,
+                &instances,
+            );
+
+        self.resumed_data = Some(ResumedData {
+            window,
+            config,
+            render_pipeline,
+            surface,
+            device,
+            queue,
+            instance_layout,
+            instance_bind_group,
+            instances,
+        });
+    }
+
+    fn window_event(
+        &mut self,
+        event_loop: &winit::event_loop::ActiveEventLoop,
+        _window_id: winit::window::WindowId,
+        event: WindowEvent,
+    ) {
+        match event {
+            WindowEvent::CloseRequested => {
+                event_loop.exit();
+            }
+            WindowEvent::Resized(PhysicalSize {
+                width,
+                height,
+            }) => {
+                let Some(ResumedData {
+                    config,
+                    surface,
+                    device,
+                    ..
+                }) = self.resumed_data.as_mut()
+                else {
+                    return;
+                };
+
+                // Reconfigure the surface with the new size,
+                // making it so that the window is *at least* 1x1
+                config.width = width.max(1);
+                config.height = height.max(1);
+
+                surface.configure(device, config);
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        logical_key: key,
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } => {
+                let count = match key.as_ref() {
+                    Key::Named(NamedKey::Escape) => {
+                        event_loop.exit();
+                        return;
+                    }
+                    Key::Named(NamedKey::ArrowUp) => {
+                        (self.instance_count * 2)
+                            .min(MAX_INSTANCE_COUNT)
+                    }
+                    Key::Named(NamedKey::ArrowDown) => {
+                        (self.instance_count / 2).max(1)
+                    }
+                    _ => return,
+                };
+                if count == self.instance_count {
+                    return;
+                }
+                self.instance_count = count;
+                info!(instance_count = count);
+
+                // The new instances are uploaded on the
+                // next redraw
+                if let Some(data) =
+                    self.resumed_data.as_mut()
+                {
+                    data.instances
+                        .set(create_instances(count));
+                }
+            }
+            WindowEvent::RedrawRequested => {
+                let Some(ResumedData {
+                    surface,
+                    device,
+                    queue,
+                    render_pipeline,
+                    instance_layout,
+                    instance_bind_group,
+                    instances,
+                    ..
+                }) = self.resumed_data.as_mut()
+                else {
+                    return;
+                };
+
+                // growing past the current buffer creates
+                // a new one, so the bind group has to be
+                // recreated to point at it
+                if instances.upload(device, queue) {
+                    *instance_bind_group =
+                        create_instance_bind_group(
+                            device,
+                            instance_layout,
+                            instances,
+                        );
+                }
+
+                let frame = surface
+                    .get_current_texture()
+                    .expect("Failed to acquire next swap chain texture");
+                let view = frame.texture.create_view(
+                    &wgpu::TextureViewDescriptor::default(),
+                );
+                let mut encoder = device
+                    .create_command_encoder(
+                        &wgpu::CommandEncoderDescriptor {
+                            label:
+                                "instancing_command_encoder"
+                                    .into(),
+                        },
+                    );
+                {
+                    let mut rpass =
+                    encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                        label: "instancing_render_pass".into(),
+                        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                            view: &view,
+                            resolve_target: None,
+                            ops: wgpu::Operations {
+                                load: wgpu::LoadOp::Clear(wgpu::Color{
+                                    r: 0.008,
+                                    g: 0.024,
+                                    b: 0.09,
+                                    a: 1.0,
+                                }),
+                                store: wgpu::StoreOp::Store,
+                            },
+                            depth_slice: None
+                        })],
+                        depth_stencil_attachment: None,
+                        timestamp_writes: None,
+                        occlusion_query_set: None,
+                        multiview_mask: None
+                    });
+                    rpass.set_pipeline(render_pipeline);
+                    rpass.set_bind_group(
+                        0,
+                        Some(&*instance_bind_group),
+                        &[],
+                    );
+                    // a single draw call covers every instance
+                    rpass
+                        .draw(0..3, 0..self.instance_count);
+                }
+
+                queue.submit(Some(encoder.finish()));
+                frame.present();
+            }
+            _ => (),
+        }
+    }
+    fn about_to_wait(
+        &mut self,
+        _event_loop: &ActiveEventLoop,
+    ) {
+        let Some(data) = self.resumed_data.as_ref() else {
+            return;
+        };
+
+        data.window.request_redraw();
+    }
+}
+
+fn main() {
+    tracing_subscriber::fmt().init();
+
+    let event_loop = EventLoop::new().unwrap();
+    event_loop.set_control_flow(ControlFlow::Poll);
+
+    let mut app = App::default();
+
+    event_loop.run_app(&mut app).expect("app to run")
+}
@@ -0,0 +1,34 @@
+// These match the bindings and vertex output of the
+// default material shader in wgpu-for-bevy
+
+struct View {
+    view_proj: mat4x4<f32>,
+    time: f32,
+}
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) world_normal: vec3<f32>,
+    @location(1) uv: vec2<f32>,
+}
+
+@group(0) @binding(0) var<uniform> view: View;
+
+struct Stripes {
+    color_a: vec4<f32>,
+    color_b: vec4<f32>,
+    speed: f32,
+}
+
+@group(2) @binding(0) var<uniform> stripes: Stripes;
+
+@fragment
+fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
+    // diagonal stripes scrolling across each face
+    let t = sin((in.uv.x + in.uv.y) * 12.0 - view.time * stripes.speed) * 0.5 + 0.5;
+    let color = mix(stripes.color_a, stripes.color_b, t);
+
+    let light = normalize(vec3<f32>(0.4, 1.0, 0.6));
+    let diffuse = max(dot(normalize(in.world_normal), light), 0.0);
+    return vec4<f32>(color.rgb * (0.2 + 0.8 * diffuse), color.a);
+}
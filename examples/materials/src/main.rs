@@ -0,0 +1,709 @@
+use encase::ShaderType;
+use futures_lite::future::block_on;
+use glam::{Mat4, Quat, Vec3, Vec4};
+use std::{f32::consts::PI, sync::Arc, time::Instant};
+use tracing::info;
+use wgpu::{
+    BindGroup, Device, PrimitiveTopology, Queue,
+    RenderPipeline, Sampler, Surface, SurfaceConfiguration,
+    TextureFormat, TextureView,
+};
+use wgpu_for_bevy::{
+    AlphaMode, AsBindGroup, GpuImage, GpuMesh, Image,
+    Indices, Material, MaterialLayouts, MaterialPipeline,
+    Mesh, ShaderRef, SpecializedMeshPipelines, Uniform,
+    material::{MeshUniform, ViewUniform},
+};
+use winit::{
+    application::ApplicationHandler,
+    dpi::PhysicalSize,
+    event::{ElementState, KeyEvent, WindowEvent},
+    event_loop::{ActiveEventLoop, ControlFlow, EventLoop},
+    keyboard::{Key, NamedKey},
+    window::Window,
+};
+
+const DEPTH_FORMAT: TextureFormat =
+    TextureFormat::Depth32Float;
+
+const IMAGE_PATH: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/../../assets/textures/checker.png"
+);
+
+/// A single color. Translucent colors are blended.
+#[derive(AsBindGroup)]
+struct FlatColorMaterial {
+    #[uniform(0)]
+    color: Vec4,
+}
+
+impl Material for FlatColorMaterial {
+    fn fragment_shader() -> ShaderRef {
+        ShaderRef::Wgsl(include_str!("flat.wgsl"))
+    }
+
+    fn alpha_mode(&self) -> AlphaMode {
+        if self.color.w < 1.0 {
+            AlphaMode::Blend
+        } else {
+            AlphaMode::Opaque
+        }
+    }
+}
+
+/// An image, and the sampler to read it with
+#[derive(AsBindGroup)]
+struct TexturedMaterial {
+    #[texture(0)]
+    image: GpuImage,
+    #[sampler(1)]
+    sampler: Sampler,
+}
+
+impl Material for TexturedMaterial {
+    fn fragment_shader() -> ShaderRef {
+        ShaderRef::Wgsl(include_str!("textured.wgsl"))
+    }
+}
+
+/// Stripes that scroll over time. The time comes from
+/// the view uniform, so the material itself never
+/// changes.
+#[derive(AsBindGroup)]
+struct AnimatedMaterial {
+    #[uniform(0)]
+    stripes: Stripes,
+}
+
+#[derive(ShaderType)]
+struct Stripes {
+    color_a: Vec4,
+    color_b: Vec4,
+    speed: f32,
+}
+
+impl Material for AnimatedMaterial {
+    fn fragment_shader() -> ShaderRef {
+        ShaderRef::Wgsl(include_str!("animated.wgsl"))
+    }
+}
+
+/// A material's pipeline, and the variants of it created
+/// so far
+struct MaterialPipelines<M: Material> {
+    pipeline: MaterialPipeline<M>,
+    specialized:
+        SpecializedMeshPipelines<MaterialPipeline<M>>,
+}
+
+impl<M: Material> MaterialPipelines<M> {
+    fn new(
+        device: &Device,
+        layouts: &MaterialLayouts,
+        format: TextureFormat,
+    ) -> Self {
+        Self {
+            pipeline: MaterialPipeline::new(
+                device,
+                layouts,
+                format,
+                DEPTH_FORMAT,
+            ),
+            specialized: SpecializedMeshPipelines::default(
+            ),
+        }
+    }
+}
+
+/// Everything needed to draw one mesh. The material's
+/// type is gone by this point: all that's left is a
+/// pipeline and a bind group.
+struct MeshObject {
+    mesh: GpuMesh,
+    render_pipeline: RenderPipeline,
+    mesh_uniform: Uniform<MeshUniform>,
+    mesh_bind_group: BindGroup,
+    material_bind_group: BindGroup,
+    alpha_mode: AlphaMode,
+    translation: Vec3,
+}
+
+impl MeshObject {
+    fn new<M: Material>(
+        device: &Device,
+        layouts: &MaterialLayouts,
+        pipelines: &mut MaterialPipelines<M>,
+        mesh: &Mesh,
+        material: &M,
+        translation: Vec3,
+    ) -> Self {
+        let render_pipeline = pipelines
+            .specialized
+            .specialize(
+                device,
+                &pipelines.pipeline,
+                MaterialPipeline::key(material, mesh),
+                &mesh.get_mesh_vertex_buffer_layout(),
+            )
+            .expect(
+                "mesh to have the attributes materials use",
+            );
+        let material_bind_group = material.as_bind_group(
+            device,
+            pipelines.pipeline.material_layout(),
+        );
+
+        let mesh_uniform = Uniform::new(
+            device,
+            "mesh_uniform",
+            MeshUniform {
+                model: Mat4::IDENTITY,
+            },
+        );
+        let mesh_bind_group =
+            device.create_bind_group(
+                &wgpu::BindGroupDescriptor {
+                    label: "mesh_bind_group".into(),
+                    layout: &layouts.mesh,
+                    entries: &[
+                        mesh_uniform.bind_group_entry(0)
+                    ],
+                },
+            );
+
+        Self {
+            mesh: mesh.upload(device, M::label()),
+            render_pipeline,
+            mesh_uniform,
+            mesh_bind_group,
+            material_bind_group,
+            alpha_mode: material.alpha_mode(),
+            translation,
+        }
+    }
+}
+
+struct ResumedData<'a> {
+    window: Arc<Window>,
+    config: SurfaceConfiguration,
+    surface: Surface<'a>,
+    device: Device,
+    queue: Queue,
+    depth_view: TextureView,
+    view_uniform: Uniform<ViewUniform>,
+    view_bind_group: BindGroup,
+    objects: Vec<MeshObject>,
+}
+
+struct App<'a> {
+    resumed_data: Option<ResumedData<'a>>,
+    start: Instant,
+}
+
+impl Default for App<'_> {
+    fn default() -> Self {
+        Self {
+            resumed_data: Default::default(),
+            start: Instant::now(),
+        }
+    }
+}
+
+/// A cube with 24 vertices (4 per face, so each face gets
+/// its own normals and uvs).
+fn cube() -> Mesh {
+    // (normal, up) for each face
+    let faces = [
+        (Vec3::X, Vec3::Y),
+        (Vec3::NEG_X, Vec3::Y),
+        (Vec3::Y, Vec3::Z),
+        (Vec3::NEG_Y, Vec3::Z),
+        (Vec3::Z, Vec3::Y),
+        (Vec3::NEG_Z, Vec3::Y),
+    ];
+
+    let mut positions = vec![];
+    let mut normals = vec![];
+    let mut uvs = vec![];
+    let mut indices = vec![];
+    for (normal, up) in faces {
+        let right = up.cross(normal);
+        let base = positions.len() as u16;
+        for (u, v) in
+            [(0., 0.), (1., 0.), (1., 1.), (0., 1.)]
+        {
+            let position = normal * 0.5
+                + right * (u - 0.5)
+                + up * (v - 0.5);
+            positions.push(position.to_array());
+            normals.push(normal.to_array());
+            uvs.push([u, 1.0 - v]);
+        }
+        indices.extend([
+            base,
+            base + 1,
+            base + 2,
+            base,
+            base + 2,
+            base + 3,
+        ]);
+    }
+
+    Mesh::new(PrimitiveTopology::TriangleList)
+        .with_inserted_attribute(
+            Mesh::ATTRIBUTE_POSITION,
+            positions,
+        )
+        .with_inserted_attribute(
+            Mesh::ATTRIBUTE_NORMAL,
+            normals,
+        )
+        .with_inserted_attribute(Mesh::ATTRIBUTE_UV_0, uvs)
+        .with_inserted_indices(Indices::U16(indices))
+}
+
+fn create_depth_view(
+    device: &Device,
+    config: &SurfaceConfiguration,
+) -> TextureView {
+    device
+        .create_texture(&wgpu::TextureDescriptor {
+            label: "depth_texture".into(),
+            size: wgpu::Extent3d {
+                width: config.width,
+                height: config.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: DEPTH_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        })
+        .create_view(&wgpu::TextureViewDescriptor::default())
+}
+
+/// Winit
+impl<'a> ApplicationHandler for App<'a> {
+    fn resumed(
+        &mut self,
+        event_loop: &winit::event_loop::ActiveEventLoop,
+    ) {
+        let window_attributes =
+            Window::default_attributes()
+                .with_title("wgpu for Bevy");
+
+        let window = Arc::new(
+            event_loop
+                .create_window(window_attributes)
+                .unwrap(),
+        );
+
+        let mut size = window.inner_size();
+        size.width = size.width.max(1);
+        size.height = size.height.max(1);
+
+        let instance = wgpu::Instance::default();
+
+        // `surface_window` is captured by the async closure,
+        // so we clone our Arc and let the closure take it
+        let surface_window = window.clone();
+        // wgpu apis for getting an adapter are async,
+        // so we block while waiting for them to complete
+        let (surface, adapter, device, queue) = block_on(
+            async move {
+                let surface = instance
+                    .create_surface(surface_window)
+                    .unwrap();
+
+                let adapter = instance
+                    .request_adapter(&wgpu::RequestAdapterOptions {
+                        power_preference:
+                            wgpu::PowerPreference::default(),
+                        force_fallback_adapter: false,
+                        // Request an adapter which can render to our surface
+                        compatible_surface: Some(&surface),
+                    })
+                    .await
+                    .expect(
+                        "Failed to find an appropriate adapter",
+                    );
+
+                info!(adapter=?adapter.get_info());
+
+                // Create the logical device and command
+                // queue
+                let (device, queue) = adapter
+                    .request_device(
+                        &wgpu::DeviceDescriptor::default(),
+                    )
+                    .await
+                    .expect("Failed to create device");
+
+                (surface, adapter, device, queue)
+            },
+        );
+
+        let swapchain_format =
+            surface.get_capabilities(&adapter).formats[0];
+
+        let layouts = MaterialLayouts::new(&device);
+        let mut flat_pipelines =
+            MaterialPipelines::<FlatColorMaterial>::new(
+                &device,
+                &layouts,
+                swapchain_format,
+            );
+        let mut textured_pipelines =
+            MaterialPipelines::<TexturedMaterial>::new(
+                &device,
+                &layouts,
+                swapchain_format,
+            );
+        let mut animated_pipelines =
+            MaterialPipelines::<AnimatedMaterial>::new(
+                &device,
+                &layouts,
+                swapchain_format,
+            );
+
+        let image = Image::load(IMAGE_PATH, true)
+            .expect("checker image to load");
+        let textured = TexturedMaterial {
+            image: image.upload(
+                &device,
+                &queue,
+                "checker_texture",
+            ),
+            sampler: device.create_sampler(
+                &wgpu::SamplerDescriptor {
+                    label: "checker_sampler".into(),
+                    ..Default::default()
+                },
+            ),
+        };
+
+        let cube = cube();
+        let objects = vec![
+            MeshObject::new(
+                &device,
+                &layouts,
+                &mut flat_pipelines,
+                &cube,
+                &FlatColorMaterial {
+                    color: Vec4::new(0.9, 0.3, 0.2, 1.0),
+                },
+                Vec3::new(-1.8, 0.0, 0.0),
+            ),
+            MeshObject::new(
+                &device,
+                &layouts,
+                &mut textured_pipelines,
+                &cube,
+                &textured,
+                Vec3::new(-0.6, 0.0, 0.0),
+            ),
+            MeshObject::new(
+                &device,
+                &layouts,
+                &mut animated_pipelines,
+                &cube,
+                &AnimatedMaterial {
+                    stripes: Stripes {
+                        color_a: Vec4::new(
+                            0.1, 0.6, 0.9, 1.0,
+                        ),
+                        color_b: Vec4::new(
+                            0.9, 0.9, 0.2, 1.0,
+                        ),
+                        speed: 4.0,
+                    },
+                },
+                Vec3::new(0.6, 0.0, 0.0),
+            ),
+            // same material type as the first cube, but
+            // translucent, so it gets a blending pipeline
+            MeshObject::new(
+                &device,
+                &layouts,
+                &mut flat_pipelines,
+                &cube,
+                &FlatColorMaterial {
+                    color: Vec4::new(0.3, 0.9, 0.4, 0.5),
+                },
+                Vec3::new(1.8, 0.0, 0.0),
+            ),
+        ];
+        info!(
+            flat_color_pipelines =
+                flat_pipelines.specialized.len(),
+            textured_pipelines =
+                textured_pipelines.specialized.len(),
+            animated_pipelines =
+                animated_pipelines.specialized.len(),
+        );
+
+        let view_uniform = Uniform::new(
+            &device,
+            "view_uniform",
+            ViewUniform {
+                view_proj: Mat4::IDENTITY,
+                time: 0.0,
+            },
+        );
+        let view_bind_group =
+            device.create_bind_group(
+                &wgpu::BindGroupDescriptor {
+                    label: "view_bind_group".into(),
+                    layout: &layouts.view,
+                    entries: &[
+                        view_uniform.bind_group_entry(0)
+                    ],
+                },
+            );
+
+        let config = surface
+            .get_default_config(
+                &adapter,
+                size.width,
+                size.height,
+            )
+            .unwrap();
+        surface.configure(&device, &config);
+        let depth_view =
+            create_depth_view(&device, &config);
+
+        self.resumed_data = Some(ResumedData {
+            window,
+            config,
+            surface,
+            device,
+            queue,
+            depth_view,
+            view_uniform,
+            view_bind_group,
+            objects,
+        });
+    }
+
+    fn window_event(
+        &mut self,
+        event_loop: &winit::event_loop::ActiveEventLoop,
+        _window_id: winit::window::WindowId,
+        event: WindowEvent,
+    ) {
+        match event {
+            WindowEvent::CloseRequested => {
+                event_loop.exit();
+            }
+            WindowEvent::Resized(PhysicalSize {
+                width,
+                height,
+            }) => {
+                let Some(ResumedData {
+                    config,
+                    surface,
+                    device,
+                    depth_view,
+                    ..
+                }) = self.resumed_data.as_mut()
+                else {
+                    return;
+                };
+
+                // Reconfigure the surface with the new size,
+                // making it so that the window is *at least* 1x1
+                config.width = width.max(1);
+                config.height = height.max(1);
+
+                surface.configure(device, config);
+                // the depth texture has to match the size
+                // of the surface texture
+                *depth_view =
+                    create_depth_view(device, config);
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        logical_key: key,
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } => {
+                // allow a single_match here so that people
+                // who use this example can easily match on
+                // new keys
+                #[allow(clippy::single_match)]
+                match key.as_ref() {
+                    Key::Named(NamedKey::Escape) => {
+                        event_loop.exit();
+                    }
+                    _ => (),
+                }
+            }
+            WindowEvent::RedrawRequested => {
+                let Some(ResumedData {
+                    config,
+                    surface,
+                    device,
+                    queue,
+                    depth_view,
+                    view_uniform,
+                    view_bind_group,
+                    objects,
+                    ..
+                }) = self.resumed_data.as_mut()
+                else {
+                    return;
+                };
+
+                let time =
+                    self.start.elapsed().as_secs_f32();
+                let aspect = config.width as f32
+                    / config.height as f32;
+                view_uniform.set(ViewUniform {
+                    view_proj: Mat4::perspective_rh(
+                        PI / 4.0,
+                        aspect,
+                        0.1,
+                        100.0,
+                    ) * Mat4::look_at_rh(
+                        Vec3::new(0.0, 1.5, 5.0),
+                        Vec3::ZERO,
+                        Vec3::Y,
+                    ),
+                    time,
+                });
+                view_uniform.upload(queue);
+
+                for object in objects.iter_mut() {
+                    object.mesh_uniform.set(MeshUniform {
+                        model:
+                            Mat4::from_rotation_translation(
+                                Quat::from_euler(
+                                    glam::EulerRot::YXZ,
+                                    time * 0.5,
+                                    time * 0.3,
+                                    0.0,
+                                ),
+                                object.translation,
+                            ) * Mat4::from_scale(
+                                Vec3::splat(0.8),
+                            ),
+                    });
+                    object.mesh_uniform.upload(queue);
+                }
+
+                let frame = surface
+                    .get_current_texture()
+                    .expect("Failed to acquire next swap chain texture");
+                let view = frame.texture.create_view(
+                    &wgpu::TextureViewDescriptor::default(),
+                );
+                let mut encoder = device
+                    .create_command_encoder(
+                        &wgpu::CommandEncoderDescriptor {
+                            label:
+                                "materials_command_encoder"
+                                    .into(),
+                        },
+                    );
+                {
+                    let mut rpass =
+                    encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                        label: "materials_render_pass".into(),
+                        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                            view: &view,
+                            resolve_target: None,
+                            ops: wgpu::Operations {
+                                load: wgpu::LoadOp::Clear(wgpu::Color{
+                                    r: 0.008,
+                                    g: 0.024,
+                                    b: 0.09,
+                                    a: 1.0,
+                                }),
+                                store: wgpu::StoreOp::Store,
+                            },
+                            depth_slice: None
+                        })],
+                        depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                            view: depth_view,
+                            depth_ops: Some(wgpu::Operations {
+                                load: wgpu::LoadOp::Clear(1.0),
+                                store: wgpu::StoreOp::Discard,
+                            }),
+                            stencil_ops: None,
+                        }),
+                        timestamp_writes: None,
+                        occlusion_query_set: None,
+                        multiview_mask: None
+                    });
+                    rpass.set_bind_group(
+                        0,
+                        Some(&*view_bind_group),
+                        &[],
+                    );
+                    // blended objects go last so what's
+                    // behind them has already been drawn,
+                    // like Bevy's opaque and transparent
+                    // phases
+                    let opaque =
+                        objects.iter().filter(|o| {
+                            o.alpha_mode
+                                == AlphaMode::Opaque
+                        });
+                    let blended =
+                        objects.iter().filter(|o| {
+                            o.alpha_mode == AlphaMode::Blend
+                        });
+                    for object in opaque.chain(blended) {
+                        rpass.set_pipeline(
+                            &object.render_pipeline,
+                        );
+                        rpass.set_bind_group(
+                            1,
+                            Some(&object.mesh_bind_group),
+                            &[],
+                        );
+                        rpass.set_bind_group(
+                            2,
+                            Some(
+                                &object.material_bind_group,
+                            ),
+                            &[],
+                        );
+                        object.mesh.draw(&mut rpass);
+                    }
+                }
+
+                queue.submit(Some(encoder.finish()));
+                frame.present();
+            }
+            _ => (),
+        }
+    }
+    fn about_to_wait(
+        &mut self,
+        _event_loop: &ActiveEventLoop,
+    ) {
+        let Some(data) = self.resumed_data.as_ref() else {
+            return;
+        };
+
+        data.window.request_redraw();
+    }
+}
+
+fn main() {
+    tracing_subscriber::fmt().init();
+
+    let event_loop = EventLoop::new().unwrap();
+    event_loop.set_control_flow(ControlFlow::Poll);
+
+    let mut app = App::default();
+
+    event_loop.run_app(&mut app).expect("app to run")
+}
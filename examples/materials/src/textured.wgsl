@@ -0,0 +1,19 @@
+// These match the bindings and vertex output of the
+// default material shader in wgpu-for-bevy
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) world_normal: vec3<f32>,
+    @location(1) uv: vec2<f32>,
+}
+
+@group(2) @binding(0) var image: texture_2d<f32>;
+@group(2) @binding(1) var image_sampler: sampler;
+
+@fragment
+fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
+    let light = normalize(vec3<f32>(0.4, 1.0, 0.6));
+    let diffuse = max(dot(normalize(in.world_normal), light), 0.0);
+    let color = textureSample(image, image_sampler, in.uv);
+    return vec4<f32>(color.rgb * (0.2 + 0.8 * diffuse), color.a);
+}
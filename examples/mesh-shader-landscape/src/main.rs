@@ -1,13 +1,21 @@
-use encase::{ShaderType, UniformBuffer};
+use encase::ShaderType;
 use futures_lite::future::block_on;
-use std::{sync::Arc, time::Instant};
-use tracing::info;
+use std::{path::PathBuf, sync::Arc, time::Duration};
+use tracing::{Instrument, error, info, info_span, warn};
 use wgpu::{
-    BindGroup, BindGroupEntry, BindGroupLayoutDescriptor,
-    BindGroupLayoutEntry, BindingType, BufferBindingType,
-    Device, ExperimentalFeatures, Limits, Queue,
-    RenderPipeline, ShaderStages, Surface,
-    SurfaceConfiguration, TaskState, util::DeviceExt,
+    BindGroup, CommandEncoder, Device,
+    ExperimentalFeatures, Limits, Queue,
+    RenderPassTimestampWrites, RenderPipeline, Surface,
+    SurfaceConfiguration, TextureView,
+};
+use wgpu_for_bevy::{
+    AsBindGroup, CachedPipelineId, CachedPipelineState,
+    Clocks, FpsOverlay, FrameRecorder, GpuProfiler,
+    PipelineCache, RecordingOutput, TimeUpdateStrategy,
+    Uniform,
+    pipeline_cache::{
+        FragmentState, MeshPipelineDescriptor, ShaderStage,
+    },
 };
 use winit::{
     application::ApplicationHandler,
@@ -21,39 +29,153 @@ use winit::{
 struct ResumedData<'a> {
     window: Arc<Window>,
     config: SurfaceConfiguration,
-    render_pipeline: RenderPipeline,
+    pipeline_cache: PipelineCache,
+    render_pipeline: CachedPipelineId,
     surface: Surface<'a>,
     device: Device,
     queue: Queue,
     time_bind_group: BindGroup,
-    time_uniform_buffer: wgpu::Buffer,
+    time_bindings: TimeBindings,
+    recorder: Option<FrameRecorder>,
+    fps_overlay: FpsOverlay,
+    profiler: GpuProfiler,
 }
 
 struct App<'a> {
     resumed_data: Option<ResumedData<'a>>,
-    start: Instant,
+    clocks: Clocks,
+    args: Args,
 }
 
-impl Default for App<'_> {
-    fn default() -> Self {
+impl App<'_> {
+    fn new(args: Args) -> Self {
+        // recordings step time by exactly one frame,
+        // however long frames take to render
+        let strategy = if args.record.is_some() {
+            TimeUpdateStrategy::FixedStep(
+                Duration::from_secs_f64(
+                    1.0 / args.fps as f64,
+                ),
+            )
+        } else {
+            TimeUpdateStrategy::Automatic
+        };
+        let mut clocks = Clocks::new(strategy);
+        clocks.virtual_time.set_elapsed(args.time);
+
         Self {
             resumed_data: Default::default(),
-            start: Instant::now(),
+            clocks,
+            args,
         }
     }
 }
 
+struct Args {
+    /// What `time` starts at
+    time: Duration,
+    /// A directory for a PNG sequence, or a `.y4m` file
+    record: Option<PathBuf>,
+    fps: u32,
+    /// Exit after recording this many frames
+    frames: Option<u64>,
+    /// Measure how long each pass takes on the GPU
+    profile_gpu: bool,
+    /// Also write the GPU timings to a Chrome trace
+    gpu_trace: Option<PathBuf>,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut args = Args {
+        time: Duration::ZERO,
+        record: None,
+        fps: 60,
+        frames: None,
+        profile_gpu: false,
+        gpu_trace: None,
+    };
+
+    let mut iter = std::env::args().skip(1);
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--time" => {
+                args.time = iter
+                    .next()
+                    .and_then(|time| {
+                        Duration::try_from_secs_f64(
+                            time.parse().ok()?,
+                        )
+                        .ok()
+                    })
+                    .ok_or("--time expects a number of seconds")?;
+            }
+            "--record" => {
+                args.record = Some(
+                    iter.next()
+                        .ok_or("--record expects a directory or a .y4m file")?
+                        .into(),
+                );
+            }
+            "--fps" => {
+                args.fps = iter
+                    .next()
+                    .and_then(|fps| fps.parse().ok())
+                    .filter(|fps| *fps > 0)
+                    .ok_or("--fps expects a frame rate")?;
+            }
+            "--frames" => {
+                args.frames = Some(
+                    iter.next()
+                        .and_then(|frames| frames.parse().ok())
+                        .ok_or("--frames expects a frame count")?,
+                );
+            }
+            "--profile-gpu" => args.profile_gpu = true,
+            "--gpu-trace" => {
+                args.profile_gpu = true;
+                args.gpu_trace = Some(
+                    iter.next()
+                        .ok_or(
+                            "--gpu-trace expects a file",
+                        )?
+                        .into(),
+                );
+            }
+            "-h" | "--help" => {
+                return Err("usage: mesh-shader-landscape [--time SECONDS] [--record DIR|FILE.y4m] [--fps N] [--frames N] [--profile-gpu] [--gpu-trace FILE.json]".to_string());
+            }
+            _ => {
+                return Err(format!(
+                    "unknown argument `{arg}`"
+                ));
+            }
+        }
+    }
+    Ok(args)
+}
+
 #[derive(ShaderType)]
 struct ShaderData {
     time: f32,
 }
 
+/// The bind group every stage reads the time from. The
+/// `Uniform` owns the buffer, so updating the time
+/// doesn't need a new bind group.
+#[derive(AsBindGroup)]
+struct TimeBindings {
+    #[uniform(0, buffer, visibility(all))]
+    uniform: Uniform<ShaderData>,
+}
+
 /// Winit
 impl<'a> ApplicationHandler for App<'a> {
     fn resumed(
         &mut self,
         event_loop: &winit::event_loop::ActiveEventLoop,
     ) {
+        let _startup_span = info_span!("startup").entered();
+
         let window_attributes =
             Window::default_attributes()
                 .with_title("wgpu for Bevy");
@@ -73,6 +195,7 @@ impl<'a> ApplicationHandler for App<'a> {
         // `surface_window` is captured by the async closure,
         // so we clone our Arc and let the closure take it
         let surface_window = window.clone();
+        let profile_gpu = self.args.profile_gpu;
         // wgpu apis for getting an adapter are async,
         // so we block while waiting for them to complete
         let (surface, adapter, device, queue) = block_on(
@@ -89,6 +212,7 @@ impl<'a> ApplicationHandler for App<'a> {
                         // Request an adapter which can render to our surface
                         compatible_surface: Some(&surface),
                     })
+                    .instrument(info_span!("request_adapter"))
                     .await
                     .expect(
                         "Failed to find an appropriate adapter",
@@ -107,18 +231,32 @@ impl<'a> ApplicationHandler for App<'a> {
 
                 info!(adapter=?adapter.get_info());
 
+                // timestamp queries are only requested when
+                // profiling, and only the ones the adapter
+                // has
+                let profiler_features = if profile_gpu {
+                    adapter.features()
+                        & GpuProfiler::FEATURES
+                } else {
+                    wgpu::Features::empty()
+                };
                 // Create the logical device and command
                 // queue
                 let (device, queue) = adapter
                     .request_device(
                         &wgpu::DeviceDescriptor{
                             label: Some("mesh_adapter"),
-                            required_features:  wgpu::Features::EXPERIMENTAL_MESH_SHADER,
+                            // the pipeline cache is only kept on disk
+                            // where the backend supports it
+                            required_features: wgpu::Features::EXPERIMENTAL_MESH_SHADER
+                                | (adapter.features() & wgpu::Features::PIPELINE_CACHE)
+                                | profiler_features,
                             experimental_features: unsafe { ExperimentalFeatures::enabled() },
                             required_limits: Limits::default().using_recommended_minimum_mesh_shader_values(),
                            ..Default::default()
                         },
                     )
+                    .instrument(info_span!("request_device"))
                     .await
                     .expect("Failed to create device");
 
@@ -130,6 +268,8 @@ impl<'a> ApplicationHandler for App<'a> {
             },
         );
 
+        let shaders_span =
+            info_span!("create_shader_modules").entered();
         info!("build task_shader");
         let task_shader = device.create_shader_module(
             wgpu::ShaderModuleDescriptor {
@@ -168,51 +308,25 @@ impl<'a> ApplicationHandler for App<'a> {
                 ),
             },
         );
-        let time_layout = device.create_bind_group_layout(
-            &BindGroupLayoutDescriptor {
-                label: "time_layout".into(),
-                entries: &[BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: ShaderStages::all(),
-                    ty: BindingType::Buffer {
-                        ty: BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: Some(
-                            ShaderData::min_size(),
-                        ),
-                    },
-                    count: None,
-                }],
-            },
-        );
-        let mut buffer =
-            UniformBuffer::new(Vec::<u8>::new());
-        let data = ShaderData {
-            time: self.start.elapsed().as_secs_f32(),
+        drop(shaders_span);
+        let time_layout =
+            TimeBindings::bind_group_layout(&device);
+        let mut time_bindings = TimeBindings {
+            uniform: Uniform::new(
+                &device,
+                "shader_data_uniform_buffer",
+                ShaderData {
+                    time: self
+                        .clocks
+                        .virtual_time
+                        .elapsed_secs(),
+                },
+            ),
         };
-        buffer.write(&data).unwrap();
-        let byte_buffer = buffer.into_inner();
-
-        let time_uniform_buf = device.create_buffer_init(
-            &wgpu::util::BufferInitDescriptor {
-                label: Some("shader_data_uniform_buffer"),
-                contents: &byte_buffer,
-                usage: wgpu::BufferUsages::UNIFORM
-                    | wgpu::BufferUsages::COPY_DST,
-            },
-        );
-        // uniform.wri
-        let time_bind_group = device.create_bind_group(
-            &wgpu::BindGroupDescriptor {
-                label: "time_bind_group".into(),
-                layout: &time_layout,
-                entries: &[BindGroupEntry {
-                    binding: 0,
-                    resource: time_uniform_buf
-                        .as_entire_binding(),
-                }],
-            },
-        );
+        time_bindings.uniform.upload(&queue);
+
+        let time_bind_group = time_bindings
+            .as_bind_group(&device, &time_layout);
         let pipeline_layout = device
             .create_pipeline_layout(
                 &wgpu::PipelineLayoutDescriptor {
@@ -222,56 +336,116 @@ impl<'a> ApplicationHandler for App<'a> {
                 },
             );
 
-        let swapchain_format =
-            surface.get_capabilities(&adapter).formats[0];
-
-        let render_pipeline = device.create_mesh_pipeline(
-            &wgpu::MeshPipelineDescriptor {
-                label: "mesh_shader_pipeline".into(),
-                layout: Some(&pipeline_layout),
-                task: Some(TaskState {
-                    module: &task_shader,
+        let capabilities =
+            surface.get_capabilities(&adapter);
+        let swapchain_format = capabilities.formats[0];
+
+        // compiled off the render thread, frames are
+        // cleared without drawing until it's ready. The
+        // compile itself is traced on that thread.
+        let pipelines_span =
+            info_span!("queue_pipelines").entered();
+        let mut pipeline_cache =
+            PipelineCache::with_disk_cache(
+                &device,
+                &adapter.get_info(),
+                std::env::temp_dir().join("wgpu-for-bevy"),
+            );
+        let render_pipeline = pipeline_cache
+            .queue_mesh_pipeline(MeshPipelineDescriptor {
+                label: Some("mesh_shader_pipeline".into()),
+                layout: Some(pipeline_layout),
+                task: Some(ShaderStage {
+                    module: task_shader,
                     entry_point: "task".into(),
-                    compilation_options: Default::default(),
                 }),
-                mesh: wgpu::MeshState {
-                    module: &mesh_shader,
+                mesh: ShaderStage {
+                    module: mesh_shader,
                     entry_point: "mesh".into(),
-                    compilation_options: Default::default(),
                 },
-                fragment: Some(wgpu::FragmentState {
-                    module: &fragment_shader,
+                fragment: Some(FragmentState {
+                    module: fragment_shader,
                     entry_point: "fragment".into(),
-                    compilation_options: Default::default(),
-                    targets: &[Some(
+                    targets: vec![Some(
                         swapchain_format.into(),
                     )],
                 }),
                 primitive: Default::default(),
                 depth_stencil: None,
                 multisample: Default::default(),
-                cache: None,
-                multiview: None,
-            },
-        );
+            });
+        drop(pipelines_span);
 
-        let config = surface
+        let mut config = surface
             .get_default_config(
                 &adapter,
                 size.width,
                 size.height,
             )
             .unwrap();
-        surface.configure(&device, &config);
+        let recorder = self.args.record.as_ref().map(|path| {
+            let recorder = FrameRecorder::new(
+                RecordingOutput::from_path(path),
+                self.args.fps,
+                &capabilities,
+            )
+            .unwrap_or_else(|error| {
+                panic!(
+                    "failed to record to {}: {error}",
+                    path.display()
+                )
+            });
+            // lets frames be copied straight out of the
+            // surface texture
+            config.usage = recorder.surface_usage();
+            info!(path = %path.display(), fps = self.args.fps, "recording");
+            recorder
+        });
+        info_span!("configure_surface").in_scope(|| {
+            surface.configure(&device, &config)
+        });
+
+        let mut fps_overlay = FpsOverlay::new(
+            &device,
+            &queue,
+            config.format,
+            &adapter.get_info(),
+        );
+        // recordings shouldn't have the overlay in them
+        // unless it's asked for with F3
+        fps_overlay.set_visible(recorder.is_none());
+
+        let mut profiler =
+            GpuProfiler::new(&device, &queue);
+        if let Some(path) = &self.args.gpu_trace {
+            profiler = profiler
+                .with_chrome_trace(path)
+                .unwrap_or_else(|error| {
+                    panic!(
+                        "failed to create {}: {error}",
+                        path.display()
+                    )
+                });
+        }
+        if self.args.profile_gpu && !profiler.is_enabled() {
+            warn!(
+                "the adapter doesn't support timestamp queries, GPU times won't be measured"
+            );
+        }
+
         self.resumed_data = Some(ResumedData {
             window,
             config,
+            pipeline_cache,
             render_pipeline,
             surface,
             device,
             queue,
             time_bind_group,
-            time_uniform_buffer: time_uniform_buf,
+            time_bindings,
+            recorder,
+            fps_overlay,
+            profiler,
         });
     }
 
@@ -306,7 +480,7 @@ impl<'a> ApplicationHandler for App<'a> {
                 config.width = width.max(1);
                 config.height = height.max(1);
 
-                surface.configure(&device, &config);
+                surface.configure(device, config);
             }
             WindowEvent::KeyboardInput {
                 event:
@@ -317,60 +491,122 @@ impl<'a> ApplicationHandler for App<'a> {
                     },
                 ..
             } => {
-                // allow a single_match here so that people
-                // who use this example can easily match on
-                // new keys
-                #[allow(clippy::single_match)]
+                let time = &mut self.clocks.virtual_time;
                 match key.as_ref() {
+                    Key::Named(NamedKey::F3) => {
+                        if let Some(data) =
+                            self.resumed_data.as_mut()
+                        {
+                            data.fps_overlay.toggle();
+                        }
+                    }
                     // WARNING: Consider using
                     // `key_without_modifiers()` if
                     // available on your platform.
                     Key::Named(NamedKey::Escape) => {
                         event_loop.exit();
                     }
+                    Key::Named(NamedKey::Space) => {
+                        time.toggle();
+                        info!(paused = time.is_paused());
+                    }
+                    Key::Character("-") => {
+                        time.set_relative_speed(
+                            time.relative_speed() / 2.0,
+                        );
+                        info!(
+                            speed = time.relative_speed()
+                        );
+                    }
+                    Key::Character("=" | "+") => {
+                        time.set_relative_speed(
+                            time.relative_speed() * 2.0,
+                        );
+                        info!(
+                            speed = time.relative_speed()
+                        );
+                    }
+                    Key::Character("0") => {
+                        time.set_relative_speed(1.0);
+                        info!(
+                            speed = time.relative_speed()
+                        );
+                    }
                     _ => (),
                 }
             }
             WindowEvent::RedrawRequested => {
+                let _frame_span =
+                    info_span!("frame").entered();
+
+                // once per frame, so everything drawn in
+                // this frame sees the same time
+                self.clocks.update();
+
                 let Some(ResumedData {
                     surface,
                     device,
                     queue,
+                    pipeline_cache,
                     render_pipeline,
+                    time_bind_group,
+                    time_bindings,
+                    recorder,
+                    fps_overlay,
+                    profiler,
+                    config,
                     ..
-                }) = self.resumed_data.as_ref()
+                }) = self.resumed_data.as_mut()
                 else {
                     return;
                 };
+                fps_overlay.update();
+
+                let pipelines_span =
+                    info_span!("process_pipelines")
+                        .entered();
+                pipeline_cache.process_queue();
+                // recordings wait for the pipeline instead of
+                // starting with empty frames
+                if recorder.is_some() {
+                    pipeline_cache.block_on_pipeline(
+                        *render_pipeline,
+                    );
+                }
+                drop(pipelines_span);
+                if let CachedPipelineState::Error(error) =
+                    pipeline_cache.get_pipeline_state(
+                        *render_pipeline,
+                    )
+                {
+                    panic!("{error}");
+                }
 
-                let frame = surface
-                    .get_current_texture()
+                let frame = info_span!("acquire_surface_texture")
+                    .in_scope(|| surface.get_current_texture())
                     .expect("Failed to acquire next swap chain texture");
                 let view = frame.texture.create_view(
                     &wgpu::TextureViewDescriptor::default(),
                 );
 
-                let mut buffer =
-                    UniformBuffer::new(Vec::<u8>::new());
-                let data = ShaderData {
-                    time: self
-                        .start
-                        .elapsed()
-                        .as_secs_f32(),
-                };
-                dbg!(self.start.elapsed().as_secs_f32());
-
-                buffer.write(&data).unwrap();
-                let byte_buffer = buffer.into_inner();
-                queue.write_buffer(
-                    &self
-                        .resumed_data
-                        .as_ref()
-                        .unwrap()
-                        .time_uniform_buffer,
-                    0,
-                    &byte_buffer,
+                let upload_span =
+                    info_span!("upload").entered();
+                let time =
+                    self.clocks.virtual_time.elapsed_secs();
+                time_bindings
+                    .uniform
+                    .set(ShaderData { time });
+                time_bindings.uniform.upload(queue);
+                fps_overlay.prepare(
+                    device,
+                    queue,
+                    config.width,
+                    config.height,
                 );
+                drop(upload_span);
+
+                let encode_span =
+                    info_span!("encode").entered();
                 let mut encoder = device
                     .create_command_encoder(
                         &wgpu::CommandEncoderDescriptor {
@@ -379,57 +615,97 @@ impl<'a> ApplicationHandler for App<'a> {
                                     .into(),
                         },
                     );
-                {
-                    let mut rpass =
-                    encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                        label: "triangle_render_pass".into(),
-                        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                            view: &view,
-                            resolve_target: None,
-                            ops: wgpu::Operations {
-                                load: wgpu::LoadOp::Clear(wgpu::Color{
-                                    r: 0.008,
-                                    g: 0.024,
-                                    b: 0.09,
-                                    a: 1.0,
-                                }),
-                                store: wgpu::StoreOp::Store,
-                            },
-                            // depth_slice allows rendering to a layer of a texture array
-                            // or a slice of a 3d texture view
-                            depth_slice: None
-                        })],
-                        depth_stencil_attachment: None,
-                        timestamp_writes: None,
-                        occlusion_query_set: None,
-                        multiview_mask: None
-                    });
-                    rpass.push_debug_group(
-                        "Prepare data for draw.",
-                    );
-                    rpass.set_pipeline(&render_pipeline);
-                    rpass.set_bind_group(
-                        0,
-                        Some(
-                            &self
-                                .resumed_data
-                                .as_ref()
-                                .unwrap()
-                                .time_bind_group,
-                        ),
-                        &[],
+                let render_pipeline = pipeline_cache
+                    .get_render_pipeline(*render_pipeline);
+                draw(
+                    &mut encoder,
+                    &view,
+                    render_pipeline,
+                    time_bind_group,
+                    profiler.render_pass_timestamp_writes(
+                        "landscape_render_pass",
+                    ),
+                );
+                profiler.push_debug_group(
+                    &mut encoder,
+                    "fps_overlay",
+                );
+                fps_overlay.render(&mut encoder, &view);
+                profiler.pop_debug_group(&mut encoder);
+                if let Some(recorder) = recorder.as_mut() {
+                    recorder.capture(
+                        device,
+                        &mut encoder,
+                        &frame.texture,
+                        |encoder, view| {
+                            // drawing the frame again for the
+                            // recording isn't measured
+                            draw(
+                                encoder,
+                                view,
+                                render_pipeline,
+                                time_bind_group,
+                                None,
+                            );
+                            fps_overlay
+                                .render(encoder, view);
+                        },
                     );
-                    rpass.pop_debug_group();
-                    rpass.insert_debug_marker("Draw!");
-                    rpass.draw_mesh_tasks(1, 1, 1);
                 }
 
-                queue.submit(Some(encoder.finish()));
-                frame.present();
+                profiler.resolve(device, &mut encoder);
+                let command_buffer = encoder.finish();
+                drop(encode_span);
+
+                info_span!("submit").in_scope(|| {
+                    queue.submit(Some(command_buffer))
+                });
+                info_span!("present")
+                    .in_scope(|| frame.present());
+                profiler.update(device);
+
+                let Some(recorder) = recorder else {
+                    return;
+                };
+                if let Err(error) = info_span!("record")
+                    .in_scope(|| recorder.update(device))
+                {
+                    error!(%error, "recording failed");
+                    event_loop.exit();
+                }
+                if self.args.frames.is_some_and(|frames| {
+                    recorder.frame() >= frames
+                }) {
+                    event_loop.exit();
+                }
             }
             _ => (),
         }
     }
+    fn exiting(&mut self, _event_loop: &ActiveEventLoop) {
+        let Some(data) = self.resumed_data.as_mut() else {
+            return;
+        };
+
+        if let Some(recorder) = data.recorder.take() {
+            match recorder.finish(&data.device) {
+                Ok(frames) => {
+                    info!(frames, "finished recording")
+                }
+                Err(error) => {
+                    error!(%error, "recording failed")
+                }
+            }
+        }
+
+        if let Err(error) = data.pipeline_cache.save() {
+            warn!(
+                ?error,
+                "failed to save the pipeline cache"
+            );
+        }
+    }
+
     fn about_to_wait(
         &mut self,
         _event_loop: &ActiveEventLoop,
@@ -442,13 +718,93 @@ impl<'a> ApplicationHandler for App<'a> {
     }
 }
 
+/// Everything drawn in a frame. While the pipeline is
+/// still compiling the frame is only cleared.
+fn draw(
+    encoder: &mut CommandEncoder,
+    view: &TextureView,
+    render_pipeline: Option<&RenderPipeline>,
+    time_bind_group: &BindGroup,
+    timestamp_writes: Option<RenderPassTimestampWrites>,
+) {
+    let mut rpass = encoder.begin_render_pass(
+        &wgpu::RenderPassDescriptor {
+            label: "triangle_render_pass".into(),
+            color_attachments: &[Some(
+                wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(
+                            wgpu::Color {
+                                r: 0.008,
+                                g: 0.024,
+                                b: 0.09,
+                                a: 1.0,
+                            },
+                        ),
+                        store: wgpu::StoreOp::Store,
+                    },
+                    // depth_slice allows rendering to a layer of a texture array
+                    // or a slice of a 3d texture view
+                    depth_slice: None,
+                },
+            )],
+            depth_stencil_attachment: None,
+            timestamp_writes,
+            occlusion_query_set: None,
+            multiview_mask: None,
+        },
+    );
+    let Some(render_pipeline) = render_pipeline else {
+        return;
+    };
+    rpass.push_debug_group("Prepare data for draw.");
+    rpass.set_pipeline(render_pipeline);
+    rpass.set_bind_group(0, Some(time_bind_group), &[]);
+    rpass.pop_debug_group();
+    rpass.insert_debug_marker("Draw!");
+    rpass.draw_mesh_tasks(1, 1, 1);
+}
+
 fn main() {
+    #[cfg(feature = "trace_chrome")]
+    let _trace_guard = init_chrome_tracing();
+    #[cfg(not(feature = "trace_chrome"))]
     tracing_subscriber::fmt().init();
 
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(message) => {
+            eprintln!("{message}");
+            std::process::exit(1);
+        }
+    };
+
     let event_loop = EventLoop::new().unwrap();
     event_loop.set_control_flow(ControlFlow::Poll);
 
-    let mut app = App::default();
+    let mut app = App::new(args);
 
     event_loop.run_app(&mut app).expect("app to run")
 }
+
+/// Log the same way as `tracing_subscriber::fmt()`, and
+/// also write every span to a Chrome trace. The trace is
+/// finished when the returned guard is dropped.
+#[cfg(feature = "trace_chrome")]
+fn init_chrome_tracing() -> wgpu_for_bevy::FlushGuard {
+    use tracing_subscriber::{
+        filter::LevelFilter, prelude::*,
+    };
+
+    let (chrome_layer, guard) =
+        wgpu_for_bevy::ChromeLayer::from_env()
+            .expect("trace file to be created");
+    tracing_subscriber::registry()
+        .with(tracing_subscriber::fmt::layer())
+        .with(chrome_layer)
+        .with(LevelFilter::INFO)
+        .init();
+    guard
+}
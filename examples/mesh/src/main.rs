@@ -0,0 +1,704 @@
+use encase::ShaderType;
+use futures_lite::future::block_on;
+use glam::{Mat4, Quat, Vec3};
+use std::{
+    borrow::Cow, f32::consts::PI, sync::Arc, time::Instant,
+};
+use tracing::{error, info};
+use wgpu::{
+    BindGroup, Device, PipelineLayout, PrimitiveTopology,
+    Queue, RenderPipeline, ShaderModule, ShaderStages,
+    Surface, SurfaceConfiguration, TextureFormat,
+    TextureView,
+};
+use wgpu_for_bevy::{
+    GpuMesh, Indices, Mesh, MeshVertexBufferLayout,
+    SpecializedMeshPipeline, SpecializedMeshPipelineError,
+    SpecializedMeshPipelines, Uniform,
+};
+use winit::{
+    application::ApplicationHandler,
+    dpi::PhysicalSize,
+    event::{ElementState, KeyEvent, WindowEvent},
+    event_loop::{ActiveEventLoop, ControlFlow, EventLoop},
+    keyboard::{Key, NamedKey},
+    window::Window,
+};
+
+const DEPTH_FORMAT: TextureFormat =
+    TextureFormat::Depth32Float;
+
+#[derive(ShaderType)]
+struct MeshUniform {
+    view_proj: Mat4,
+    model: Mat4,
+}
+
+/// Everything needed to draw one mesh
+struct MeshObject {
+    mesh: GpuMesh,
+    render_pipeline: RenderPipeline,
+    uniform: Uniform<MeshUniform>,
+    bind_group: BindGroup,
+    translation: Vec3,
+}
+
+struct ResumedData<'a> {
+    window: Arc<Window>,
+    config: SurfaceConfiguration,
+    surface: Surface<'a>,
+    device: Device,
+    queue: Queue,
+    depth_view: TextureView,
+    objects: Vec<MeshObject>,
+}
+
+struct App<'a> {
+    resumed_data: Option<ResumedData<'a>>,
+    start: Instant,
+}
+
+impl Default for App<'_> {
+    fn default() -> Self {
+        Self {
+            resumed_data: Default::default(),
+            start: Instant::now(),
+        }
+    }
+}
+
+/// A cube with 24 vertices (4 per face, so each face gets
+/// its own normals) and 16-bit indices.
+fn cube() -> Mesh {
+    // (normal, up) for each face
+    let faces = [
+        (Vec3::X, Vec3::Y),
+        (Vec3::NEG_X, Vec3::Y),
+        (Vec3::Y, Vec3::Z),
+        (Vec3::NEG_Y, Vec3::Z),
+        (Vec3::Z, Vec3::Y),
+        (Vec3::NEG_Z, Vec3::Y),
+    ];
+
+    let mut positions = vec![];
+    let mut normals = vec![];
+    let mut uvs = vec![];
+    let mut colors = vec![];
+    let mut indices = vec![];
+    for (normal, up) in faces {
+        let right = up.cross(normal);
+        let base = positions.len() as u16;
+        for (u, v) in
+            [(0., 0.), (1., 0.), (1., 1.), (0., 1.)]
+        {
+            let position = normal * 0.5
+                + right * (u - 0.5)
+                + up * (v - 0.5);
+            positions.push(position.to_array());
+            normals.push(normal.to_array());
+            uvs.push([u, v]);
+            let color = normal.abs() * 0.6 + 0.4;
+            colors.push(color.extend(1.0).to_array());
+        }
+        indices.extend([
+            base,
+            base + 1,
+            base + 2,
+            base,
+            base + 2,
+            base + 3,
+        ]);
+    }
+
+    Mesh::new(PrimitiveTopology::TriangleList)
+        .with_inserted_attribute(
+            Mesh::ATTRIBUTE_POSITION,
+            positions,
+        )
+        .with_inserted_attribute(
+            Mesh::ATTRIBUTE_NORMAL,
+            normals,
+        )
+        .with_inserted_attribute(Mesh::ATTRIBUTE_UV_0, uvs)
+        .with_inserted_attribute(
+            Mesh::ATTRIBUTE_COLOR,
+            colors,
+        )
+        .with_inserted_indices(Indices::U16(indices))
+}
+
+/// A UV sphere. With enough sectors and stacks this goes
+/// past the 65536 vertices `u16` indices can address, so
+/// it uses 32-bit indices. It has no vertex colors, so it
+/// gets its own pipeline.
+fn uv_sphere(sectors: u32, stacks: u32) -> Mesh {
+    let mut positions = vec![];
+    let mut normals = vec![];
+    let mut uvs = vec![];
+    for stack in 0..=stacks {
+        let v = stack as f32 / stacks as f32;
+        let phi = PI * v;
+        for sector in 0..=sectors {
+            let u = sector as f32 / sectors as f32;
+            let theta = 2.0 * PI * u;
+            let normal = Vec3::new(
+                phi.sin() * theta.cos(),
+                phi.cos(),
+                phi.sin() * theta.sin(),
+            );
+            positions.push((normal * 0.6).to_array());
+            normals.push(normal.to_array());
+            uvs.push([u, v]);
+        }
+    }
+
+    let mut indices = vec![];
+    for stack in 0..stacks {
+        for sector in 0..sectors {
+            let a = stack * (sectors + 1) + sector;
+            let b = a + sectors + 1;
+            indices.extend([a, a + 1, b, a + 1, b + 1, b]);
+        }
+    }
+
+    Mesh::new(PrimitiveTopology::TriangleList)
+        .with_inserted_attribute(
+            Mesh::ATTRIBUTE_POSITION,
+            positions,
+        )
+        .with_inserted_attribute(
+            Mesh::ATTRIBUTE_NORMAL,
+            normals,
+        )
+        .with_inserted_attribute(Mesh::ATTRIBUTE_UV_0, uvs)
+        .with_inserted_indices(Indices::U32(indices))
+}
+
+/// A triangle with only positions. The shader needs
+/// normals and uvs, so specializing a pipeline for it
+/// fails.
+fn positions_only() -> Mesh {
+    Mesh::new(PrimitiveTopology::TriangleList)
+        .with_inserted_attribute(
+            Mesh::ATTRIBUTE_POSITION,
+            vec![
+                [0.0, 0.5, 0.0],
+                [-0.5, -0.5, 0.0],
+                [0.5, -0.5, 0.0],
+            ],
+        )
+}
+
+/// Everything that can change between variants of the
+/// mesh pipeline
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct MeshPipelineKey {
+    vertex_colors: bool,
+    primitive_topology: PrimitiveTopology,
+}
+
+impl MeshPipelineKey {
+    fn from_mesh_layout(
+        layout: &MeshVertexBufferLayout,
+        primitive_topology: PrimitiveTopology,
+    ) -> Self {
+        Self {
+            vertex_colors: layout
+                .contains(Mesh::ATTRIBUTE_COLOR.id),
+            primitive_topology,
+        }
+    }
+}
+
+/// The parts of the mesh pipeline that are shared by
+/// every variant
+struct MeshPipeline {
+    shader: ShaderModule,
+    pipeline_layout: PipelineLayout,
+    format: TextureFormat,
+}
+
+impl SpecializedMeshPipeline for MeshPipeline {
+    type Key = MeshPipelineKey;
+
+    fn specialize(
+        &self,
+        device: &Device,
+        key: Self::Key,
+        layout: &MeshVertexBufferLayout,
+    ) -> Result<RenderPipeline, SpecializedMeshPipelineError>
+    {
+        let mut attributes = vec![
+            Mesh::ATTRIBUTE_POSITION.at_shader_location(0),
+            Mesh::ATTRIBUTE_NORMAL.at_shader_location(1),
+            Mesh::ATTRIBUTE_UV_0.at_shader_location(2),
+        ];
+        let entry_point = if key.vertex_colors {
+            attributes.push(
+                Mesh::ATTRIBUTE_COLOR.at_shader_location(3),
+            );
+            "vertex"
+        } else {
+            "vertex_uncolored"
+        };
+        let vertex_layout =
+            layout.get_layout(&attributes)?;
+        info!(
+            ?vertex_layout,
+            "specializing mesh pipeline"
+        );
+
+        Ok(device.create_render_pipeline(
+            &wgpu::RenderPipelineDescriptor {
+                label: "mesh_pipeline".into(),
+                layout: Some(&self.pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &self.shader,
+                    entry_point: entry_point.into(),
+                    buffers: &[vertex_layout.as_wgpu()],
+                    compilation_options: Default::default(),
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &self.shader,
+                    entry_point: "fragment".into(),
+                    compilation_options: Default::default(),
+                    targets: &[Some(self.format.into())],
+                }),
+                primitive: wgpu::PrimitiveState {
+                    topology: key.primitive_topology,
+                    cull_mode: Some(wgpu::Face::Back),
+                    ..Default::default()
+                },
+                depth_stencil: Some(
+                    wgpu::DepthStencilState {
+                        format: DEPTH_FORMAT,
+                        depth_write_enabled: true,
+                        depth_compare:
+                            wgpu::CompareFunction::Less,
+                        stencil: Default::default(),
+                        bias: Default::default(),
+                    },
+                ),
+                multisample:
+                    wgpu::MultisampleState::default(),
+                multiview_mask: None,
+                cache: None,
+            },
+        ))
+    }
+}
+
+fn create_depth_view(
+    device: &Device,
+    config: &SurfaceConfiguration,
+) -> TextureView {
+    device
+        .create_texture(&wgpu::TextureDescriptor {
+            label: "depth_texture".into(),
+            size: wgpu::Extent3d {
+                width: config.width,
+                height: config.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: DEPTH_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        })
+        .create_view(&wgpu::TextureViewDescriptor::default())
+}
+
+/// Winit
+impl<'a> ApplicationHandler for App<'a> {
+    fn resumed(
+        &mut self,
+        event_loop: &winit::event_loop::ActiveEventLoop,
+    ) {
+        let window_attributes =
+            Window::default_attributes()
+                .with_title("wgpu for Bevy");
+
+        let window = Arc::new(
+            event_loop
+                .create_window(window_attributes)
+                .unwrap(),
+        );
+
+        let mut size = window.inner_size();
+        size.width = size.width.max(1);
+        size.height = size.height.max(1);
+
+        let instance = wgpu::Instance::default();
+
+        // `surface_window` is captured by the async closure,
+        // so we clone our Arc and let the closure take it
+        let surface_window = window.clone();
+        // wgpu apis for getting an adapter are async,
+        // so we block while waiting for them to complete
+        let (surface, adapter, device, queue) = block_on(
+            async move {
+                let surface = instance
+                    .create_surface(surface_window)
+                    .unwrap();
+
+                let adapter = instance
+                    .request_adapter(&wgpu::RequestAdapterOptions {
+                        power_preference:
+                            wgpu::PowerPreference::default(),
+                        force_fallback_adapter: false,
+                        // Request an adapter which can render to our surface
+                        compatible_surface: Some(&surface),
+                    })
+                    .await
+                    .expect(
+                        "Failed to find an appropriate adapter",
+                    );
+
+                info!(adapter=?adapter.get_info());
+
+                // Create the logical device and command
+                // queue
+                let (device, queue) = adapter
+                    .request_device(
+                        &wgpu::DeviceDescriptor::default(),
+                    )
+                    .await
+                    .expect("Failed to create device");
+
+                (surface, adapter, device, queue)
+            },
+        );
+
+        let shader = device.create_shader_module(
+            wgpu::ShaderModuleDescriptor {
+                label: "mesh_shader".into(),
+                source: wgpu::ShaderSource::Wgsl(
+                    Cow::Borrowed(include_str!(
+                        "mesh.wgsl"
+                    )),
+                ),
+            },
+        );
+
+        let mesh_layout = device.create_bind_group_layout(
+            &wgpu::BindGroupLayoutDescriptor {
+                label: "mesh_layout".into(),
+                entries: &[
+                    Uniform::<MeshUniform>::layout_entry(
+                        0,
+                        ShaderStages::VERTEX,
+                    ),
+                ],
+            },
+        );
+
+        let pipeline_layout = device
+            .create_pipeline_layout(
+                &wgpu::PipelineLayoutDescriptor {
+                    label: "mesh_pipeline_layout".into(),
+                    bind_group_layouts: &[&mesh_layout],
+                    immediate_size: 0,
+                },
+            );
+
+        let mesh_pipeline = MeshPipeline {
+            shader,
+            pipeline_layout,
+            format: surface
+                .get_capabilities(&adapter)
+                .formats[0],
+        };
+        let mut pipelines =
+            SpecializedMeshPipelines::default();
+
+        // The two cubes share a layout, so they share a
+        // pipeline. The sphere has no colors and gets a
+        // second one.
+        let meshes = [
+            (
+                "cube",
+                cube(),
+                Vec3::new(-1.4, 0.0, 0.0),
+            ),
+            (
+                "sphere",
+                uv_sphere(256, 256),
+                Vec3::new(0.0, 0.0, 0.0),
+            ),
+            (
+                "cube_2",
+                cube(),
+                Vec3::new(1.4, 0.0, 0.0),
+            ),
+            (
+                "positions_only",
+                positions_only(),
+                Vec3::new(0.0, 1.2, 0.0),
+            ),
+        ];
+
+        let objects = meshes
+            .into_iter()
+            .filter_map(|(label, mesh, translation)| {
+                let layout =
+                    mesh.get_mesh_vertex_buffer_layout();
+                let key = MeshPipelineKey::from_mesh_layout(
+                    &layout,
+                    mesh.primitive_topology(),
+                );
+                let render_pipeline = match pipelines
+                    .specialize(
+                        &device,
+                        &mesh_pipeline,
+                        key,
+                        &layout,
+                    ) {
+                    Ok(render_pipeline) => render_pipeline,
+                    Err(error) => {
+                        error!(
+                            mesh = label,
+                            "skipping mesh: {error}"
+                        );
+                        return None;
+                    }
+                };
+
+                let uniform = Uniform::new(
+                    &device,
+                    &format!("{label}_uniform"),
+                    MeshUniform {
+                        view_proj: Mat4::IDENTITY,
+                        model: Mat4::IDENTITY,
+                    },
+                );
+                let bind_group = device.create_bind_group(
+                    &wgpu::BindGroupDescriptor {
+                        label: Some(&format!(
+                            "{label}_bind_group"
+                        )),
+                        layout: &mesh_layout,
+                        entries: &[
+                            uniform.bind_group_entry(0)
+                        ],
+                    },
+                );
+                Some(MeshObject {
+                    mesh: mesh.upload(&device, label),
+                    render_pipeline,
+                    uniform,
+                    bind_group,
+                    translation,
+                })
+            })
+            .collect();
+        info!(pipelines = pipelines.len());
+
+        let config = surface
+            .get_default_config(
+                &adapter,
+                size.width,
+                size.height,
+            )
+            .unwrap();
+        surface.configure(&device, &config);
+        let depth_view =
+            create_depth_view(&device, &config);
+
+        self.resumed_data = Some(ResumedData {
+            window,
+            config,
+            surface,
+            device,
+            queue,
+            depth_view,
+            objects,
+        });
+    }
+
+    fn window_event(
+        &mut self,
+        event_loop: &winit::event_loop::ActiveEventLoop,
+        _window_id: winit::window::WindowId,
+        event: WindowEvent,
+    ) {
+        match event {
+            WindowEvent::CloseRequested => {
+                event_loop.exit();
+            }
+            WindowEvent::Resized(PhysicalSize {
+                width,
+                height,
+            }) => {
+                let Some(ResumedData {
+                    config,
+                    surface,
+                    device,
+                    depth_view,
+                    ..
+                }) = self.resumed_data.as_mut()
+                else {
+                    return;
+                };
+
+                // Reconfigure the surface with the new size,
+                // making it so that the window is *at least* 1x1
+                config.width = width.max(1);
+                config.height = height.max(1);
+
+                surface.configure(device, config);
+                // the depth texture has to match the size
+                // of the surface texture
+                *depth_view =
+                    create_depth_view(device, config);
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        logical_key: key,
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } => {
+                // allow a single_match here so that people
+                // who use this example can easily match on
+                // new keys
+                #[allow(clippy::single_match)]
+                match key.as_ref() {
+                    Key::Named(NamedKey::Escape) => {
+                        event_loop.exit();
+                    }
+                    _ => (),
+                }
+            }
+            WindowEvent::RedrawRequested => {
+                let Some(ResumedData {
+                    config,
+                    surface,
+                    device,
+                    queue,
+                    depth_view,
+                    objects,
+                    ..
+                }) = self.resumed_data.as_mut()
+                else {
+                    return;
+                };
+
+                let time =
+                    self.start.elapsed().as_secs_f32();
+                let aspect = config.width as f32
+                    / config.height as f32;
+                let view_proj = Mat4::perspective_rh(
+                    PI / 4.0,
+                    aspect,
+                    0.1,
+                    100.0,
+                ) * Mat4::look_at_rh(
+                    Vec3::new(0.0, 1.5, 4.0),
+                    Vec3::ZERO,
+                    Vec3::Y,
+                );
+                for object in objects.iter_mut() {
+                    object.uniform.set(MeshUniform {
+                        view_proj,
+                        model:
+                            Mat4::from_rotation_translation(
+                                Quat::from_euler(
+                                    glam::EulerRot::YXZ,
+                                    time,
+                                    time * 0.5,
+                                    0.0,
+                                ),
+                                object.translation,
+                            ),
+                    });
+                    object.uniform.upload(queue);
+                }
+
+                let frame = surface
+                    .get_current_texture()
+                    .expect("Failed to acquire next swap chain texture");
+                let view = frame.texture.create_view(
+                    &wgpu::TextureViewDescriptor::default(),
+                );
+                let mut encoder = device
+                    .create_command_encoder(
+                        &wgpu::CommandEncoderDescriptor {
+                            label: "mesh_command_encoder"
+                                .into(),
+                        },
+                    );
+                {
+                    let mut rpass =
+                    encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                        label: "mesh_render_pass".into(),
+                        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                            view: &view,
+                            resolve_target: None,
+                            ops: wgpu::Operations {
+                                load: wgpu::LoadOp::Clear(wgpu::Color{
+                                    r: 0.008,
+                                    g: 0.024,
+                                    b: 0.09,
+                                    a: 1.0,
+                                }),
+                                store: wgpu::StoreOp::Store,
+                            },
+                            depth_slice: None
+                        })],
+                        depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                            view: depth_view,
+                            depth_ops: Some(wgpu::Operations {
+                                load: wgpu::LoadOp::Clear(1.0),
+                                store: wgpu::StoreOp::Discard,
+                            }),
+                            stencil_ops: None,
+                        }),
+                        timestamp_writes: None,
+                        occlusion_query_set: None,
+                        multiview_mask: None
+                    });
+                    for object in objects.iter() {
+                        rpass.set_pipeline(
+                            &object.render_pipeline,
+                        );
+                        rpass.set_bind_group(
+                            0,
+                            Some(&object.bind_group),
+                            &[],
+                        );
+                        object.mesh.draw(&mut rpass);
+                    }
+                }
+
+                queue.submit(Some(encoder.finish()));
+                frame.present();
+            }
+            _ => (),
+        }
+    }
+    fn about_to_wait(
+        &mut self,
+        _event_loop: &ActiveEventLoop,
+    ) {
+        let Some(data) = self.resumed_data.as_ref() else {
+            return;
+        };
+
+        data.window.request_redraw();
+    }
+}
+
+fn main() {
+    tracing_subscriber::fmt().init();
+
+    let event_loop = EventLoop::new().unwrap();
+    event_loop.set_control_flow(ControlFlow::Poll);
+
+    let mut app = App::default();
+
+    event_loop.run_app(&mut app).expect("app to run")
+}
@@ -0,0 +1,64 @@
+struct MeshUniform {
+    view_proj: mat4x4<f32>,
+    model: mat4x4<f32>,
+}
+
+@group(0) @binding(0) var<uniform> mesh: MeshUniform;
+
+// Meshes don't decide these locations. The pipeline is
+// specialized for each mesh's layout, mapping attribute
+// ids to the locations below.
+struct Vertex {
+    @location(0) position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(2) uv: vec2<f32>,
+    @location(3) color: vec4<f32>,
+}
+
+// used for meshes without `Mesh::ATTRIBUTE_COLOR`
+struct UncoloredVertex {
+    @location(0) position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(2) uv: vec2<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) world_normal: vec3<f32>,
+    @location(1) uv: vec2<f32>,
+    @location(2) color: vec4<f32>,
+}
+
+fn transform(position: vec3<f32>, normal: vec3<f32>, uv: vec2<f32>, color: vec4<f32>) -> VertexOutput {
+    var out: VertexOutput;
+    out.position = mesh.view_proj * mesh.model * vec4<f32>(position, 1.0);
+    // fine for rotations and uniform scales, which is
+    // all this example uses
+    out.world_normal = (mesh.model * vec4<f32>(normal, 0.0)).xyz;
+    out.uv = uv;
+    out.color = color;
+    return out;
+}
+
+@vertex
+fn vertex(vertex: Vertex) -> VertexOutput {
+    return transform(vertex.position, vertex.normal, vertex.uv, vertex.color);
+}
+
+@vertex
+fn vertex_uncolored(vertex: UncoloredVertex) -> VertexOutput {
+    return transform(vertex.position, vertex.normal, vertex.uv, vec4<f32>(0.9, 0.5, 0.2, 1.0));
+}
+
+@fragment
+fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
+    let light = normalize(vec3<f32>(0.4, 1.0, 0.6));
+    let diffuse = max(dot(normalize(in.world_normal), light), 0.0);
+
+    // a checkerboard from the uvs, so it's visible that
+    // they made it through the vertex buffer
+    let checker = (u32(floor(in.uv.x * 8.0)) + u32(floor(in.uv.y * 8.0))) % 2u;
+    let pattern = 0.8 + 0.2 * f32(checker);
+
+    return vec4<f32>(in.color.rgb * pattern * (0.2 + 0.8 * diffuse), in.color.a);
+}
@@ -0,0 +1,513 @@
+use encase::ShaderType;
+use futures_lite::future::block_on;
+use glam::{Mat4, Vec3};
+use std::{
+    borrow::Cow, f32::consts::PI, sync::Arc, time::Instant,
+};
+use tracing::info;
+use wgpu::{
+    BindGroup, BindGroupLayout, Device, Queue,
+    RenderPipeline, Sampler, ShaderStages, Surface,
+    SurfaceConfiguration,
+};
+use wgpu_for_bevy::{
+    GpuImage, Image, MipmapGenerator, Uniform,
+};
+use winit::{
+    application::ApplicationHandler,
+    dpi::PhysicalSize,
+    event::{ElementState, KeyEvent, WindowEvent},
+    event_loop::{ActiveEventLoop, ControlFlow, EventLoop},
+    keyboard::{Key, NamedKey},
+    window::Window,
+};
+
+const IMAGE_PATH: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/../../assets/textures/checker.png"
+);
+
+#[derive(ShaderType)]
+struct CameraUniform {
+    view_proj: Mat4,
+}
+
+struct ResumedData<'a> {
+    window: Arc<Window>,
+    config: SurfaceConfiguration,
+    render_pipeline: RenderPipeline,
+    surface: Surface<'a>,
+    device: Device,
+    queue: Queue,
+    camera: Uniform<CameraUniform>,
+    camera_bind_group: BindGroup,
+    /// The same image uploaded twice, once with only its
+    /// first level and once with a full mip chain
+    plain_bind_group: BindGroup,
+    mipmapped_bind_group: BindGroup,
+}
+
+struct App<'a> {
+    resumed_data: Option<ResumedData<'a>>,
+    start: Instant,
+    use_mipmaps: bool,
+}
+
+impl Default for App<'_> {
+    fn default() -> Self {
+        Self {
+            resumed_data: Default::default(),
+            start: Instant::now(),
+            use_mipmaps: true,
+        }
+    }
+}
+
+fn create_image_bind_group(
+    device: &Device,
+    layout: &BindGroupLayout,
+    image: &GpuImage,
+    sampler: &Sampler,
+) -> BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: "image_bind_group".into(),
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource:
+                    wgpu::BindingResource::TextureView(
+                        &image.view,
+                    ),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(
+                    sampler,
+                ),
+            },
+        ],
+    })
+}
+
+/// Winit
+impl<'a> ApplicationHandler for App<'a> {
+    fn resumed(
+        &mut self,
+        event_loop: &winit::event_loop::ActiveEventLoop,
+    ) {
+        let window_attributes =
+            Window::default_attributes()
+                .with_title("wgpu for Bevy");
+
+        let window = Arc::new(
+            event_loop
+                .create_window(window_attributes)
+                .unwrap(),
+        );
+
+        let mut size = window.inner_size();
+        size.width = size.width.max(1);
+        size.height = size.height.max(1);
+
+        let instance = wgpu::Instance::default();
+
+        // `surface_window` is captured by the async closure,
+        // so we clone our Arc and let the closure take it
+        let surface_window = window.clone();
+        // wgpu apis for getting an adapter are async,
+        // so we block while waiting for them to complete
+        let (surface, adapter, device, queue) = block_on(
+            async move {
+                let surface = instance
+                    .create_surface(surface_window)
+                    .unwrap();
+
+                let adapter = instance
+                    .request_adapter(&wgpu::RequestAdapterOptions {
+                        power_preference:
+                            wgpu::PowerPreference::default(),
+                        force_fallback_adapter: false,
+                        // Request an adapter which can render to our surface
+                        compatible_surface: Some(&surface),
+                    })
+                    .await
+                    .expect(
+                        "Failed to find an appropriate adapter",
+                    );
+
+                info!(adapter=?adapter.get_info());
+
+                // Create the logical device and command
+                // queue
+                let (device, queue) = adapter
+                    .request_device(
+                        &wgpu::DeviceDescriptor::default(),
+                    )
+                    .await
+                    .expect("Failed to create device");
+
+                (surface, adapter, device, queue)
+            },
+        );
+
+        let shader = device.create_shader_module(
+            wgpu::ShaderModuleDescriptor {
+                label: "plane_shader".into(),
+                source: wgpu::ShaderSource::Wgsl(
+                    Cow::Borrowed(include_str!(
+                        "plane.wgsl"
+                    )),
+                ),
+            },
+        );
+
+        let camera_layout = device
+            .create_bind_group_layout(
+            &wgpu::BindGroupLayoutDescriptor {
+                label: "camera_layout".into(),
+                entries: &[
+                    Uniform::<CameraUniform>::layout_entry(
+                        0,
+                        ShaderStages::VERTEX,
+                    ),
+                ],
+            },
+        );
+
+        let image_layout = device
+            .create_bind_group_layout(
+            &wgpu::BindGroupLayoutDescriptor {
+                label: "image_layout".into(),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type:
+                                wgpu::TextureSampleType::Float {
+                                    filterable: true,
+                                },
+                            view_dimension:
+                                wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler(
+                            wgpu::SamplerBindingType::Filtering,
+                        ),
+                        count: None,
+                    },
+                ],
+            },
+        );
+
+        let pipeline_layout = device
+            .create_pipeline_layout(
+                &wgpu::PipelineLayoutDescriptor {
+                    label: "plane_pipeline_layout".into(),
+                    bind_group_layouts: &[
+                        &camera_layout,
+                        &image_layout,
+                    ],
+                    immediate_size: 0,
+                },
+            );
+
+        let swapchain_format =
+            surface.get_capabilities(&adapter).formats[0];
+
+        let render_pipeline = device
+            .create_render_pipeline(
+                &wgpu::RenderPipelineDescriptor {
+                    label: "plane_pipeline".into(),
+                    layout: Some(&pipeline_layout),
+                    vertex: wgpu::VertexState {
+                        module: &shader,
+                        entry_point: "vertex".into(),
+                        buffers: &[],
+                        compilation_options:
+                            Default::default(),
+                    },
+                    fragment: Some(wgpu::FragmentState {
+                        module: &shader,
+                        entry_point: "fragment".into(),
+                        compilation_options:
+                            Default::default(),
+                        targets: &[Some(
+                            swapchain_format.into(),
+                        )],
+                    }),
+                    primitive:
+                        wgpu::PrimitiveState::default(),
+                    depth_stencil: None,
+                    multisample:
+                        wgpu::MultisampleState::default(),
+                    multiview_mask: None,
+                    cache: None,
+                },
+            );
+
+        let config = surface
+            .get_default_config(
+                &adapter,
+                size.width,
+                size.height,
+            )
+            .unwrap();
+        surface.configure(&device, &config);
+
+        let camera = Uniform::new(
+            &device,
+            "camera_uniform",
+            CameraUniform {
+                view_proj: Mat4::IDENTITY,
+            },
+        );
+        let camera_bind_group = device.create_bind_group(
+            &wgpu::BindGroupDescriptor {
+                label: "camera_bind_group".into(),
+                layout: &camera_layout,
+                entries: &[camera.bind_group_entry(0)],
+            },
+        );
+
+        let image = Image::load(IMAGE_PATH, true)
+            .expect("checker image to load");
+        let plain =
+            image.upload(&device, &queue, "plain_texture");
+        let mut mipmaps = MipmapGenerator::new(&device);
+        let mipmapped = image.upload_with_mipmaps(
+            &device,
+            &queue,
+            &mut mipmaps,
+            "mipmapped_texture",
+        );
+        info!(
+            mip_level_count = mipmapped.mip_level_count(),
+            "generated mipmaps"
+        );
+
+        // one sampler for both textures. Linear filtering
+        // between mip levels only does anything for the
+        // texture that has them.
+        let sampler = device.create_sampler(
+            &wgpu::SamplerDescriptor {
+                label: "plane_sampler".into(),
+                address_mode_u: wgpu::AddressMode::Repeat,
+                address_mode_v: wgpu::AddressMode::Repeat,
+                mag_filter: wgpu::FilterMode::Linear,
+                min_filter: wgpu::FilterMode::Linear,
+                mipmap_filter:
+                    wgpu::MipmapFilterMode::Linear,
+                ..Default::default()
+            },
+        );
+        let plain_bind_group = create_image_bind_group(
+            &device,
+            &image_layout,
+            &plain,
+            &sampler,
+        );
+        let mipmapped_bind_group = create_image_bind_group(
+            &device,
+            &image_layout,
+            &mipmapped,
+            &sampler,
+        );
+
+        self.resumed_data = Some(ResumedData {
+            window,
+            config,
+            render_pipeline,
+            surface,
+            device,
+            queue,
+            camera,
+            camera_bind_group,
+            plain_bind_group,
+            mipmapped_bind_group,
+        });
+    }
+
+    fn window_event(
+        &mut self,
+        event_loop: &winit::event_loop::ActiveEventLoop,
+        _window_id: winit::window::WindowId,
+        event: WindowEvent,
+    ) {
+        match event {
+            WindowEvent::CloseRequested => {
+                event_loop.exit();
+            }
+            WindowEvent::Resized(PhysicalSize {
+                width,
+                height,
+            }) => {
+                let Some(ResumedData {
+                    config,
+                    surface,
+                    device,
+                    ..
+                }) = self.resumed_data.as_mut()
+                else {
+                    return;
+                };
+
+                // Reconfigure the surface with the new size,
+                // making it so that the window is *at least* 1x1
+                config.width = width.max(1);
+                config.height = height.max(1);
+
+                surface.configure(device, config);
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        logical_key: key,
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } => match key.as_ref() {
+                Key::Named(NamedKey::Escape) => {
+                    event_loop.exit();
+                }
+                Key::Character("m") => {
+                    self.use_mipmaps = !self.use_mipmaps;
+                    info!(use_mipmaps = self.use_mipmaps);
+                }
+                _ => (),
+            },
+            WindowEvent::RedrawRequested => {
+                let Some(ResumedData {
+                    config,
+                    surface,
+                    device,
+                    queue,
+                    render_pipeline,
+                    camera,
+                    camera_bind_group,
+                    plain_bind_group,
+                    mipmapped_bind_group,
+                    ..
+                }) = self.resumed_data.as_mut()
+                else {
+                    return;
+                };
+
+                // move forward one tile per two seconds,
+                // wrapping around so the camera never runs
+                // out of plane. The image repeats every
+                // unit, so the wrap isn't visible.
+                let time =
+                    self.start.elapsed().as_secs_f32();
+                let eye = Vec3::new(
+                    0.0,
+                    0.5,
+                    -(time * 0.5) % 1.0,
+                );
+                let aspect = config.width as f32
+                    / config.height as f32;
+                camera.set(CameraUniform {
+                    view_proj: Mat4::perspective_rh(
+                        PI / 3.0,
+                        aspect,
+                        0.1,
+                        1000.0,
+                    ) * Mat4::look_at_rh(
+                        eye,
+                        eye + Vec3::new(0.0, -0.1, -1.0),
+                        Vec3::Y,
+                    ),
+                });
+                camera.upload(queue);
+
+                let image_bind_group = if self.use_mipmaps {
+                    mipmapped_bind_group
+                } else {
+                    plain_bind_group
+                };
+
+                let frame = surface
+                    .get_current_texture()
+                    .expect("Failed to acquire next swap chain texture");
+                let view = frame.texture.create_view(
+                    &wgpu::TextureViewDescriptor::default(),
+                );
+                let mut encoder = device
+                    .create_command_encoder(
+                        &wgpu::CommandEncoderDescriptor {
+                            label: "plane_command_encoder"
+                                .into(),
+                        },
+                    );
+                {
+                    let mut rpass =
+                    encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                        label: "plane_render_pass".into(),
+                        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                            view: &view,
+                            resolve_target: None,
+                            ops: wgpu::Operations {
+                                load: wgpu::LoadOp::Clear(wgpu::Color{
+                                    r: 0.008,
+                                    g: 0.024,
+                                    b: 0.09,
+                                    a: 1.0,
+                                }),
+                                store: wgpu::StoreOp::Store,
+                            },
+                            depth_slice: None
+                        })],
+                        depth_stencil_attachment: None,
+                        timestamp_writes: None,
+                        occlusion_query_set: None,
+                        multiview_mask: None
+                    });
+                    rpass.set_pipeline(render_pipeline);
+                    rpass.set_bind_group(
+                        0,
+                        Some(&*camera_bind_group),
+                        &[],
+                    );
+                    rpass.set_bind_group(
+                        1,
+                        Some(&*image_bind_group),
+                        &[],
+                    );
+                    rpass.draw(0..6, 0..1);
+                }
+
+                queue.submit(Some(encoder.finish()));
+                frame.present();
+            }
+            _ => (),
+        }
+    }
+    fn about_to_wait(
+        &mut self,
+        _event_loop: &ActiveEventLoop,
+    ) {
+        let Some(data) = self.resumed_data.as_ref() else {
+            return;
+        };
+
+        data.window.request_redraw();
+    }
+}
+
+fn main() {
+    tracing_subscriber::fmt().init();
+
+    let event_loop = EventLoop::new().unwrap();
+    event_loop.set_control_flow(ControlFlow::Poll);
+
+    let mut app = App::default();
+
+    event_loop.run_app(&mut app).expect("app to run")
+}
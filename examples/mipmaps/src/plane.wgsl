@@ -0,0 +1,39 @@
+struct Camera {
+    view_proj: mat4x4<f32>,
+}
+
+@group(0) @binding(0) var<uniform> camera: Camera;
+
+@group(1) @binding(0) var image: texture_2d<f32>;
+@group(1) @binding(1) var image_sampler: sampler;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+}
+
+// A large ground plane reaching toward the horizon,
+// where each texel covers less than a pixel.
+@vertex
+fn vertex(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(-200.0, 10.0),
+        vec2<f32>(200.0, 10.0),
+        vec2<f32>(200.0, -400.0),
+        vec2<f32>(-200.0, 10.0),
+        vec2<f32>(200.0, -400.0),
+        vec2<f32>(-200.0, -400.0),
+    );
+    let corner = corners[vertex_index];
+
+    var out: VertexOutput;
+    out.position = camera.view_proj * vec4<f32>(corner.x, 0.0, corner.y, 1.0);
+    // the image repeats once per world unit
+    out.uv = corner;
+    return out;
+}
+
+@fragment
+fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
+    return textureSample(image, image_sampler, in.uv);
+}
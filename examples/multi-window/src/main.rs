@@ -0,0 +1,414 @@
+use futures_lite::future::block_on;
+use std::{borrow::Cow, collections::HashMap, sync::Arc};
+use tracing::{error, info};
+use wgpu::{
+    Adapter, Device, Instance, Queue, RenderPipeline,
+    Surface, SurfaceConfiguration, TextureFormat,
+};
+use winit::{
+    application::ApplicationHandler,
+    dpi::PhysicalSize,
+    event::{ElementState, KeyEvent, WindowEvent},
+    event_loop::{ActiveEventLoop, ControlFlow, EventLoop},
+    keyboard::{Key, NamedKey},
+    window::{Window, WindowId},
+};
+
+/// Clear colors for each new window, so they're easy to
+/// tell apart. Windows past the end start over.
+const CLEAR_COLORS: [wgpu::Color; 4] = [
+    wgpu::Color {
+        r: 0.008,
+        g: 0.024,
+        b: 0.09,
+        a: 1.0,
+    },
+    wgpu::Color {
+        r: 0.09,
+        g: 0.024,
+        b: 0.03,
+        a: 1.0,
+    },
+    wgpu::Color {
+        r: 0.02,
+        g: 0.07,
+        b: 0.03,
+        a: 1.0,
+    },
+    wgpu::Color {
+        r: 0.06,
+        g: 0.05,
+        b: 0.01,
+        a: 1.0,
+    },
+];
+
+/// Everything every window shares. There's one device
+/// for the whole app, like Bevy's `RenderDevice`, no
+/// matter how many windows are open.
+struct GpuData {
+    instance: Instance,
+    adapter: Adapter,
+    device: Device,
+    queue: Queue,
+    /// Every surface is configured with this format, so
+    /// one pipeline can draw into any of them
+    swapchain_format: TextureFormat,
+    render_pipeline: RenderPipeline,
+}
+
+/// What each window has of its own, like the surfaces
+/// Bevy keeps per window in `WindowSurfaces`
+struct WindowData<'a> {
+    window: Arc<Window>,
+    surface: Surface<'a>,
+    config: SurfaceConfiguration,
+    clear_color: wgpu::Color,
+}
+
+#[derive(Default)]
+struct App<'a> {
+    gpu: Option<GpuData>,
+    windows: HashMap<WindowId, WindowData<'a>>,
+    /// How many windows have been opened, for titles and
+    /// colors
+    opened: usize,
+}
+
+impl App<'_> {
+    /// Open a window and set up its surface on the shared
+    /// device. The first window is also the one the
+    /// adapter is picked for.
+    fn open_window(
+        &mut self,
+        event_loop: &ActiveEventLoop,
+    ) {
+        let window_attributes = Window::default_attributes(
+        )
+        .with_title(format!(
+            "wgpu for Bevy (window {})",
+            self.opened + 1
+        ));
+        let window = Arc::new(
+            event_loop
+                .create_window(window_attributes)
+                .unwrap(),
+        );
+
+        let gpu = match &self.gpu {
+            Some(gpu) => gpu,
+            None => {
+                self.gpu.insert(request_device(&window))
+            }
+        };
+
+        let surface = gpu
+            .instance
+            .create_surface(window.clone())
+            .unwrap();
+        // the adapter was picked for the first window's
+        // surface. Others are almost always the same, but
+        // a window on another monitor could be different.
+        let capabilities =
+            surface.get_capabilities(&gpu.adapter);
+        if !capabilities
+            .formats
+            .contains(&gpu.swapchain_format)
+        {
+            error!(
+                format = ?gpu.swapchain_format,
+                "the new window's surface doesn't support the shared format"
+            );
+            return;
+        }
+
+        let mut size = window.inner_size();
+        size.width = size.width.max(1);
+        size.height = size.height.max(1);
+        let mut config = surface
+            .get_default_config(
+                &gpu.adapter,
+                size.width,
+                size.height,
+            )
+            .unwrap();
+        config.format = gpu.swapchain_format;
+        surface.configure(&gpu.device, &config);
+
+        info!(window_id = ?window.id(), "opened window");
+        self.windows.insert(
+            window.id(),
+            WindowData {
+                window,
+                surface,
+                config,
+                clear_color: CLEAR_COLORS
+                    [self.opened % CLEAR_COLORS.len()],
+            },
+        );
+        self.opened += 1;
+    }
+
+    /// Dropping a window's data drops its surface and
+    /// closes it. The app only exits once there are none
+    /// left, like `ExitCondition::OnAllClosed`.
+    fn close_window(
+        &mut self,
+        event_loop: &ActiveEventLoop,
+        window_id: WindowId,
+    ) {
+        self.windows.remove(&window_id);
+        info!(
+            ?window_id,
+            remaining = self.windows.len(),
+            "closed window"
+        );
+        if self.windows.is_empty() {
+            event_loop.exit();
+        }
+    }
+}
+
+/// Winit
+impl<'a> ApplicationHandler for App<'a> {
+    fn resumed(
+        &mut self,
+        event_loop: &winit::event_loop::ActiveEventLoop,
+    ) {
+        // resumed can run more than once on some
+        // platforms, which shouldn't open more windows
+        if self.windows.is_empty() {
+            self.open_window(event_loop);
+        }
+    }
+
+    fn window_event(
+        &mut self,
+        event_loop: &winit::event_loop::ActiveEventLoop,
+        window_id: WindowId,
+        event: WindowEvent,
+    ) {
+        // events can still arrive for a window that was
+        // just closed
+        if !self.windows.contains_key(&window_id) {
+            return;
+        }
+
+        match event {
+            WindowEvent::CloseRequested => {
+                self.close_window(event_loop, window_id);
+            }
+            WindowEvent::Resized(PhysicalSize {
+                width,
+                height,
+            }) => {
+                let (Some(gpu), Some(data)) = (
+                    self.gpu.as_ref(),
+                    self.windows.get_mut(&window_id),
+                ) else {
+                    return;
+                };
+
+                // Reconfigure the surface with the new size,
+                // making it so that the window is *at least* 1x1
+                data.config.width = width.max(1);
+                data.config.height = height.max(1);
+
+                data.surface
+                    .configure(&gpu.device, &data.config);
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        logical_key: key,
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } => match key.as_ref() {
+                // closes the window the key was pressed in
+                Key::Named(NamedKey::Escape) => {
+                    self.close_window(
+                        event_loop, window_id,
+                    );
+                }
+                Key::Character("n") => {
+                    self.open_window(event_loop);
+                }
+                _ => (),
+            },
+            WindowEvent::RedrawRequested => {
+                let (Some(gpu), Some(data)) = (
+                    self.gpu.as_ref(),
+                    self.windows.get(&window_id),
+                ) else {
+                    return;
+                };
+                draw(gpu, data);
+            }
+            _ => (),
+        }
+    }
+    fn about_to_wait(
+        &mut self,
+        _event_loop: &ActiveEventLoop,
+    ) {
+        for data in self.windows.values() {
+            data.window.request_redraw();
+        }
+    }
+}
+
+/// Create the instance, adapter, and device every window
+/// will share, with an adapter that can present to
+/// `window`
+fn request_device(window: &Arc<Window>) -> GpuData {
+    let instance = wgpu::Instance::default();
+
+    // `surface_window` is captured by the async closure,
+    // so we clone our Arc and let the closure take it.
+    // This surface is only used to pick an adapter.
+    let surface_window = window.clone();
+    // wgpu apis for getting an adapter are async,
+    // so we block while waiting for them to complete
+    let (swapchain_format, adapter, device, queue) =
+        block_on(async {
+            let surface = instance
+                .create_surface(surface_window)
+                .unwrap();
+
+            let adapter = instance
+                .request_adapter(&wgpu::RequestAdapterOptions {
+                    power_preference:
+                        wgpu::PowerPreference::default(),
+                    force_fallback_adapter: false,
+                    // Request an adapter which can render to our surface
+                    compatible_surface: Some(&surface),
+                })
+                .await
+                .expect("Failed to find an appropriate adapter");
+
+            info!(adapter=?adapter.get_info());
+
+            // Create the logical device and command
+            // queue
+            let (device, queue) = adapter
+                .request_device(
+                    &wgpu::DeviceDescriptor::default(),
+                )
+                .await
+                .expect("Failed to create device");
+
+            let swapchain_format = surface
+                .get_capabilities(&adapter)
+                .formats[0];
+            (swapchain_format, adapter, device, queue)
+        });
+
+    let shader = device.create_shader_module(
+        wgpu::ShaderModuleDescriptor {
+            label: "triangle_shader".into(),
+            source: wgpu::ShaderSource::Wgsl(
+                Cow::Borrowed(include_str!(
+                    "triangle.wgsl"
+                )),
+            ),
+        },
+    );
+
+    let pipeline_layout = device.create_pipeline_layout(
+        &wgpu::PipelineLayoutDescriptor {
+            label: "triangle_layout".into(),
+            bind_group_layouts: &[],
+            immediate_size: 0,
+        },
+    );
+
+    let render_pipeline = device.create_render_pipeline(
+        &wgpu::RenderPipelineDescriptor {
+            label: "triangle_pipeline".into(),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vertex".into(),
+                buffers: &[],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fragment".into(),
+                compilation_options: Default::default(),
+                targets: &[Some(swapchain_format.into())],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview_mask: None,
+            cache: None,
+        },
+    );
+
+    GpuData {
+        instance,
+        adapter,
+        device,
+        queue,
+        swapchain_format,
+        render_pipeline,
+    }
+}
+
+fn draw(gpu: &GpuData, data: &WindowData) {
+    let frame = data.surface.get_current_texture().expect(
+        "Failed to acquire next swap chain texture",
+    );
+    let view = frame.texture.create_view(
+        &wgpu::TextureViewDescriptor::default(),
+    );
+    let mut encoder = gpu.device.create_command_encoder(
+        &wgpu::CommandEncoderDescriptor {
+            label: "multi_window_command_encoder".into(),
+        },
+    );
+    {
+        let mut rpass = encoder.begin_render_pass(
+            &wgpu::RenderPassDescriptor {
+                label: "multi_window_render_pass".into(),
+                color_attachments: &[Some(
+                    wgpu::RenderPassColorAttachment {
+                        view: &view,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(
+                                data.clear_color,
+                            ),
+                            store: wgpu::StoreOp::Store,
+                        },
+                        depth_slice: None,
+                    },
+                )],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+                multiview_mask: None,
+            },
+        );
+        rpass.set_pipeline(&gpu.render_pipeline);
+        rpass.draw(0..3, 0..1);
+    }
+
+    // every window submits to the same queue
+    gpu.queue.submit(Some(encoder.finish()));
+    frame.present();
+}
+
+fn main() {
+    tracing_subscriber::fmt().init();
+
+    let event_loop = EventLoop::new().unwrap();
+    event_loop.set_control_flow(ControlFlow::Poll);
+
+    let mut app = App::default();
+
+    event_loop.run_app(&mut app).expect("app to run")
+}
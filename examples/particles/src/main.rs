@@ -0,0 +1,784 @@
+use encase::ShaderType;
+use futures_lite::future::block_on;
+use glam::Vec2;
+use std::{
+    borrow::Cow, path::PathBuf, sync::Arc, time::Instant,
+};
+use tracing::{info, warn};
+use wgpu::{
+    BindGroup, BindGroupLayout, BufferUsages,
+    ComputePipeline, Device, Queue, RenderPipeline,
+    ShaderStages, Surface, SurfaceConfiguration,
+};
+use wgpu_for_bevy::{
+    GpuProfiler, Readback, Storage, Uniform,
+};
+use winit::{
+    application::ApplicationHandler,
+    dpi::{PhysicalPosition, PhysicalSize},
+    event::{
+        ElementState, KeyEvent, MouseButton, WindowEvent,
+    },
+    event_loop::{ActiveEventLoop, ControlFlow, EventLoop},
+    keyboard::{Key, NamedKey},
+    window::Window,
+};
+
+const INITIAL_PARTICLE_COUNT: u32 = 1 << 15;
+const MAX_PARTICLE_COUNT: u32 = 1 << 20;
+/// Must match `@workgroup_size` in `particles.wgsl`
+const WORKGROUP_SIZE: u32 = 64;
+
+#[derive(ShaderType)]
+struct Particle {
+    position: Vec2,
+    velocity: Vec2,
+}
+
+/// Everything the simulation needs from the CPU, written
+/// once per frame
+#[derive(ShaderType)]
+struct SimParams {
+    attractor: Vec2,
+    attractor_strength: f32,
+    delta_time: f32,
+    damping: f32,
+    particle_count: u32,
+    particle_size: f32,
+    aspect: f32,
+}
+
+struct ResumedData<'a> {
+    window: Arc<Window>,
+    config: SurfaceConfiguration,
+    compute_pipeline: ComputePipeline,
+    render_pipeline: RenderPipeline,
+    surface: Surface<'a>,
+    device: Device,
+    queue: Queue,
+    params: Uniform<SimParams>,
+    particles: [Storage<Vec<Particle>>; 2],
+    compute_bind_groups: [BindGroup; 2],
+    render_bind_groups: [BindGroup; 2],
+    profiler: GpuProfiler,
+}
+
+struct App<'a> {
+    resumed_data: Option<ResumedData<'a>>,
+    particle_count: u32,
+    /// Which of the two particle buffers is read this
+    /// frame
+    frame: usize,
+    last_frame: Instant,
+    cursor: Vec2,
+    repel: bool,
+    args: Args,
+}
+
+impl App<'_> {
+    fn new(args: Args) -> Self {
+        Self {
+            resumed_data: Default::default(),
+            particle_count: INITIAL_PARTICLE_COUNT,
+            frame: 0,
+            last_frame: Instant::now(),
+            cursor: Vec2::ZERO,
+            repel: false,
+            args,
+        }
+    }
+}
+
+struct Args {
+    /// Measure how long each pass takes on the GPU
+    profile_gpu: bool,
+    /// Also write the GPU timings to a Chrome trace
+    gpu_trace: Option<PathBuf>,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut args = Args {
+        profile_gpu: false,
+        gpu_trace: None,
+    };
+
+    let mut iter = std::env::args().skip(1);
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--profile-gpu" => args.profile_gpu = true,
+            "--gpu-trace" => {
+                args.profile_gpu = true;
+                args.gpu_trace = Some(
+                    iter.next()
+                        .ok_or(
+                            "--gpu-trace expects a file",
+                        )?
+                        .into(),
+                );
+            }
+            "-h" | "--help" => {
+                return Err("usage: particles [--profile-gpu] [--gpu-trace FILE.json]".to_string());
+            }
+            _ => {
+                return Err(format!(
+                    "unknown argument `{arg}`"
+                ));
+            }
+        }
+    }
+    Ok(args)
+}
+
+/// The same cheap hash as the instancing example
+fn hash(mut x: u32) -> f32 {
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x7feb_352d);
+    x ^= x >> 15;
+    x = x.wrapping_mul(0x846c_a68b);
+    x ^= x >> 16;
+    x as f32 / u32::MAX as f32
+}
+
+/// Particles in a ring, orbiting the middle of the window
+fn create_particles() -> Vec<Particle> {
+    (0..MAX_PARTICLE_COUNT)
+        .map(|i| {
+            let angle = hash(i * 2) * std::f32::consts::TAU;
+            let radius = 0.3 + hash(i * 2 + 1) * 0.4;
+            let direction = Vec2::from_angle(angle);
+            Particle {
+                position: direction * radius,
+                velocity: direction.perp() * 0.5,
+            }
+        })
+        .collect()
+}
+
+fn create_bind_group(
+    device: &Device,
+    label: &str,
+    layout: &BindGroupLayout,
+    entries: &[wgpu::BindGroupEntry],
+) -> BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some(label),
+        layout,
+        entries,
+    })
+}
+
+/// Winit
+impl<'a> ApplicationHandler for App<'a> {
+    fn resumed(
+        &mut self,
+        event_loop: &winit::event_loop::ActiveEventLoop,
+    ) {
+        let window_attributes =
+            Window::default_attributes()
+                .with_title("wgpu for Bevy");
+
+        let window = Arc::new(
+            event_loop
+                .create_window(window_attributes)
+                .unwrap(),
+        );
+
+        let mut size = window.inner_size();
+        size.width = size.width.max(1);
+        size.height = size.height.max(1);
+
+        let instance = wgpu::Instance::default();
+
+        // `surface_window` is captured by the async closure,
+        // so we clone our Arc and let the closure take it
+        let surface_window = window.clone();
+        let profile_gpu = self.args.profile_gpu;
+        // wgpu apis for getting an adapter are async,
+        // so we block while waiting for them to complete
+        let (surface, adapter, device, queue) = block_on(
+            async move {
+                let surface = instance
+                    .create_surface(surface_window)
+                    .unwrap();
+
+                let adapter = instance
+                    .request_adapter(&wgpu::RequestAdapterOptions {
+                        power_preference:
+                            wgpu::PowerPreference::default(),
+                        force_fallback_adapter: false,
+                        // Request an adapter which can render to our surface
+                        compatible_surface: Some(&surface),
+                    })
+                    .await
+                    .expect(
+                        "Failed to find an appropriate adapter",
+                    );
+
+                info!(adapter=?adapter.get_info());
+
+                // timestamp queries are only requested when
+                // profiling, and only the ones the adapter
+                // has
+                let required_features = if profile_gpu {
+                    adapter.features()
+                        & GpuProfiler::FEATURES
+                } else {
+                    wgpu::Features::empty()
+                };
+                // Create the logical device and command
+                // queue
+                let (device, queue) = adapter
+                    .request_device(
+                        &wgpu::DeviceDescriptor {
+                            required_features,
+                            ..Default::default()
+                        },
+                    )
+                    .await
+                    .expect("Failed to create device");
+
+                (surface, adapter, device, queue)
+            },
+        );
+
+        let shader = device.create_shader_module(
+            wgpu::ShaderModuleDescriptor {
+                label: "particles_shader".into(),
+                source: wgpu::ShaderSource::Wgsl(
+                    Cow::Borrowed(include_str!(
+                        "particles.wgsl"
+                    )),
+                ),
+            },
+        );
+
+        let compute_layout = device
+            .create_bind_group_layout(
+            &wgpu::BindGroupLayoutDescriptor {
+                label: "simulate_layout".into(),
+                entries: &[
+                    Uniform::<SimParams>::layout_entry(
+                        0,
+                        ShaderStages::COMPUTE,
+                    ),
+                    Storage::<Vec<Particle>>::layout_entry(
+                        1,
+                        ShaderStages::COMPUTE,
+                        true,
+                    ),
+                    Storage::<Vec<Particle>>::layout_entry(
+                        2,
+                        ShaderStages::COMPUTE,
+                        false,
+                    ),
+                ],
+            },
+        );
+        let render_layout = device
+            .create_bind_group_layout(
+            &wgpu::BindGroupLayoutDescriptor {
+                label: "particles_render_layout".into(),
+                entries: &[
+                    Uniform::<SimParams>::layout_entry(
+                        0,
+                        ShaderStages::VERTEX,
+                    ),
+                    Storage::<Vec<Particle>>::layout_entry(
+                        1,
+                        ShaderStages::VERTEX,
+                        true,
+                    ),
+                ],
+            },
+        );
+
+        let compute_pipeline = device
+            .create_compute_pipeline(
+            &wgpu::ComputePipelineDescriptor {
+                label: "simulate_pipeline".into(),
+                layout: Some(
+                    &device.create_pipeline_layout(
+                        &wgpu::PipelineLayoutDescriptor {
+                            label: "simulate_layout".into(),
+                            bind_group_layouts: &[
+                                &compute_layout,
+                            ],
+                            immediate_size: 0,
+                        },
+                    ),
+                ),
+                module: &shader,
+                entry_point: "simulate".into(),
+                compilation_options: Default::default(),
+                cache: None,
+            },
+        );
+
+        let swapchain_format =
+            surface.get_capabilities(&adapter).formats[0];
+
+        let render_pipeline = device
+            .create_render_pipeline(
+                &wgpu::RenderPipelineDescriptor {
+                    label: "particles_render_pipeline".into(),
+                    layout: Some(
+                        &device.create_pipeline_layout(
+                            &wgpu::PipelineLayoutDescriptor {
+                                label: "particles_render_layout"
+                                    .into(),
+                                bind_group_layouts: &[
+                                    &render_layout,
+                                ],
+                                immediate_size: 0,
+                            },
+                        ),
+                    ),
+                    vertex: wgpu::VertexState {
+                        module: &shader,
+                        entry_point: "vertex".into(),
+                        // the quad's corners are in the
+                        // shader, and each instance reads
+                        // its particle from storage
+                        buffers: &[],
+                        compilation_options:
+                            Default::default(),
+                    },
+                    fragment: Some(wgpu::FragmentState {
+                        module: &shader,
+                        entry_point: "fragment".into(),
+                        compilation_options:
+                            Default::default(),
+                        targets: &[Some(
+                            wgpu::ColorTargetState {
+                                format: swapchain_format,
+                                // additive, so dense clusters
+                                // glow
+                                blend: Some(wgpu::BlendState {
+                                    color: wgpu::BlendComponent {
+                                        src_factor: wgpu::BlendFactor::SrcAlpha,
+                                        dst_factor: wgpu::BlendFactor::One,
+                                        operation: wgpu::BlendOperation::Add,
+                                    },
+                                    alpha: wgpu::BlendComponent::OVER,
+                                }),
+                                write_mask:
+                                    wgpu::ColorWrites::ALL,
+                            },
+                        )],
+                    }),
+                    primitive:
+                        wgpu::PrimitiveState::default(),
+                    depth_stencil: None,
+                    multisample:
+                        wgpu::MultisampleState::default(),
+                    multiview_mask: None,
+                    cache: None,
+                },
+            );
+
+        let config = surface
+            .get_default_config(
+                &adapter,
+                size.width,
+                size.height,
+            )
+            .unwrap();
+        surface.configure(&device, &config);
+
+        // both buffers start out the same. They're sized for
+        // the maximum count, so changing the count only
+        // changes how many are simulated and drawn.
+        let particles =
+            ["particles_a", "particles_b"].map(|label| {
+                // `COPY_SRC` so they can be read back
+                let mut particles = Storage::with_usage(
+                    &device,
+                    label,
+                    create_particles(),
+                    BufferUsages::COPY_SRC,
+                );
+                particles.upload(&device, &queue);
+                particles
+            });
+        let params = Uniform::new(
+            &device,
+            "sim_params",
+            self.params(0.0, &config),
+        );
+
+        // bind group `i` reads buffer `i` and writes the
+        // other one, and the matching render bind group
+        // draws what was written
+        let compute_bind_groups = [0, 1].map(|i| {
+            create_bind_group(
+                &device,
+                "simulate_bind_group",
+                &compute_layout,
+                &[
+                    params.bind_group_entry(0),
+                    particles[i].bind_group_entry(1),
+                    particles[1 - i].bind_group_entry(2),
+                ],
+            )
+        });
+        let render_bind_groups = [0, 1].map(|i| {
+            create_bind_group(
+                &device,
+                "particles_render_bind_group",
+                &render_layout,
+                &[
+                    params.bind_group_entry(0),
+                    particles[1 - i].bind_group_entry(1),
+                ],
+            )
+        });
+
+        let mut profiler =
+            GpuProfiler::new(&device, &queue);
+        if let Some(path) = &self.args.gpu_trace {
+            profiler = profiler
+                .with_chrome_trace(path)
+                .unwrap_or_else(|error| {
+                    panic!(
+                        "failed to create {}: {error}",
+                        path.display()
+                    )
+                });
+        }
+        if self.args.profile_gpu && !profiler.is_enabled() {
+            warn!(
+                "the adapter doesn't support timestamp queries, GPU times won't be measured"
+            );
+        }
+
+        self.last_frame = Instant::now();
+        self.resumed_data = Some(ResumedData {
+            window,
+            config,
+            compute_pipeline,
+            render_pipeline,
+            surface,
+            device,
+            queue,
+            params,
+            particles,
+            compute_bind_groups,
+            render_bind_groups,
+            profiler,
+        });
+    }
+
+    fn window_event(
+        &mut self,
+        event_loop: &winit::event_loop::ActiveEventLoop,
+        _window_id: winit::window::WindowId,
+        event: WindowEvent,
+    ) {
+        match event {
+            WindowEvent::CloseRequested => {
+                event_loop.exit();
+            }
+            WindowEvent::Resized(PhysicalSize {
+                width,
+                height,
+            }) => {
+                let Some(ResumedData {
+                    config,
+                    surface,
+                    device,
+                    ..
+                }) = self.resumed_data.as_mut()
+                else {
+                    return;
+                };
+
+                // Reconfigure the surface with the new size,
+                // making it so that the window is *at least* 1x1
+                config.width = width.max(1);
+                config.height = height.max(1);
+
+                surface.configure(device, config);
+            }
+            WindowEvent::CursorMoved {
+                position: PhysicalPosition { x, y },
+                ..
+            } => {
+                let Some(ResumedData { config, .. }) =
+                    self.resumed_data.as_ref()
+                else {
+                    return;
+                };
+                // window pixels to clip space, which is
+                // where the particles live
+                self.cursor = Vec2::new(
+                    x as f32 / config.width as f32 * 2.0
+                        - 1.0,
+                    1.0 - y as f32 / config.height as f32
+                        * 2.0,
+                );
+            }
+            WindowEvent::MouseInput {
+                state,
+                button: MouseButton::Left,
+                ..
+            } => {
+                self.repel = state.is_pressed();
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        logical_key: key,
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } => {
+                let count = match key.as_ref() {
+                    Key::Named(NamedKey::Escape) => {
+                        event_loop.exit();
+                        return;
+                    }
+                    Key::Named(NamedKey::ArrowUp) => {
+                        (self.particle_count * 2)
+                            .min(MAX_PARTICLE_COUNT)
+                    }
+                    Key::Named(NamedKey::ArrowDown) => {
+                        (self.particle_count / 2).max(1)
+                    }
+                    Key::Character("r") => {
+                        self.log_average_speed();
+                        return;
+                    }
+                    _ => return,
+                };
+                // the new count reaches the GPU with the
+                // next frame's params
+                self.particle_count = count;
+                info!(particle_count = count);
+            }
+            WindowEvent::RedrawRequested => {
+                // clamped, so a long stall (like dragging
+                // the window) doesn't fling every particle
+                // into a wall
+                let now = Instant::now();
+                let delta_time = (now - self.last_frame)
+                    .as_secs_f32()
+                    .min(1.0 / 30.0);
+                self.last_frame = now;
+
+                let Some(data) = self.resumed_data.as_ref()
+                else {
+                    return;
+                };
+                let params =
+                    self.params(delta_time, &data.config);
+
+                let Some(ResumedData {
+                    surface,
+                    device,
+                    queue,
+                    compute_pipeline,
+                    render_pipeline,
+                    params: params_uniform,
+                    compute_bind_groups,
+                    render_bind_groups,
+                    profiler,
+                    ..
+                }) = self.resumed_data.as_mut()
+                else {
+                    return;
+                };
+
+                params_uniform.set(params);
+                params_uniform.upload(queue);
+
+                let frame = surface
+                    .get_current_texture()
+                    .expect("Failed to acquire next swap chain texture");
+                let view = frame.texture.create_view(
+                    &wgpu::TextureViewDescriptor::default(),
+                );
+                let mut encoder = device
+                    .create_command_encoder(
+                        &wgpu::CommandEncoderDescriptor {
+                            label:
+                                "particles_command_encoder"
+                                    .into(),
+                        },
+                    );
+                profiler.push_debug_group(
+                    &mut encoder,
+                    "particles",
+                );
+                {
+                    let mut cpass = encoder
+                        .begin_compute_pass(
+                            &wgpu::ComputePassDescriptor {
+                                label: "simulate_pass"
+                                    .into(),
+                                timestamp_writes: profiler
+                                    .compute_pass_timestamp_writes(
+                                        "simulate_pass",
+                                    ),
+                            },
+                        );
+                    cpass.set_pipeline(compute_pipeline);
+                    cpass.set_bind_group(
+                        0,
+                        Some(
+                            &compute_bind_groups
+                                [self.frame],
+                        ),
+                        &[],
+                    );
+                    cpass.dispatch_workgroups(
+                        self.particle_count
+                            .div_ceil(WORKGROUP_SIZE),
+                        1,
+                        1,
+                    );
+                }
+                {
+                    let mut rpass =
+                    encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                        label: "particles_render_pass".into(),
+                        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                            view: &view,
+                            resolve_target: None,
+                            ops: wgpu::Operations {
+                                load: wgpu::LoadOp::Clear(wgpu::Color{
+                                    r: 0.008,
+                                    g: 0.024,
+                                    b: 0.09,
+                                    a: 1.0,
+                                }),
+                                store: wgpu::StoreOp::Store,
+                            },
+                            depth_slice: None
+                        })],
+                        depth_stencil_attachment: None,
+                        timestamp_writes: profiler
+                            .render_pass_timestamp_writes(
+                                "particles_render_pass",
+                            ),
+                        occlusion_query_set: None,
+                        multiview_mask: None
+                    });
+                    rpass.set_pipeline(render_pipeline);
+                    rpass.set_bind_group(
+                        0,
+                        Some(
+                            &render_bind_groups[self.frame],
+                        ),
+                        &[],
+                    );
+                    // 6 vertices per quad, one instance per
+                    // particle
+                    rpass
+                        .draw(0..6, 0..self.particle_count);
+                }
+                profiler.pop_debug_group(&mut encoder);
+                profiler.resolve(device, &mut encoder);
+
+                queue.submit(Some(encoder.finish()));
+                frame.present();
+                profiler.update(device);
+
+                // what was written this frame is read next
+                // frame
+                self.frame = 1 - self.frame;
+            }
+            _ => (),
+        }
+    }
+    fn about_to_wait(
+        &mut self,
+        _event_loop: &ActiveEventLoop,
+    ) {
+        let Some(data) = self.resumed_data.as_ref() else {
+            return;
+        };
+
+        data.window.request_redraw();
+    }
+}
+
+impl App<'_> {
+    /// Read the particles back from the GPU and log how
+    /// fast they move on average
+    fn log_average_speed(&self) {
+        let Some(ResumedData {
+            device,
+            queue,
+            particles,
+            ..
+        }) = self.resumed_data.as_ref()
+        else {
+            return;
+        };
+
+        // the buffer written last frame
+        let particles = &particles[self.frame];
+        let size = Particle::min_size().get()
+            * self.particle_count as u64;
+        let particles: Vec<Particle> = Readback::buffer(
+            device,
+            queue,
+            particles.buffer(),
+            0..size,
+        )
+        .read_blocking(device)
+        .and_then(|data| data.create())
+        .expect("particles to be read back");
+
+        let average_speed = particles
+            .iter()
+            .map(|particle| particle.velocity.length())
+            .sum::<f32>()
+            / particles.len() as f32;
+        info!(
+            particles = particles.len(),
+            average_speed
+        );
+    }
+
+    fn params(
+        &self,
+        delta_time: f32,
+        config: &SurfaceConfiguration,
+    ) -> SimParams {
+        SimParams {
+            attractor: self.cursor,
+            attractor_strength: if self.repel {
+                -0.5
+            } else {
+                0.05
+            },
+            delta_time,
+            damping: 0.6,
+            particle_count: self.particle_count,
+            particle_size: 0.004,
+            aspect: config.width as f32
+                / config.height as f32,
+        }
+    }
+}
+
+fn main() {
+    tracing_subscriber::fmt().init();
+
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(message) => {
+            eprintln!("{message}");
+            std::process::exit(1);
+        }
+    };
+
+    let event_loop = EventLoop::new().unwrap();
+    event_loop.set_control_flow(ControlFlow::Poll);
+
+    let mut app = App::new(args);
+
+    event_loop.run_app(&mut app).expect("app to run")
+}
@@ -0,0 +1,103 @@
+struct Particle {
+    position: vec2<f32>,
+    velocity: vec2<f32>,
+}
+
+// Written by the CPU every frame
+struct SimParams {
+    attractor: vec2<f32>,
+    attractor_strength: f32,
+    delta_time: f32,
+    damping: f32,
+    particle_count: u32,
+    particle_size: f32,
+    aspect: f32,
+}
+
+@group(0) @binding(0) var<uniform> params: SimParams;
+
+// The compute pass reads last frame's particles from one
+// buffer and writes this frame's into the other. Reading
+// and writing the same buffer would let a particle see
+// some neighbours before and some after their update.
+@group(0) @binding(1) var<storage, read> particles_src: array<Particle>;
+@group(0) @binding(2) var<storage, read_write> particles_dst: array<Particle>;
+
+@compute @workgroup_size(64)
+fn simulate(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = id.x;
+    // the last workgroup can run past the end
+    if index >= params.particle_count {
+        return;
+    }
+
+    var particle = particles_src[index];
+    let dt = params.delta_time;
+
+    // pulled towards the attractor, more strongly up close
+    let to_attractor = params.attractor - particle.position;
+    let distance_squared = dot(to_attractor, to_attractor) + 0.01;
+    particle.velocity += normalize(to_attractor) * params.attractor_strength / distance_squared * dt;
+    particle.velocity *= pow(params.damping, dt);
+    particle.position += particle.velocity * dt;
+
+    // bounce off the edges of the window
+    if abs(particle.position.x) > 1.0 {
+        particle.position.x = clamp(particle.position.x, -1.0, 1.0);
+        particle.velocity.x = -particle.velocity.x;
+    }
+    if abs(particle.position.y) > 1.0 {
+        particle.position.y = clamp(particle.position.y, -1.0, 1.0);
+        particle.velocity.y = -particle.velocity.y;
+    }
+
+    particles_dst[index] = particle;
+}
+
+// The render pass reads the buffer the compute pass just
+// wrote, through a separate bind group
+@group(0) @binding(1) var<storage, read> particles: array<Particle>;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) color: vec4<f32>,
+}
+
+@vertex
+fn vertex(
+    @builtin(vertex_index) in_vertex_index: u32,
+    @builtin(instance_index) in_instance_index: u32,
+) -> VertexOutput {
+    // two triangles making a quad, from (-1, -1) to (1, 1)
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(-1.0, -1.0),
+        vec2<f32>(1.0, -1.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(-1.0, -1.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(-1.0, 1.0),
+    );
+    let corner = corners[in_vertex_index];
+    let particle = particles[in_instance_index];
+
+    // slow particles are blue, fast ones orange
+    let speed = clamp(length(particle.velocity) * 0.5, 0.0, 1.0);
+
+    var out: VertexOutput;
+    out.position = vec4<f32>(
+        particle.position + corner * params.particle_size * vec2<f32>(1.0 / params.aspect, 1.0),
+        0.0,
+        1.0,
+    );
+    out.uv = corner;
+    out.color = mix(vec4<f32>(0.1, 0.3, 1.0, 1.0), vec4<f32>(1.0, 0.5, 0.1, 1.0), speed);
+    return out;
+}
+
+@fragment
+fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
+    // a soft round dot instead of a square
+    let falloff = 1.0 - clamp(length(in.uv), 0.0, 1.0);
+    return vec4<f32>(in.color.rgb, in.color.a * falloff * falloff);
+}
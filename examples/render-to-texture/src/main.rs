@@ -0,0 +1,537 @@
+use encase::ShaderType;
+use futures_lite::future::block_on;
+use glam::{Mat4, Vec3};
+use std::{borrow::Cow, sync::Arc, time::Instant};
+use tracing::info;
+use wgpu::{
+    BindGroup, BindGroupLayout, CommandEncoder, Device,
+    Extent3d, PipelineLayout, Queue, RenderPipeline,
+    Sampler, ShaderModule, Surface, SurfaceConfiguration,
+    TextureFormat, TextureView,
+};
+use wgpu_for_bevy::{AsBindGroup, GpuImage, Uniform};
+use winit::{
+    application::ApplicationHandler,
+    dpi::PhysicalSize,
+    event::{ElementState, KeyEvent, WindowEvent},
+    event_loop::{ActiveEventLoop, ControlFlow, EventLoop},
+    keyboard::{Key, NamedKey},
+    window::Window,
+};
+
+/// The offscreen target's format. Unlike the surface's
+/// format, this one is picked by us, so it's one every
+/// adapter can both render to and sample from.
+const TARGET_FORMAT: TextureFormat =
+    TextureFormat::Rgba8UnormSrgb;
+
+#[derive(ShaderType)]
+struct QuadUniform {
+    transform: Mat4,
+}
+
+/// Everything the second pass reads. The image is the
+/// one the first pass renders into, like a material
+/// holding the same image handle a camera's
+/// `RenderTarget::Image` points at.
+#[derive(AsBindGroup)]
+struct QuadBindings {
+    #[uniform(0, buffer, visibility(vertex))]
+    quad: Uniform<QuadUniform>,
+    #[texture(1, visibility(fragment))]
+    image: GpuImage,
+    #[sampler(2, visibility(fragment))]
+    sampler: Sampler,
+}
+
+struct ResumedData<'a> {
+    window: Arc<Window>,
+    config: SurfaceConfiguration,
+    surface: Surface<'a>,
+    device: Device,
+    queue: Queue,
+    triangle_pipeline: RenderPipeline,
+    quad_pipeline: RenderPipeline,
+    quad_layout: BindGroupLayout,
+    quad: QuadBindings,
+    quad_bind_group: BindGroup,
+}
+
+struct App<'a> {
+    resumed_data: Option<ResumedData<'a>>,
+    start: Instant,
+}
+
+impl Default for App<'_> {
+    fn default() -> Self {
+        Self {
+            resumed_data: Default::default(),
+            start: Instant::now(),
+        }
+    }
+}
+
+/// The offscreen target is the same size as the window,
+/// so the triangle is drawn at the resolution it's shown
+/// at.
+fn create_target(
+    device: &Device,
+    config: &SurfaceConfiguration,
+) -> GpuImage {
+    GpuImage::new_render_target(
+        device,
+        "offscreen_target",
+        Extent3d {
+            width: config.width,
+            height: config.height,
+            depth_or_array_layers: 1,
+        },
+        TARGET_FORMAT,
+    )
+}
+
+/// A quad with the window's aspect ratio, spinning
+/// around its vertical axis in front of the camera
+fn quad_transform(
+    config: &SurfaceConfiguration,
+    time: f32,
+) -> Mat4 {
+    let aspect = config.width as f32 / config.height as f32;
+    let projection = Mat4::perspective_rh(
+        45f32.to_radians(),
+        aspect,
+        0.1,
+        10.0,
+    );
+    let view = Mat4::look_at_rh(
+        Vec3::new(0.0, 0.0, 3.0),
+        Vec3::ZERO,
+        Vec3::Y,
+    );
+    let model = Mat4::from_rotation_y(time * 0.8)
+        * Mat4::from_scale(Vec3::new(
+            aspect * 0.8,
+            0.8,
+            1.0,
+        ));
+    projection * view * model
+}
+
+fn create_pipeline(
+    device: &Device,
+    label: &str,
+    shader: &ShaderModule,
+    layout: &PipelineLayout,
+    format: TextureFormat,
+) -> RenderPipeline {
+    device.create_render_pipeline(
+        &wgpu::RenderPipelineDescriptor {
+            label: label.into(),
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module: shader,
+                entry_point: "vertex".into(),
+                buffers: &[],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: shader,
+                entry_point: "fragment".into(),
+                compilation_options: Default::default(),
+                targets: &[Some(format.into())],
+            }),
+            // no culling, so the back of the quad shows
+            // the image too (mirrored)
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview_mask: None,
+            cache: None,
+        },
+    )
+}
+
+/// Winit
+impl<'a> ApplicationHandler for App<'a> {
+    fn resumed(
+        &mut self,
+        event_loop: &winit::event_loop::ActiveEventLoop,
+    ) {
+        let window_attributes =
+            Window::default_attributes()
+                .with_title("wgpu for Bevy");
+
+        let window = Arc::new(
+            event_loop
+                .create_window(window_attributes)
+                .unwrap(),
+        );
+
+        let mut size = window.inner_size();
+        size.width = size.width.max(1);
+        size.height = size.height.max(1);
+
+        let instance = wgpu::Instance::default();
+
+        // `surface_window` is captured by the async closure,
+        // so we clone our Arc and let the closure take it
+        let surface_window = window.clone();
+        // wgpu apis for getting an adapter are async,
+        // so we block while waiting for them to complete
+        let (surface, adapter, device, queue) = block_on(
+            async move {
+                let surface = instance
+                    .create_surface(surface_window)
+                    .unwrap();
+
+                let adapter = instance
+                    .request_adapter(&wgpu::RequestAdapterOptions {
+                        power_preference:
+                            wgpu::PowerPreference::default(),
+                        force_fallback_adapter: false,
+                        // Request an adapter which can render to our surface
+                        compatible_surface: Some(&surface),
+                    })
+                    .await
+                    .expect(
+                        "Failed to find an appropriate adapter",
+                    );
+
+                info!(adapter=?adapter.get_info());
+
+                // Create the logical device and command
+                // queue
+                let (device, queue) = adapter
+                    .request_device(
+                        &wgpu::DeviceDescriptor::default(),
+                    )
+                    .await
+                    .expect("Failed to create device");
+
+                (surface, adapter, device, queue)
+            },
+        );
+
+        let swapchain_format =
+            surface.get_capabilities(&adapter).formats[0];
+
+        // the first pass draws the triangle into the
+        // offscreen target, so its pipeline uses the
+        // target's format instead of the surface's
+        let triangle_shader = device.create_shader_module(
+            wgpu::ShaderModuleDescriptor {
+                label: "triangle_shader".into(),
+                source: wgpu::ShaderSource::Wgsl(
+                    Cow::Borrowed(include_str!(
+                        "triangle.wgsl"
+                    )),
+                ),
+            },
+        );
+        let triangle_layout = device
+            .create_pipeline_layout(
+                &wgpu::PipelineLayoutDescriptor {
+                    label: "triangle_layout".into(),
+                    bind_group_layouts: &[],
+                    immediate_size: 0,
+                },
+            );
+        let triangle_pipeline = create_pipeline(
+            &device,
+            "triangle_pipeline",
+            &triangle_shader,
+            &triangle_layout,
+            TARGET_FORMAT,
+        );
+
+        let quad_shader = device.create_shader_module(
+            wgpu::ShaderModuleDescriptor {
+                label: "quad_shader".into(),
+                source: wgpu::ShaderSource::Wgsl(
+                    Cow::Borrowed(include_str!(
+                        "quad.wgsl"
+                    )),
+                ),
+            },
+        );
+        let quad_layout =
+            QuadBindings::bind_group_layout(&device);
+        let quad_pipeline_layout = device
+            .create_pipeline_layout(
+                &wgpu::PipelineLayoutDescriptor {
+                    label: "quad_pipeline_layout".into(),
+                    bind_group_layouts: &[&quad_layout],
+                    immediate_size: 0,
+                },
+            );
+        let quad_pipeline = create_pipeline(
+            &device,
+            "quad_pipeline",
+            &quad_shader,
+            &quad_pipeline_layout,
+            swapchain_format,
+        );
+
+        let config = surface
+            .get_default_config(
+                &adapter,
+                size.width,
+                size.height,
+            )
+            .unwrap();
+        surface.configure(&device, &config);
+
+        let quad = QuadBindings {
+            quad: Uniform::new(
+                &device,
+                "quad_uniform",
+                QuadUniform {
+                    transform: quad_transform(&config, 0.0),
+                },
+            ),
+            image: create_target(&device, &config),
+            sampler: device.create_sampler(
+                &wgpu::SamplerDescriptor {
+                    label: "offscreen_sampler".into(),
+                    mag_filter: wgpu::FilterMode::Linear,
+                    min_filter: wgpu::FilterMode::Linear,
+                    ..Default::default()
+                },
+            ),
+        };
+        let quad_bind_group =
+            quad.as_bind_group(&device, &quad_layout);
+
+        self.resumed_data = Some(ResumedData {
+            window,
+            config,
+            surface,
+            device,
+            queue,
+            triangle_pipeline,
+            quad_pipeline,
+            quad_layout,
+            quad,
+            quad_bind_group,
+        });
+    }
+
+    fn window_event(
+        &mut self,
+        event_loop: &winit::event_loop::ActiveEventLoop,
+        _window_id: winit::window::WindowId,
+        event: WindowEvent,
+    ) {
+        match event {
+            WindowEvent::CloseRequested => {
+                event_loop.exit();
+            }
+            WindowEvent::Resized(PhysicalSize {
+                width,
+                height,
+            }) => {
+                let Some(ResumedData {
+                    config,
+                    surface,
+                    device,
+                    quad_layout,
+                    quad,
+                    quad_bind_group,
+                    ..
+                }) = self.resumed_data.as_mut()
+                else {
+                    return;
+                };
+
+                // Reconfigure the surface with the new size,
+                // making it so that the window is *at least* 1x1
+                config.width = width.max(1);
+                config.height = height.max(1);
+
+                surface.configure(device, config);
+                // textures can't be resized, so the target
+                // is replaced, and the bind group that
+                // pointed at the old one with it
+                quad.image = create_target(device, config);
+                *quad_bind_group =
+                    quad.as_bind_group(device, quad_layout);
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        logical_key: key,
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } => {
+                // allow a single_match here so that people
+                // who use this example can easily match on
+                // new keys
+                #[allow(clippy::single_match)]
+                match key.as_ref() {
+                    Key::Named(NamedKey::Escape) => {
+                        event_loop.exit();
+                    }
+                    _ => (),
+                }
+            }
+            WindowEvent::RedrawRequested => {
+                let Some(ResumedData {
+                    config,
+                    surface,
+                    device,
+                    queue,
+                    triangle_pipeline,
+                    quad_pipeline,
+                    quad,
+                    quad_bind_group,
+                    ..
+                }) = self.resumed_data.as_mut()
+                else {
+                    return;
+                };
+
+                quad.quad.set(QuadUniform {
+                    transform: quad_transform(
+                        config,
+                        self.start.elapsed().as_secs_f32(),
+                    ),
+                });
+                quad.quad.upload(queue);
+
+                let frame = surface
+                    .get_current_texture()
+                    .expect("Failed to acquire next swap chain texture");
+                let view = frame.texture.create_view(
+                    &wgpu::TextureViewDescriptor::default(),
+                );
+                let mut encoder = device
+                    .create_command_encoder(
+                        &wgpu::CommandEncoderDescriptor {
+                            label:
+                                "render_to_texture_command_encoder"
+                                    .into(),
+                        },
+                    );
+                // passes run in the order they're encoded,
+                // so the target is finished before the
+                // second pass samples it
+                draw_triangle(
+                    &mut encoder,
+                    &quad.image.view,
+                    triangle_pipeline,
+                );
+                draw_quad(
+                    &mut encoder,
+                    &view,
+                    quad_pipeline,
+                    quad_bind_group,
+                );
+
+                queue.submit(Some(encoder.finish()));
+                frame.present();
+            }
+            _ => (),
+        }
+    }
+    fn about_to_wait(
+        &mut self,
+        _event_loop: &ActiveEventLoop,
+    ) {
+        let Some(data) = self.resumed_data.as_ref() else {
+            return;
+        };
+
+        data.window.request_redraw();
+    }
+}
+
+/// The first pass, into the offscreen target
+fn draw_triangle(
+    encoder: &mut CommandEncoder,
+    target: &TextureView,
+    render_pipeline: &RenderPipeline,
+) {
+    let mut rpass = encoder.begin_render_pass(
+        &wgpu::RenderPassDescriptor {
+            label: "triangle_render_pass".into(),
+            color_attachments: &[Some(
+                wgpu::RenderPassColorAttachment {
+                    view: target,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(
+                            wgpu::Color {
+                                r: 0.008,
+                                g: 0.024,
+                                b: 0.09,
+                                a: 1.0,
+                            },
+                        ),
+                        // kept for the second pass to read
+                        store: wgpu::StoreOp::Store,
+                    },
+                    depth_slice: None,
+                },
+            )],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+            multiview_mask: None,
+        },
+    );
+    rpass.set_pipeline(render_pipeline);
+    rpass.draw(0..3, 0..1);
+}
+
+/// The second pass, into the surface, sampling the
+/// target on a spinning quad
+fn draw_quad(
+    encoder: &mut CommandEncoder,
+    view: &TextureView,
+    render_pipeline: &RenderPipeline,
+    bind_group: &BindGroup,
+) {
+    let mut rpass = encoder.begin_render_pass(
+        &wgpu::RenderPassDescriptor {
+            label: "quad_render_pass".into(),
+            color_attachments: &[Some(
+                wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(
+                            wgpu::Color {
+                                r: 0.05,
+                                g: 0.05,
+                                b: 0.05,
+                                a: 1.0,
+                            },
+                        ),
+                        store: wgpu::StoreOp::Store,
+                    },
+                    depth_slice: None,
+                },
+            )],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+            multiview_mask: None,
+        },
+    );
+    rpass.set_pipeline(render_pipeline);
+    rpass.set_bind_group(0, bind_group, &[]);
+    rpass.draw(0..6, 0..1);
+}
+
+fn main() {
+    tracing_subscriber::fmt().init();
+
+    let event_loop = EventLoop::new().unwrap();
+    event_loop.set_control_flow(ControlFlow::Poll);
+
+    let mut app = App::default();
+
+    event_loop.run_app(&mut app).expect("app to run")
+}
@@ -0,0 +1,698 @@
+use encase::ShaderType;
+use futures_lite::future::block_on;
+use glam::{Mat4, UVec2, Vec3, Vec4};
+use std::{borrow::Cow, sync::Arc, time::Instant};
+use tracing::info;
+use wgpu::{
+    BindGroup, BindGroupLayout, Device, Queue,
+    RenderPipeline, ShaderStages, Surface,
+    SurfaceConfiguration, TextureFormat, TextureView,
+};
+use wgpu_for_bevy::DynamicUniform;
+use winit::{
+    application::ApplicationHandler,
+    dpi::PhysicalSize,
+    event::{ElementState, KeyEvent, WindowEvent},
+    event_loop::{ActiveEventLoop, ControlFlow, EventLoop},
+    keyboard::{Key, NamedKey},
+    window::Window,
+};
+
+const DEPTH_FORMAT: TextureFormat =
+    TextureFormat::Depth32Float;
+
+/// Pixels of the clear color left showing between views
+const BORDER: u32 = 2;
+
+/// The per-view data. Bevy's equivalent is `ViewUniform`,
+/// which every camera gets its own copy of.
+#[derive(ShaderType)]
+struct CameraUniform {
+    view_proj: Mat4,
+    background: Vec4,
+}
+
+/// Where a view is drawn on the surface, in physical
+/// pixels, like Bevy's `Viewport`
+#[derive(Clone, Copy, Debug)]
+struct Viewport {
+    physical_position: UVec2,
+    physical_size: UVec2,
+}
+
+/// How the surface is split between views
+#[derive(Clone, Copy, Debug)]
+enum Layout {
+    SideBySide,
+    Stacked,
+    Grid,
+}
+
+impl Layout {
+    fn next(self) -> Self {
+        match self {
+            Layout::SideBySide => Layout::Stacked,
+            Layout::Stacked => Layout::Grid,
+            Layout::Grid => Layout::SideBySide,
+        }
+    }
+
+    /// One viewport per view, left to right and then top
+    /// to bottom. Bevy leaves this to the app, which
+    /// usually recomputes it on `WindowResized`.
+    fn viewports(
+        self,
+        width: u32,
+        height: u32,
+    ) -> Vec<Viewport> {
+        let (columns, rows) = match self {
+            Layout::SideBySide => (2, 1),
+            Layout::Stacked => (1, 2),
+            Layout::Grid => (2, 2),
+        };
+        (0..rows)
+            .flat_map(|row| {
+                (0..columns).map(move |column| {
+                    // neighbouring views round their shared
+                    // edge the same way, so there are no
+                    // gaps or overlaps at odd sizes
+                    let left = width * column / columns;
+                    let right =
+                        width * (column + 1) / columns;
+                    let top = height * row / rows;
+                    let bottom = height * (row + 1) / rows;
+                    Viewport {
+                        physical_position: UVec2::new(
+                            left, top,
+                        ),
+                        physical_size: UVec2::new(
+                            right - left,
+                            bottom - top,
+                        )
+                        .max(UVec2::ONE),
+                    }
+                })
+            })
+            .collect()
+    }
+}
+
+/// How a view's camera circles the scene
+struct Orbit {
+    distance: f32,
+    height: f32,
+    /// Radians per second
+    speed: f32,
+    start_angle: f32,
+    background: Vec4,
+}
+
+/// The camera for each view, in the order the layout
+/// places them
+const ORBITS: [Orbit; 4] = [
+    Orbit {
+        distance: 12.0,
+        height: 6.0,
+        speed: 0.3,
+        start_angle: 0.0,
+        background: Vec4::new(0.008, 0.024, 0.09, 1.0),
+    },
+    Orbit {
+        distance: 6.0,
+        height: 14.0,
+        speed: -0.2,
+        start_angle: 1.0,
+        background: Vec4::new(0.09, 0.024, 0.03, 1.0),
+    },
+    Orbit {
+        distance: 9.0,
+        height: 1.5,
+        speed: 0.6,
+        start_angle: 2.0,
+        background: Vec4::new(0.02, 0.07, 0.03, 1.0),
+    },
+    Orbit {
+        distance: 16.0,
+        height: 3.0,
+        speed: -0.1,
+        start_angle: 4.0,
+        background: Vec4::new(0.06, 0.05, 0.01, 1.0),
+    },
+];
+
+/// Cubes per side of the grid, which has to match
+/// `GRID_SIZE` in the shader
+const GRID_SIZE: u32 = 9;
+
+struct ResumedData<'a> {
+    window: Arc<Window>,
+    config: SurfaceConfiguration,
+    surface: Surface<'a>,
+    device: Device,
+    queue: Queue,
+    depth_view: TextureView,
+    scene_pipeline: RenderPipeline,
+    background_pipeline: RenderPipeline,
+    camera_layout: BindGroupLayout,
+    camera_bind_group: BindGroup,
+    cameras: DynamicUniform<CameraUniform>,
+    viewports: Vec<Viewport>,
+}
+
+struct App<'a> {
+    resumed_data: Option<ResumedData<'a>>,
+    start: Instant,
+    layout: Layout,
+}
+
+impl Default for App<'_> {
+    fn default() -> Self {
+        Self {
+            resumed_data: Default::default(),
+            start: Instant::now(),
+            layout: Layout::Grid,
+        }
+    }
+}
+
+/// Fill `cameras` with one uniform per viewport for the
+/// current frame, returning the dynamic offset of each.
+fn prepare_cameras(
+    cameras: &mut DynamicUniform<CameraUniform>,
+    viewports: &[Viewport],
+    time: f32,
+) -> Vec<u32> {
+    cameras.clear();
+    viewports
+        .iter()
+        .zip(&ORBITS)
+        .map(|(viewport, orbit)| {
+            let angle =
+                orbit.start_angle + time * orbit.speed;
+            let eye = Vec3::new(
+                angle.cos() * orbit.distance,
+                orbit.height,
+                angle.sin() * orbit.distance,
+            );
+            let view = Mat4::look_at_rh(
+                eye,
+                Vec3::new(0.0, 1.0, 0.0),
+                Vec3::Y,
+            );
+            // the aspect ratio comes from the viewport,
+            // not the window, or every view would be
+            // stretched
+            let size = viewport.physical_size.as_vec2();
+            let projection = Mat4::perspective_rh(
+                45f32.to_radians(),
+                size.x / size.y,
+                0.1,
+                100.0,
+            );
+
+            cameras.push(&CameraUniform {
+                view_proj: projection * view,
+                background: orbit.background,
+            })
+        })
+        .collect()
+}
+
+fn create_camera_bind_group(
+    device: &Device,
+    layout: &BindGroupLayout,
+    cameras: &DynamicUniform<CameraUniform>,
+) -> BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: "camera_bind_group".into(),
+        layout,
+        entries: &[cameras
+            .bind_group_entry(0)
+            .expect("camera buffer to be written")],
+    })
+}
+
+fn create_depth_view(
+    device: &Device,
+    config: &SurfaceConfiguration,
+) -> TextureView {
+    device
+        .create_texture(&wgpu::TextureDescriptor {
+            label: "depth_texture".into(),
+            size: wgpu::Extent3d {
+                width: config.width,
+                height: config.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: DEPTH_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        })
+        .create_view(&wgpu::TextureViewDescriptor::default())
+}
+
+/// Winit
+impl<'a> ApplicationHandler for App<'a> {
+    fn resumed(
+        &mut self,
+        event_loop: &winit::event_loop::ActiveEventLoop,
+    ) {
+        let window_attributes =
+            Window::default_attributes()
+                .with_title("wgpu for Bevy");
+
+        let window = Arc::new(
+            event_loop
+                .create_window(window_attributes)
+                .unwrap(),
+        );
+
+        let mut size = window.inner_size();
+        size.width = size.width.max(1);
+        size.height = size.height.max(1);
+
+        let instance = wgpu::Instance::default();
+
+        // `surface_window` is captured by the async closure,
+        // so we clone our Arc and let the closure take it
+        let surface_window = window.clone();
+        // wgpu apis for getting an adapter are async,
+        // so we block while waiting for them to complete
+        let (surface, adapter, device, queue) = block_on(
+            async move {
+                let surface = instance
+                    .create_surface(surface_window)
+                    .unwrap();
+
+                let adapter = instance
+                    .request_adapter(&wgpu::RequestAdapterOptions {
+                        power_preference:
+                            wgpu::PowerPreference::default(),
+                        force_fallback_adapter: false,
+                        // Request an adapter which can render to our surface
+                        compatible_surface: Some(&surface),
+                    })
+                    .await
+                    .expect(
+                        "Failed to find an appropriate adapter",
+                    );
+
+                info!(adapter=?adapter.get_info());
+
+                // Create the logical device and command
+                // queue
+                let (device, queue) = adapter
+                    .request_device(
+                        &wgpu::DeviceDescriptor::default(),
+                    )
+                    .await
+                    .expect("Failed to create device");
+
+                (surface, adapter, device, queue)
+            },
+        );
+
+        let shader = device.create_shader_module(
+            wgpu::ShaderModuleDescriptor {
+                label: "split_screen_shader".into(),
+                source: wgpu::ShaderSource::Wgsl(
+                    Cow::Borrowed(include_str!(
+                        "split_screen.wgsl"
+                    )),
+                ),
+            },
+        );
+
+        let camera_layout =
+            device.create_bind_group_layout(
+                &wgpu::BindGroupLayoutDescriptor {
+                    label: "camera_layout".into(),
+                    entries: &[DynamicUniform::<
+                        CameraUniform,
+                    >::layout_entry(
+                        0,
+                        ShaderStages::VERTEX_FRAGMENT,
+                    )],
+                },
+            );
+
+        let pipeline_layout = device
+            .create_pipeline_layout(
+                &wgpu::PipelineLayoutDescriptor {
+                    label: "split_screen_pipeline_layout"
+                        .into(),
+                    bind_group_layouts: &[&camera_layout],
+                    immediate_size: 0,
+                },
+            );
+
+        let swapchain_format =
+            surface.get_capabilities(&adapter).formats[0];
+
+        // the scene and the background only differ in
+        // their shader entry points and depth testing
+        let create_pipeline =
+            |label: &str,
+             vertex: &str,
+             fragment: &str,
+             depth_stencil: wgpu::DepthStencilState| {
+                device.create_render_pipeline(
+                    &wgpu::RenderPipelineDescriptor {
+                        label: label.into(),
+                        layout: Some(&pipeline_layout),
+                        vertex: wgpu::VertexState {
+                            module: &shader,
+                            entry_point: vertex.into(),
+                            buffers: &[],
+                            compilation_options:
+                                Default::default(),
+                        },
+                        fragment: Some(wgpu::FragmentState {
+                            module: &shader,
+                            entry_point: fragment.into(),
+                            compilation_options:
+                                Default::default(),
+                            targets: &[Some(
+                                swapchain_format.into(),
+                            )],
+                        }),
+                        primitive:
+                            wgpu::PrimitiveState::default(),
+                        depth_stencil: Some(depth_stencil),
+                        multisample:
+                            wgpu::MultisampleState::default(
+                            ),
+                        multiview_mask: None,
+                        cache: None,
+                    },
+                )
+            };
+        let scene_pipeline = create_pipeline(
+            "scene_pipeline",
+            "vertex",
+            "fragment",
+            wgpu::DepthStencilState {
+                format: DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: Default::default(),
+                bias: Default::default(),
+            },
+        );
+        // drawn first in each view, behind everything
+        let background_pipeline = create_pipeline(
+            "background_pipeline",
+            "vertex_background",
+            "fragment_background",
+            wgpu::DepthStencilState {
+                format: DEPTH_FORMAT,
+                depth_write_enabled: false,
+                depth_compare:
+                    wgpu::CompareFunction::Always,
+                stencil: Default::default(),
+                bias: Default::default(),
+            },
+        );
+
+        let config = surface
+            .get_default_config(
+                &adapter,
+                size.width,
+                size.height,
+            )
+            .unwrap();
+        surface.configure(&device, &config);
+        let depth_view =
+            create_depth_view(&device, &config);
+
+        let viewports = self
+            .layout
+            .viewports(config.width, config.height);
+        info!(layout = ?self.layout, ?viewports);
+
+        // The bind group needs a buffer to point at, so
+        // write the first frame's cameras right away
+        let mut cameras =
+            DynamicUniform::new(&device, "camera_uniforms");
+        prepare_cameras(&mut cameras, &viewports, 0.0);
+        cameras.write_buffer(&device, &queue);
+        let camera_bind_group = create_camera_bind_group(
+            &device,
+            &camera_layout,
+            &cameras,
+        );
+
+        self.resumed_data = Some(ResumedData {
+            window,
+            config,
+            surface,
+            device,
+            queue,
+            depth_view,
+            scene_pipeline,
+            background_pipeline,
+            camera_layout,
+            camera_bind_group,
+            cameras,
+            viewports,
+        });
+    }
+
+    fn window_event(
+        &mut self,
+        event_loop: &winit::event_loop::ActiveEventLoop,
+        _window_id: winit::window::WindowId,
+        event: WindowEvent,
+    ) {
+        match event {
+            WindowEvent::CloseRequested => {
+                event_loop.exit();
+            }
+            WindowEvent::Resized(PhysicalSize {
+                width,
+                height,
+            }) => {
+                let Some(ResumedData {
+                    config,
+                    surface,
+                    device,
+                    depth_view,
+                    viewports,
+                    ..
+                }) = self.resumed_data.as_mut()
+                else {
+                    return;
+                };
+
+                // Reconfigure the surface with the new size,
+                // making it so that the window is *at least* 1x1
+                config.width = width.max(1);
+                config.height = height.max(1);
+
+                surface.configure(device, config);
+                // the depth texture has to match the size
+                // of the surface texture
+                *depth_view =
+                    create_depth_view(device, config);
+                // viewports are in pixels, so they don't
+                // follow the window on their own
+                *viewports = self
+                    .layout
+                    .viewports(config.width, config.height);
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        logical_key: key,
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } => match key.as_ref() {
+                Key::Named(NamedKey::Escape) => {
+                    event_loop.exit();
+                }
+                Key::Named(NamedKey::Tab) => {
+                    self.layout = self.layout.next();
+                    if let Some(data) =
+                        self.resumed_data.as_mut()
+                    {
+                        data.viewports =
+                            self.layout.viewports(
+                                data.config.width,
+                                data.config.height,
+                            );
+                        info!(
+                            layout = ?self.layout,
+                            viewports = ?data.viewports
+                        );
+                    }
+                }
+                _ => (),
+            },
+            WindowEvent::RedrawRequested => {
+                let Some(ResumedData {
+                    surface,
+                    device,
+                    queue,
+                    depth_view,
+                    scene_pipeline,
+                    background_pipeline,
+                    camera_layout,
+                    camera_bind_group,
+                    cameras,
+                    viewports,
+                    ..
+                }) = self.resumed_data.as_mut()
+                else {
+                    return;
+                };
+
+                let offsets = prepare_cameras(
+                    cameras,
+                    viewports,
+                    self.start.elapsed().as_secs_f32(),
+                );
+                // the buffer only grows if more cameras
+                // are pushed than before, but when it does
+                // the old bind group points at a dead buffer
+                if cameras.write_buffer(device, queue) {
+                    *camera_bind_group =
+                        create_camera_bind_group(
+                            device,
+                            camera_layout,
+                            cameras,
+                        );
+                }
+
+                let frame = surface
+                    .get_current_texture()
+                    .expect("Failed to acquire next swap chain texture");
+                let view = frame.texture.create_view(
+                    &wgpu::TextureViewDescriptor::default(),
+                );
+                let mut encoder = device
+                    .create_command_encoder(
+                    &wgpu::CommandEncoderDescriptor {
+                        label:
+                            "split_screen_command_encoder"
+                                .into(),
+                    },
+                );
+                {
+                    // one pass for every view. Clearing
+                    // always covers the whole attachment,
+                    // so each view draws its own background
+                    let mut rpass =
+                    encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                        label: "split_screen_render_pass".into(),
+                        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                            view: &view,
+                            resolve_target: None,
+                            ops: wgpu::Operations {
+                                load: wgpu::LoadOp::Clear(wgpu::Color{
+                                    r: 0.3,
+                                    g: 0.3,
+                                    b: 0.3,
+                                    a: 1.0,
+                                }),
+                                store: wgpu::StoreOp::Store,
+                            },
+                            depth_slice: None
+                        })],
+                        depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                            view: depth_view,
+                            depth_ops: Some(wgpu::Operations {
+                                load: wgpu::LoadOp::Clear(1.0),
+                                store: wgpu::StoreOp::Discard,
+                            }),
+                            stencil_ops: None,
+                        }),
+                        timestamp_writes: None,
+                        occlusion_query_set: None,
+                        multiview_mask: None
+                    });
+                    for (viewport, offset) in
+                        viewports.iter().zip(offsets)
+                    {
+                        let position =
+                            viewport.physical_position;
+                        let size = viewport.physical_size;
+                        // the viewport maps clip space onto
+                        // this view's rectangle, scaling the
+                        // whole image to fit
+                        rpass.set_viewport(
+                            position.x as f32,
+                            position.y as f32,
+                            size.x as f32,
+                            size.y as f32,
+                            0.0,
+                            1.0,
+                        );
+                        // the scissor rect only discards
+                        // pixels, without scaling anything.
+                        // Insetting it leaves a border of
+                        // the clear color around the view.
+                        let inset = size.saturating_sub(
+                            UVec2::splat(BORDER * 2),
+                        );
+                        if inset.x == 0 || inset.y == 0 {
+                            // too small to see anything
+                            continue;
+                        }
+                        rpass.set_scissor_rect(
+                            position.x + BORDER,
+                            position.y + BORDER,
+                            inset.x,
+                            inset.y,
+                        );
+                        // One bind group for every view.
+                        // Only the offset changes.
+                        rpass.set_bind_group(
+                            0,
+                            Some(&*camera_bind_group),
+                            &[offset],
+                        );
+                        rpass.set_pipeline(
+                            background_pipeline,
+                        );
+                        rpass.draw(0..3, 0..1);
+                        rpass.set_pipeline(scene_pipeline);
+                        rpass.draw(
+                            0..36,
+                            0..GRID_SIZE * GRID_SIZE,
+                        );
+                    }
+                }
+
+                queue.submit(Some(encoder.finish()));
+                frame.present();
+            }
+            _ => (),
+        }
+    }
+    fn about_to_wait(
+        &mut self,
+        _event_loop: &ActiveEventLoop,
+    ) {
+        let Some(data) = self.resumed_data.as_ref() else {
+            return;
+        };
+
+        data.window.request_redraw();
+    }
+}
+
+fn main() {
+    tracing_subscriber::fmt().init();
+
+    let event_loop = EventLoop::new().unwrap();
+    event_loop.set_control_flow(ControlFlow::Poll);
+
+    let mut app = App::default();
+
+    event_loop.run_app(&mut app).expect("app to run")
+}
@@ -0,0 +1,74 @@
+struct Camera {
+    view_proj: mat4x4<f32>,
+    background: vec4<f32>,
+}
+
+// Every view binds the same buffer. The dynamic offset
+// passed to `set_bind_group` picks which view's camera
+// this reads.
+@group(0) @binding(0) var<uniform> camera: Camera;
+
+// cubes per side of the square grid the scene is made of
+const GRID_SIZE: u32 = 9u;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) normal: vec3<f32>,
+    @location(1) color: vec3<f32>,
+}
+
+@vertex
+fn vertex(
+    @builtin(vertex_index) vertex_index: u32,
+    @builtin(instance_index) instance_index: u32,
+) -> VertexOutput {
+    // 6 faces of 2 triangles each. Each face picks an
+    // axis and a side, then spans the other two axes.
+    let face = vertex_index / 6u;
+    let corner = vertex_index % 6u;
+    let axis = face % 3u;
+    let side = select(-1.0, 1.0, face < 3u);
+    let u = f32((corner + 1u) % 6u / 3u) * 2.0 - 1.0;
+    let v = f32(corner % 2u) * 2.0 - 1.0;
+
+    var local = vec3<f32>(0.0);
+    var normal = vec3<f32>(0.0);
+    local[axis] = side;
+    normal[axis] = side;
+    local[(axis + 1u) % 3u] = u;
+    local[(axis + 2u) % 3u] = v;
+
+    // a grid of cubes with heights that make a hill
+    let x = f32(instance_index % GRID_SIZE) - f32(GRID_SIZE - 1u) / 2.0;
+    let z = f32(instance_index / GRID_SIZE) - f32(GRID_SIZE - 1u) / 2.0;
+    let height = 0.5 + 2.0 * exp(-(x * x + z * z) / 12.0);
+    let scale = vec3<f32>(0.4, height, 0.4);
+    let world = local * scale + vec3<f32>(x, height, z);
+
+    var out: VertexOutput;
+    out.position = camera.view_proj * vec4<f32>(world, 1.0);
+    out.normal = normal;
+    out.color = vec3<f32>(0.3 + 0.05 * (x + 4.0), 0.4 + 0.2 * height, 0.8 - 0.05 * (z + 4.0));
+    return out;
+}
+
+@fragment
+fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
+    let light = normalize(vec3<f32>(0.4, 1.0, 0.6));
+    let diffuse = max(dot(in.normal, light), 0.0);
+    return vec4<f32>(in.color * (0.2 + 0.8 * diffuse), 1.0);
+}
+
+@vertex
+fn vertex_background(@builtin(vertex_index) vertex_index: u32) -> @builtin(position) vec4<f32> {
+    // one triangle that covers all of clip space, which
+    // the viewport maps onto just this view
+    let x = f32(vertex_index / 2u) * 4.0 - 1.0;
+    let y = f32(vertex_index % 2u) * 4.0 - 1.0;
+    return vec4<f32>(x, y, 1.0, 1.0);
+}
+
+@fragment
+fn fragment_background() -> @location(0) vec4<f32> {
+    return camera.background;
+}
@@ -0,0 +1,587 @@
+use futures_lite::future::block_on;
+use std::{
+    borrow::Cow,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use tracing::{error, info};
+use wgpu::{
+    AddressMode, BindGroup, BindGroupLayout, Device,
+    FilterMode, Queue, RenderPipeline, Sampler,
+    ShaderStages, Surface, SurfaceConfiguration,
+};
+use wgpu_for_bevy::{
+    GpuImage, Image, TEXTURE_COMPRESSION_FEATURES,
+};
+use winit::{
+    application::ApplicationHandler,
+    dpi::PhysicalSize,
+    event::{ElementState, KeyEvent, WindowEvent},
+    event_loop::{ActiveEventLoop, ControlFlow, EventLoop},
+    keyboard::{Key, NamedKey},
+    window::Window,
+};
+
+const ASSET_DIR: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/../../assets"
+);
+
+/// The address modes cycled through with `A`
+const ADDRESS_MODES: [AddressMode; 3] = [
+    AddressMode::ClampToEdge,
+    AddressMode::Repeat,
+    AddressMode::MirrorRepeat,
+];
+
+struct ResumedData<'a> {
+    window: Arc<Window>,
+    config: SurfaceConfiguration,
+    render_pipeline: RenderPipeline,
+    surface: Surface<'a>,
+    device: Device,
+    queue: Queue,
+    texture_layout: BindGroupLayout,
+    texture_bind_group: BindGroup,
+    image: GpuImage,
+    sampler: Sampler,
+}
+
+struct App<'a> {
+    resumed_data: Option<ResumedData<'a>>,
+    /// The images to show, cycled through with `Space`
+    images: Vec<PathBuf>,
+    image_index: usize,
+    filter: FilterMode,
+    address_mode: usize,
+    /// Whether to request the adapter's texture
+    /// compression features. Without them KTX2 files are
+    /// decompressed on the CPU.
+    texture_compression: bool,
+}
+
+impl App<'_> {
+    fn new(
+        images: Vec<PathBuf>,
+        texture_compression: bool,
+    ) -> Self {
+        Self {
+            resumed_data: None,
+            images,
+            image_index: 0,
+            texture_compression,
+            // nearest filtering keeps the pixels of a
+            // small image sharp when it's magnified
+            filter: FilterMode::Nearest,
+            address_mode: 0,
+        }
+    }
+}
+
+fn load_image(
+    device: &Device,
+    queue: &Queue,
+    path: &Path,
+) -> GpuImage {
+    let image = if path
+        .extension()
+        .is_some_and(|extension| extension == "ktx2")
+    {
+        Image::load_ktx2(path, device.features())
+            .map_err(|error| error.to_string())
+    } else {
+        Image::load(path, true)
+            .map_err(|error| error.to_string())
+    }
+    .unwrap_or_else(|error| {
+        panic!(
+            "failed to load {}: {error}",
+            path.display()
+        )
+    });
+    info!(
+        path = %path.display(),
+        width = image.width(),
+        height = image.height(),
+        format = ?image.format,
+        mip_level_count = image.mip_level_count,
+        "loaded image"
+    );
+    image.upload(device, queue, "image_texture")
+}
+
+fn create_sampler(
+    device: &Device,
+    filter: FilterMode,
+    address_mode: AddressMode,
+) -> Sampler {
+    info!(
+        ?filter,
+        ?address_mode,
+        "creating sampler"
+    );
+    device.create_sampler(&wgpu::SamplerDescriptor {
+        label: "image_sampler".into(),
+        address_mode_u: address_mode,
+        address_mode_v: address_mode,
+        address_mode_w: address_mode,
+        mag_filter: filter,
+        min_filter: filter,
+        ..Default::default()
+    })
+}
+
+fn create_texture_bind_group(
+    device: &Device,
+    layout: &BindGroupLayout,
+    image: &GpuImage,
+    sampler: &Sampler,
+) -> BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: "texture_bind_group".into(),
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource:
+                    wgpu::BindingResource::TextureView(
+                        &image.view,
+                    ),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(
+                    sampler,
+                ),
+            },
+        ],
+    })
+}
+
+/// Winit
+impl<'a> ApplicationHandler for App<'a> {
+    fn resumed(
+        &mut self,
+        event_loop: &winit::event_loop::ActiveEventLoop,
+    ) {
+        let window_attributes =
+            Window::default_attributes()
+                .with_title("wgpu for Bevy");
+
+        let window = Arc::new(
+            event_loop
+                .create_window(window_attributes)
+                .unwrap(),
+        );
+
+        let mut size = window.inner_size();
+        size.width = size.width.max(1);
+        size.height = size.height.max(1);
+
+        let instance = wgpu::Instance::default();
+
+        // `surface_window` is captured by the async closure,
+        // so we clone our Arc and let the closure take it
+        let surface_window = window.clone();
+        let texture_compression = self.texture_compression;
+        // wgpu apis for getting an adapter are async,
+        // so we block while waiting for them to complete
+        let (surface, adapter, device, queue) = block_on(
+            async move {
+                let surface = instance
+                    .create_surface(surface_window)
+                    .unwrap();
+
+                let adapter = instance
+                    .request_adapter(&wgpu::RequestAdapterOptions {
+                        power_preference:
+                            wgpu::PowerPreference::default(),
+                        force_fallback_adapter: false,
+                        // Request an adapter which can render to our surface
+                        compatible_surface: Some(&surface),
+                    })
+                    .await
+                    .expect(
+                        "Failed to find an appropriate adapter",
+                    );
+
+                info!(adapter=?adapter.get_info());
+
+                // Create the logical device and command
+                // queue
+                // compressed formats are optional
+                // features, so they have to be asked for
+                // here to be usable at all
+                let required_features =
+                    if texture_compression {
+                        adapter.features()
+                            & TEXTURE_COMPRESSION_FEATURES
+                    } else {
+                        wgpu::Features::empty()
+                    };
+                info!(?required_features);
+
+                let (device, queue) = adapter
+                    .request_device(
+                        &wgpu::DeviceDescriptor {
+                            required_features,
+                            ..Default::default()
+                        },
+                    )
+                    .await
+                    .expect("Failed to create device");
+
+                (surface, adapter, device, queue)
+            },
+        );
+
+        let shader = device.create_shader_module(
+            wgpu::ShaderModuleDescriptor {
+                label: "texture_shader".into(),
+                source: wgpu::ShaderSource::Wgsl(
+                    Cow::Borrowed(include_str!(
+                        "texture.wgsl"
+                    )),
+                ),
+            },
+        );
+
+        let texture_layout = device
+            .create_bind_group_layout(
+            &wgpu::BindGroupLayoutDescriptor {
+                label: "texture_layout".into(),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type:
+                                wgpu::TextureSampleType::Float {
+                                    filterable: true,
+                                },
+                            view_dimension:
+                                wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler(
+                            wgpu::SamplerBindingType::Filtering,
+                        ),
+                        count: None,
+                    },
+                ],
+            },
+        );
+
+        let pipeline_layout = device
+            .create_pipeline_layout(
+                &wgpu::PipelineLayoutDescriptor {
+                    label: "texture_pipeline_layout".into(),
+                    bind_group_layouts: &[&texture_layout],
+                    immediate_size: 0,
+                },
+            );
+
+        let swapchain_format =
+            surface.get_capabilities(&adapter).formats[0];
+
+        let render_pipeline = device
+            .create_render_pipeline(
+                &wgpu::RenderPipelineDescriptor {
+                    label: "texture_pipeline".into(),
+                    layout: Some(&pipeline_layout),
+                    vertex: wgpu::VertexState {
+                        module: &shader,
+                        entry_point: "vertex".into(),
+                        buffers: &[],
+                        compilation_options:
+                            Default::default(),
+                    },
+                    fragment: Some(wgpu::FragmentState {
+                        module: &shader,
+                        entry_point: "fragment".into(),
+                        compilation_options:
+                            Default::default(),
+                        targets: &[Some(
+                            swapchain_format.into(),
+                        )],
+                    }),
+                    primitive:
+                        wgpu::PrimitiveState::default(),
+                    depth_stencil: None,
+                    multisample:
+                        wgpu::MultisampleState::default(),
+                    multiview_mask: None,
+                    cache: None,
+                },
+            );
+
+        let config = surface
+            .get_default_config(
+                &adapter,
+                size.width,
+                size.height,
+            )
+            .unwrap();
+        surface.configure(&device, &config);
+
+        let image = load_image(
+            &device,
+            &queue,
+            &self.images[self.image_index],
+        );
+        let sampler = create_sampler(
+            &device,
+            self.filter,
+            ADDRESS_MODES[self.address_mode],
+        );
+        let texture_bind_group = create_texture_bind_group(
+            &device,
+            &texture_layout,
+            &image,
+            &sampler,
+        );
+
+        self.resumed_data = Some(ResumedData {
+            window,
+            config,
+            render_pipeline,
+            surface,
+            device,
+            queue,
+            texture_layout,
+            texture_bind_group,
+            image,
+            sampler,
+        });
+    }
+
+    fn window_event(
+        &mut self,
+        event_loop: &winit::event_loop::ActiveEventLoop,
+        _window_id: winit::window::WindowId,
+        event: WindowEvent,
+    ) {
+        match event {
+            WindowEvent::CloseRequested => {
+                event_loop.exit();
+            }
+            WindowEvent::Resized(PhysicalSize {
+                width,
+                height,
+            }) => {
+                let Some(ResumedData {
+                    config,
+                    surface,
+                    device,
+                    ..
+                }) = self.resumed_data.as_mut()
+                else {
+                    return;
+                };
+
+                // Reconfigure the surface with the new size,
+                // making it so that the window is *at least* 1x1
+                config.width = width.max(1);
+                config.height = height.max(1);
+
+                surface.configure(device, config);
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        logical_key: key,
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } => {
+                match key.as_ref() {
+                    Key::Named(NamedKey::Escape) => {
+                        event_loop.exit();
+                        return;
+                    }
+                    Key::Character("f") => {
+                        self.filter = match self.filter {
+                            FilterMode::Nearest => {
+                                FilterMode::Linear
+                            }
+                            FilterMode::Linear => {
+                                FilterMode::Nearest
+                            }
+                        };
+                    }
+                    Key::Character("a") => {
+                        self.address_mode =
+                            (self.address_mode + 1)
+                                % ADDRESS_MODES.len();
+                    }
+                    Key::Named(NamedKey::Space) => {
+                        self.image_index =
+                            (self.image_index + 1)
+                                % self.images.len();
+                    }
+                    _ => return,
+                }
+
+                let Some(ResumedData {
+                    device,
+                    queue,
+                    texture_layout,
+                    texture_bind_group,
+                    image,
+                    sampler,
+                    ..
+                }) = self.resumed_data.as_mut()
+                else {
+                    return;
+                };
+
+                // bind groups can't be changed after
+                // they're created, so a new sampler or
+                // texture means a new bind group
+                if let Key::Named(NamedKey::Space) =
+                    key.as_ref()
+                {
+                    *image = load_image(
+                        device,
+                        queue,
+                        &self.images[self.image_index],
+                    );
+                } else {
+                    *sampler = create_sampler(
+                        device,
+                        self.filter,
+                        ADDRESS_MODES[self.address_mode],
+                    );
+                }
+                *texture_bind_group =
+                    create_texture_bind_group(
+                        device,
+                        texture_layout,
+                        image,
+                        sampler,
+                    );
+            }
+            WindowEvent::RedrawRequested => {
+                let Some(ResumedData {
+                    surface,
+                    device,
+                    queue,
+                    render_pipeline,
+                    texture_bind_group,
+                    ..
+                }) = self.resumed_data.as_mut()
+                else {
+                    return;
+                };
+
+                let frame = surface
+                    .get_current_texture()
+                    .expect("Failed to acquire next swap chain texture");
+                let view = frame.texture.create_view(
+                    &wgpu::TextureViewDescriptor::default(),
+                );
+                let mut encoder = device
+                    .create_command_encoder(
+                        &wgpu::CommandEncoderDescriptor {
+                            label:
+                                "texture_command_encoder"
+                                    .into(),
+                        },
+                    );
+                {
+                    let mut rpass =
+                    encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                        label: "texture_render_pass".into(),
+                        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                            view: &view,
+                            resolve_target: None,
+                            ops: wgpu::Operations {
+                                load: wgpu::LoadOp::Clear(wgpu::Color{
+                                    r: 0.008,
+                                    g: 0.024,
+                                    b: 0.09,
+                                    a: 1.0,
+                                }),
+                                store: wgpu::StoreOp::Store,
+                            },
+                            depth_slice: None
+                        })],
+                        depth_stencil_attachment: None,
+                        timestamp_writes: None,
+                        occlusion_query_set: None,
+                        multiview_mask: None
+                    });
+                    rpass.set_pipeline(render_pipeline);
+                    rpass.set_bind_group(
+                        0,
+                        Some(&*texture_bind_group),
+                        &[],
+                    );
+                    rpass.draw(0..6, 0..1);
+                }
+
+                queue.submit(Some(encoder.finish()));
+                frame.present();
+            }
+            _ => (),
+        }
+    }
+    fn about_to_wait(
+        &mut self,
+        _event_loop: &ActiveEventLoop,
+    ) {
+        let Some(data) = self.resumed_data.as_ref() else {
+            return;
+        };
+
+        data.window.request_redraw();
+    }
+}
+
+fn main() {
+    tracing_subscriber::fmt().init();
+
+    // any PNG, JPEG, or KTX2 files passed on the command
+    // line are shown instead of the bundled images.
+    // `--no-texture-compression` forces KTX2 files to be
+    // decompressed on the CPU.
+    let mut texture_compression = true;
+    let mut images: Vec<PathBuf> = vec![];
+    for arg in std::env::args_os().skip(1) {
+        if arg == "--no-texture-compression" {
+            texture_compression = false;
+        } else {
+            images.push(arg.into());
+        }
+    }
+    if images.is_empty() {
+        images =
+            ["checker.png", "rings.jpg", "rings_bc1.ktx2"]
+                .into_iter()
+                .map(|name| {
+                    PathBuf::from(ASSET_DIR)
+                        .join("textures")
+                        .join(name)
+                })
+                .collect();
+    }
+    if let Some(missing) =
+        images.iter().find(|path| !path.is_file())
+    {
+        error!(path = %missing.display(), "image not found");
+        std::process::exit(1);
+    }
+
+    let event_loop = EventLoop::new().unwrap();
+    event_loop.set_control_flow(ControlFlow::Poll);
+
+    let mut app = App::new(images, texture_compression);
+
+    event_loop.run_app(&mut app).expect("app to run")
+}
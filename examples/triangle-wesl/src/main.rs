@@ -210,7 +210,7 @@ impl<'a> ApplicationHandler for App<'a> {
                 config.width = width.max(1);
                 config.height = height.max(1);
 
-                surface.configure(&device, &config);
+                surface.configure(device, config);
             }
             WindowEvent::KeyboardInput {
                 event:
@@ -286,7 +286,7 @@ impl<'a> ApplicationHandler for App<'a> {
                         occlusion_query_set: None,
                         multiview_mask: None
                     });
-                    rpass.set_pipeline(&render_pipeline);
+                    rpass.set_pipeline(render_pipeline);
                     rpass.draw(0..3, 0..1);
                 }
 
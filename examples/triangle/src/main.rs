@@ -1,9 +1,15 @@
 use futures_lite::future::block_on;
-use std::{borrow::Cow, sync::Arc};
-use tracing::info;
+use std::{borrow::Cow, cell::Cell, rc::Rc, sync::Arc};
+use tracing::{error, info, warn};
 use wgpu::{
-    Device, Queue, RenderPipeline, Surface,
-    SurfaceConfiguration,
+    Adapter, CommandEncoder, Device, Queue, RenderPipeline,
+    Surface, SurfaceConfiguration, TextureFormat,
+    TextureView,
+};
+use wgpu_for_bevy::{
+    DeviceLostWatcher, GpuError, GpuResources,
+    ResourceHandle, ScreenshotManager, capture_errors,
+    log_uncaptured_errors,
 };
 use winit::{
     application::ApplicationHandler,
@@ -17,10 +23,17 @@ use winit::{
 struct ResumedData<'a> {
     window: Arc<Window>,
     config: SurfaceConfiguration,
-    render_pipeline: RenderPipeline,
+    resources: GpuResources,
+    /// The format the pipeline is created for, which is
+    /// shared with its closure so a rebuild after losing
+    /// the device can pick a different one
+    swapchain_format: Rc<Cell<TextureFormat>>,
+    render_pipeline: ResourceHandle<RenderPipeline>,
     surface: Surface<'a>,
     device: Device,
     queue: Queue,
+    screenshots: ScreenshotManager,
+    device_lost: DeviceLostWatcher,
 }
 
 #[derive(Default)]
@@ -48,121 +61,78 @@ impl<'a> ApplicationHandler for App<'a> {
         size.width = size.width.max(1);
         size.height = size.height.max(1);
 
-        let instance = wgpu::Instance::default();
-
-        // `surface_window` is captured by the async closure,
-        // so we clone our Arc and let the closure take it
-        let surface_window = window.clone();
-        // wgpu apis for getting an adapter are async,
-        // so we block while waiting for them to complete
-        let (surface, adapter, device, queue) = block_on(
-            async move {
-                let surface = instance
-                    .create_surface(surface_window)
-                    .unwrap();
-
-                let adapter = instance
-                    .request_adapter(&wgpu::RequestAdapterOptions {
-                        power_preference:
-                            wgpu::PowerPreference::default(),
-                        force_fallback_adapter: false,
-                        // Request an adapter which can render to our surface
-                        compatible_surface: Some(&surface),
-                    })
-                    .await
-                    .expect(
-                        "Failed to find an appropriate adapter",
-                    );
+        let (surface, adapter, device, queue) =
+            request_device(window.clone());
 
-                info!(adapter=?adapter.get_info());
-
-                // Create the logical device and command
-                // queue
-                let (device, queue) = adapter
-                    .request_device(
-                        &wgpu::DeviceDescriptor::default(),
-                    )
-                    .await
-                    .expect("Failed to create device");
-
-                // device.limits will print the hard limits of the
-                // device. This includes things like max texture dimensions,
-                // max color attachments, and max vertex buffers.
-                // info!(limits=?device.limits());
-                (surface, adapter, device, queue)
-            },
-        );
-
-        // Load the shaders from disk
-        let shader = device.create_shader_module(
-            wgpu::ShaderModuleDescriptor {
-                label: "triangle_shader".into(),
-                source: wgpu::ShaderSource::Wgsl(
-                    Cow::Borrowed(include_str!(
-                        "triangle.wgsl"
-                    )),
-                ),
-            },
-        );
-
-        let pipeline_layout = device
-            .create_pipeline_layout(
-                &wgpu::PipelineLayoutDescriptor {
-                    label: "triangle_layout".into(),
-                    bind_group_layouts: &[],
-                    immediate_size: 0,
-                },
-            );
+        // wgpu's default is to panic on any error that
+        // isn't caught by an error scope
+        log_uncaptured_errors(&device);
+        let device_lost = DeviceLostWatcher::new(&device);
 
+        let capabilities =
+            surface.get_capabilities(&adapter);
         let swapchain_format =
-            surface.get_capabilities(&adapter).formats[0];
-
-        let render_pipeline = device
-            .create_render_pipeline(
-                &wgpu::RenderPipelineDescriptor {
-                    label: "triangle_pipeline".into(),
-                    layout: Some(&pipeline_layout),
-                    vertex: wgpu::VertexState {
-                        module: &shader,
-                        entry_point: "vertex".into(),
-                        buffers: &[],
-                        compilation_options:
-                            Default::default(),
+            Rc::new(Cell::new(capabilities.formats[0]));
+
+        // a shader that doesn't compile or a pipeline that
+        // doesn't validate shuts the app down with the
+        // error, instead of panicking on the first frame
+        // that uses it
+        //
+        // everything made from the device is registered, so
+        // it can be made again on a new one if this device
+        // is lost
+        let mut resources = GpuResources::new();
+        let pipeline_format = swapchain_format.clone();
+        let render_pipeline = capture_errors(
+            &device,
+            "triangle_pipeline",
+            || {
+                resources.register(
+                    &device,
+                    &queue,
+                    move |cx| {
+                        create_pipeline(
+                            cx.device,
+                            pipeline_format.get(),
+                        )
                     },
-                    fragment: Some(wgpu::FragmentState {
-                        module: &shader,
-                        entry_point: "fragment".into(),
-                        compilation_options:
-                            Default::default(),
-                        targets: &[Some(
-                            swapchain_format.into(),
-                        )],
-                    }),
-                    primitive:
-                        wgpu::PrimitiveState::default(),
-                    depth_stencil: None,
-                    multisample:
-                        wgpu::MultisampleState::default(),
-                    multiview_mask: None,
-                    cache: None,
-                },
-            );
+                )
+            },
+        );
+        let render_pipeline = match render_pipeline {
+            Ok(render_pipeline) => render_pipeline,
+            Err(error) => {
+                error!(%error);
+                event_loop.exit();
+                return;
+            }
+        };
 
-        let config = surface
+        let screenshots =
+            ScreenshotManager::new(&capabilities);
+        let mut config = surface
             .get_default_config(
                 &adapter,
                 size.width,
                 size.height,
             )
             .unwrap();
+        // lets screenshots copy the frame straight out of
+        // the surface texture
+        config.usage = screenshots.surface_usage();
         surface.configure(&device, &config);
         self.resumed_data = Some(ResumedData {
             window,
             config,
+            resources,
+            swapchain_format,
             render_pipeline,
             surface,
             device,
             queue,
+            screenshots,
+            device_lost,
         });
     }
 
@@ -197,7 +167,7 @@ impl<'a> ApplicationHandler for App<'a> {
                 config.width = width.max(1);
                 config.height = height.max(1);
 
-                surface.configure(&device, &config);
+                surface.configure(device, config);
             }
             WindowEvent::KeyboardInput {
                 event:
@@ -208,10 +178,6 @@ impl<'a> ApplicationHandler for App<'a> {
                     },
                 ..
             } => {
-                // allow a single_match here so that people
-                // who use this example can easily match on
-                // new keys
-                #[allow(clippy::single_match)]
                 match key.as_ref() {
                     // WARNING: Consider using
                     // `key_without_modifiers()` if
@@ -219,24 +185,94 @@ impl<'a> ApplicationHandler for App<'a> {
                     Key::Named(NamedKey::Escape) => {
                         event_loop.exit();
                     }
+                    Key::Named(NamedKey::F12) => {
+                        if let Some(data) =
+                            self.resumed_data.as_mut()
+                        {
+                            data.screenshots
+                                .save_screenshot();
+                        }
+                    }
+                    // simulates losing the device, to test
+                    // recovering from it
+                    Key::Character("l") => {
+                        if let Some(data) =
+                            self.resumed_data.as_ref()
+                        {
+                            warn!("destroying the device");
+                            data.device.destroy();
+                        }
+                    }
                     _ => (),
                 }
             }
             WindowEvent::RedrawRequested => {
+                // nothing created from a lost device works
+                // anymore, so everything is made again on a
+                // new one before drawing
+                let lost =
+                    self.resumed_data.as_ref().and_then(
+                        |data| data.device_lost.check(),
+                    );
+                if let Some(lost) = lost {
+                    warn!(%lost, "recreating the gpu context");
+                    let data =
+                        self.resumed_data.take().unwrap();
+                    match recover(data) {
+                        Ok(data) => {
+                            self.resumed_data = Some(data);
+                        }
+                        Err(error) => {
+                            error!(%error);
+                            event_loop.exit();
+                        }
+                    }
+                    return;
+                }
+
                 let Some(ResumedData {
+                    config,
                     surface,
                     device,
                     queue,
+                    resources,
                     render_pipeline,
+                    screenshots,
                     ..
-                }) = self.resumed_data.as_ref()
+                }) = self.resumed_data.as_mut()
                 else {
                     return;
                 };
+                let render_pipeline =
+                    resources.get(*render_pipeline);
 
-                let frame = surface
+                let frame = match surface
                     .get_current_texture()
-                    .expect("Failed to acquire next swap chain texture");
+                {
+                    Ok(frame) => frame,
+                    // the surface has to be configured again,
+                    // which also happens while the device is
+                    // being lost, before the watcher hears
+                    // about it
+                    Err(
+                        wgpu::SurfaceError::Lost
+                        | wgpu::SurfaceError::Outdated,
+                    ) => {
+                        surface.configure(device, config);
+                        return;
+                    }
+                    Err(wgpu::SurfaceError::Timeout) => {
+                        warn!(
+                            "timed out acquiring a frame"
+                        );
+                        return;
+                    }
+                    Err(error) => {
+                        error!(%error);
+                        event_loop.exit();
+                        return;
+                    }
+                };
                 let view = frame.texture.create_view(
                     &wgpu::TextureViewDescriptor::default(),
                 );
@@ -248,37 +284,19 @@ impl<'a> ApplicationHandler for App<'a> {
                                     .into(),
                         },
                     );
-                {
-                    let mut rpass =
-                    encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                        label: "triangle_render_pass".into(),
-                        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                            view: &view,
-                            resolve_target: None,
-                            ops: wgpu::Operations {
-                                load: wgpu::LoadOp::Clear(wgpu::Color{
-                                    r: 0.008,
-                                    g: 0.024,
-                                    b: 0.09,
-                                    a: 1.0,
-                                }),
-                                store: wgpu::StoreOp::Store,
-                            },
-                            // depth_slice allows rendering to a layer of a texture array
-                            // or a slice of a 3d texture view
-                            depth_slice: None
-                        })],
-                        depth_stencil_attachment: None,
-                        timestamp_writes: None,
-                        occlusion_query_set: None,
-                        multiview_mask: None
-                    });
-                    rpass.set_pipeline(&render_pipeline);
-                    rpass.draw(0..3, 0..1);
-                }
+                draw(&mut encoder, &view, render_pipeline);
+                screenshots.capture(
+                    device,
+                    &mut encoder,
+                    &frame.texture,
+                    |encoder, view| {
+                        draw(encoder, view, render_pipeline)
+                    },
+                );
 
                 queue.submit(Some(encoder.finish()));
                 frame.present();
+                screenshots.update(device);
             }
             _ => (),
         }
@@ -295,6 +313,213 @@ impl<'a> ApplicationHandler for App<'a> {
     }
 }
 
+/// Create a surface for `window`, and an adapter and
+/// device that can render to it
+fn request_device(
+    window: Arc<Window>,
+) -> (Surface<'static>, Adapter, Device, Queue) {
+    let instance = wgpu::Instance::default();
+
+    // wgpu apis for getting an adapter are async,
+    // so we block while waiting for them to complete
+    block_on(async move {
+        let surface =
+            instance.create_surface(window).unwrap();
+
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference:
+                    wgpu::PowerPreference::default(),
+                force_fallback_adapter: false,
+                // Request an adapter which can render to our surface
+                compatible_surface: Some(&surface),
+            })
+            .await
+            .expect(
+                "Failed to find an appropriate adapter",
+            );
+
+        info!(adapter=?adapter.get_info());
+
+        // Create the logical device and command
+        // queue
+        let (device, queue) = adapter
+            .request_device(
+                &wgpu::DeviceDescriptor::default(),
+            )
+            .await
+            .expect("Failed to create device");
+
+        // device.limits will print the hard limits of the
+        // device. This includes things like max texture dimensions,
+        // max color attachments, and max vertex buffers.
+        // info!(limits=?device.limits());
+        (surface, adapter, device, queue)
+    })
+}
+
+/// Drop everything that belongs to the lost device, then
+/// request a new one for the same window and rebuild the
+/// registered resources on it
+fn recover(
+    data: ResumedData<'_>,
+) -> Result<ResumedData<'_>, GpuError> {
+    let ResumedData {
+        window,
+        mut config,
+        mut resources,
+        swapchain_format,
+        render_pipeline,
+        surface,
+        device,
+        queue,
+        screenshots,
+        device_lost,
+    } = data;
+    // some platforms only allow one surface per window
+    // at a time, so the old one has to go first
+    drop((
+        surface,
+        screenshots,
+        device_lost,
+        queue,
+        device,
+    ));
+
+    let (surface, adapter, device, queue) =
+        request_device(window.clone());
+    log_uncaptured_errors(&device);
+    let device_lost = DeviceLostWatcher::new(&device);
+
+    // the new adapter might not offer the old surface's
+    // format, so the pipeline is rebuilt for whichever
+    // format it prefers
+    let capabilities = surface.get_capabilities(&adapter);
+    let Some(&format) = capabilities.formats.first() else {
+        return Err(GpuError {
+            label: "triangle_surface".to_string(),
+            error: wgpu::Error::Validation {
+                source: "no surface formats".into(),
+                description: "the new adapter can't present to the window".to_string(),
+            },
+        });
+    };
+    swapchain_format.set(format);
+    config.format = format;
+    config.view_formats.clear();
+
+    capture_errors(&device, "triangle_resources", || {
+        resources.rebuild(&device, &queue)
+    })?;
+
+    let screenshots = ScreenshotManager::new(&capabilities);
+    config.usage = screenshots.surface_usage();
+    surface.configure(&device, &config);
+    info!("recovered from losing the device");
+
+    Ok(ResumedData {
+        window,
+        config,
+        resources,
+        swapchain_format,
+        render_pipeline,
+        surface,
+        device,
+        queue,
+        screenshots,
+        device_lost,
+    })
+}
+
+/// The shader and pipeline, which are created inside
+/// error scopes
+fn create_pipeline(
+    device: &Device,
+    swapchain_format: TextureFormat,
+) -> RenderPipeline {
+    // Load the shaders from disk
+    let shader = device.create_shader_module(
+        wgpu::ShaderModuleDescriptor {
+            label: "triangle_shader".into(),
+            source: wgpu::ShaderSource::Wgsl(
+                Cow::Borrowed(include_str!(
+                    "triangle.wgsl"
+                )),
+            ),
+        },
+    );
+
+    let pipeline_layout = device.create_pipeline_layout(
+        &wgpu::PipelineLayoutDescriptor {
+            label: "triangle_layout".into(),
+            bind_group_layouts: &[],
+            immediate_size: 0,
+        },
+    );
+
+    device.create_render_pipeline(
+        &wgpu::RenderPipelineDescriptor {
+            label: "triangle_pipeline".into(),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vertex".into(),
+                buffers: &[],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fragment".into(),
+                compilation_options: Default::default(),
+                targets: &[Some(swapchain_format.into())],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview_mask: None,
+            cache: None,
+        },
+    )
+}
+
+fn draw(
+    encoder: &mut CommandEncoder,
+    view: &TextureView,
+    render_pipeline: &RenderPipeline,
+) {
+    let mut rpass = encoder.begin_render_pass(
+        &wgpu::RenderPassDescriptor {
+            label: "triangle_render_pass".into(),
+            color_attachments: &[Some(
+                wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(
+                            wgpu::Color {
+                                r: 0.008,
+                                g: 0.024,
+                                b: 0.09,
+                                a: 1.0,
+                            },
+                        ),
+                        store: wgpu::StoreOp::Store,
+                    },
+                    // depth_slice allows rendering to a layer of a texture array
+                    // or a slice of a 3d texture view
+                    depth_slice: None,
+                },
+            )],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+            multiview_mask: None,
+        },
+    );
+    rpass.set_pipeline(render_pipeline);
+    rpass.draw(0..3, 0..1);
+}
+
 fn main() {
     tracing_subscriber::fmt().init();
 
@@ -0,0 +1,445 @@
+//! Parse and validate every `.wgsl` and `.wesl` file in
+//! the examples and crates without creating a window or
+//! a GPU device.
+//!
+//! ```sh
+//! cargo run -p validate-shaders
+//! cargo run -p validate-shaders -- --emit spv,msl examples/triangle
+//! ```
+//!
+//! `--emit` writes what each wgpu backend would receive
+//! into `--out` (default `target/shaders`), which is
+//! useful for seeing what naga turns our WGSL into.
+use naga::{
+    back::{glsl, hlsl, msl, spv},
+    valid::{
+        Capabilities, ModuleInfo, ValidationFlags,
+        Validator,
+    },
+};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+use wesl::Wesl;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Target {
+    Spv,
+    Msl,
+    Hlsl,
+    Glsl,
+}
+
+impl Target {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Spv => "spv",
+            Self::Msl => "msl",
+            Self::Hlsl => "hlsl",
+            Self::Glsl => "glsl",
+        }
+    }
+
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "spv" | "spirv" => Some(Self::Spv),
+            "msl" | "metal" => Some(Self::Msl),
+            "hlsl" => Some(Self::Hlsl),
+            "glsl" => Some(Self::Glsl),
+            _ => None,
+        }
+    }
+}
+
+const USAGE: &str = "usage: validate-shaders [--emit spv,msl,hlsl,glsl] [--out DIR] [PATH...]";
+
+struct Args {
+    paths: Vec<PathBuf>,
+    emit: Vec<Target>,
+    out: PathBuf,
+}
+
+/// Run `f`, turning a panic into an error with the
+/// panic's message.
+///
+/// The message is reported with the failed translation,
+/// so the default hook (which would also print it) is
+/// silenced while `f` runs, and put back afterwards so
+/// panics anywhere else are still printed.
+fn catch_backend_panic<T>(
+    f: impl FnOnce() -> T + std::panic::UnwindSafe,
+) -> Result<T, String> {
+    let hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(|_| {}));
+    let result = std::panic::catch_unwind(f);
+    std::panic::set_hook(hook);
+
+    result.map_err(|payload| {
+        payload
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| {
+                payload.downcast_ref::<String>().cloned()
+            })
+            .unwrap_or_else(|| {
+                "backend panicked".to_string()
+            })
+    })
+}
+
+fn workspace_root() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("../..")
+        .canonicalize()
+        .expect("workspace root to exist")
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut args = Args {
+        paths: vec![],
+        emit: vec![],
+        out: workspace_root().join("target/shaders"),
+    };
+
+    let mut iter = std::env::args().skip(1);
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--emit" => {
+                let list = iter.next().ok_or(
+                    "--emit expects a list like spv,msl",
+                )?;
+                for name in list.split(',') {
+                    args.emit.push(
+                        Target::parse(name).ok_or(
+                            format!(
+                                "unknown target `{name}`"
+                            ),
+                        )?,
+                    );
+                }
+            }
+            "--out" => {
+                args.out = iter
+                    .next()
+                    .ok_or("--out expects a directory")?
+                    .into();
+            }
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                std::process::exit(0);
+            }
+            _ => args.paths.push(arg.into()),
+        }
+    }
+
+    if args.paths.is_empty() {
+        let root = workspace_root();
+        args.paths.push(root.join("examples"));
+        args.paths.push(root.join("crates"));
+    }
+    Ok(args)
+}
+
+/// Recursively collect shader files, skipping build
+/// output directories.
+fn collect_shaders(path: &Path, found: &mut Vec<PathBuf>) {
+    if path.is_file() {
+        if matches!(
+            path.extension().and_then(|ext| ext.to_str()),
+            Some("wgsl" | "wesl")
+        ) {
+            found.push(path.to_path_buf());
+        }
+        return;
+    }
+
+    let Ok(entries) = fs::read_dir(path) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path
+            .file_name()
+            .is_some_and(|name| name == "target")
+        {
+            continue;
+        }
+        collect_shaders(&path, found);
+    }
+}
+
+/// `.wesl` files are compiled down to WGSL first, which
+/// is also what the `triangle-wesl` example does at
+/// runtime. The file's directory is used as the package
+/// root so sibling imports resolve.
+fn load_source(path: &Path) -> Result<String, String> {
+    if path.extension().is_some_and(|ext| ext == "wgsl") {
+        return fs::read_to_string(path)
+            .map_err(|e| e.to_string());
+    }
+
+    let base = path.parent().unwrap_or(Path::new("."));
+    let stem = path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .ok_or("shader file name is not utf-8")?;
+    let module = format!("package::{stem}")
+        .parse()
+        .map_err(|e| format!("{e}"))?;
+
+    Wesl::new(base)
+        .compile(&module)
+        .map(|result| result.to_string())
+        .map_err(|e| format!("WESL error: {e}"))
+}
+
+fn validate(
+    path: &Path,
+    source: &str,
+) -> Result<(naga::Module, ModuleInfo), String> {
+    let display = path.display().to_string();
+
+    let module = naga::front::wgsl::parse_str(source)
+        .map_err(|e| {
+            e.emit_to_string_with_path(source, &display)
+        })?;
+
+    // `Capabilities::all()` accepts anything any backend
+    // could support, including the `wgpu_mesh_shader`
+    // extension the landscape example enables. Whether a
+    // particular adapter supports it is a runtime check.
+    let info = Validator::new(
+        ValidationFlags::all(),
+        Capabilities::all(),
+    )
+    .validate(&module)
+    .map_err(|e| {
+        e.emit_to_string_with_path(source, &display)
+    })?;
+
+    Ok((module, info))
+}
+
+fn emit(
+    target: Target,
+    module: &naga::Module,
+    info: &ModuleInfo,
+    out: &Path,
+) -> Result<Vec<PathBuf>, String> {
+    let mut written = vec![];
+    let mut write =
+        |ext: &str, bytes: &[u8]| -> Result<(), String> {
+            let path = out.with_extension(ext);
+            fs::write(&path, bytes)
+                .map_err(|e| e.to_string())?;
+            written.push(path);
+            Ok(())
+        };
+
+    match target {
+        Target::Spv => {
+            // wgpu asks for SPIR-V 1.0 unless a feature
+            // such as mesh shading needs something newer,
+            // so retry with whatever naga says it requires
+            let mut options = spv::Options::default();
+            let words = loop {
+                match spv::write_vec(
+                    module, info, &options, None,
+                ) {
+                    Err(
+                        spv::Error::SpirvVersionTooLow(
+                            major,
+                            minor,
+                        ),
+                    ) if options.lang_version
+                        < (major, minor) =>
+                    {
+                        options.lang_version =
+                            (major, minor);
+                    }
+                    result => {
+                        break result
+                            .map_err(|e| e.to_string())?;
+                    }
+                }
+            };
+            let bytes: Vec<u8> = words
+                .iter()
+                .flat_map(|word| word.to_le_bytes())
+                .collect();
+            write("spv", &bytes)?;
+        }
+        Target::Msl => {
+            // naga defaults to MSL 1.0, which doesn't have
+            // builtins such as `@builtin(instance_index)`.
+            // wgpu's Metal backend asks for the newest
+            // version the OS supports, which is 3.1 since
+            // macOS 14 and iOS 17.
+            let options = msl::Options {
+                lang_version: (3, 1),
+                ..Default::default()
+            };
+            let (source, _) = msl::write_string(
+                module,
+                info,
+                &options,
+                &msl::PipelineOptions::default(),
+            )
+            .map_err(|e| e.to_string())?;
+            write("metal", source.as_bytes())?;
+        }
+        Target::Hlsl => {
+            let options = hlsl::Options::default();
+            let pipeline_options =
+                hlsl::PipelineOptions::default();
+            let mut source = String::new();
+            hlsl::Writer::new(
+                &mut source,
+                &options,
+                &pipeline_options,
+            )
+            .write(module, info, None)
+            .map_err(|e| e.to_string())?;
+            write("hlsl", source.as_bytes())?;
+        }
+        Target::Glsl => {
+            // GLSL has one entry point per file, so each
+            // entry point gets its own output
+            for entry_point in &module.entry_points {
+                let stage = entry_point.stage;
+                let ext = match stage {
+                    naga::ShaderStage::Vertex => "vert",
+                    naga::ShaderStage::Fragment => "frag",
+                    naga::ShaderStage::Compute => "comp",
+                    // GLSL has no task or mesh stages
+                    naga::ShaderStage::Task
+                    | naga::ShaderStage::Mesh => continue,
+                };
+                let mut source = String::new();
+                glsl::Writer::new(
+                    &mut source,
+                    module,
+                    info,
+                    &glsl::Options {
+                        version: glsl::Version::Desktop(
+                            450,
+                        ),
+                        ..Default::default()
+                    },
+                    &glsl::PipelineOptions {
+                        shader_stage: stage,
+                        entry_point: entry_point
+                            .name
+                            .clone(),
+                        multiview: None,
+                    },
+                    naga::proc::BoundsCheckPolicies::default(),
+                )
+                .and_then(|mut writer| writer.write())
+                .map_err(|e| e.to_string())?;
+                write(
+                    &format!("{}.{ext}", entry_point.name),
+                    source.as_bytes(),
+                )?;
+            }
+        }
+    }
+
+    Ok(written)
+}
+
+fn main() -> ExitCode {
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(message) => {
+            eprintln!("{message}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let mut shaders = vec![];
+    for path in &args.paths {
+        collect_shaders(path, &mut shaders);
+    }
+    shaders.sort();
+
+    if !args.emit.is_empty()
+        && let Err(e) = fs::create_dir_all(&args.out)
+    {
+        eprintln!(
+            "failed to create {}: {e}",
+            args.out.display()
+        );
+        return ExitCode::FAILURE;
+    }
+
+    let root = workspace_root();
+    let mut failures = 0;
+    for path in &shaders {
+        let relative =
+            path.strip_prefix(&root).unwrap_or(path);
+
+        let result = load_source(path)
+            .and_then(|source| validate(relative, &source));
+        let (module, info) = match result {
+            Ok(validated) => validated,
+            Err(diagnostic) => {
+                failures += 1;
+                println!("error {}", relative.display());
+                eprintln!("{diagnostic}");
+                continue;
+            }
+        };
+        println!("ok    {}", relative.display());
+
+        // examples/triangle/src/triangle.wgsl becomes
+        // target/shaders/examples_triangle_src_triangle.*
+        let out = args.out.join(
+            relative
+                .with_extension("")
+                .to_string_lossy()
+                .replace(['/', '\\'], "_"),
+        );
+        for target in &args.emit {
+            // naga's backends `unimplemented!()` some
+            // newer features (such as mesh shaders in
+            // MSL and HLSL), so treat a panic as a failed
+            // translation instead of aborting the run
+            let result = catch_backend_panic(|| {
+                emit(*target, &module, &info, &out)
+            })
+            .and_then(|result| result);
+            match result {
+                Ok(written) => {
+                    for path in written {
+                        println!(
+                            "        -> {}",
+                            path.display()
+                        );
+                    }
+                }
+                Err(e) => {
+                    // a backend that can't express a
+                    // feature isn't a broken shader, so
+                    // this doesn't affect the exit code
+                    println!(
+                        "        {} translation failed: {e}",
+                        target.name()
+                    );
+                }
+            }
+        }
+    }
+
+    println!(
+        "{} shaders, {failures} failures",
+        shaders.len()
+    );
+    if failures > 0 {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}